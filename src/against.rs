@@ -0,0 +1,147 @@
+use crate::diagnostics::{self, CargoMessage, Diagnostic};
+use crate::util::{self, Workspace};
+use crate::{git, runner, store, Config};
+use anyhow::{Result, Context, bail};
+use std::collections::HashSet;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// Diagnostics of a build of another commit, to hide those the working tree didn't introduce.
+pub struct Against {
+    /// Short hash of the commit that was built
+    pub commit: String,
+    fingerprints: HashSet<String>,
+}
+
+impl Against {
+    pub fn contains(&self, diagnostic: &Diagnostic) -> bool {
+        self.fingerprints.contains(&diagnostic.fingerprint())
+    }
+}
+
+fn against_dir(workspace: &Workspace) -> PathBuf {
+    workspace.state_dir().join("against")
+}
+
+/// Cached diagnostics of `commit`, per command and warning setting since both change the result.
+fn cache_path(workspace: &Workspace, commit: &str, config: &Config) -> PathBuf {
+    let key = util::stable_hash(&[&runner::format_command(config), &config.include_warnings.to_string()]);
+    against_dir(workspace).join(format!("{}-{}.jsonl", commit, key))
+}
+
+/// Removes the temporary worktree however the build of it ends.
+struct Worktree<'a> {
+    repo: &'a Path,
+    path: PathBuf,
+}
+
+impl Drop for Worktree<'_> {
+    fn drop(&mut self) {
+        let path = self.path.display().to_string();
+        let _ = git::output(self.repo, &["worktree", "remove", "--force", &path]);
+    }
+}
+
+/// Diagnostics of `reference`, replayed from the cache or built in a temporary worktree.
+pub fn load(workspace: &Workspace, reference: &str, config: &Config) -> Result<Against> {
+    let commit = git::output(&workspace.root, &["rev-parse", "--verify", &format!("{}^{{commit}}", reference)])
+        .with_context(|| format!("--against: `{}` is not a commit", reference))?;
+    let short: String = commit.chars().take(10).collect();
+
+    let cache = cache_path(workspace, &commit, config);
+    let diagnostics = if cache.exists() {
+        if !config.quiet {
+            eprintln!("cargo-builder: Using the cached build of {} ({})", reference, short);
+        }
+        store::read_diagnostics(&cache)?
+    } else {
+        if !config.quiet {
+            eprintln!("cargo-builder: Building {} ({}) to compare with...", reference, short);
+        }
+        let diagnostics = build_commit(workspace, &commit, config)?;
+        store::write_diagnostics(&cache, &diagnostics)?;
+        diagnostics
+    };
+
+    Ok(Against {
+        commit: short,
+        fingerprints: diagnostics.iter().map(Diagnostic::fingerprint).collect(),
+    })
+}
+
+/// The cargo arguments with `--manifest-path` moved from the workspace at `root` to the
+/// same place in the worktree, so it builds the commit rather than the working tree.
+fn worktree_args(args: &[String], root: &Path, dir_in_worktree: &Path) -> Vec<String> {
+    let root = fs::canonicalize(root).unwrap_or_else(|_| root.to_path_buf());
+    let rebase = |manifest: &str| {
+        let manifest = fs::canonicalize(manifest).unwrap_or_else(|_| PathBuf::from(manifest));
+        match manifest.strip_prefix(&root) {
+            Ok(relative) => dir_in_worktree.join(relative).display().to_string(),
+            Err(_) => manifest.display().to_string(),
+        }
+    };
+    let mut rebased = Vec::with_capacity(args.len());
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            rebased.push(arg.clone());
+            rebased.extend(iter.by_ref().cloned());
+        } else if arg == "--manifest-path" {
+            rebased.push(arg.clone());
+            rebased.extend(iter.next().map(|manifest| rebase(manifest)));
+        } else if let Some(manifest) = arg.strip_prefix("--manifest-path=") {
+            rebased.push(format!("--manifest-path={}", rebase(manifest)));
+        } else {
+            rebased.push(arg.clone());
+        }
+    }
+    rebased
+}
+
+/// Build `commit` with the same arguments in a detached worktree and collect its diagnostics.
+///
+/// The worktree gets its own target directory, so its artifacts never replace
+/// the working tree's, but it is kept between runs to make later builds incremental.
+fn build_commit(workspace: &Workspace, commit: &str, config: &Config) -> Result<Vec<Diagnostic>> {
+    let dir = against_dir(workspace);
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+
+    // A worktree left behind by an interrupted run would block `worktree add`
+    let path = dir.join("worktree");
+    let _ = git::output(&workspace.root, &["worktree", "remove", "--force", &path.display().to_string()]);
+    git::output(&workspace.root, &["worktree", "add", "--detach", &path.display().to_string(), commit])?;
+    let worktree = Worktree { repo: &workspace.root, path };
+
+    // The workspace may be in a subdirectory of the repository
+    let prefix = git::output(&workspace.root, &["rev-parse", "--show-prefix"])?;
+    let dir_in_worktree = worktree.path.join(prefix);
+    let mut cmd = Command::new(&config.cargo);
+    cmd.arg("build")
+        .arg("--message-format=json")
+        .args(worktree_args(&config.cargo_args, &workspace.root, &dir_in_worktree))
+        .current_dir(&dir_in_worktree)
+        .env("CARGO_TARGET_DIR", dir.join("target"))
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null());
+    runner::setup_environment(&mut cmd, config)?;
+
+    let mut child = cmd.spawn().context("Failed to spawn cargo build process")?;
+    let stdout = child.stdout.take().context("Failed to capture stdout")?;
+    let mut collected = Vec::new();
+    for line in BufReader::new(stdout).lines() {
+        let line = line.context("Failed to read stdout line")?;
+        if let Some(CargoMessage::CompilerMessage(diagnostic)) = diagnostics::parse_cargo_message(&line)? {
+            if matches!(diagnostic.level.as_str(), "error" | "warning") {
+                collected.push(diagnostic);
+            }
+        }
+    }
+    let status = child.wait().context("Failed to wait for cargo build process")?;
+    if !status.success() && collected.is_empty() {
+        bail!("--against: building {} failed without diagnostics", commit);
+    }
+    Ok(collected)
+}
@@ -0,0 +1,107 @@
+use crate::{context, Config};
+use anyhow::{Result, bail};
+
+/// cargo's own aliases, which aliases in `.cargo/config.toml` can't redefine.
+const BUILTIN: [(&str, &str); 6] = [("b", "build"), ("c", "check"), ("d", "doc"), ("t", "test"), ("r", "run"), ("rm", "remove")];
+
+/// Aliases may expand to other aliases; a chain longer than this is taken for a cycle.
+const MAX_DEPTH: usize = 16;
+
+/// The cargo arguments with a leading `build`, `b` or alias dropped or expanded, so the
+/// build runs what `cargo <args>` would. `cargo build` has no positional arguments, so
+/// only a first argument that isn't a flag can be one.
+pub fn expand(config: &Config) -> Result<Vec<String>> {
+    expand_with(&config.cargo_args, |name| user_alias(config, name))
+}
+
+fn expand_with(args: &[String], lookup: impl Fn(&str) -> Option<Vec<String>>) -> Result<Vec<String>> {
+    let mut args = args.to_vec();
+    let mut seen: Vec<String> = Vec::new();
+    while let Some(first) = args.first().filter(|arg| !arg.starts_with('-')).cloned() {
+        if first == "build" {
+            args.remove(0);
+            return Ok(args);
+        }
+        let expansion = match BUILTIN.iter().find(|(alias, _)| *alias == first) {
+            Some((_, command)) => Some(vec![command.to_string()]),
+            None if BUILTIN.iter().any(|(_, command)| *command == first) => None,
+            None => lookup(&first),
+        };
+        let Some(expansion) = expansion else {
+            match seen.first() {
+                Some(alias) => bail!("alias `{}` runs `cargo {}`; cargo-builder only wraps `cargo build`", alias, first),
+                None if BUILTIN.iter().any(|(_, command)| *command == first) => {
+                    bail!("cargo-builder only wraps `cargo build`, not `cargo {}`", first)
+                }
+                // Not an alias either; cargo will say what's wrong with it
+                None => return Ok(args),
+            }
+        };
+        if seen.len() == MAX_DEPTH || seen.contains(&first) {
+            bail!("alias `{}` expands to itself", seen[0]);
+        }
+        seen.push(first);
+        args.splice(..1, expansion);
+    }
+    Ok(args)
+}
+
+/// What `[alias] name` in cargo's configuration expands to.
+fn user_alias(config: &Config, name: &str) -> Option<Vec<String>> {
+    parse_alias(context::cargo_config(config, &format!("alias.{}", name))?)
+}
+
+/// An alias as cargo's configuration has it: a string split at whitespace, or a
+/// list of arguments.
+fn parse_alias(value: serde_json::Value) -> Option<Vec<String>> {
+    match value {
+        serde_json::Value::String(command) => Some(command.split_whitespace().map(str::to_string).collect()),
+        serde_json::Value::Array(args) => args.into_iter().map(|arg| arg.as_str().map(str::to_string)).collect(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn aliases(name: &str) -> Option<Vec<String>> {
+        match name {
+            "br" => Some(args(&["build", "--release"])),
+            "bb" => Some(args(&["br", "-p", "app"])),
+            "ck" => Some(args(&["check", "--all-targets"])),
+            "loop" => Some(args(&["loop", "-q"])),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_expand() {
+        assert_eq!(expand_with(&args(&["--release"]), aliases).unwrap(), args(&["--release"]));
+        assert_eq!(expand_with(&args(&["b", "-p", "foo"]), aliases).unwrap(), args(&["-p", "foo"]));
+        assert_eq!(expand_with(&args(&["build", "-p", "foo"]), aliases).unwrap(), args(&["-p", "foo"]));
+        assert_eq!(expand_with(&args(&["br", "--locked"]), aliases).unwrap(), args(&["--release", "--locked"]));
+        // Aliases of aliases
+        assert_eq!(expand_with(&args(&["bb"]), aliases).unwrap(), args(&["--release", "-p", "app"]));
+        // Left for cargo to reject
+        assert_eq!(expand_with(&args(&["frobnicate"]), aliases).unwrap(), args(&["frobnicate"]));
+
+        let error = expand_with(&args(&["ck"]), aliases).unwrap_err();
+        assert_eq!(error.to_string(), "alias `ck` runs `cargo check`; cargo-builder only wraps `cargo build`");
+        let error = expand_with(&args(&["test"]), aliases).unwrap_err();
+        assert_eq!(error.to_string(), "cargo-builder only wraps `cargo build`, not `cargo test`");
+        assert!(expand_with(&args(&["t"]), aliases).is_err());
+        assert!(expand_with(&args(&["loop"]), aliases).is_err());
+    }
+
+    #[test]
+    fn test_parse_alias() {
+        assert_eq!(parse_alias(serde_json::json!("build --release")), Some(args(&["build", "--release"])));
+        assert_eq!(parse_alias(serde_json::json!(["build", "-p", "my app"])), Some(args(&["build", "-p", "my app"])));
+        assert_eq!(parse_alias(serde_json::json!({})), None);
+    }
+}
@@ -0,0 +1,328 @@
+use crate::diagnostics::Diagnostic;
+use crate::settings::ArtifactStep;
+use crate::steps::{self, Problem};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What `--out-name` templates can use
+const PLACEHOLDERS: [&str; 8] = ["name", "file", "stem", "ext", "kind", "version", "target", "profile"];
+
+/// A file the build produced that something outside cargo could use.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Artifact {
+    /// `bin`, `example`, `test`, `lib` for rlibs, `cdylib`, `staticlib` or `dylib`
+    pub kind: &'static str,
+    #[serde(rename = "target")]
+    pub target_name: String,
+    pub package_id: String,
+    pub path: PathBuf,
+    /// With `--checksums`, once the steps after the build are done with it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+impl Artifact {
+    /// The files of a compiler artifact worth listing: the program of a binary,
+    /// example or test, and the rlib, dynamic or static library of a library.
+    /// Metadata, debug info and import libraries are left out.
+    pub fn from_message(package_id: &str, target_name: &str, target_kind: &[String], test: bool, filenames: &[String], executable: Option<&str>) -> Vec<Artifact> {
+        let has_kind = |name: &str| target_kind.iter().any(|kind| kind == name);
+        let artifact = |kind, path: &str| Artifact {
+            kind,
+            target_name: target_name.to_string(),
+            package_id: package_id.to_string(),
+            path: PathBuf::from(path),
+            sha256: None,
+        };
+        if let Some(executable) = executable {
+            let kind = match () {
+                _ if test || has_kind("test") || has_kind("bench") => "test",
+                _ if has_kind("example") => "example",
+                _ => "bin",
+            };
+            return vec![artifact(kind, executable)];
+        }
+        filenames.iter().filter_map(|file| {
+            let extension = Path::new(file).extension()?.to_str()?;
+            let kind = match extension {
+                "rlib" => "lib",
+                "so" | "dylib" | "dll" | "wasm" if has_kind("cdylib") => "cdylib",
+                "so" | "dylib" | "dll" if has_kind("dylib") => "dylib",
+                "a" => "staticlib",
+                // Windows import libraries come with DLLs
+                "lib" if !file.ends_with(".dll.lib") => "staticlib",
+                _ => return None,
+            };
+            Some(artifact(kind, file))
+        }).collect()
+    }
+
+    /// Whether it's a program or library to ship, rather than tests or an rlib for rustc
+    pub fn is_shipped(&self) -> bool {
+        !matches!(self.kind, "test" | "lib")
+    }
+
+    /// Its file name, which stays the same from one build to the next
+    pub fn name(&self) -> String {
+        self.path.file_name().map_or_else(|| self.target_name.clone(), |name| name.to_string_lossy().into_owned())
+    }
+
+    /// Built from a package in the workspace or next to it, not a dependency
+    pub fn is_local(&self) -> bool {
+        self.package_id.contains("path+file://")
+    }
+
+    /// Its package's version, from an id like `path+file:///app#0.1.0`,
+    /// `path+file:///work#app@0.1.0` or, before cargo 1.77, `app 0.1.0 (path+file:///app)`
+    fn version(&self) -> &str {
+        match self.package_id.rsplit_once('#') {
+            Some((_, spec)) => spec.rsplit_once('@').map_or(spec, |(_, version)| version),
+            None => self.package_id.split(' ').nth(1).unwrap_or_default(),
+        }
+    }
+
+    /// Its file name after `--out-name`'s template, e.g. `{name}-{version}-{target}{ext}`.
+    fn file_name(&self, template: &str, target: &str, profile: &str) -> String {
+        self.render(template, target, profile)
+    }
+
+    /// `text` with the placeholders of `--out-name` and `{path}` filled in.
+    fn render(&self, text: &str, target: &str, profile: &str) -> String {
+        let file = self.name();
+        let (stem, ext) = match file.split_once('.') {
+            Some((stem, ext)) => (stem.to_string(), format!(".{}", ext)),
+            None => (file.clone(), String::new()),
+        };
+        text
+            .replace("{path}", &self.path.display().to_string())
+            .replace("{name}", &self.target_name)
+            .replace("{file}", &file)
+            .replace("{stem}", &stem)
+            .replace("{ext}", &ext)
+            .replace("{kind}", self.kind)
+            .replace("{version}", self.version())
+            .replace("{target}", target)
+            .replace("{profile}", profile)
+    }
+}
+
+/// The value of `--out-name`: a file name whose `{placeholders}` are all known.
+pub fn parse_template(text: &str) -> std::result::Result<String, String> {
+    if text.contains(['/', '\\']) {
+        return Err("the name can't contain a directory; put that in --out-dir".to_string());
+    }
+    let mut rest = text;
+    while let Some(start) = rest.find('{') {
+        let Some(end) = rest[start..].find('}') else { break };
+        let name = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&name) {
+            return Err(format!("unknown placeholder `{{{}}}`, expected some of {{{}}}", name, PLACEHOLDERS.join("}, {")));
+        }
+        rest = &rest[start + end + 1..];
+    }
+    Ok(text.to_string())
+}
+
+/// Copy the programs and libraries to ship into `dir`, named after `template`, and
+/// return the copies.
+pub fn copy(artifacts: &[Artifact], dir: &Path, template: &str, target: &str, profile: &str) -> Result<Vec<Artifact>, Problem> {
+    let template = parse_template(template).map_err(|e| Problem { message: format!("invalid artifact name template: {}", e), output: None, help: None })?;
+    let mut destinations: BTreeMap<PathBuf, &Artifact> = BTreeMap::new();
+    for artifact in artifacts.iter().filter(|artifact| artifact.is_local() && artifact.is_shipped()) {
+        let destination = dir.join(artifact.file_name(&template, target, profile));
+        if let Some(other) = destinations.insert(destination.clone(), artifact) {
+            return Err(Problem::new(
+                format!("{} and {} would both be copied to {}", other.path.display(), artifact.path.display(), destination.display()),
+                "tell them apart in the name template, e.g. with {kind} or {file}".to_string(),
+            ));
+        }
+    }
+    fs::create_dir_all(dir)
+        .map_err(|e| Problem { message: format!("failed to create {}: {}", dir.display(), e), output: None, help: None })?;
+    for (destination, artifact) in &destinations {
+        fs::copy(&artifact.path, destination).map_err(|e| Problem {
+            message: format!("failed to copy {} to {}: {}", artifact.path.display(), destination.display(), e),
+            output: None,
+            help: None,
+        })?;
+    }
+    Ok(destinations.into_iter().map(|(path, artifact)| Artifact { path, ..artifact.clone() }).collect())
+}
+
+/// Fill in the SHA-256 of each artifact.
+pub fn checksum(artifacts: &mut [Artifact]) -> Result<(), Problem> {
+    for artifact in artifacts {
+        let hash = sha256(&artifact.path)
+            .map_err(|e| Problem { message: format!("failed to read {}: {}", artifact.path.display(), e), output: None, help: None })?;
+        artifact.sha256 = Some(hash);
+    }
+    Ok(())
+}
+
+fn sha256(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+/// The checksums as a note for the log, in the format `sha256sum --check` reads.
+pub fn checksum_diagnostic(artifacts: &[Artifact]) -> Diagnostic {
+    let message = format!("SHA-256 of {} {}", artifacts.len(), if artifacts.len() == 1 { "artifact" } else { "artifacts" });
+    let lines: String = artifacts.iter()
+        .filter_map(|artifact| Some(format!("  {}  {}\n", artifact.sha256.as_ref()?, artifact.path.display())))
+        .collect();
+    Diagnostic {
+        rendered: format!("note: {}\n{}", message, lines),
+        ..Diagnostic::new("note", message)
+    }
+}
+
+/// Run each step on the programs and libraries to ship of its kinds, stopping at the
+/// first that fails. Returns a note with what each step that printed anything printed.
+pub fn run_steps(steps: &[ArtifactStep], artifacts: &[Artifact], target: &str, profile: &str) -> Result<Vec<Diagnostic>, Problem> {
+    let mut notes = Vec::new();
+    for artifact in artifacts.iter().filter(|artifact| artifact.is_local() && artifact.is_shipped()) {
+        for step in steps.iter().filter(|step| step.kinds.is_empty() || step.kinds.iter().any(|kind| kind == artifact.kind)) {
+            // Split before filling in, so paths with spaces stay one argument
+            let mut args = step.command.split_whitespace().map(|arg| artifact.render(arg, target, profile));
+            let Some(program) = args.next() else { continue };
+            let mut cmd = Command::new(&program);
+            cmd.args(args);
+            let install = format!("install it, or change the step `{}` in cargo-builder.toml", step.command);
+            let printed = steps::output(cmd, &program, &artifact.name(), &install, |_| None)?;
+            if !printed.is_empty() {
+                let message = format!("{} on {}", program, artifact.name());
+                let lines: String = printed.lines().map(|line| format!("  {}\n", line)).collect();
+                notes.push(Diagnostic {
+                    rendered: format!("note: {}\n{}", message, lines),
+                    package_id: Some(artifact.package_id.clone()),
+                    ..Diagnostic::new("note", message)
+                });
+            }
+        }
+    }
+    Ok(notes)
+}
+
+/// A line per artifact for `--list-artifacts`: its kind, target and path.
+pub fn format_list(artifacts: &[Artifact]) -> Vec<String> {
+    artifacts.iter().map(|artifact| format!("{}\t{}\t{}", artifact.kind, artifact.target_name, artifact.path.display())).collect()
+}
+
+/// The list as a note for the log, which `--log-on-success` keeps.
+pub fn diagnostic(artifacts: &[Artifact]) -> Diagnostic {
+    let message = format!("the build produced {} {}", artifacts.len(), if artifacts.len() == 1 { "artifact" } else { "artifacts" });
+    let lines: String = format_list(artifacts).iter().map(|line| format!("  {}\n", line)).collect();
+    Diagnostic {
+        rendered: format!("note: {}\n{}", message, lines),
+        ..Diagnostic::new("note", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_from_message() {
+        let id = "path+file:///app#0.1.0";
+        let kinds = |target_kind: &[&str], test: bool, filenames: &[&str], executable: Option<&str>| -> Vec<(&'static str, String)> {
+            Artifact::from_message(id, "app", &strings(target_kind), test, &strings(filenames), executable)
+                .iter().map(|artifact| (artifact.kind, artifact.name())).collect()
+        };
+        assert_eq!(kinds(&["bin"], false, &["/t/debug/app"], Some("/t/debug/app")), [("bin", "app".to_string())]);
+        assert_eq!(kinds(&["lib"], true, &["/t/debug/deps/app-1f2e"], Some("/t/debug/deps/app-1f2e")), [("test", "app-1f2e".to_string())]);
+        assert_eq!(kinds(&["example"], false, &[], Some("/t/debug/examples/demo")), [("example", "demo".to_string())]);
+        assert_eq!(kinds(&["lib"], false, &["/t/debug/libapp.rlib", "/t/debug/deps/libapp.rmeta"], None), [("lib", "libapp.rlib".to_string())]);
+        assert_eq!(
+            kinds(&["cdylib", "staticlib"], false, &["/t/debug/app.dll", "/t/debug/app.dll.lib", "/t/debug/app.pdb", "/t/debug/app.lib"], None),
+            [("cdylib", "app.dll".to_string()), ("staticlib", "app.lib".to_string())],
+        );
+        assert!(kinds(&["lib"], false, &["/t/debug/deps/libapp.rmeta"], None).is_empty());
+    }
+
+    #[test]
+    fn test_format_list() {
+        let artifacts = Artifact::from_message("path+file:///app#0.1.0", "app", &strings(&["bin"]), false, &[], Some("/t/release/app"));
+        assert!(artifacts[0].is_local() && artifacts[0].is_shipped());
+        assert_eq!(format_list(&artifacts), ["bin\tapp\t/t/release/app"]);
+        assert_eq!(diagnostic(&artifacts).rendered, "note: the build produced 1 artifact\n  bin\tapp\t/t/release/app\n");
+    }
+
+    #[test]
+    fn test_file_name() {
+        let library = &Artifact::from_message("path+file:///work#app@1.2.0", "app", &strings(&["cdylib"]), false, &strings(&["/t/release/libapp.so"]), None)[0];
+        assert_eq!(library.file_name("{file}", "x86_64-unknown-linux-gnu", "release"), "libapp.so");
+        assert_eq!(library.file_name("{name}-{version}-{target}{ext}", "x86_64-unknown-linux-gnu", "release"), "app-1.2.0-x86_64-unknown-linux-gnu.so");
+        let program = Artifact { package_id: "app 0.3.1 (path+file:///app)".to_string(), ..library.clone() };
+        assert_eq!(program.file_name("{stem}-{version}-{profile}", "wasm32-wasip1", "dev"), "libapp-0.3.1-dev");
+
+        assert!(parse_template("{name}-{target}{ext}").is_ok());
+        assert_eq!(parse_template("{name}-{arch}").unwrap_err().split(',').next().unwrap(), "unknown placeholder `{arch}`");
+        assert!(parse_template("bin/{file}").is_err());
+    }
+
+    #[test]
+    fn test_copy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let built = temp_dir.path().join("app");
+        fs::write(&built, "program").unwrap();
+        let id = "path+file:///app#0.1.0";
+        let artifacts = [
+            Artifact::from_message(id, "app", &strings(&["bin"]), false, &[], built.to_str()),
+            Artifact::from_message(id, "app", &strings(&["bin"]), true, &[], Some("/t/debug/deps/app-1f2e")),
+            Artifact::from_message("registry+https://github.com/rust-lang/crates.io-index#dep@1.0.0", "dep", &strings(&["cdylib"]), false, &strings(&["/t/debug/libdep.so"]), None),
+        ].concat();
+        let dist = temp_dir.path().join("dist");
+        let copied = copy(&artifacts, &dist, "{name}-{version}", "x86_64-unknown-linux-gnu", "release").unwrap();
+        assert_eq!(copied.iter().map(|artifact| artifact.path.clone()).collect::<Vec<_>>(), [dist.join("app-0.1.0")]);
+        assert_eq!(fs::read_to_string(&copied[0].path).unwrap(), "program");
+
+        let twice = [artifacts[0].clone(), Artifact { kind: "example", ..artifacts[0].clone() }];
+        assert!(copy(&twice, &dist, "{name}", "x86_64-unknown-linux-gnu", "release").unwrap_err().message.contains("would both be copied"));
+    }
+
+
+    #[test]
+    fn test_checksum() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let built = temp_dir.path().join("app");
+        fs::write(&built, "abc").unwrap();
+        let mut artifacts = Artifact::from_message("path+file:///app#0.1.0", "app", &strings(&["bin"]), false, &[], built.to_str());
+        checksum(&mut artifacts).unwrap();
+        let hash = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+        assert_eq!(artifacts[0].sha256.as_deref(), Some(hash));
+        assert_eq!(checksum_diagnostic(&artifacts).rendered, format!("note: SHA-256 of 1 artifact\n  {}  {}\n", hash, built.display()));
+        assert_eq!(serde_json::to_value(&artifacts[0]).unwrap()["sha256"], hash);
+
+        fs::remove_file(&built).unwrap();
+        assert!(checksum(&mut artifacts).unwrap_err().message.starts_with("failed to read"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_run_steps() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let built = temp_dir.path().join("my app");
+        fs::write(&built, "program").unwrap();
+        let artifacts = Artifact::from_message("path+file:///app#0.1.0", "app", &strings(&["bin"]), false, &[], built.to_str());
+        let step = |command: &str, kinds: &[&str]| ArtifactStep { command: command.to_string(), kinds: strings(kinds) };
+
+        let notes = run_steps(&[step("ls {path}", &[]), step("false", &["cdylib"])], &artifacts, "x86_64-unknown-linux-gnu", "release").unwrap();
+        assert_eq!(notes.len(), 1);
+        assert_eq!(notes[0].rendered, format!("note: ls on my app\n  {}\n", built.display()));
+
+        let problem = run_steps(&[step("true", &[]), step("false {file}", &[])], &artifacts, "x86_64-unknown-linux-gnu", "release").unwrap_err();
+        assert_eq!(problem.message, "false failed on my app");
+        assert!(run_steps(&[step("no-such-tool {path}", &[])], &artifacts, "", "").unwrap_err().message.contains("isn't installed"));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::diagnostics::Diagnostic;
+use crate::steps::Problem;
+use crate::Config;
+use anyhow::Result;
+use serde_json::Value;
+use std::path::Path;
+use std::process::Command;
+
+const INSTALL: &str = "install it with `cargo install cargo-audit --locked`";
+
+/// How severe a vulnerability is, from its CVSS base score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    None,
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+impl Severity {
+    /// The CVSS v3 rating of a base score
+    fn of_score(score: f64) -> Self {
+        match score {
+            s if s >= 9.0 => Severity::Critical,
+            s if s >= 7.0 => Severity::High,
+            s if s >= 4.0 => Severity::Medium,
+            s if s > 0.0 => Severity::Low,
+            _ => Severity::None,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Severity::None => "none",
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+            Severity::Critical => "critical",
+        }
+    }
+}
+
+impl std::str::FromStr for Severity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "low" => Ok(Severity::Low),
+            "medium" => Ok(Severity::Medium),
+            "high" => Ok(Severity::High),
+            "critical" => Ok(Severity::Critical),
+            _ => Err(anyhow::anyhow!("Invalid severity: {}", s)),
+        }
+    }
+}
+
+/// What `cargo audit` found in Cargo.lock.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// An entry per advisory: vulnerabilities at or above the severity given to
+    /// `--audit` are errors, the rest warnings
+    pub diagnostics: Vec<Diagnostic>,
+    pub vulnerabilities: usize,
+    /// Unmaintained, unsound and yanked crates
+    pub warnings: usize,
+}
+
+impl Report {
+    pub fn describe(&self) -> String {
+        format!(
+            "{} {}, {} {}",
+            self.vulnerabilities, if self.vulnerabilities == 1 { "vulnerability" } else { "vulnerabilities" },
+            self.warnings, if self.warnings == 1 { "warning" } else { "warnings" },
+        )
+    }
+}
+
+/// Run `cargo audit` on the workspace's Cargo.lock.
+pub fn run(config: &Config, root: &Path, deny: Severity) -> Result<Report, Problem> {
+    let lock = root.join("Cargo.lock");
+    let mut cmd = Command::new(&config.cargo);
+    cmd.args(["audit", "--json", "--file"]).arg(&lock);
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    let output = cmd.output().map_err(|e| Problem { message: format!("failed to run cargo audit: {}", e), output: None, help: None })?;
+    // It exits with 1 when it finds vulnerabilities, and prints its report either way
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match serde_json::from_str::<Value>(&stdout) {
+        Ok(json) => Ok(parse(&json, deny)),
+        Err(_) => {
+            let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+            if stderr.contains("no such command") {
+                return Err(Problem::new("cargo-audit isn't installed".to_string(), INSTALL.to_string()));
+            }
+            Err(Problem {
+                message: format!("cargo audit failed on {}", lock.display()),
+                output: Some(stderr).filter(|stderr| !stderr.is_empty()),
+                help: None,
+            })
+        }
+    }
+}
+
+fn parse(json: &Value, deny: Severity) -> Report {
+    let mut report = Report::default();
+    let vulnerabilities = json.pointer("/vulnerabilities/list").and_then(Value::as_array).into_iter().flatten();
+    for vulnerability in vulnerabilities {
+        let severity = vulnerability.pointer("/advisory/cvss").and_then(Value::as_str).and_then(base_score).map(|score| (score, Severity::of_score(score)));
+        // Without a score there's no telling it's harmless
+        let level = if severity.is_none_or(|(_, severity)| severity >= deny) { "error" } else { "warning" };
+        let severity = severity.map(|(score, severity)| format!("severity {} (CVSS {:.1})", severity.name(), score));
+        report.diagnostics.push(diagnostic(level, vulnerability, severity));
+        report.vulnerabilities += 1;
+    }
+    let warnings = json.get("warnings").and_then(Value::as_object).into_iter().flat_map(|kinds| kinds.values());
+    for warning in warnings.filter_map(Value::as_array).flatten() {
+        let kind = warning.get("kind").and_then(Value::as_str).unwrap_or("warning");
+        report.diagnostics.push(diagnostic("warning", warning, Some(format!("{} crate", kind))));
+        report.warnings += 1;
+    }
+    report
+}
+
+/// An advisory as an entry on Cargo.lock, with its id as the code.
+fn diagnostic(level: &str, finding: &Value, note: Option<String>) -> Diagnostic {
+    let text = |pointer: &str| finding.pointer(pointer).and_then(Value::as_str).unwrap_or_default().to_string();
+    let (name, version) = (text("/package/name"), text("/package/version"));
+    let id = finding.pointer("/advisory/id").and_then(Value::as_str).map(String::from);
+    let message = match (&id, finding.pointer("/advisory/title").and_then(Value::as_str)) {
+        (Some(id), Some(title)) => format!("{}: {} in {} {}", id, title, name, version),
+        // Yanked crates have no advisory
+        _ => format!("{} {} is {}", name, version, text("/kind")),
+    };
+    let mut rendered = format!("{}: {}\n --> Cargo.lock\n", level, message);
+    if let Some(note) = note {
+        rendered.push_str(&format!("  = note: {}\n", note));
+    }
+    let patched: Vec<&str> = finding.pointer("/versions/patched").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str).collect();
+    if id.is_some() {
+        rendered.push_str(&match patched.is_empty() {
+            true => "  = help: no patched version is available\n".to_string(),
+            false => format!("  = help: upgrade to {}\n", patched.join(" or ")),
+        });
+        let url = finding.pointer("/advisory/url").and_then(Value::as_str).map(String::from)
+            .unwrap_or_else(|| format!("https://rustsec.org/advisories/{}", text("/advisory/id")));
+        rendered.push_str(&format!("  = note: see {}\n", url));
+    }
+    Diagnostic {
+        code: id,
+        file: Some("Cargo.lock".to_string()),
+        rendered,
+        ..Diagnostic::new(level, message)
+    }
+}
+
+/// The base score of a CVSS v3 vector, e.g. `CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H`,
+/// as the specification computes it.
+fn base_score(vector: &str) -> Option<f64> {
+    let metric = |name: &str| vector.split('/').find_map(|part| part.strip_prefix(name)?.strip_prefix(':'));
+    let changed = match metric("S")? {
+        "U" => false,
+        "C" => true,
+        _ => return None,
+    };
+    let attack_vector: f64 = match metric("AV")? { "N" => 0.85, "A" => 0.62, "L" => 0.55, "P" => 0.2, _ => return None };
+    let complexity = match metric("AC")? { "L" => 0.77, "H" => 0.44, _ => return None };
+    let privileges = match (metric("PR")?, changed) {
+        ("N", _) => 0.85,
+        ("L", false) => 0.62,
+        ("L", true) => 0.68,
+        ("H", false) => 0.27,
+        ("H", true) => 0.5,
+        _ => return None,
+    };
+    let interaction = match metric("UI")? { "N" => 0.85, "R" => 0.62, _ => return None };
+    let impact = |name: &str| match metric(name)? { "H" => Some(0.56), "L" => Some(0.22), "N" => Some(0.0), _ => None };
+    let iss: f64 = 1.0 - (1.0 - impact("C")?) * (1.0 - impact("I")?) * (1.0 - impact("A")?);
+    let impact = match changed {
+        false => 6.42 * iss,
+        true => 7.52 * (iss - 0.029) - 3.25 * (iss - 0.02).powi(15),
+    };
+    if impact <= 0.0 {
+        return Some(0.0);
+    }
+    let exploitability = 8.22 * attack_vector * complexity * privileges * interaction;
+    let score = if changed { 1.08 * (impact + exploitability) } else { impact + exploitability };
+    Some(round_up(score.min(10.0)))
+}
+
+/// The specification's rounding up to one decimal, which avoids floating-point
+/// results like 4.000000001 turning into 4.1
+fn round_up(value: f64) -> f64 {
+    let scaled = (value * 100_000.0).round() as i64;
+    if scaled % 10_000 == 0 {
+        scaled as f64 / 100_000.0
+    } else {
+        (scaled / 10_000 + 1) as f64 / 10.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_base_score() {
+        assert_eq!(base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:H/I:H/A:H"), Some(9.8));
+        assert_eq!(base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:H"), Some(7.5));
+        assert_eq!(base_score("CVSS:3.1/AV:N/AC:L/PR:L/UI:N/S:C/C:L/I:L/A:N"), Some(6.4));
+        assert_eq!(base_score("CVSS:3.0/AV:L/AC:H/PR:H/UI:R/S:U/C:L/I:N/A:N"), Some(1.8));
+        assert_eq!(base_score("CVSS:3.1/AV:N/AC:L/PR:N/UI:N/S:U/C:N/I:N/A:N"), Some(0.0));
+        assert_eq!(base_score("CVSS:3.1/AV:N"), None);
+    }
+
+    #[test]
+    fn test_parse() {
+        let json: Value = serde_json::from_str(r#"{
+            "vulnerabilities": {"found": true, "count": 2, "list": [
+                {"advisory": {"id": "RUSTSEC-2020-0071", "title": "Potential segfault in the time crate", "cvss": "CVSS:3.1/AV:N/AC:H/PR:N/UI:N/S:U/C:N/I:N/A:H", "url": null},
+                 "versions": {"patched": [">=0.2.23"], "unaffected": ["=0.2.0"]},
+                 "package": {"name": "time", "version": "0.1.45"}},
+                {"advisory": {"id": "RUSTSEC-2023-0001", "title": "Data race", "cvss": null},
+                 "versions": {"patched": []},
+                 "package": {"name": "racy", "version": "1.0.0"}}
+            ]},
+            "warnings": {
+                "unmaintained": [{"kind": "unmaintained", "package": {"name": "ansi_term", "version": "0.12.1"},
+                                  "advisory": {"id": "RUSTSEC-2021-0139", "title": "ansi_term is Unmaintained", "cvss": null},
+                                  "versions": {"patched": []}}],
+                "yanked": [{"kind": "yanked", "package": {"name": "foo", "version": "0.3.1"}, "advisory": null, "versions": null}]
+            }
+        }"#).unwrap();
+        let report = parse(&json, Severity::High);
+        assert_eq!((report.vulnerabilities, report.warnings), (2, 2));
+        let levels: Vec<_> = report.diagnostics.iter().map(|d| d.level.as_str()).collect();
+        // 5.9 is below high; without a score it's an error all the same
+        assert_eq!(levels, ["warning", "error", "warning", "warning"]);
+        assert_eq!(
+            report.diagnostics[0].rendered,
+            "warning: RUSTSEC-2020-0071: Potential segfault in the time crate in time 0.1.45\n --> Cargo.lock\n  = note: severity medium (CVSS 5.9)\n  = help: upgrade to >=0.2.23\n  = note: see https://rustsec.org/advisories/RUSTSEC-2020-0071\n",
+        );
+        assert_eq!(report.diagnostics[3].message, "foo 0.3.1 is yanked");
+        assert_eq!(report.diagnostics[3].code, None);
+    }
+}
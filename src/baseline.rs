@@ -0,0 +1,145 @@
+use crate::diagnostics::Diagnostic;
+use crate::{store, util};
+use anyhow::{Result, Context, bail};
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Baseline written by `bless` when no `--baseline` is given, at the workspace root
+pub const DEFAULT_FILE: &str = "cargo-builder-baseline.txt";
+
+/// Fingerprints of known diagnostics that `--baseline` hides.
+///
+/// The file has one diagnostic per line: its fingerprint, then a description
+/// for reviewers that is ignored when reading. Blank lines and lines starting
+/// with `#` are comments.
+#[derive(Debug, Default)]
+pub struct Baseline {
+    fingerprints: HashSet<String>,
+}
+
+impl Baseline {
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read baseline: {}", path.display()))?;
+        Ok(Self::parse(&content))
+    }
+
+    fn parse(content: &str) -> Self {
+        let fingerprints = content.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_whitespace().next())
+            .map(str::to_string)
+            .collect();
+        Baseline { fingerprints }
+    }
+
+    pub fn contains(&self, diagnostic: &Diagnostic) -> bool {
+        self.fingerprints.contains(&diagnostic.fingerprint())
+    }
+}
+
+/// Position-free description of a diagnostic, so the file only changes when the set does.
+fn describe(diagnostic: &Diagnostic) -> String {
+    let mut text = diagnostic.level.clone();
+    if let Some(code) = &diagnostic.code {
+        text.push_str(&format!("[{}]", code));
+    }
+    text.push_str(": ");
+    text.push_str(diagnostic.message.lines().next().unwrap_or(""));
+    if let Some(file) = diagnostic.workspace_file() {
+        text.push_str(&format!(" ({})", file));
+    }
+    text
+}
+
+/// Baseline file contents: one line per distinct diagnostic, sorted by file and description.
+fn render(diagnostics: &[Diagnostic]) -> String {
+    let mut entries: Vec<(&str, String, String)> = diagnostics.iter()
+        .map(|d| (d.workspace_file().unwrap_or(""), describe(d), d.fingerprint()))
+        .collect();
+    entries.sort();
+    entries.dedup_by(|a, b| a.2 == b.2);
+
+    let mut content = String::from(
+        "# Known diagnostics hidden by `cargo builder --baseline`.\n\
+         # Regenerate with `cargo builder bless`; each line is a fingerprint and a description.\n",
+    );
+    for (_, description, fingerprint) in entries {
+        content.push_str(&format!("{}  {}\n", fingerprint, description));
+    }
+    content
+}
+
+/// Write the diagnostics of the build that just ran as the new baseline.
+pub fn bless(path: Option<&str>, manifest_path: Option<&Path>, exit_code: i32) -> Result<i32> {
+    let workspace = util::find_workspace_for(manifest_path)?;
+    let diagnostics = store::read_diagnostics(&store::last_run_path(&workspace))?;
+    if exit_code != 0 && diagnostics.is_empty() {
+        bail!("The build failed without diagnostics; not writing a baseline");
+    }
+
+    let path = path.map(PathBuf::from)
+        .unwrap_or_else(|| workspace.root.join(DEFAULT_FILE));
+    let previous = if path.exists() { Baseline::load(&path)? } else { Baseline::default() };
+
+    let content = render(&diagnostics);
+    fs::write(&path, &content)
+        .with_context(|| format!("Failed to write baseline: {}", path.display()))?;
+
+    let current = Baseline::parse(&content);
+    let added = current.fingerprints.difference(&previous.fingerprints).count();
+    let removed = previous.fingerprints.difference(&current.fingerprints).count();
+    eprintln!(
+        "cargo-builder: Wrote {} diagnostics to {} ({} added, {} removed)",
+        current.fingerprints.len(), path.display(), added, removed,
+    );
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_is_stable() {
+        let other_file = Diagnostic::new("warning", "unused import").at("src/a.rs", 40, 1);
+        let unused = Diagnostic::new("warning", "unused variable: `x`").at("src/lib.rs", 1, 1);
+        let diagnostics = [unused.clone(), other_file.clone(), unused];
+
+        let content = render(&diagnostics);
+        let lines: Vec<&str> = content.lines().filter(|l| !l.starts_with('#')).collect();
+        assert_eq!(lines, [
+            format!("{}  warning: unused import (src/a.rs)", other_file.fingerprint()),
+            format!("{}  warning: unused variable: `x` (src/lib.rs)", diagnostics[0].fingerprint()),
+        ]);
+
+        // Order of the build's output doesn't matter, and the file reads back
+        let reversed: Vec<Diagnostic> = diagnostics.iter().rev().cloned().collect();
+        assert_eq!(render(&reversed), content);
+        assert!(Baseline::parse(&content).contains(&other_file));
+    }
+
+    #[test]
+    fn test_render_uses_reported_paths() {
+        // As the build showed it with `--paths absolute`
+        let mut absolute = Diagnostic::new("warning", "unused import").at("src/lib.rs", 1, 1);
+        absolute.cargo_file = absolute.file.replace("/work/app/src/lib.rs".to_string());
+        assert_eq!(render(&[absolute]), render(&[Diagnostic::new("warning", "unused import").at("src/lib.rs", 1, 1)]));
+    }
+
+    #[test]
+    fn test_parse() {
+        let known = Diagnostic::new("warning", "unused variable: `x`").at("src/lib.rs", 1, 1);
+        let content = format!(
+            "# cargo-builder baseline\n\n{}  warning: unused variable: `x` (src/lib.rs)\n",
+            known.fingerprint(),
+        );
+        let baseline = Baseline::parse(&content);
+
+        assert_eq!(baseline.fingerprints.len(), 1);
+        assert!(baseline.contains(&known));
+        assert!(!baseline.contains(&Diagnostic::new("warning", "unused variable: `y`").at("src/lib.rs", 1, 1)));
+    }
+}
@@ -0,0 +1,209 @@
+use crate::diagnostics::{self, Diagnostic};
+use std::fs;
+use std::path::Path;
+
+/// Lines of a failed build script's stdout and stderr shown, from the end
+const TAIL: usize = 20;
+
+/// What the build script of a package in the workspace or next to it printed with
+/// `cargo:warning=` or `cargo::error=`, as diagnostics of the package. cargo only shows
+/// them among its own output, and those of dependencies not at all, which this follows.
+/// Read from the `output` file next to `out_dir`, which cargo keeps when the script
+/// doesn't run again, so they're reported on every build as rustc's warnings are.
+pub fn messages(package_id: &str, out_dir: &str) -> Vec<Diagnostic> {
+    if !package_id.contains("path+file://") {
+        return Vec::new();
+    }
+    let Some(output) = Path::new(out_dir).parent().and_then(|dir| fs::read_to_string(dir.join("output")).ok()) else {
+        return Vec::new();
+    };
+    parse(package_id, &output)
+}
+
+fn parse(package_id: &str, output: &str) -> Vec<Diagnostic> {
+    let package = match diagnostics::package_version(package_id) {
+        Some(version) => format!("{} v{}", diagnostics::package_name(package_id), version),
+        None => diagnostics::package_name(package_id).to_string(),
+    };
+    output.lines().filter_map(|line| {
+        let (level, message) = match line.split_once('=')? {
+            ("cargo:warning" | "cargo::warning", message) => ("warning", message),
+            ("cargo::error", message) => ("error", message),
+            _ => return None,
+        };
+        Some(Diagnostic {
+            package_id: Some(package_id.to_string()),
+            target_kind: vec!["custom-build".to_string()],
+            rendered: format!("{}: {}\n  = note: printed by the build script of {}\n", level, message, package),
+            ..Diagnostic::new(level, message)
+        })
+    }).collect()
+}
+
+/// Build scripts that failed, from what cargo says of them on its stderr: the command
+/// it ran, with the exit status, and everything the script printed, indented.
+#[derive(Default)]
+pub struct Failures {
+    partial: String,
+    failures: Vec<Failure>,
+    section: Option<Section>,
+}
+
+struct Failure {
+    /// As cargo names it, e.g. `openssl-sys v0.9.103` or `app v0.1.0 (/work/app)`
+    package: String,
+    /// e.g. `exit status: 1` or `signal: 9, SIGKILL: kill`
+    status: Option<String>,
+    stdout: Vec<String>,
+    stderr: Vec<String>,
+}
+
+/// Where in cargo's report of a failure a line is
+enum Section {
+    Cause,
+    Stdout,
+    Stderr,
+}
+
+impl Failures {
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.partial.push_str(&String::from_utf8_lossy(chunk));
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            self.line(diagnostics::strip_ansi_codes(&line).trim_end());
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        if let Some(package) = line.strip_prefix("error: failed to run custom build command for `").and_then(|rest| rest.strip_suffix('`')) {
+            self.failures.push(Failure { package: package.to_string(), status: None, stdout: Vec::new(), stderr: Vec::new() });
+            self.section = Some(Section::Cause);
+            return;
+        }
+        let (Some(section), Some(failure)) = (&self.section, self.failures.last_mut()) else { return };
+        // The script's output is indented; cargo's next message isn't
+        let Some(indented) = line.strip_prefix("  ").or_else(|| line.is_empty().then_some("")) else {
+            if !matches!(section, Section::Cause) || line.starts_with("error: ") || line.starts_with("warning: ") {
+                self.section = None;
+            }
+            return;
+        };
+        match (section, indented) {
+            (_, "--- stdout") => self.section = Some(Section::Stdout),
+            (_, "--- stderr") => self.section = Some(Section::Stderr),
+            (Section::Cause, cause) => {
+                if let Some((_, status)) = cause.strip_prefix("process didn't exit successfully: `").and_then(|rest| rest.rsplit_once("` (")) {
+                    failure.status = Some(status.trim_end_matches(')').to_string());
+                }
+            }
+            (Section::Stdout, line) => failure.stdout.push(line.to_string()),
+            (Section::Stderr, line) => failure.stderr.push(line.to_string()),
+        }
+    }
+
+    /// An error per failed build script, with the end of what it printed.
+    pub fn finish(self) -> Vec<Diagnostic> {
+        self.failures.into_iter().map(|failure| failure.diagnostic()).collect()
+    }
+}
+
+impl Failure {
+    fn diagnostic(&self) -> Diagnostic {
+        let package = self.package.split(" (").next().unwrap_or(&self.package);
+        let message = match &self.status {
+            Some(status) => format!("the build script of {} failed with {}", package, status),
+            None => format!("the build script of {} failed", package),
+        };
+        let mut rendered = format!("error: {}\n", message);
+        // Instructions to cargo are no help in finding what went wrong
+        let stdout: Vec<&str> = self.stdout.iter().map(String::as_str).filter(|line| !line.starts_with("cargo:")).collect();
+        let stderr: Vec<&str> = self.stderr.iter().map(String::as_str).collect();
+        for (name, lines) in [("stdout", stdout), ("stderr", stderr)] {
+            let lines = trimmed(&lines);
+            if lines.is_empty() {
+                continue;
+            }
+            let tail = &lines[lines.len().saturating_sub(TAIL)..];
+            let shown = if tail.len() < lines.len() { format!(", the last {} of {} lines", tail.len(), lines.len()) } else { String::new() };
+            rendered.push_str(&format!("  = note: its {}{}:\n", name, shown));
+            for line in tail {
+                rendered.push_str(format!("          {}", line).trim_end());
+                rendered.push('\n');
+            }
+        }
+        rendered.push('\n');
+        Diagnostic {
+            // The `name version (source)` form, as `package_name` reads it
+            package_id: Some(self.package.replacen(" v", " ", 1)),
+            target_kind: vec!["custom-build".to_string()],
+            rendered,
+            ..Diagnostic::new("error", message)
+        }
+    }
+}
+
+/// Lines without the blank ones around them
+fn trimmed<'a>(lines: &'a [&'a str]) -> &'a [&'a str] {
+    let start = lines.iter().position(|line| !line.trim().is_empty()).unwrap_or(lines.len());
+    let end = lines.iter().rposition(|line| !line.trim().is_empty()).map_or(start, |end| end + 1);
+    &lines[start..end]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let output = "cargo:rerun-if-changed=build.rs\ncargo:warning=libfoo not found, building without it\ncargo::warning=a=b\ncargo::error=protoc failed\ncargo:rustc-cfg=foo\n";
+        let found = parse("path+file:///work/app#0.1.0", output);
+        let levels: Vec<_> = found.iter().map(|d| (d.level.as_str(), d.message.as_str())).collect();
+        assert_eq!(levels, [("warning", "libfoo not found, building without it"), ("warning", "a=b"), ("error", "protoc failed")]);
+        assert_eq!(found[0].rendered, "warning: libfoo not found, building without it\n  = note: printed by the build script of app v0.1.0\n");
+        assert_eq!(found[0].target_category(), "build-script");
+        assert!(messages("registry+https://github.com/rust-lang/crates.io-index#openssl-sys@0.9.0", "/nonexistent/out").is_empty());
+    }
+
+    #[test]
+    fn test_failures() {
+        let stderr = "   Compiling openssl-sys v0.9.103
+error: failed to run custom build command for `openssl-sys v0.9.103`
+note: To improve backtraces for build dependencies, set the CARGO_PROFILE_DEV_BUILD_OVERRIDE_DEBUG=true environment variable to enable debug information generation.
+
+Caused by:
+  process didn't exit successfully: `/work/target/debug/build/openssl-sys-1a2b3c4d5e6f7a8b/build-script-main` (exit status: 101)
+  --- stdout
+  cargo:rerun-if-env-changed=OPENSSL_DIR
+  running pkg-config
+
+  --- stderr
+
+  Could not find directory of OpenSSL installation.
+
+warning: build failed, waiting for other jobs to finish...
+error: failed to run custom build command for `app v0.1.0 (/work/app)`
+
+Caused by:
+  process didn't exit successfully: `/work/target/debug/build/app-0f0f0f0f0f0f0f0f/build-script-build` (signal: 9, SIGKILL: kill)
+";
+        let mut failures = Failures::default();
+        for chunk in stderr.as_bytes().chunks(64) {
+            failures.feed(chunk);
+        }
+        let found = failures.finish();
+        assert_eq!(found.len(), 2);
+        assert_eq!(found[0].message, "the build script of openssl-sys v0.9.103 failed with exit status: 101");
+        assert_eq!(found[0].rendered, "error: the build script of openssl-sys v0.9.103 failed with exit status: 101\n  = note: its stdout:\n          running pkg-config\n  = note: its stderr:\n          Could not find directory of OpenSSL installation.\n\n");
+        assert_eq!(found[0].package_name(), Some("openssl-sys"));
+        assert_eq!(found[0].target_category(), "build-script");
+        assert_eq!(found[1].rendered, "error: the build script of app v0.1.0 failed with signal: 9, SIGKILL: kill\n\n");
+
+        // Only the end of a long output
+        let mut failures = Failures::default();
+        let lines: String = (1..=30).map(|n| format!("  line {}\n", n)).collect();
+        failures.feed(format!("error: failed to run custom build command for `app v0.1.0 (/work/app)`\n\nCaused by:\n  --- stderr\n{}", lines).as_bytes());
+        let rendered = &failures.finish()[0].rendered;
+        assert!(rendered.contains("  = note: its stderr, the last 20 of 30 lines:\n          line 11\n"));
+        assert!(rendered.ends_with("          line 30\n\n"));
+    }
+}
@@ -0,0 +1,84 @@
+use crate::steps::Problem;
+use crate::{context, Config};
+use std::env;
+use std::path::Path;
+
+/// A problem cargo would only report with an error that doesn't say what to do,
+/// when the build asks for `-Zbuild-std`.
+pub fn check(config: &Config) -> Option<Problem> {
+    if !requested(&config.cargo_args) {
+        return None;
+    }
+
+    // RUSTC_BOOTSTRAP lets a stable toolchain take unstable flags
+    let cargo = context::cargo_version(config)?;
+    if !is_nightly(cargo) && env::var_os("RUSTC_BOOTSTRAP").is_none() {
+        return Some(Problem::new(
+            format!("-Zbuild-std needs a nightly toolchain, and this build uses {}", cargo),
+            "build with `--toolchain nightly`, after `rustup toolchain install nightly --component rust-src`".to_string(),
+        ));
+    }
+
+    let sysroot = context::sysroot(config)?;
+    if has_rust_src(Path::new(&sysroot)) {
+        return None;
+    }
+    let toolchain = config.toolchain.as_deref().or_else(|| context::active_toolchain(config));
+    Some(Problem::new(
+        format!("-Zbuild-std builds the standard library from its sources, which aren't installed in {}", sysroot),
+        format!("run `rustup component add rust-src{}`", toolchain.map(|toolchain| format!(" --toolchain {}", toolchain)).unwrap_or_default()),
+    ))
+}
+
+/// Whether the cargo arguments include `-Zbuild-std`, as `-Zbuild-std[=crates]` or
+/// `-Z build-std[=crates]`.
+fn requested(args: &[String]) -> bool {
+    let mut args = args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        let flag = match arg.strip_prefix("-Z") {
+            Some("") => args.next().map_or("", String::as_str),
+            Some(flag) => flag,
+            None => continue,
+        };
+        if flag == "build-std" || flag.starts_with("build-std=") {
+            return true;
+        }
+    }
+    false
+}
+
+/// e.g. `cargo 1.81.0-nightly (...)`, or `-dev` for a toolchain built locally
+pub fn is_nightly(version: &str) -> bool {
+    version.split_whitespace().nth(1).is_some_and(|number| number.contains("-nightly") || number.contains("-dev"))
+}
+
+/// Where rustup's rust-src component puts the standard library's sources.
+fn has_rust_src(sysroot: &Path) -> bool {
+    sysroot.join("lib").join("rustlib").join("src").join("rust").join("library").is_dir()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_requested() {
+        assert!(requested(&args(&["-Zbuild-std", "--target", "x86_64-unknown-linux-gnu"])));
+        assert!(requested(&args(&["--release", "-Z", "build-std=core,alloc"])));
+        assert!(!requested(&args(&["-Zbuild-std-features=panic_immediate_abort"])));
+        assert!(!requested(&args(&["-Ztimings"])));
+        assert!(!requested(&args(&["--", "-Zbuild-std"])));
+    }
+
+    #[test]
+    fn test_is_nightly() {
+        assert!(is_nightly("cargo 1.81.0-nightly (a1f47ec3f 2024-06-15)"));
+        assert!(is_nightly("cargo 1.80.0-dev"));
+        assert!(!is_nightly("cargo 1.80.0 (376290515 2024-07-16)"));
+        assert!(!is_nightly("cargo 1.80.0-beta.6 (376290515 2024-07-16)"));
+    }
+}
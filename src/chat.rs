@@ -0,0 +1,140 @@
+use crate::diagnostics;
+use crate::notify::BuildOutcome;
+use serde_json::{json, Value};
+
+/// Errors quoted in a chat message
+const EXCERPT_ERRORS: usize = 3;
+/// Slack rejects section text over 3000 characters
+const SLACK_TEXT_LIMIT: usize = 3000;
+/// Discord rejects embed descriptions over 4096 characters
+const DISCORD_DESCRIPTION_LIMIT: usize = 4096;
+
+const GREEN: u32 = 0x2eb67d;
+const RED: u32 = 0xe01e5a;
+
+/// Cut `text` to at most `max` characters, marking the cut with an ellipsis.
+pub fn truncate(text: &str, max: usize) -> String {
+    if text.chars().count() <= max {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(max.saturating_sub(1)).collect();
+    cut.push('…');
+    cut
+}
+
+/// The first few errors as at most `max` characters of plain text, to be shown in a code block.
+fn excerpt(outcome: &BuildOutcome, max: usize) -> Option<String> {
+    let errors: Vec<String> = outcome.errors()
+        .take(EXCERPT_ERRORS)
+        .map(|d| diagnostics::strip_ansi_codes(&d.rendered).trim_end().to_string())
+        .collect();
+    if errors.is_empty() {
+        return None;
+    }
+
+    let mut text = errors.join("\n\n");
+    let more = outcome.error_count().saturating_sub(EXCERPT_ERRORS);
+    if more > 0 {
+        text.push_str(&format!("\n\n… and {} more", more));
+    }
+    Some(truncate(&text.replace("```", "'''"), max))
+}
+
+fn color(outcome: &BuildOutcome) -> u32 {
+    if outcome.success { GREEN } else { RED }
+}
+
+fn with_mention(text: String, mention: Option<&str>) -> String {
+    match mention {
+        Some(mention) => format!("{} {}", mention, text),
+        None => text,
+    }
+}
+
+/// Incoming-webhook message for Slack: a colored attachment with the summary
+/// and an excerpt of the errors.
+pub fn slack_payload(outcome: &BuildOutcome, mention: Option<&str>) -> Value {
+    let summary = format!("*{}*\n`{}`", outcome.headline(), outcome.command);
+    let mut blocks = vec![json!({
+        "type": "section",
+        "text": { "type": "mrkdwn", "text": truncate(&summary, SLACK_TEXT_LIMIT) },
+    })];
+    if let Some(errors) = excerpt(outcome, SLACK_TEXT_LIMIT - 6) {
+        blocks.push(json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("```{}```", errors) },
+        }));
+    }
+
+    json!({
+        "text": with_mention(outcome.headline(), mention),
+        "attachments": [{
+            "color": format!("#{:06x}", color(outcome)),
+            "blocks": blocks,
+        }],
+    })
+}
+
+/// Webhook message for Discord: an embed colored by status with counts as fields.
+pub fn discord_payload(outcome: &BuildOutcome, mention: Option<&str>) -> Value {
+    let mut description = format!("`{}`", truncate(&outcome.command, 1000));
+    let room = DISCORD_DESCRIPTION_LIMIT - description.chars().count() - 8;
+    if let Some(errors) = excerpt(outcome, room) {
+        description.push_str(&format!("\n```\n{}```", errors));
+    }
+
+    let mut payload = json!({
+        "embeds": [{
+            "title": outcome.headline(),
+            "description": description,
+            "color": color(outcome),
+            "timestamp": humantime::format_rfc3339_seconds(outcome.started_at).to_string(),
+            "fields": [
+                { "name": "Errors", "value": outcome.error_count().to_string(), "inline": true },
+                { "name": "Warnings", "value": outcome.warning_count().to_string(), "inline": true },
+            ],
+        }],
+    });
+    if let Some(mention) = mention {
+        payload["content"] = json!(mention);
+    }
+    payload
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostic;
+    use crate::notify::test_outcome;
+
+    #[test]
+    fn test_truncate() {
+        assert_eq!(truncate("short", 10), "short");
+        assert_eq!(truncate("abcdef", 4), "abc…");
+    }
+
+    #[test]
+    fn test_slack_payload() {
+        let errors: Vec<_> = (1..=4)
+            .map(|n| Diagnostic::new("error", format!("problem {}", n)).with_rendered(format!("\x1b[1merror\x1b[0m: problem {}\n", n)))
+            .collect();
+        let payload = slack_payload(&test_outcome(&errors), Some("@here"));
+
+        assert_eq!(payload["text"], "@here Build failed: 4 errors, 0 warnings in 4.2s");
+        assert_eq!(payload["attachments"][0]["color"], "#e01e5a");
+        let excerpt = payload["attachments"][0]["blocks"][1]["text"]["text"].as_str().unwrap();
+        assert!(excerpt.starts_with("```error: problem 1"));
+        assert!(excerpt.contains("problem 3") && !excerpt.contains("problem 4"));
+        assert!(excerpt.ends_with("… and 1 more```"));
+    }
+
+    #[test]
+    fn test_discord_payload() {
+        let payload = discord_payload(&BuildOutcome { success: true, ..test_outcome(&[]) }, None);
+        let embed = &payload["embeds"][0];
+        assert_eq!(embed["title"], "Build succeeded: 0 errors, 0 warnings in 4.2s");
+        assert_eq!(embed["color"], GREEN);
+        assert_eq!(embed["description"], "`cargo build`");
+        assert!(payload.get("content").is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use crate::diagnostics::Diagnostic;
+use std::env;
+
+/// CI service the build runs on, detected from the variables each one sets.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Provider {
+    GitHubActions,
+    GitLab,
+    Jenkins,
+    Buildkite,
+}
+
+impl Provider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Provider::GitHubActions => "GitHub Actions",
+            Provider::GitLab => "GitLab CI",
+            Provider::Jenkins => "Jenkins",
+            Provider::Buildkite => "Buildkite",
+        }
+    }
+
+    /// Whether the job log understands workflow commands such as `::error` and `::group::`.
+    pub fn workflow_commands(&self) -> bool {
+        *self == Provider::GitHubActions
+    }
+}
+
+/// The CI service of the current environment, if any.
+pub fn detect() -> Option<Provider> {
+    detect_from(|name| env::var(name).ok())
+}
+
+fn detect_from(var: impl Fn(&str) -> Option<String>) -> Option<Provider> {
+    let is_true = |name| var(name).is_some_and(|value| value == "true");
+    if is_true("GITHUB_ACTIONS") {
+        Some(Provider::GitHubActions)
+    } else if is_true("GITLAB_CI") {
+        Some(Provider::GitLab)
+    } else if is_true("BUILDKITE") {
+        Some(Provider::Buildkite)
+    } else if var("JENKINS_URL").is_some_and(|url| !url.is_empty()) {
+        Some(Provider::Jenkins)
+    } else {
+        None
+    }
+}
+
+/// A GitHub Actions workflow command that annotates the diagnostic's line in the
+/// job summary and the pull request diff, e.g. `::error file=src/main.rs,line=3,col=5,title=E0308::mismatched types`.
+pub fn annotation(diagnostic: &Diagnostic) -> Option<String> {
+    let command = match diagnostic.level.as_str() {
+        "error" => "error",
+        "warning" => "warning",
+        _ => return None,
+    };
+
+    let mut properties = Vec::new();
+    if let Some(file) = &diagnostic.file {
+        properties.push(format!("file={}", escape_property(&file.replace('\\', "/"))));
+        if let Some(line) = diagnostic.line {
+            properties.push(format!("line={}", line));
+        }
+        if let Some(column) = diagnostic.column {
+            properties.push(format!("col={}", column));
+        }
+    }
+    if let Some(code) = &diagnostic.code {
+        properties.push(format!("title={}", escape_property(code)));
+    }
+
+    Some(format!("::{} {}::{}", command, properties.join(","), escape_data(&diagnostic.message)))
+}
+
+/// Print `body` as a collapsed section of a GitHub Actions job log.
+pub fn print_group(title: &str, body: &str) {
+    eprintln!("::group::{}", escape_data(title));
+    eprint!("{}", body);
+    if !body.ends_with('\n') {
+        eprintln!();
+    }
+    eprintln!("::endgroup::");
+}
+
+fn escape_data(text: &str) -> String {
+    text.replace('%', "%25").replace('\r', "%0D").replace('\n', "%0A")
+}
+
+fn escape_property(text: &str) -> String {
+    escape_data(text).replace(':', "%3A").replace(',', "%2C")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn detect_with(vars: &[(&str, &str)]) -> Option<Provider> {
+        detect_from(|name| vars.iter().find(|(key, _)| *key == name).map(|(_, value)| value.to_string()))
+    }
+
+    #[test]
+    fn test_detect() {
+        assert_eq!(detect_with(&[("GITHUB_ACTIONS", "true"), ("CI", "true")]), Some(Provider::GitHubActions));
+        assert_eq!(detect_with(&[("GITLAB_CI", "true")]), Some(Provider::GitLab));
+        assert_eq!(detect_with(&[("BUILDKITE", "true")]), Some(Provider::Buildkite));
+        assert_eq!(detect_with(&[("JENKINS_URL", "https://ci.example.com/")]), Some(Provider::Jenkins));
+        assert_eq!(detect_with(&[("GITHUB_ACTIONS", "false")]), None);
+        assert_eq!(detect_with(&[]), None);
+    }
+
+    #[test]
+    fn test_annotation() {
+        let diagnostic = Diagnostic::new("error", "mismatched types\n100% sure").with_code("E0308").at("src\\main.rs", 3, 18);
+        assert_eq!(
+            annotation(&diagnostic).unwrap(),
+            "::error file=src/main.rs,line=3,col=18,title=E0308::mismatched types%0A100%25 sure",
+        );
+
+        let linker = Diagnostic { file: None, line: None, column: None, code: None, level: "warning".to_string(), ..diagnostic.clone() };
+        assert_eq!(annotation(&linker).unwrap(), "::warning ::mismatched types%0A100%25 sure");
+        assert!(annotation(&Diagnostic { level: "note".to_string(), ..diagnostic }).is_none());
+    }
+}
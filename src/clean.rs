@@ -0,0 +1,240 @@
+use crate::{store, util};
+use anyhow::{Context, Result};
+use clap::{Arg, ArgAction, ArgGroup, Command};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// The directories of a profile directory that hold one entry or more per compiled unit
+const UNIT_DIRS: [&str; 5] = [".fingerprint", "build", "deps", "examples", "incremental"];
+
+pub fn run(args: &[String]) -> Result<i32> {
+    let matches = Command::new("cargo-builder clean")
+        .about("Remove stale build artifacts from the target directory, leaving the rest for the next build")
+        .arg(
+            Arg::new("older-than")
+                .long("older-than")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration)
+                .help("Remove what no build has written or read for this long, e.g. 30d")
+        )
+        .arg(
+            Arg::new("keep-current-profile")
+                .long("keep-current-profile")
+                .action(ArgAction::SetTrue)
+                .help("Leave the current profile's directory alone, and clean the others")
+        )
+        .group(ArgGroup::new("what").args(["older-than", "keep-current-profile"]).required(true).multiple(true))
+        .arg(
+            Arg::new("profile")
+                .long("profile")
+                .value_name("NAME")
+                .help("The current profile, instead of the one the last build used")
+        )
+        .arg(
+            Arg::new("release")
+                .short('r')
+                .long("release")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("profile")
+                .help("Same as --profile release")
+        )
+        .arg(
+            Arg::new("dry-run")
+                .short('n')
+                .long("dry-run")
+                .action(ArgAction::SetTrue)
+                .help("List what would be removed without removing it")
+        )
+        .try_get_matches_from(std::iter::once("cargo-builder clean".to_string()).chain(args.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+
+    let workspace = util::find_workspace()?;
+    let cutoff = matches.get_one::<Duration>("older-than").map(|age| SystemTime::now().checked_sub(*age).unwrap_or(SystemTime::UNIX_EPOCH));
+    let keep = if matches.get_flag("keep-current-profile") {
+        let profile = match matches.get_one::<String>("profile") {
+            Some(profile) => profile.clone(),
+            None if matches.get_flag("release") => "release".to_string(),
+            None => store::load_history(&workspace)?.last().and_then(|run| run.profile.clone())
+                .unwrap_or_else(|| "dev".to_string()),
+        };
+        Some(profile_dir(&profile).to_string())
+    } else {
+        None
+    };
+
+    let stale = find_stale(&workspace.target_directory, cutoff, keep.as_deref());
+    let dry_run = matches.get_flag("dry-run");
+    let mut freed = 0;
+    for path in &stale {
+        let size = disk_usage(path);
+        freed += size;
+        let shown = path.strip_prefix(&workspace.root).unwrap_or(path);
+        if dry_run {
+            eprintln!("Would remove {} ({})", shown.display(), util::format_size(size));
+            continue;
+        }
+        if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        }.with_context(|| format!("Failed to remove {}", path.display()))?;
+    }
+    let count = format!("{} {}", stale.len(), if stale.len() == 1 { "entry" } else { "entries" });
+    match (stale.is_empty(), dry_run) {
+        (true, _) => eprintln!("cargo-builder: Nothing to remove"),
+        (false, true) => eprintln!("cargo-builder: Would remove {} ({}); run without --dry-run to remove them", count, util::format_size(freed)),
+        (false, false) => eprintln!("cargo-builder: Removed {} ({})", count, util::format_size(freed)),
+    }
+    Ok(0)
+}
+
+/// The directory of a profile's output: `debug` for dev and test, `release` for
+/// release and bench, and the profile's name for the others.
+fn profile_dir(profile: &str) -> &str {
+    match profile {
+        "dev" | "test" => "debug",
+        "bench" => "release",
+        _ => profile,
+    }
+}
+
+/// What to remove from the profile directories of `target`, including those of
+/// `--target` builds a level down, except the one named `keep`. With a cutoff, the
+/// files of each compiled unit no build has used since; without, whole directories.
+fn find_stale(target: &Path, cutoff: Option<SystemTime>, keep: Option<&str>) -> Vec<PathBuf> {
+    let mut stale = Vec::new();
+    for dir in profile_dirs(target) {
+        if keep.is_some_and(|keep| dir.file_name().is_some_and(|name| name == keep)) {
+            continue;
+        }
+        match cutoff {
+            Some(cutoff) => stale.extend(stale_units(&dir, cutoff)),
+            None => stale.push(dir),
+        }
+    }
+    stale
+}
+
+/// Directories cargo built a profile into, found by their fingerprints. cargo-builder's
+/// own state directory has none.
+pub fn profile_dirs(target: &Path) -> Vec<PathBuf> {
+    let mut dirs = Vec::new();
+    for dir in subdirs(target) {
+        if dir.join(".fingerprint").is_dir() {
+            dirs.push(dir);
+        } else {
+            dirs.extend(subdirs(&dir).into_iter().filter(|dir| dir.join(".fingerprint").is_dir()));
+        }
+    }
+    dirs
+}
+
+fn subdirs(dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(dir) else { return Vec::new() };
+    let mut dirs: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect();
+    dirs.sort();
+    dirs
+}
+
+/// The entries of the units in a profile directory last used before `cutoff`, or the
+/// whole directory when that's all of them. A unit's fingerprint, outputs and build
+/// script directory share the hash in their names, and are kept or removed together.
+fn stale_units(dir: &Path, cutoff: SystemTime) -> Vec<PathBuf> {
+    let mut units: BTreeMap<String, (SystemTime, Vec<PathBuf>)> = BTreeMap::new();
+    for sub in UNIT_DIRS {
+        let Ok(entries) = fs::read_dir(dir.join(sub)) else { continue };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            let Some(key) = path.file_name().and_then(|name| unit_key(&name.to_string_lossy())) else { continue };
+            let used = last_used(&path);
+            let unit = units.entry(key).or_insert((SystemTime::UNIX_EPOCH, Vec::new()));
+            unit.0 = unit.0.max(used);
+            unit.1.push(path);
+        }
+    }
+    if !units.is_empty() && units.values().all(|(used, _)| *used < cutoff) {
+        return vec![dir.to_path_buf()];
+    }
+    units.into_values().filter(|(used, _)| *used < cutoff).flat_map(|(_, paths)| paths).collect()
+}
+
+/// The hash cargo puts in the names of a unit's files, as in `libserde-1a2b3c4d5e6f7a8b.rlib`
+/// or `.fingerprint/serde-1a2b3c4d5e6f7a8b`. Incremental directories have a shorter hash
+/// of their own, like `serde-0ujd7er6a32sn`, and are a unit by themselves.
+pub fn unit_key(name: &str) -> Option<String> {
+    let stem = name.split('.').next()?;
+    let (_, hash) = stem.rsplit_once('-')?;
+    let is_hash = hash.len() >= 13 && hash.bytes().all(|byte| byte.is_ascii_digit() || byte.is_ascii_lowercase());
+    is_hash.then(|| hash.to_string())
+}
+
+/// When a file was last written or read, or the latest of that for the files of a
+/// directory. Directories' own times change when they're listed, this one included.
+fn last_used(path: &Path) -> SystemTime {
+    let Ok(metadata) = fs::symlink_metadata(path) else { return SystemTime::UNIX_EPOCH };
+    if metadata.is_dir() {
+        let files = fs::read_dir(path).into_iter().flatten().flatten().map(|entry| last_used(&entry.path()));
+        return files.max().unwrap_or_else(|| metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH));
+    }
+    let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+    metadata.accessed().map_or(modified, |accessed| accessed.max(modified))
+}
+
+/// The bytes a file or directory takes up, roughly.
+fn disk_usage(path: &Path) -> u64 {
+    let Ok(metadata) = fs::symlink_metadata(path) else { return 0 };
+    if metadata.is_dir() {
+        return fs::read_dir(path).into_iter().flatten().flatten().map(|entry| disk_usage(&entry.path())).sum();
+    }
+    metadata.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::{File, FileTimes};
+
+    fn touch(path: &Path, time: SystemTime) {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        let file = File::create(path).unwrap();
+        file.set_times(FileTimes::new().set_accessed(time).set_modified(time)).unwrap();
+    }
+
+    #[test]
+    fn test_unit_key() {
+        assert_eq!(unit_key("libserde-1a2b3c4d5e6f7a8b.rlib").as_deref(), Some("1a2b3c4d5e6f7a8b"));
+        assert_eq!(unit_key("serde-1a2b3c4d5e6f7a8b.d").as_deref(), Some("1a2b3c4d5e6f7a8b"));
+        assert_eq!(unit_key("build-script-build-0f0f0f0f0f0f0f0f"), Some("0f0f0f0f0f0f0f0f".to_string()));
+        assert_eq!(unit_key("app-0ujd7er6a32sn").as_deref(), Some("0ujd7er6a32sn"));
+        assert_eq!(unit_key("app"), None);
+        assert_eq!(unit_key("tokio-macros"), None);
+    }
+
+    #[test]
+    fn test_find_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let target = dir.path();
+        let now = SystemTime::now();
+        let old = now - Duration::from_secs(90 * 86400);
+        let cutoff = now - Duration::from_secs(30 * 86400);
+        let debug = target.join("debug");
+        touch(&debug.join(".fingerprint/serde-1111111111111111/lib-serde"), now);
+        touch(&debug.join("deps/libserde-1111111111111111.rlib"), old);
+        touch(&debug.join(".fingerprint/itoa-2222222222222222/lib-itoa"), old);
+        touch(&debug.join("deps/libitoa-2222222222222222.rlib"), old);
+        touch(&debug.join("app"), old);
+        let release = target.join("x86_64-unknown-linux-gnu/release");
+        touch(&release.join(".fingerprint/app-3333333333333333/bin-app"), old);
+        touch(&target.join("cargo-builder/history.jsonl"), old);
+
+        // A unit goes as a whole, and a profile with nothing recent goes entirely
+        assert_eq!(find_stale(target, Some(cutoff), None), [
+            debug.join(".fingerprint/itoa-2222222222222222"),
+            debug.join("deps/libitoa-2222222222222222.rlib"),
+            release.clone(),
+        ]);
+        assert_eq!(find_stale(target, Some(cutoff), Some("release")).len(), 2);
+        assert_eq!(find_stale(target, None, Some("debug")), [release]);
+    }
+}
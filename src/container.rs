@@ -0,0 +1,137 @@
+use crate::paths;
+use crate::util::{self, Workspace};
+use anyhow::{anyhow, Result};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+/// Where the host's directories are mounted in the container.
+const WORKSPACE: &str = "/workspace";
+const CARGO_HOME: &str = "/cargo";
+/// Only for a target directory outside the workspace; one inside comes with it
+const TARGET: &str = "/target";
+
+/// Looked for on PATH in this order when `--container-engine` isn't given.
+const ENGINES: [&str; 2] = ["docker", "podman"];
+
+/// The container engine to run: the one given, or the first of docker and podman on PATH.
+pub fn engine(given: Option<&str>) -> Result<String> {
+    if let Some(engine) = given {
+        return Ok(engine.to_string());
+    }
+    ENGINES.iter()
+        .find(|name| on_path(name))
+        .map(|name| name.to_string())
+        .ok_or_else(|| anyhow!("--container needs docker or podman on PATH, or --container-engine"))
+}
+
+fn on_path(name: &str) -> bool {
+    let file = format!("{}{}", name, env::consts::EXE_SUFFIX);
+    env::var_os("PATH").is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(&file).is_file()))
+}
+
+/// `cargo` as configured in `cargo`, run by `engine` in a container of `image` with the
+/// workspace mounted. Its environment goes along; the host's otherwise stays out.
+pub fn command(cargo: &Command, engine: &str, image: &str, workspace: &Workspace) -> Result<Command> {
+    let cwd = env::current_dir()?;
+    let mut cmd = Command::new(engine);
+    cmd.args(args(cargo, engine, image, workspace, &cwd, util::cargo_home().as_deref())?);
+    Ok(cmd)
+}
+
+fn args(cargo: &Command, engine: &str, image: &str, workspace: &Workspace, cwd: &Path, cargo_home: Option<&Path>) -> Result<Vec<String>> {
+    let relative = cwd.strip_prefix(&workspace.root).map_err(|_| {
+        anyhow!("--container builds from inside the workspace, and {} is outside {}", cwd.display(), workspace.root.display())
+    })?;
+    let mut workdir = WORKSPACE.to_string();
+    for component in relative.components() {
+        workdir.push('/');
+        workdir.push_str(&component.as_os_str().to_string_lossy());
+    }
+
+    // --init passes Ctrl-C on to cargo, which wouldn't get it as the container's first process
+    let mut args: Vec<String> = vec!["run".into(), "--rm".into(), "--init".into()];
+    args.extend(user(engine));
+    args.extend(["--volume".into(), format!("{}:{}", workspace.root.display(), WORKSPACE), "--workdir".into(), workdir]);
+    if !workspace.target_directory.starts_with(&workspace.root) {
+        args.extend(["--volume".into(), format!("{}:{}", workspace.target_directory.display(), TARGET)]);
+        args.extend(["--env".into(), format!("CARGO_TARGET_DIR={}", TARGET)]);
+    }
+    // Dependencies already downloaded on the host aren't downloaded again
+    if let Some(cargo_home) = cargo_home {
+        args.extend(["--volume".into(), format!("{}:{}", cargo_home.display(), CARGO_HOME)]);
+        args.extend(["--env".into(), format!("CARGO_HOME={}", CARGO_HOME)]);
+    }
+    for (name, value) in cargo.get_envs() {
+        if let Some(value) = value {
+            args.extend(["--env".into(), format!("{}={}", name.to_string_lossy(), value.to_string_lossy())]);
+        }
+    }
+    args.push(image.to_string());
+    // A --cargo-path is the host's; the image has its own cargo
+    args.push("cargo".into());
+    args.extend(cargo.get_args().map(|arg| arg.to_string_lossy().into_owned()));
+    Ok(args)
+}
+
+/// Builds as the host's user, so the target directory doesn't fill with files owned by root.
+#[cfg(unix)]
+fn user(engine: &str) -> Vec<String> {
+    if Path::new(engine).file_stem().is_some_and(|stem| stem == "podman") {
+        // Rootless podman already runs as the user; this keeps their id inside too
+        return vec!["--userns=keep-id".into()];
+    }
+    // SAFETY: neither call can fail or touch memory
+    let (uid, gid) = unsafe { (libc::getuid(), libc::getgid()) };
+    vec!["--user".into(), format!("{}:{}", uid, gid)]
+}
+
+#[cfg(windows)]
+fn user(_engine: &str) -> Vec<String> {
+    Vec::new()
+}
+
+/// `--path-map`s that turn paths in the container into the host's.
+pub fn path_maps(workspace: &Workspace) -> Vec<(String, String)> {
+    maps(workspace, util::cargo_home().as_deref())
+}
+
+fn maps(workspace: &Workspace, cargo_home: Option<&Path>) -> Vec<(String, String)> {
+    paths::mount_maps(workspace, WORKSPACE, TARGET, CARGO_HOME, cargo_home)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::PathRewriter;
+
+    #[test]
+    fn test_args() {
+        let workspace = Workspace { root: "/home/me/app".into(), target_directory: "/home/me/app/target".into() };
+        let mut cargo = Command::new("/home/me/.cargo/bin/cargo");
+        cargo.args(["build", "--release"]).env("CARGO_TERM_COLOR", "always");
+        let run = args(&cargo, "podman", "rust:1.80", &workspace, Path::new("/home/me/app/crates/cli"), Some(Path::new("/home/me/.cargo"))).unwrap();
+        assert_eq!(run.join(" "), "run --rm --init --userns=keep-id --volume /home/me/app:/workspace --workdir /workspace/crates/cli \
+            --volume /home/me/.cargo:/cargo --env CARGO_HOME=/cargo --env CARGO_TERM_COLOR=always rust:1.80 cargo build --release");
+
+        // A target directory of its own is mounted as well
+        let outside = Workspace { target_directory: "/tmp/target".into(), ..workspace };
+        let run = args(&cargo, "podman", "rust", &outside, Path::new("/home/me/app"), None).unwrap();
+        assert!(run.join(" ").contains("--volume /tmp/target:/target --env CARGO_TARGET_DIR=/target"));
+
+        assert!(args(&cargo, "podman", "rust", &outside, Path::new("/home/me"), None).is_err());
+    }
+
+    #[test]
+    fn test_maps() {
+        let workspace = Workspace { root: "/home/me/app".into(), target_directory: "/tmp/target".into() };
+        let maps = maps(&workspace, Some(Path::new("/home/me/.cargo")));
+        let rewriter = PathRewriter::new(&workspace.root, None, &maps).unwrap();
+        assert_eq!(rewriter.rewrite("/workspace/src/main.rs"), "/home/me/app/src/main.rs");
+        assert_eq!(rewriter.rewrite("/target/debug/build/app-1234/out/generated.rs"), "/tmp/target/debug/build/app-1234/out/generated.rs");
+        assert_eq!(
+            rewriter.rewrite("/cargo/git/checkouts/foo-3f63/abc1234/src/lib.rs"),
+            "/home/me/.cargo/git/checkouts/foo-3f63/abc1234/src/lib.rs",
+        );
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{git, Config};
+use std::env;
+use std::path::Path;
+use std::process::Command;
+use std::sync::OnceLock;
+use std::time::SystemTime;
+
+/// Facts about the current build, recorded in log headers so a log is self-describing.
+#[derive(Debug, Clone)]
+pub struct BuildContext {
+    pub timestamp: String,
+    pub command: String,
+    pub cargo_version: Option<String>,
+    pub rustc_version: Option<String>,
+    pub host: Option<String>,
+    /// The rustup toolchain cargo resolves to, e.g. `nightly-2024-06-01-x86_64-unknown-linux-gnu`
+    pub toolchain: Option<String>,
+    pub profile: String,
+    pub features: String,
+    pub vcs: Option<git::VcsState>,
+}
+
+/// What cargo, rustc and rustup report, the same for every build of the process.
+#[derive(Debug, Clone)]
+struct Toolchain {
+    cargo_version: Option<String>,
+    rustc_version: Option<String>,
+    host: Option<String>,
+    toolchain: Option<String>,
+}
+
+/// Collect the build context. Spawning `rustc`/`cargo` is only worth it when a log
+/// actually gets written, and only once per process, however many builds it runs.
+pub fn get(config: &Config) -> BuildContext {
+    let toolchain = toolchain(config).clone();
+
+    BuildContext {
+        timestamp: humantime::format_rfc3339_seconds(SystemTime::now()).to_string(),
+        command: crate::runner::format_command(config),
+        cargo_version: toolchain.cargo_version,
+        rustc_version: toolchain.rustc_version,
+        host: toolchain.host,
+        toolchain: toolchain.toolchain,
+        profile: profile(&config.cargo_args),
+        features: features(&config.cargo_args),
+        // cargo finds the workspace from the current directory or the manifest, and git the repository
+        vcs: git::VcsState::load(manifest_dir(&config.cargo_args).unwrap_or(Path::new("."))),
+    }
+}
+
+/// The version cargo reports, e.g. `cargo 1.81.0-nightly (...)`.
+pub fn cargo_version(config: &Config) -> Option<&'static str> {
+    toolchain(config).cargo_version.as_deref()
+}
+
+/// The host rustc reports, e.g. `x86_64-unknown-linux-gnu`.
+pub fn host(config: &Config) -> Option<&'static str> {
+    toolchain(config).host.as_deref()
+}
+
+/// The rustup toolchain cargo resolves to; `None` without rustup.
+pub fn active_toolchain(config: &Config) -> Option<&'static str> {
+    toolchain(config).toolchain.as_deref()
+}
+
+fn toolchain(config: &Config) -> &'static Toolchain {
+    static TOOLCHAIN: OnceLock<Toolchain> = OnceLock::new();
+    TOOLCHAIN.get_or_init(|| collect_toolchain(config))
+}
+
+fn collect_toolchain(config: &Config) -> Toolchain {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_verbose = command_output(&rustc, &["-vV"], config);
+
+    Toolchain {
+        cargo_version: command_output(&config.cargo, &["-V"], config),
+        rustc_version: rustc_verbose.as_deref().and_then(|v| v.lines().next()).map(str::to_string),
+        host: rustc_verbose.as_deref().and_then(|v| {
+            v.lines().find_map(|line| line.strip_prefix("host: ")).map(str::to_string)
+        }),
+        // e.g. `stable-x86_64-unknown-linux-gnu (default)`; absent without rustup
+        toolchain: command_output("rustup", &["show", "active-toolchain"], config)
+            .and_then(|active| active.split_whitespace().next().map(str::to_string)),
+    }
+}
+
+/// The sysroot of the toolchain the build uses, where its standard library and its
+/// sources are.
+pub fn sysroot(config: &Config) -> Option<String> {
+    let rustc = env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    command_output(&rustc, &["--print", "sysroot"], config)
+}
+
+/// Output of a toolchain command, run with the toolchain the build uses.
+fn command_output(program: &str, args: &[&str], config: &Config) -> Option<String> {
+    let mut cmd = Command::new(program);
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    let output = cmd.args(args).output().ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    Some(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// A value of cargo's configuration, merged from the `.cargo/config.toml` files and
+/// `CARGO_*` variables as cargo sees them in the current directory; `None` when it isn't
+/// set. `cargo config` is unstable, which `RUSTC_BOOTSTRAP` allows on a stable cargo
+/// for this one query.
+pub fn cargo_config(config: &Config, key: &str) -> Option<serde_json::Value> {
+    let mut cmd = Command::new(&config.cargo);
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    let output = cmd
+        .args(["-Zunstable-options", "config", "get", "--format", "json-value", key])
+        .env("RUSTC_BOOTSTRAP", "1")
+        .output()
+        .ok()?;
+    // Also fails when the key isn't set
+    if !output.status.success() {
+        return None;
+    }
+    serde_json::from_slice(&output.stdout).ok()
+}
+
+/// Value of a cargo option given as `--name value` or `--name=value`; the last one wins.
+pub fn option_value<'a>(args: &'a [String], names: &[&str]) -> Option<&'a str> {
+    let mut value = None;
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        }
+        for name in names {
+            if arg == name {
+                value = iter.next().map(String::as_str);
+            } else if let Some(v) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+                value = Some(v);
+            }
+        }
+    }
+    value
+}
+
+/// All values of a repeatable cargo option such as `--features`.
+pub fn option_values<'a>(args: &'a [String], names: &[&str]) -> Vec<&'a str> {
+    let mut values = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            break;
+        }
+        for name in names {
+            if arg == name {
+                values.extend(iter.next().map(String::as_str));
+            } else if let Some(v) = arg.strip_prefix(name).and_then(|rest| rest.strip_prefix('=')) {
+                values.push(v);
+            }
+        }
+    }
+    values
+}
+
+/// `--manifest-path` as given to cargo.
+pub fn manifest_path(args: &[String]) -> Option<&Path> {
+    option_value(args, &["--manifest-path"]).map(Path::new)
+}
+
+/// The directory of `--manifest-path`, unless it's in the current directory.
+fn manifest_dir(args: &[String]) -> Option<&Path> {
+    manifest_path(args)?.parent().filter(|dir| !dir.as_os_str().is_empty())
+}
+
+pub fn has_flag(args: &[String], flag: &str) -> bool {
+    args.iter().take_while(|arg| *arg != "--").any(|arg| arg == flag)
+}
+
+/// Cargo profile selected by the arguments: `--profile NAME`, `--release`, or `dev`.
+pub fn profile(args: &[String]) -> String {
+    if let Some(name) = option_value(args, &["--profile"]) {
+        return name.to_string();
+    }
+    if has_flag(args, "--release") || has_flag(args, "-r") {
+        "release".to_string()
+    } else {
+        "dev".to_string()
+    }
+}
+
+/// Human-readable description of the features requested on the command line.
+pub fn features(args: &[String]) -> String {
+    if has_flag(args, "--all-features") {
+        return "all".to_string();
+    }
+
+    let requested: Vec<&str> = option_values(args, &["--features", "-F"])
+        .into_iter()
+        .flat_map(|v| v.split([',', ' ']))
+        .filter(|f| !f.is_empty())
+        .collect();
+    let mut parts = Vec::new();
+    if !has_flag(args, "--no-default-features") {
+        parts.push("default");
+    }
+    parts.extend(requested);
+
+    if parts.is_empty() {
+        "none".to_string()
+    } else {
+        parts.join(", ")
+    }
+}
+
+impl BuildContext {
+    /// Header lines for log files, one `Key: value` pair per line.
+    pub fn header_lines(&self) -> Vec<String> {
+        let unknown = || "unknown".to_string();
+        vec![
+            format!("Date:     {}", self.timestamp),
+            format!("Command:  {}", self.command),
+            format!("Cargo:    {}", self.cargo_version.clone().unwrap_or_else(unknown)),
+            format!("Rustc:    {}", self.rustc_version.clone().unwrap_or_else(unknown)),
+            format!("Host:     {}", self.host.clone().unwrap_or_else(unknown)),
+            format!("Toolchain: {}", self.toolchain.clone().unwrap_or_else(|| "none".to_string())),
+            format!("Profile:  {}", self.profile),
+            format!("Features: {}", self.features),
+            format!("Commit:   {}", self.vcs.as_ref().map_or_else(|| "none".to_string(), git::VcsState::describe)),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_profile() {
+        assert_eq!(profile(&args(&[])), "dev");
+        assert_eq!(profile(&args(&["--release"])), "release");
+        assert_eq!(profile(&args(&["--profile", "bench"])), "bench");
+        assert_eq!(profile(&args(&["--release", "--profile=ci"])), "ci");
+    }
+
+    #[test]
+    fn test_features() {
+        assert_eq!(features(&args(&[])), "default");
+        assert_eq!(features(&args(&["--features", "a,b", "-F", "c"])), "default, a, b, c");
+        assert_eq!(features(&args(&["--no-default-features", "--features=x"])), "x");
+        assert_eq!(features(&args(&["--no-default-features"])), "none");
+        assert_eq!(features(&args(&["--all-features"])), "all");
+    }
+
+    #[test]
+    fn test_option_value_stops_at_separator() {
+        assert_eq!(option_value(&args(&["--", "--profile", "x"]), &["--profile"]), None);
+        assert_eq!(option_value(&args(&["--target", "wasm32-unknown-unknown"]), &["--target"]), Some("wasm32-unknown-unknown"));
+    }
+}
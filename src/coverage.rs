@@ -0,0 +1,143 @@
+use crate::steps::Problem;
+use crate::Config;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const INSTALL: &str = "install it with `cargo install cargo-llvm-cov --locked`, and llvm-tools with `rustup component add llvm-tools-preview`";
+
+/// The formats of the detailed report `--coverage` can write
+pub const FORMATS: [&str; 5] = ["html", "lcov", "json", "cobertura", "codecov"];
+
+/// How much of one kind of thing the tests covered.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Covered {
+    pub covered: u64,
+    pub count: u64,
+}
+
+impl Covered {
+    pub fn percent(&self) -> f64 {
+        if self.count == 0 {
+            100.0
+        } else {
+            self.covered as f64 * 100.0 / self.count as f64
+        }
+    }
+
+    fn from_json(totals: &Value, kind: &str) -> Option<Self> {
+        let totals = totals.get(kind)?;
+        Some(Covered { covered: totals.get("covered")?.as_u64()?, count: totals.get("count")?.as_u64()? })
+    }
+}
+
+/// The coverage of the whole run, and where the detailed report went.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Report {
+    pub lines: Covered,
+    pub functions: Covered,
+    pub regions: Covered,
+    pub path: PathBuf,
+}
+
+impl Report {
+    pub fn describe(&self) -> String {
+        format!(
+            "{:.1}% of lines ({}/{}), {:.1}% of functions, {:.1}% of regions",
+            self.lines.percent(), self.lines.covered, self.lines.count, self.functions.percent(), self.regions.percent(),
+        )
+    }
+
+    /// For the webhook's JSON summary
+    pub fn to_json(&self) -> Value {
+        json!({
+            "lines": self.lines.percent(),
+            "functions": self.functions.percent(),
+            "regions": self.regions.percent(),
+            "report": self.path.display().to_string(),
+        })
+    }
+}
+
+/// Turn what the instrumented tests recorded into a summary and a detailed report
+/// in `format`, written under `target/llvm-cov`.
+pub fn report(config: &Config, target_directory: &Path, format: &str) -> Result<Report, Problem> {
+    let output = report_command(config, &["--json", "--summary-only"]).output()
+        .map_err(|e| Problem { message: format!("failed to run cargo llvm-cov: {}", e), output: None, help: None })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let Some((lines, functions, regions)) = serde_json::from_str(&stdout).ok().and_then(|json: Value| totals(&json)) else {
+        return Err(failure(&output.stderr));
+    };
+
+    let dir = target_directory.join("llvm-cov");
+    let (path, args) = match format {
+        "html" => (dir.join("html").join("index.html"), vec!["--html".to_string()]),
+        _ => {
+            let path = dir.join(match format {
+                "lcov" => "lcov.info",
+                "cobertura" => "cobertura.xml",
+                "codecov" => "codecov.json",
+                _ => "coverage.json",
+            });
+            (path.clone(), vec![format!("--{}", format), "--output-path".to_string(), path.display().to_string()])
+        }
+    };
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    let output = report_command(config, &args).output()
+        .map_err(|e| Problem { message: format!("failed to run cargo llvm-cov: {}", e), output: None, help: None })?;
+    if !output.status.success() {
+        return Err(failure(&output.stderr));
+    }
+    Ok(Report { lines, functions, regions, path })
+}
+
+fn report_command(config: &Config, args: &[&str]) -> Command {
+    let mut cmd = Command::new(&config.cargo);
+    cmd.args(["llvm-cov", "report"]).args(args);
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    // The report is of the workspace the tests ran in
+    for (i, arg) in config.cargo_args.iter().enumerate() {
+        if arg == "--manifest-path" {
+            cmd.args(["--manifest-path", config.cargo_args.get(i + 1).map_or("", String::as_str)]);
+        } else if arg.starts_with("--manifest-path=") {
+            cmd.arg(arg);
+        }
+    }
+    cmd
+}
+
+fn failure(stderr: &[u8]) -> Problem {
+    let stderr = String::from_utf8_lossy(stderr).trim_end().to_string();
+    if stderr.contains("no such command") {
+        return Problem::new("cargo-llvm-cov isn't installed".to_string(), INSTALL.to_string());
+    }
+    Problem { message: "cargo llvm-cov report failed".to_string(), output: Some(stderr).filter(|stderr| !stderr.is_empty()), help: None }
+}
+
+/// Lines, functions and regions covered in llvm-cov's JSON export.
+fn totals(json: &Value) -> Option<(Covered, Covered, Covered)> {
+    let totals = json.get("data")?.get(0)?.get("totals")?;
+    Some((Covered::from_json(totals, "lines")?, Covered::from_json(totals, "functions")?, Covered::from_json(totals, "regions")?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_totals() {
+        let json: Value = serde_json::from_str(r#"{"data":[{"totals":{
+            "branches":{"count":0,"covered":0,"notcovered":0,"percent":0},
+            "functions":{"count":12,"covered":9,"percent":75},
+            "lines":{"count":1466,"covered":1234,"percent":84.17},
+            "regions":{"count":800,"covered":600,"notcovered":200,"percent":75}
+        }}],"type":"llvm.coverage.json.export","version":"2.0.1"}"#).unwrap();
+        let (lines, functions, regions) = totals(&json).unwrap();
+        let report = Report { lines, functions, regions, path: PathBuf::from("target/llvm-cov/lcov.info") };
+        assert_eq!(report.describe(), "84.2% of lines (1234/1466), 75.0% of functions, 75.0% of regions");
+        assert_eq!(report.to_json()["report"], "target/llvm-cov/lcov.info");
+        assert_eq!(Covered::default().percent(), 100.0);
+    }
+}
@@ -0,0 +1,49 @@
+use crate::paths;
+use crate::util::{self, Workspace};
+use crate::{context, Config};
+use std::path::Path;
+
+/// Where cross mounts the host's directories in its containers. Recent versions
+/// mount the workspace at its host path, older ones at `/project`.
+const PROJECT: &str = "/project";
+const CARGO_HOME: &str = "/cargo";
+const TARGET: &str = "/target";
+/// The host's toolchain, with the standard library's sources
+const SYSROOT: &str = "/rust";
+
+/// `--path-map`s that turn paths in cross's containers into the host's, its toolchain included.
+pub fn path_maps(workspace: &Workspace, config: &Config) -> Vec<(String, String)> {
+    maps(workspace, util::cargo_home().as_deref(), context::sysroot(config).as_deref())
+}
+
+fn maps(workspace: &Workspace, cargo_home: Option<&Path>, sysroot: Option<&str>) -> Vec<(String, String)> {
+    let mut maps = paths::mount_maps(workspace, PROJECT, TARGET, CARGO_HOME, cargo_home);
+    if let Some(sysroot) = sysroot {
+        maps.push((SYSROOT.to_string(), sysroot.to_string()));
+    }
+    maps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::paths::PathRewriter;
+
+    #[test]
+    fn test_maps() {
+        let workspace = Workspace { root: "/home/me/app".into(), target_directory: "/home/me/app/target".into() };
+        let maps = maps(&workspace, Some(Path::new("/home/me/.cargo")), Some("/home/me/.rustup/toolchains/stable"));
+        let rewriter = PathRewriter::new(&workspace.root, None, &maps).unwrap();
+        assert_eq!(rewriter.rewrite("/project/src/main.rs"), "/home/me/app/src/main.rs");
+        assert_eq!(
+            rewriter.rewrite("/cargo/registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.0/src/lib.rs"),
+            "/home/me/.cargo/registry/src/index.crates.io-6f17d22bba15001f/serde-1.0.0/src/lib.rs",
+        );
+        assert_eq!(
+            rewriter.rewrite("/rust/lib/rustlib/src/rust/library/core/src/option.rs"),
+            "/home/me/.rustup/toolchains/stable/lib/rustlib/src/rust/library/core/src/option.rs",
+        );
+        // Workspace-relative paths, as cargo reports them for the workspace's own crates
+        assert_eq!(rewriter.rewrite("src/lib.rs"), "src/lib.rs");
+    }
+}
@@ -0,0 +1,465 @@
+use crate::{diagnostics, store, tui, util};
+use crate::diagnostics::Diagnostic;
+use crate::search::Matcher;
+use crate::tui::{Line, Style, Terminal};
+use anyhow::{Result, bail};
+use clap::Command;
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use std::ops::Range;
+
+enum View {
+    Runs,
+    Run(RunView),
+}
+
+/// What a key did to a search.
+#[derive(Debug, PartialEq)]
+enum SearchKey {
+    /// Not a key of the search, for the view
+    Ignored,
+    /// Opened, closed or typed at the prompt
+    Edited,
+    /// A new query to filter with
+    Applied,
+}
+
+/// A `/` search, fuzzy over messages, codes and paths like `cargo builder search --fuzzy`.
+#[derive(Clone, Default)]
+struct Search {
+    /// The query being typed, while the prompt is open
+    typing: Option<String>,
+    query: String,
+    matcher: Option<Matcher>,
+}
+
+impl Search {
+    fn handle_key(&mut self, key: KeyEvent) -> Result<SearchKey> {
+        let Some(typing) = &mut self.typing else {
+            if key.code != KeyCode::Char('/') {
+                return Ok(SearchKey::Ignored);
+            }
+            self.typing = Some(self.query.clone());
+            return Ok(SearchKey::Edited);
+        };
+        match key.code {
+            KeyCode::Char(c) => typing.push(c),
+            KeyCode::Backspace => {
+                typing.pop();
+            }
+            // Keeps the query applied before
+            KeyCode::Esc => self.typing = None,
+            KeyCode::Enter => {
+                self.query = self.typing.take().unwrap_or_default();
+                // An empty query shows everything again
+                self.matcher = match self.query.is_empty() {
+                    true => None,
+                    false => Some(Matcher::new(&self.query, true, true)?),
+                };
+                return Ok(SearchKey::Applied);
+            }
+            _ => {}
+        }
+        Ok(SearchKey::Edited)
+    }
+
+    fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        self.matcher.as_ref().is_none_or(|matcher| matcher.matches_diagnostic(diagnostic))
+    }
+
+    /// The prompt while it's open, else the keys of the view.
+    fn footer(&self, keys: &str) -> String {
+        match &self.typing {
+            Some(typing) => format!("/{}▏  Enter search  Esc cancel", typing),
+            None => keys.to_string(),
+        }
+    }
+
+    /// Added to the title of a filtered view.
+    fn describe(&self) -> String {
+        match &self.matcher {
+            Some(_) => format!(" matching \"{}\"", self.query),
+            None => String::new(),
+        }
+    }
+}
+
+/// Diagnostics of a single run, each collapsed to its header line unless expanded.
+struct RunView {
+    diagnostics: Vec<Diagnostic>,
+    expanded: Vec<bool>,
+    search: Search,
+    /// Indices of the diagnostics the search matches
+    shown: Vec<usize>,
+    /// Index into `shown`
+    selected: usize,
+    /// First line shown, which may be within the selected diagnostic's body
+    offset: usize,
+}
+
+impl RunView {
+    fn new(diagnostics: Vec<Diagnostic>, search: Search) -> Self {
+        let expanded = vec![false; diagnostics.len()];
+        let mut view = RunView { diagnostics, expanded, search, shown: Vec::new(), selected: 0, offset: 0 };
+        view.filter();
+        view
+    }
+
+    fn filter(&mut self) {
+        self.shown = (0..self.diagnostics.len()).filter(|&i| self.search.matches(&self.diagnostics[i])).collect();
+        self.selected = 0;
+        self.offset = 0;
+    }
+
+    /// All lines of the view, plus the lines of the selected diagnostic, header and body.
+    fn lines(&self) -> (Vec<Line>, Range<usize>) {
+        if self.diagnostics.is_empty() {
+            return (vec![Line::new("No diagnostics recorded for this run", Style::Dim)], 0..1);
+        }
+        if self.shown.is_empty() {
+            return (vec![Line::new("No diagnostics match", Style::Dim)], 0..1);
+        }
+
+        let mut lines = Vec::new();
+        let mut selected_lines = 0..0;
+        for (shown, &i) in self.shown.iter().enumerate() {
+            let diagnostic = &self.diagnostics[i];
+            let text = diagnostics::strip_ansi_codes(&diagnostic.rendered);
+            let mut body = text.lines();
+            let marker = if self.expanded[i] { "▾" } else { "▸" };
+            let header_style = if shown == self.selected {
+                selected_lines.start = lines.len();
+                Style::Selected
+            } else {
+                match diagnostic.level.as_str() {
+                    "error" => Style::Error,
+                    "warning" => Style::Warning,
+                    _ => Style::Normal,
+                }
+            };
+            lines.push(Line::new(
+                format!("{} {}{}", marker, body.next().unwrap_or(""), format_location(diagnostic)),
+                header_style,
+            ));
+
+            if self.expanded[i] {
+                for line in body {
+                    lines.push(Line::new(format!("  {}", line), Style::Normal));
+                }
+            }
+            if shown == self.selected {
+                selected_lines.end = lines.len();
+            }
+        }
+        (lines, selected_lines)
+    }
+
+    /// Keep some of the selected diagnostic on screen; when it's all off screen, its header.
+    fn follow(&mut self, selected: Range<usize>, height: usize) {
+        if selected.end <= self.offset || selected.start >= self.offset + height {
+            self.offset = tui::scroll_to(selected.start, self.offset, height);
+        }
+    }
+
+    fn handle_key(&mut self, key: KeyEvent, height: usize) -> Result<()> {
+        match self.search.handle_key(key)? {
+            SearchKey::Ignored => {}
+            SearchKey::Edited => return Ok(()),
+            SearchKey::Applied => {
+                self.filter();
+                return Ok(());
+            }
+        }
+        let (_, selected) = self.lines();
+        let step = if matches!(key.code, KeyCode::PageUp | KeyCode::PageDown) { height.max(1) } else { 1 };
+        match key.code {
+            // Through the rest of an expanded diagnostic before going on to the next one
+            KeyCode::Down | KeyCode::Char('j') | KeyCode::PageDown if selected.end > self.offset + height => {
+                self.offset = (self.offset + step).min(selected.end - height);
+            }
+            KeyCode::Up | KeyCode::Char('k') | KeyCode::PageUp if selected.start < self.offset => {
+                self.offset = self.offset.saturating_sub(step).max(selected.start);
+            }
+            KeyCode::Enter | KeyCode::Char(' ') | KeyCode::Right => {
+                if let Some(expanded) = self.shown.get(self.selected).and_then(|&i| self.expanded.get_mut(i)) {
+                    *expanded = !*expanded;
+                }
+            }
+            KeyCode::Char('e') => self.expanded.iter_mut().for_each(|e| *e = true),
+            KeyCode::Char('c') => self.expanded.iter_mut().for_each(|e| *e = false),
+            _ => self.selected = move_selection(key, self.selected, self.shown.len(), height),
+        }
+        let (_, selected) = self.lines();
+        self.follow(selected, height);
+        Ok(())
+    }
+}
+
+pub fn run(args: &[String]) -> Result<i32> {
+    Command::new("cargo-builder dashboard")
+        .about("Browse previous builds and their diagnostics")
+        .try_get_matches_from(std::iter::once("cargo-builder dashboard".to_string()).chain(args.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+
+    let workspace = util::find_workspace()?;
+    let mut runs = store::load_history(&workspace)?;
+    if runs.is_empty() {
+        eprintln!("cargo-builder: No builds recorded yet; run a build first");
+        return Ok(1);
+    }
+    if !atty::is(atty::Stream::Stdout) {
+        bail!("The dashboard needs an interactive terminal");
+    }
+
+    // Newest first
+    runs.reverse();
+
+    let mut terminal = Terminal::new()?;
+    let mut view = View::Runs;
+    let mut search = Search::default();
+    // Indices of the runs the search matches, by their command or a diagnostic
+    let mut shown: Vec<usize> = (0..runs.len()).collect();
+    // Index into `shown`
+    let mut selected = 0;
+    let mut offset = 0;
+
+    loop {
+        let height = terminal.body_height()?;
+        match &mut view {
+            View::Runs => {
+                offset = tui::scroll_to(selected, offset, height);
+                let mut body: Vec<Line> = shown.iter()
+                    .enumerate()
+                    .skip(offset)
+                    .take(height)
+                    .map(|(i, &run)| {
+                        let run = &runs[run];
+                        let style = if i == selected {
+                            Style::Selected
+                        } else if run.success {
+                            Style::Normal
+                        } else {
+                            Style::Error
+                        };
+                        Line::new(run.format_row(), style)
+                    })
+                    .collect();
+                if shown.is_empty() {
+                    body.push(Line::new("No runs match", Style::Dim));
+                }
+                terminal.draw(
+                    &format!("cargo-builder dashboard ({} runs){}", runs.len(), search.describe()),
+                    &body,
+                    &search.footer("↑/↓ select  Enter open  / search  q quit"),
+                )?;
+
+                let key = terminal.read_key()?;
+                if is_interrupt(key) {
+                    break;
+                }
+                match search.handle_key(key)? {
+                    SearchKey::Ignored => {}
+                    SearchKey::Edited => continue,
+                    SearchKey::Applied => {
+                        shown.clear();
+                        for (i, run) in runs.iter().enumerate() {
+                            if search.matcher.as_ref().is_none_or(|matcher| matcher.is_match(&run.command))
+                                || store::load_run_diagnostics(&workspace, run.id)?.iter().any(|d| search.matches(d))
+                            {
+                                shown.push(i);
+                            }
+                        }
+                        selected = 0;
+                        continue;
+                    }
+                }
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => break,
+                    KeyCode::Enter if !shown.is_empty() => {
+                        let diagnostics = store::load_run_diagnostics(&workspace, runs[shown[selected]].id)?;
+                        // Opened on what the search found
+                        view = View::Run(RunView::new(diagnostics, Search { typing: None, ..search.clone() }));
+                    }
+                    _ => selected = move_selection(key, selected, shown.len(), height),
+                }
+            }
+            View::Run(run_view) => {
+                let run = &runs[shown[selected]];
+                let (lines, selected_lines) = run_view.lines();
+                // The terminal may have been resized since
+                run_view.follow(selected_lines, height);
+                let body: Vec<Line> = lines.into_iter().skip(run_view.offset).take(height).collect();
+                terminal.draw(
+                    &format!("Run #{} — {}{}", run.id, run.command, run_view.search.describe()),
+                    &body,
+                    &run_view.search.footer("↑/↓ scroll  Enter expand/collapse  e expand all  c collapse all  / search  Esc back  q quit"),
+                )?;
+
+                let key = terminal.read_key()?;
+                if is_interrupt(key) {
+                    break;
+                }
+                match key.code {
+                    KeyCode::Char('q') if run_view.search.typing.is_none() => break,
+                    KeyCode::Esc | KeyCode::Backspace | KeyCode::Left if run_view.search.typing.is_none() => view = View::Runs,
+                    _ => run_view.handle_key(key, height)?,
+                }
+            }
+        }
+    }
+
+    Ok(0)
+}
+
+fn format_location(diagnostic: &Diagnostic) -> String {
+    match (&diagnostic.file, diagnostic.line) {
+        (Some(file), Some(line)) => format!("  ({}:{})", file, line),
+        (Some(file), None) => format!("  ({})", file),
+        _ => String::new(),
+    }
+}
+
+/// Ctrl-C, which comes as a key rather than SIGINT while the terminal is in raw mode.
+fn is_interrupt(key: KeyEvent) -> bool {
+    key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL)
+}
+
+/// Apply a navigation key to a cursor position within `len` items.
+fn move_selection(key: KeyEvent, current: usize, len: usize, page: usize) -> usize {
+    let last = len.saturating_sub(1);
+    match key.code {
+        KeyCode::Up | KeyCode::Char('k') => current.saturating_sub(1),
+        KeyCode::Down | KeyCode::Char('j') => (current + 1).min(last),
+        KeyCode::PageUp => current.saturating_sub(page),
+        KeyCode::PageDown => (current + page).min(last),
+        KeyCode::Home | KeyCode::Char('g') => 0,
+        KeyCode::End | KeyCode::Char('G') => last,
+        _ => current,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_run_view_collapsed_by_default() {
+        let mut view = RunView::new(vec![
+            Diagnostic::new("error", "").at("src/lib.rs", 3, 1).with_rendered("error: first\n --> src/lib.rs:3:1\n"),
+            Diagnostic::new("error", "").at("src/lib.rs", 3, 1).with_rendered("error: second\n --> src/lib.rs:3:1\n"),
+        ], Search::default());
+        let (lines, _) = view.lines();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].text, "▸ error: first  (src/lib.rs:3)");
+
+        view.handle_key(KeyEvent::new(KeyCode::Enter, KeyModifiers::NONE), 10).unwrap();
+        let (lines, _) = view.lines();
+        assert_eq!(lines.len(), 3);
+
+        view.handle_key(KeyEvent::new(KeyCode::Char('e'), KeyModifiers::NONE), 10).unwrap();
+        let (lines, selected_lines) = view.lines();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(selected_lines, 0..2);
+
+        view.handle_key(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE), 10).unwrap();
+        assert_eq!(view.lines().0.len(), 2);
+    }
+
+    #[test]
+    fn test_ctrl_c_quits_rather_than_collapsing() {
+        assert!(is_interrupt(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)));
+        assert!(!is_interrupt(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::NONE)));
+        assert!(!is_interrupt(KeyEvent::new(KeyCode::Char('q'), KeyModifiers::CONTROL)));
+    }
+
+    #[test]
+    fn test_run_view_scrolls_through_expanded_diagnostic() {
+        let key = |code| KeyEvent::new(code, KeyModifiers::NONE);
+        let mut view = RunView::new(vec![
+            Diagnostic::new("error", "").with_rendered("error: first\n1\n2\n3\n4\n5\n"),
+            Diagnostic::new("error", "").with_rendered("error: second\n"),
+        ], Search::default());
+        view.handle_key(key(KeyCode::Enter), 3).unwrap();
+
+        // Down shows the rest of the body a line at a time, keeping the selection
+        view.handle_key(key(KeyCode::Down), 3).unwrap();
+        assert_eq!((view.selected, view.offset), (0, 1));
+        view.handle_key(key(KeyCode::PageDown), 3).unwrap();
+        assert_eq!((view.selected, view.offset), (0, 3));
+        // Then goes on to the next diagnostic
+        view.handle_key(key(KeyCode::Down), 3).unwrap();
+        assert_eq!((view.selected, view.offset), (1, 4));
+
+        // Up goes back to the first diagnostic, then up through it to its header
+        view.handle_key(key(KeyCode::Up), 3).unwrap();
+        assert_eq!((view.selected, view.offset), (0, 4));
+        view.handle_key(key(KeyCode::PageUp), 3).unwrap();
+        assert_eq!((view.selected, view.offset), (0, 1));
+        view.handle_key(key(KeyCode::Up), 3).unwrap();
+        assert_eq!((view.selected, view.offset), (0, 0));
+    }
+
+    /// The keys of typing `text`, with `\x08` for Backspace and `\n` for Enter.
+    fn typed(text: &str) -> Vec<KeyEvent> {
+        text.chars()
+            .map(|c| match c {
+                '\x08' => KeyCode::Backspace,
+                '\n' => KeyCode::Enter,
+                c => KeyCode::Char(c),
+            })
+            .map(|code| KeyEvent::new(code, KeyModifiers::NONE))
+            .collect()
+    }
+
+    #[test]
+    fn test_run_view_search() {
+        let mut view = RunView::new(vec![
+            Diagnostic::new("error", "").with_code("E0382").with_rendered("error[E0382]: borrow of moved value\n"),
+            Diagnostic::new("error", "").with_rendered("error: cannot find value `x`\n"),
+            Diagnostic::new("error", "").at("src/borrow.rs", 3, 1).with_rendered("error: mismatched types\n"),
+        ], Search::default());
+
+        // Nothing is filtered while typing, and the view's keys are typed
+        for key in typed("/bre\x08w") {
+            view.handle_key(key, 10).unwrap();
+        }
+        assert_eq!(view.search.footer("keys"), "/brw▏  Enter search  Esc cancel");
+        assert_eq!(view.lines().0.len(), 3);
+        view.handle_key(typed("\n")[0], 10).unwrap();
+        let (lines, _) = view.lines();
+        assert_eq!(lines.len(), 1);
+        assert_eq!(lines[0].text, "▸ error: mismatched types  (src/borrow.rs:3)");
+
+        // Esc leaves the search applied before
+        view.handle_key(typed("/")[0], 10).unwrap();
+        view.handle_key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE), 10).unwrap();
+        assert_eq!(view.search.describe(), " matching \"brw\"");
+        assert_eq!(view.search.footer("keys"), "keys");
+    }
+
+    #[test]
+    fn test_search_matches_codes_and_clears() {
+        let mut search = Search::default();
+        assert_eq!(search.handle_key(KeyEvent::new(KeyCode::Down, KeyModifiers::NONE)).unwrap(), SearchKey::Ignored);
+        let keys: Vec<SearchKey> = typed("/03\n").into_iter().map(|key| search.handle_key(key).unwrap()).collect();
+        assert_eq!(keys.last(), Some(&SearchKey::Applied));
+        let cannot_find = Diagnostic::new("error", "cannot find value `x`");
+        assert!(search.matches(&Diagnostic::new("error", "borrow of moved value").with_code("E0382")));
+        assert!(!search.matches(&cannot_find));
+
+        // An empty query shows everything again
+        for key in typed("/\x08\x08\n") {
+            search.handle_key(key).unwrap();
+        }
+        assert!(search.matcher.is_none());
+        assert!(search.matches(&cannot_find));
+    }
+
+    #[test]
+    fn test_move_selection_clamps() {
+        let down = KeyEvent::new(KeyCode::Down, KeyModifiers::NONE);
+        let up = KeyEvent::new(KeyCode::Up, KeyModifiers::NONE);
+        assert_eq!(move_selection(down, 4, 5, 10), 4);
+        assert_eq!(move_selection(up, 0, 5, 10), 0);
+        assert_eq!(move_selection(down, 1, 5, 10), 2);
+    }
+}
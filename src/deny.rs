@@ -0,0 +1,153 @@
+use crate::diagnostics::Diagnostic;
+use crate::steps::Problem;
+use crate::Config;
+use serde_json::Value;
+use std::process::Command;
+
+const INSTALL: &str = "install it with `cargo install cargo-deny --locked`";
+
+/// The checks `--deny-check` can run, as cargo-deny names them
+pub const CHECKS: [&str; 5] = ["advisories", "bans", "licenses", "sources", "all"];
+
+/// The value of `--deny-check`: a comma-separated list of `CHECKS`.
+pub fn parse_checks(text: &str) -> std::result::Result<String, String> {
+    match text.split(',').find(|check| !CHECKS.contains(check)) {
+        Some(check) => Err(format!("unknown check `{}`, expected some of {}", check, CHECKS.join(", "))),
+        None => Ok(text.to_string()),
+    }
+}
+
+/// What `cargo deny check` found.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// Its errors and warnings; notes and help go with them
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl Report {
+    pub fn describe(&self) -> String {
+        let errors = self.diagnostics.iter().filter(|d| d.level == "error").count();
+        let warnings = self.diagnostics.len() - errors;
+        format!(
+            "{} {}, {} {}",
+            errors, if errors == 1 { "error" } else { "errors" },
+            warnings, if warnings == 1 { "warning" } else { "warnings" },
+        )
+    }
+}
+
+/// Run `cargo deny check` with the comma-separated `checks`.
+pub fn run(config: &Config, checks: &str) -> Result<Report, Problem> {
+    let mut cmd = Command::new(&config.cargo);
+    cmd.args(["deny", "--format", "json", "--color", "never"]);
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    // Of the build's options, only the manifest matters, and it comes before `check`
+    for (i, arg) in config.cargo_args.iter().enumerate() {
+        if arg == "--manifest-path" {
+            cmd.args(["--manifest-path", config.cargo_args.get(i + 1).map_or("", String::as_str)]);
+        } else if arg.starts_with("--manifest-path=") {
+            cmd.arg(arg);
+        }
+    }
+    cmd.arg("check").args(checks.split(','));
+    let output = cmd.output().map_err(|e| Problem { message: format!("failed to run cargo deny: {}", e), output: None, help: None })?;
+    // Everything it reports, JSON or not, goes to stderr
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let (report, errors) = parse(&stderr);
+    if output.status.success() || !report.diagnostics.is_empty() {
+        return Ok(report);
+    }
+    if stderr.contains("no such command") {
+        return Err(Problem::new("cargo-deny isn't installed".to_string(), INSTALL.to_string()));
+    }
+    let printed = if errors.is_empty() { stderr.trim_end().to_string() } else { errors.join("\n") };
+    Err(Problem {
+        message: format!("cargo deny check {} failed", checks.replace(',', " ")),
+        output: Some(printed).filter(|printed| !printed.is_empty()),
+        help: None,
+    })
+}
+
+/// The errors and warnings among cargo-deny's JSON lines, and the messages it
+/// logged at the error level, which say why it couldn't check.
+fn parse(stderr: &str) -> (Report, Vec<String>) {
+    let mut report = Report::default();
+    let mut errors = Vec::new();
+    for json in stderr.lines().filter_map(|line| serde_json::from_str::<Value>(line).ok()) {
+        let Some(fields) = json.get("fields") else { continue };
+        match json.get("type").and_then(Value::as_str) {
+            Some("diagnostic") => report.diagnostics.extend(diagnostic(fields)),
+            Some("log") if fields.get("level").and_then(Value::as_str).is_some_and(|level| level.eq_ignore_ascii_case("error")) => {
+                errors.extend(fields.get("message").and_then(Value::as_str).map(String::from));
+            }
+            _ => {}
+        }
+    }
+    (report, errors)
+}
+
+/// A finding as an entry like the compiler's, its labels and notes below it.
+fn diagnostic(fields: &Value) -> Option<Diagnostic> {
+    let level = match fields.get("severity")?.as_str()? {
+        level @ ("error" | "warning") => level,
+        _ => return None,
+    };
+    let message = fields.get("message")?.as_str()?.to_string();
+    let code = fields.get("code").and_then(Value::as_str).map(String::from);
+    let mut rendered = match &code {
+        Some(code) => format!("{}[{}]: {}\n", level, code, message),
+        None => format!("{}: {}\n", level, message),
+    };
+    for label in fields.get("labels").and_then(Value::as_array).into_iter().flatten() {
+        let span = label.get("span").and_then(Value::as_str).unwrap_or_default();
+        match label.get("message").and_then(Value::as_str).filter(|message| !message.is_empty()) {
+            Some(message) => rendered.push_str(&format!("  = `{}`: {}\n", span, message)),
+            None => rendered.push_str(&format!("  = `{}`\n", span)),
+        }
+    }
+    for note in fields.get("notes").and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+        rendered.push_str(&format!("  = note: {}\n", note));
+    }
+    Some(Diagnostic {
+        code,
+        rendered,
+        ..Diagnostic::new(level, message)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_checks() {
+        assert_eq!(parse_checks("licenses,bans,advisories").as_deref(), Ok("licenses,bans,advisories"));
+        assert!(parse_checks("licenses,crates").unwrap_err().starts_with("unknown check `crates`"));
+    }
+
+    #[test]
+    fn test_parse() {
+        let stderr = [
+            r#"{"type":"log","fields":{"timestamp":"2024-05-01T10:00:00Z","level":"WARN","message":"license exception 'ring' was not used"}}"#,
+            r#"{"type":"diagnostic","fields":{"severity":"error","code":"rejected","message":"failed to satisfy license requirements","labels":[{"line":3,"column":12,"message":"license expression retrieved via Cargo.toml `license`","span":"GPL-3.0"}],"notes":["GPL-3.0 - GNU General Public License v3.0 only:","  - No allow clause"],"graphs":[{"Krate":{"name":"copyleft","version":"1.0.0"}}]}}"#,
+            r#"{"type":"diagnostic","fields":{"severity":"warning","code":"duplicate","message":"found 2 duplicate entries for crate 'syn'","labels":[],"notes":[]}}"#,
+            r#"{"type":"diagnostic","fields":{"severity":"help","code":"license-exception-not-encountered","message":"license exception was not encountered","labels":[],"notes":[]}}"#,
+            r#"{"type":"summary","fields":{"advisories":{"errors":0,"warnings":0},"bans":{"errors":0,"warnings":1},"licenses":{"errors":1,"warnings":0}}}"#,
+            "2024-05-01 10:00:00 [ERROR] this isn't JSON",
+        ].join("\n");
+        let (report, errors) = parse(&stderr);
+        assert!(errors.is_empty());
+        assert_eq!(report.describe(), "1 error, 1 warning");
+        assert_eq!(
+            report.diagnostics[0].rendered,
+            "error[rejected]: failed to satisfy license requirements\n  = `GPL-3.0`: license expression retrieved via Cargo.toml `license`\n  = note: GPL-3.0 - GNU General Public License v3.0 only:\n  = note:   - No allow clause\n",
+        );
+        assert_eq!(report.diagnostics[1].code.as_deref(), Some("duplicate"));
+
+        let (report, errors) = parse(r#"{"type":"log","fields":{"level":"ERROR","message":"failed to load advisory database: failed to fetch"}}"#);
+        assert!(report.diagnostics.is_empty());
+        assert_eq!(errors, ["failed to load advisory database: failed to fetch"]);
+    }
+}
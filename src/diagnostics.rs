@@ -1,13 +1,157 @@
 use crate::{Config, ColorChoice};
 use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use regex::Regex;
+use std::time::Duration;
+
+/// A single compiler diagnostic, reduced to the fields we display, log and search.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Diagnostic {
+    pub level: String,
+    pub code: Option<String>,
+    pub message: String,
+    /// File of the primary span, relative to the workspace root as reported by cargo
+    pub file: Option<String>,
+    /// `file` as cargo reported it, kept when `--paths`, `--path-map`, `--cross` or
+    /// `--container` rewrote it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cargo_file: Option<String>,
+    pub line: Option<u64>,
+    pub column: Option<u64>,
+    pub package_id: Option<String>,
+    /// `target.kind` of the compiled target, e.g. `["lib"]`, `["bin"]`, `["test"]`
+    #[serde(default)]
+    pub target_kind: Vec<String>,
+    pub rendered: String,
+}
+
+impl Diagnostic {
+    /// A diagnostic with no location, package or rendering, to fill in with struct
+    /// update syntax, e.g. `Diagnostic { file: Some(file), ..Diagnostic::new("error", message) }`.
+    pub fn new(level: &str, message: impl Into<String>) -> Self {
+        Diagnostic { level: level.to_string(), message: message.into(), ..Default::default() }
+    }
+
+    /// Single-line form for sinks that cannot show rendered snippets,
+    /// e.g. ``error[E0425]: cannot find value `x` (src/main.rs:2:20)``.
+    pub fn summary(&self) -> String {
+        let mut summary = self.level.clone();
+        if let Some(code) = &self.code {
+            summary.push_str(&format!("[{}]", code));
+        }
+        summary.push_str(": ");
+        summary.push_str(&self.message);
+        if let Some(file) = &self.file {
+            summary.push_str(&format!(" ({}", file));
+            if let (Some(line), Some(column)) = (self.line, self.column) {
+                summary.push_str(&format!(":{}:{}", line, column));
+            }
+            summary.push(')');
+        }
+        summary
+    }
+
+    /// Stable identity of the diagnostic across runs: a hash of its level, code,
+    /// file and message, leaving out the line and column so edits elsewhere in
+    /// the file don't make it look new. The file is the one cargo reported, so
+    /// how paths are shown doesn't change it either.
+    pub fn fingerprint(&self) -> String {
+        crate::util::stable_hash(&[
+            self.level.as_str(),
+            self.code.as_deref().unwrap_or(""),
+            self.workspace_file().unwrap_or(""),
+            self.message.as_str(),
+        ])
+    }
+
+    /// File of the primary span as cargo reported it, before any rewriting.
+    pub fn workspace_file(&self) -> Option<&str> {
+        self.cargo_file.as_deref().or(self.file.as_deref())
+    }
+
+    /// Name of the package the diagnostic came from.
+    pub fn package_name(&self) -> Option<&str> {
+        self.package_id.as_deref().map(package_name)
+    }
+
+    /// Coarse target category used to split logs: lib, bin, test, bench, example or build-script.
+    pub fn target_category(&self) -> &'static str {
+        match self.target_kind.first().map(String::as_str) {
+            Some("bin") => "bin",
+            Some("test") => "test",
+            Some("bench") => "bench",
+            Some("example") => "example",
+            Some("custom-build") => "build-script",
+            _ => "lib",
+        }
+    }
+}
+
+/// Shorthands for tests, e.g.
+/// `Diagnostic::new("error", "mismatched types").with_code("E0308").at("src/lib.rs", 1, 1)`.
+#[cfg(test)]
+impl Diagnostic {
+    pub fn with_code(self, code: &str) -> Self {
+        Diagnostic { code: Some(code.to_string()), ..self }
+    }
+
+    pub fn at(self, file: &str, line: u64, column: u64) -> Self {
+        Diagnostic { file: Some(file.to_string()), line: Some(line), column: Some(column), ..self }
+    }
+
+    pub fn with_rendered(self, rendered: impl Into<String>) -> Self {
+        Diagnostic { rendered: rendered.into(), ..self }
+    }
+}
+
+/// Package name from either package id format:
+/// `app 0.1.0 (path+file:///ws/app)` or `path+file:///ws/app#0.1.0`.
+pub fn package_name(id: &str) -> &str {
+    let Some((source, fragment)) = id.split_once('#') else {
+        return id.split_whitespace().next().unwrap_or(id);
+    };
+    match fragment.split_once('@') {
+        Some((name, _)) => name,
+        None => source.trim_end_matches('/').rsplit('/').next().unwrap_or(source),
+    }
+}
+
+/// Package version from either package id format, if it has one.
+pub fn package_version(id: &str) -> Option<&str> {
+    match id.split_once('#') {
+        Some((_, fragment)) => Some(fragment.rsplit('@').next().unwrap_or(fragment)),
+        None => id.split_whitespace().nth(1),
+    }
+}
 
 #[derive(Debug)]
 pub enum CargoMessage {
-    CompilerMessage {
-        level: String,
-        rendered: String,
+    CompilerMessage(Diagnostic),
+    /// A unit finished compiling, or was `fresh` and didn't need to
+    CompilerArtifact {
+        package_id: String,
+        fresh: bool,
+        /// The files it wrote, e.g. the `.wasm` of a cdylib
+        filenames: Vec<String>,
+        /// The program, for a binary, example or test
+        executable: Option<String>,
+        /// Name and kinds of the target, e.g. `["bin"]` or `["cdylib", "rlib"]`
+        target_name: String,
+        target_kind: Vec<String>,
+        /// Whether it was built as a test harness
+        test: bool,
+    },
+    /// A build script ran, or its earlier output was reused
+    BuildScriptExecuted {
+        package_id: String,
+        /// Its `OUT_DIR`, next to the `output` file with what it printed
+        out_dir: String,
+    },
+    /// Exact compile time of a unit, reported with `-Zunstable-options --timings=json`
+    TimingInfo {
+        package_id: String,
+        duration: Duration,
     },
     BuildFinished {
         success: bool,
@@ -44,11 +188,116 @@ pub fn parse_cargo_message(line: &str) -> Result<Option<CargoMessage>> {
                 .unwrap_or("")
                 .to_string();
 
-            if !rendered.is_empty() {
-                Ok(Some(CargoMessage::CompilerMessage { level, rendered }))
-            } else {
-                Ok(None)
+            if rendered.is_empty() {
+                return Ok(None);
             }
+
+            let code = message.get("code")
+                .and_then(|c| c.get("code"))
+                .and_then(|c| c.as_str())
+                .map(str::to_string);
+
+            let text = message.get("message")
+                .and_then(|m| m.as_str())
+                .unwrap_or("")
+                .to_string();
+
+            let primary_span = message.get("spans")
+                .and_then(|s| s.as_array())
+                .and_then(|spans| {
+                    spans.iter().find(|span| {
+                        span.get("is_primary").and_then(|p| p.as_bool()).unwrap_or(false)
+                    })
+                });
+
+            let file = primary_span
+                .and_then(|s| s.get("file_name"))
+                .and_then(|f| f.as_str())
+                .map(str::to_string);
+            let line = primary_span
+                .and_then(|s| s.get("line_start"))
+                .and_then(|l| l.as_u64());
+            let column = primary_span
+                .and_then(|s| s.get("column_start"))
+                .and_then(|c| c.as_u64());
+
+            let package_id = json.get("package_id")
+                .and_then(|p| p.as_str())
+                .map(str::to_string);
+
+            let target_kind = json.get("target")
+                .and_then(|t| t.get("kind"))
+                .and_then(|k| k.as_array())
+                .map(|kinds| kinds.iter().filter_map(|k| k.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+
+            Ok(Some(CargoMessage::CompilerMessage(Diagnostic {
+                level,
+                code,
+                message: text,
+                file,
+                cargo_file: None,
+                line,
+                column,
+                package_id,
+                target_kind,
+                rendered,
+            })))
+        }
+        "compiler-artifact" => {
+            let Some(package_id) = json.get("package_id").and_then(|p| p.as_str()) else {
+                return Ok(None);
+            };
+            let fresh = json.get("fresh")
+                .and_then(|f| f.as_bool())
+                .unwrap_or(false);
+            let filenames = json.get("filenames")
+                .and_then(|f| f.as_array())
+                .map(|files| files.iter().filter_map(|f| f.as_str().map(String::from)).collect())
+                .unwrap_or_default();
+            let executable = json.get("executable").and_then(|e| e.as_str()).map(String::from);
+            let target = json.get("target");
+            let target_name = target.and_then(|t| t.get("name")).and_then(|n| n.as_str()).unwrap_or_default().to_string();
+            let target_kind = target
+                .and_then(|t| t.get("kind"))
+                .and_then(|k| k.as_array())
+                .map(|kinds| kinds.iter().filter_map(|k| k.as_str().map(str::to_string)).collect())
+                .unwrap_or_default();
+            let test = json.pointer("/profile/test").and_then(|t| t.as_bool()).unwrap_or(false);
+
+            Ok(Some(CargoMessage::CompilerArtifact {
+                package_id: package_id.to_string(),
+                fresh,
+                filenames,
+                executable,
+                target_name,
+                target_kind,
+                test,
+            }))
+        }
+        "build-script-executed" => {
+            let package_id = json.get("package_id").and_then(|p| p.as_str());
+            let out_dir = json.get("out_dir").and_then(|o| o.as_str());
+            let (Some(package_id), Some(out_dir)) = (package_id, out_dir) else {
+                return Ok(None);
+            };
+
+            Ok(Some(CargoMessage::BuildScriptExecuted {
+                package_id: package_id.to_string(),
+                out_dir: out_dir.to_string(),
+            }))
+        }
+        "timing-info" => {
+            let package_id = json.get("package_id").and_then(|p| p.as_str());
+            let duration = json.get("duration").and_then(|d| d.as_f64());
+            let (Some(package_id), Some(duration)) = (package_id, duration) else {
+                return Ok(None);
+            };
+
+            Ok(Some(CargoMessage::TimingInfo {
+                package_id: package_id.to_string(),
+                duration: Duration::try_from_secs_f64(duration).unwrap_or_default(),
+            }))
         }
         "build-finished" => {
             let success = json.get("success")
@@ -61,8 +310,8 @@ pub fn parse_cargo_message(line: &str) -> Result<Option<CargoMessage>> {
     }
 }
 
-pub fn format_for_terminal(rendered: &str, config: &Config) -> String {
-    match config.terminal_color {
+pub fn format_for_terminal(rendered: &str, color: &ColorChoice) -> String {
+    match color {
         ColorChoice::Never => strip_ansi_codes(rendered),
         ColorChoice::Always => rendered.to_string(),
         ColorChoice::Auto => {
@@ -86,7 +335,7 @@ pub fn format_for_log(rendered: &str, config: &Config) -> String {
     }
 }
 
-fn strip_ansi_codes(text: &str) -> String {
+pub fn strip_ansi_codes(text: &str) -> String {
     lazy_static::lazy_static! {
         static ref ANSI_REGEX: Regex = Regex::new(r"\x1b\[[0-9;]*[mGKH]").unwrap();
     }
@@ -103,14 +352,78 @@ mod tests {
 
         let result = parse_cargo_message(json_line).unwrap();
         match result {
-            Some(CargoMessage::CompilerMessage { level, rendered }) => {
-                assert_eq!(level, "error");
-                assert!(rendered.contains("cannot find value `undefined_var`"));
+            Some(CargoMessage::CompilerMessage(diagnostic)) => {
+                assert_eq!(diagnostic.level, "error");
+                assert!(diagnostic.rendered.contains("cannot find value `undefined_var`"));
+                assert_eq!(diagnostic.code.as_deref(), Some("E0425"));
+                assert_eq!(diagnostic.file.as_deref(), Some("src/main.rs"));
+                assert_eq!(diagnostic.line, Some(2));
+                assert_eq!(diagnostic.column, Some(20));
+                assert_eq!(diagnostic.target_kind, vec!["bin"]);
+                assert_eq!(diagnostic.target_category(), "bin");
+                assert_eq!(diagnostic.package_name(), Some("test"));
+                assert_eq!(
+                    diagnostic.summary(),
+                    "error[E0425]: cannot find value `undefined_var` in this scope (src/main.rs:2:20)"
+                );
             }
             _ => panic!("Expected CompilerMessage"),
         }
     }
 
+    #[test]
+    fn test_fingerprint_ignores_position() {
+        let diagnostic = Diagnostic::new("warning", "unused variable: `x`").at("src/lib.rs", 3, 9);
+        let moved = Diagnostic { line: Some(30), rendered: "moved".to_string(), ..diagnostic.clone() };
+        let other = Diagnostic { message: "unused variable: `y`".to_string(), ..diagnostic.clone() };
+
+        assert_eq!(diagnostic.fingerprint().len(), 16);
+        assert_eq!(diagnostic.fingerprint(), moved.fingerprint());
+        assert_ne!(diagnostic.fingerprint(), other.fingerprint());
+    }
+
+    #[test]
+    fn test_package_name_from_opaque_ids() {
+        let with_id = |id: &str| Diagnostic {
+            package_id: Some(id.to_string()),
+            ..Diagnostic::new("warning", "")
+        };
+        assert_eq!(with_id("path+file:///ws/crates/core#0.1.0").package_name(), Some("core"));
+        assert_eq!(with_id("path+file:///ws/crates/core#core-impl@0.1.0").package_name(), Some("core-impl"));
+        assert_eq!(with_id("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.200").package_name(), Some("serde"));
+
+        assert_eq!(package_version("path+file:///ws/crates/core#0.1.0"), Some("0.1.0"));
+        assert_eq!(package_version("registry+https://github.com/rust-lang/crates.io-index#serde@1.0.200"), Some("1.0.200"));
+        assert_eq!(package_version("test 0.1.0 (path+file:///tmp/test)"), Some("0.1.0"));
+    }
+
+    #[test]
+    fn test_parse_artifact_and_timing_info() {
+        let artifact = r#"{"reason":"compiler-artifact","package_id":"path+file:///tmp/test#0.1.0","target":{"kind":["bin"],"name":"test"},"filenames":["/tmp/test/target/debug/test"],"executable":"/tmp/test/target/debug/test","fresh":true}"#;
+        match parse_cargo_message(artifact).unwrap() {
+            Some(CargoMessage::CompilerArtifact { package_id, fresh, filenames, executable, target_name, target_kind, test }) => {
+                assert_eq!(package_id, "path+file:///tmp/test#0.1.0");
+                assert!(fresh);
+                assert_eq!(filenames, ["/tmp/test/target/debug/test"]);
+                assert_eq!(executable.as_deref(), Some("/tmp/test/target/debug/test"));
+                assert_eq!((target_name.as_str(), &target_kind[..], test), ("test", &["bin".to_string()][..], false));
+            }
+            _ => panic!("Expected CompilerArtifact"),
+        }
+
+        let timing = r#"{"reason":"timing-info","package_id":"path+file:///tmp/test#0.1.0","target":{"kind":["bin"],"name":"test"},"mode":"build","duration":1.25,"rmeta_time":0.5}"#;
+        match parse_cargo_message(timing).unwrap() {
+            Some(CargoMessage::TimingInfo { duration, .. }) => assert_eq!(duration, Duration::from_millis(1250)),
+            _ => panic!("Expected TimingInfo"),
+        }
+
+        let executed = r#"{"reason":"build-script-executed","package_id":"path+file:///tmp/test#0.1.0","linked_libs":[],"linked_paths":[],"cfgs":[],"env":[],"out_dir":"/tmp/test/target/debug/build/test-0f0f0f0f0f0f0f0f/out"}"#;
+        match parse_cargo_message(executed).unwrap() {
+            Some(CargoMessage::BuildScriptExecuted { out_dir, .. }) => assert_eq!(out_dir, "/tmp/test/target/debug/build/test-0f0f0f0f0f0f0f0f/out"),
+            _ => panic!("Expected BuildScriptExecuted"),
+        }
+    }
+
     #[test]
     fn test_parse_build_finished() {
         let json_line = r#"{"reason":"build-finished","success":false}"#;
@@ -118,7 +431,7 @@ mod tests {
         let result = parse_cargo_message(json_line).unwrap();
         match result {
             Some(CargoMessage::BuildFinished { success }) => {
-                assert_eq!(success, false);
+                assert!(!success);
             }
             _ => panic!("Expected BuildFinished"),
         }
@@ -0,0 +1,177 @@
+use crate::{diagnostics, history, store, util, ColorChoice};
+use crate::diagnostics::Diagnostic;
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, Command};
+use std::collections::HashSet;
+
+const RED: &str = "\x1b[1m\x1b[91m";
+const GREEN: &str = "\x1b[1m\x1b[92m";
+const RESET: &str = "\x1b[0m";
+
+/// Diagnostics of two runs matched up by fingerprint, each listed once.
+pub struct DiagnosticDiff<'a> {
+    pub new: Vec<&'a Diagnostic>,
+    pub fixed: Vec<&'a Diagnostic>,
+    pub unchanged: Vec<&'a Diagnostic>,
+}
+
+/// First occurrence of each fingerprint, in order.
+fn distinct(diagnostics: &[Diagnostic]) -> Vec<(String, &Diagnostic)> {
+    let mut seen = HashSet::new();
+    diagnostics.iter()
+        .map(|d| (d.fingerprint(), d))
+        .filter(|(fingerprint, _)| seen.insert(fingerprint.clone()))
+        .collect()
+}
+
+pub fn compare<'a>(before: &'a [Diagnostic], after: &'a [Diagnostic]) -> DiagnosticDiff<'a> {
+    let before = distinct(before);
+    let after = distinct(after);
+    let before_set: HashSet<&str> = before.iter().map(|(f, _)| f.as_str()).collect();
+    let after_set: HashSet<&str> = after.iter().map(|(f, _)| f.as_str()).collect();
+
+    DiagnosticDiff {
+        new: after.iter().filter(|(f, _)| !before_set.contains(f.as_str())).map(|(_, d)| *d).collect(),
+        fixed: before.iter().filter(|(f, _)| !after_set.contains(f.as_str())).map(|(_, d)| *d).collect(),
+        unchanged: after.iter().filter(|(f, _)| before_set.contains(f.as_str())).map(|(_, d)| *d).collect(),
+    }
+}
+
+impl DiagnosticDiff<'_> {
+    /// `+` lines for new diagnostics and `-` lines for fixed ones, then unchanged ones if asked.
+    pub fn print(&self, color: &ColorChoice, show_unchanged: bool) {
+        let mut lines = Vec::new();
+        for d in &self.new {
+            lines.push(format!("{}+ {}{}", RED, d.summary(), RESET));
+        }
+        for d in &self.fixed {
+            lines.push(format!("{}- {}{}", GREEN, d.summary(), RESET));
+        }
+        if show_unchanged {
+            for d in &self.unchanged {
+                lines.push(format!("  {}", d.summary()));
+            }
+        }
+        for line in lines {
+            eprintln!("{}", diagnostics::format_for_terminal(&line, color));
+        }
+    }
+
+    pub fn counts(&self) -> String {
+        format!("{} new, {} fixed, {} unchanged", self.new.len(), self.fixed.len(), self.unchanged.len())
+    }
+}
+
+/// `--diff-last`: compare a just-recorded run with the one before it.
+pub fn print_against_previous(workspace: &util::Workspace, id: u64, diagnostics: &[Diagnostic], color: &ColorChoice) -> Result<()> {
+    let history = store::load_history(workspace)?;
+    let Some(previous) = history.iter().rev().find(|run| run.id < id) else {
+        eprintln!("cargo-builder: No previous build to compare with");
+        return Ok(());
+    };
+
+    let previous_diagnostics = store::load_run_diagnostics(workspace, previous.id)?;
+    let diff = compare(&previous_diagnostics, diagnostics);
+    diff.print(color, false);
+    eprintln!("cargo-builder: Since #{}: {}", previous.id, diff.counts());
+    Ok(())
+}
+
+pub fn run(args: &[String]) -> Result<i32> {
+    let matches = Command::new("cargo-builder diff")
+        .about("Compare the diagnostics of two recorded builds")
+        .arg(
+            Arg::new("before")
+                .value_name("RUN_A")
+                .help("Earlier run (default: the run before RUN_B)")
+        )
+        .arg(
+            Arg::new("after")
+                .value_name("RUN_B")
+                .default_value("last")
+                .help("Later run, or `last`")
+        )
+        .arg(
+            Arg::new("level")
+                .long("level")
+                .value_name("LEVEL")
+                .help("Only compare diagnostics of this level (error, warning, ...)")
+        )
+        .arg(
+            Arg::new("unchanged")
+                .long("unchanged")
+                .action(ArgAction::SetTrue)
+                .help("Also list the diagnostics present in both runs")
+        )
+        .arg(
+            Arg::new("terminal-color")
+                .long("terminal-color")
+                .value_name("CHOICE")
+                .value_parser(["auto", "never", "always"])
+                .default_value("auto")
+                .help("Color control for terminal output")
+        )
+        .try_get_matches_from(std::iter::once("cargo-builder diff".to_string()).chain(args.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+
+    let color: ColorChoice = matches.get_one::<String>("terminal-color").unwrap().parse()?;
+    let level = matches.get_one::<String>("level");
+
+    let workspace = util::find_workspace()?;
+    let history = store::load_history(&workspace)?;
+    if history.is_empty() {
+        eprintln!("cargo-builder: No builds recorded yet; run a build first");
+        return Ok(1);
+    }
+    let after = history::find_run(&history, matches.get_one::<String>("after").unwrap())?;
+    let before = match matches.get_one::<String>("before") {
+        Some(id) => history::find_run(&history, id)?,
+        None => match history.iter().rev().find(|run| run.id < after.id) {
+            Some(run) => run,
+            None => bail!("No run before #{} to compare with", after.id),
+        },
+    };
+
+    let load = |id| -> Result<Vec<Diagnostic>> {
+        Ok(store::load_run_diagnostics(&workspace, id)?
+            .into_iter()
+            .filter(|d| level.is_none_or(|level| &d.level == level))
+            .collect())
+    };
+    let (before_diagnostics, after_diagnostics) = (load(before.id)?, load(after.id)?);
+    let diff = compare(&before_diagnostics, &after_diagnostics);
+
+    eprintln!("{}", before.format_row());
+    eprintln!("{}", after.format_row());
+    diff.print(&color, matches.get_flag("unchanged"));
+    eprintln!("cargo-builder: #{} → #{}: {}", before.id, after.id, diff.counts());
+
+    // Fail when the later run introduced something, e.g. to check a cleanup branch
+    Ok(if diff.new.is_empty() { 0 } else { 1 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare() {
+        let before = [
+            Diagnostic::new("warning", "unused variable: `a`").at("src/lib.rs", 1, 1),
+            Diagnostic::new("warning", "unused variable: `b`").at("src/lib.rs", 2, 1),
+        ];
+        // `b` moved down a line and is reported twice; `a` is fixed and `c` is new
+        let after = [
+            Diagnostic::new("warning", "unused variable: `b`").at("src/lib.rs", 3, 1),
+            Diagnostic::new("warning", "unused variable: `c`").at("src/lib.rs", 4, 1),
+            Diagnostic::new("warning", "unused variable: `b`").at("src/lib.rs", 3, 1),
+        ];
+        let diff = compare(&before, &after);
+
+        assert_eq!(diff.new.iter().map(|d| d.message.as_str()).collect::<Vec<_>>(), ["unused variable: `c`"]);
+        assert_eq!(diff.fixed.iter().map(|d| d.message.as_str()).collect::<Vec<_>>(), ["unused variable: `a`"]);
+        assert_eq!(diff.unchanged.len(), 1);
+        assert_eq!(diff.unchanged[0].line, Some(3));
+        assert_eq!(diff.counts(), "1 new, 1 fixed, 1 unchanged");
+    }
+}
@@ -0,0 +1,158 @@
+use crate::diagnostics::{strip_ansi_codes, Diagnostic};
+use crate::nextest::{self, Tally};
+use crate::Config;
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::process::Command;
+
+/// Options of `cargo build` that pick what `cargo test --doc` tests, with a value and
+/// without. Test filters and nextest's own options aren't among them; nextest's
+/// `--profile` is its own, and cargo's is `--cargo-profile`.
+const PASSED_WITH_VALUES: [&str; 8] = ["-p", "--package", "--exclude", "--features", "-F", "--target", "--manifest-path", "--target-dir"];
+const PASSED: [&str; 9] = ["--workspace", "--all", "--all-features", "--no-default-features", "--release", "-r", "--locked", "--frozen", "--offline"];
+
+/// Lines of rustc's output that say nothing the errors above them don't
+const NOISE: [&str; 3] = ["error: aborting due to", "For more information about", "Couldn't compile the test."];
+
+lazy_static::lazy_static! {
+    /// `---- tests::it_works stdout ----`
+    static ref HEADER: Regex = Regex::new(r"^---- (.+) stdout ----$").unwrap();
+    /// The name of a doctest: `src/lib.rs - add (line 3)`
+    static ref DOCTEST: Regex = Regex::new(r"^(.+?) - (.+) \(line (\d+)\)$").unwrap();
+    static ref RESULT: Regex = Regex::new(r"^test result: \w+\. (\d+) passed; (\d+) failed; (\d+) ignored").unwrap();
+}
+
+/// What libtest reported, for `cargo test --doc` or the tests of `--coverage`.
+#[derive(Debug, Default)]
+pub struct Results {
+    pub tally: Tally,
+    /// An error entry for each failed doctest
+    pub failures: Vec<Diagnostic>,
+}
+
+/// Run the doctests, which nextest leaves out, with the packages and features of the build.
+pub fn run(config: &Config) -> Result<Results> {
+    let mut cmd = Command::new(&config.cargo);
+    cmd.args(["test", "--doc"]).args(args(&config.cargo_args)).env("CARGO_TERM_COLOR", "never");
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    let output = cmd.output().with_context(|| format!("Failed to run {} test --doc", config.cargo))?;
+    Ok(parse(&String::from_utf8_lossy(&output.stdout)))
+}
+
+fn args(cargo_args: &[String]) -> Vec<String> {
+    let mut passed = Vec::new();
+    let mut args = cargo_args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--cargo-profile" {
+            passed.push("--profile".to_string());
+            passed.extend(args.next().cloned());
+        } else if let Some(profile) = arg.strip_prefix("--cargo-profile=") {
+            passed.extend(["--profile".to_string(), profile.to_string()]);
+        } else if PASSED.contains(&arg.as_str()) {
+            passed.push(arg.clone());
+        } else if PASSED_WITH_VALUES.contains(&arg.as_str()) {
+            passed.push(arg.clone());
+            passed.extend(args.next().cloned());
+        } else if PASSED_WITH_VALUES.iter().any(|flag| arg.strip_prefix(flag).is_some_and(|rest| rest.starts_with('='))) {
+            passed.push(arg.clone());
+        }
+    }
+    passed
+}
+
+/// The results and failures in libtest's output, as `cargo test --doc` and the test
+/// binaries of `cargo test` print it. A failure's output runs from its
+/// `---- ... stdout ----` header to the next one, or to the list of failures that
+/// ends each binary's.
+pub fn parse(stdout: &str) -> Results {
+    let stdout = strip_ansi_codes(stdout);
+    let mut doctests = Results::default();
+    let mut failure: Option<(&str, Vec<&str>)> = None;
+    for line in stdout.lines() {
+        let header = HEADER.captures(line);
+        if header.is_some() || line == "failures:" {
+            doctests.failures.extend(failure.take().map(|(header, output)| diagnostic(header, &output)));
+        }
+        if let Some(header) = header {
+            failure = Some((header.get(1).map_or("", |name| name.as_str()), Vec::new()));
+        } else if let Some((_, output)) = &mut failure {
+            output.push(line);
+        } else if let Some(result) = RESULT.captures(line) {
+            doctests.tally.passed += result[1].parse::<usize>().unwrap_or(0);
+            doctests.tally.failed += result[2].parse::<usize>().unwrap_or(0);
+            doctests.tally.ignored += result[3].parse::<usize>().unwrap_or(0);
+        }
+    }
+    doctests.failures.extend(failure.map(|(header, output)| diagnostic(header, &output)));
+    doctests
+}
+
+/// A failed test as an error entry, a doctest's at the start of its code block.
+fn diagnostic(name: &str, output: &[&str]) -> Diagnostic {
+    let Some(doctest) = DOCTEST.captures(name) else {
+        return nextest::diagnostic(name, None, output.join("\n").trim());
+    };
+    let (file, item, line) = (&doctest[1], &doctest[2], &doctest[3]);
+    let compile_error = output.iter().any(|line| line.starts_with("Couldn't compile the test."));
+    let message = format!("doctest of `{}` {}", item, if compile_error { "failed to compile" } else { "failed" });
+    let mut rendered = format!("error: {}\n --> {}:{}:1\n", message, file, line);
+    let kept: Vec<&str> = output.iter().copied().filter(|line| !NOISE.iter().any(|noise| line.starts_with(noise))).collect();
+    for line in kept.iter().skip_while(|line| line.is_empty()) {
+        if line.is_empty() {
+            rendered.push('\n');
+        } else {
+            rendered.push_str(&format!("  {}\n", line));
+        }
+    }
+    // Blank lines before the next header or the list of failures
+    let rendered = format!("{}\n", rendered.trim_end());
+    Diagnostic {
+        file: Some(file.to_string()),
+        line: line.parse().ok(),
+        column: Some(1),
+        target_kind: vec!["doctest".to_string()],
+        rendered,
+        ..Diagnostic::new("error", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args_of(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_args() {
+        let cargo_args = args_of(&["-p", "app", "--features=cli", "--release", "--profile", "ci", "--cargo-profile=fast", "-E", "test(foo)", "my_filter", "--", "--nocapture"]);
+        assert_eq!(args(&cargo_args), args_of(&["-p", "app", "--features=cli", "--release", "--profile", "fast"]));
+    }
+
+    #[test]
+    fn test_parse() {
+        let stdout = "\nrunning 3 tests\ntest src/lib.rs - add (line 3) ... FAILED\ntest src/lib.rs - Parser::parse (line 20) ... FAILED\ntest src/lib.rs - ok (line 12) ... ok\n\nfailures:\n\n---- src/lib.rs - add (line 3) stdout ----\nerror[E0425]: cannot find value `x` in this scope\n --> src/lib.rs:5:1\n  |\n3 | x\n  | ^ not found in this scope\n\nerror: aborting due to 1 previous error\n\nFor more information about this error, try `rustc --explain E0425`.\nCouldn't compile the test.\n---- src/lib.rs - Parser::parse (line 20) stdout ----\nTest executable failed (exit status: 101).\n\nstderr:\nthread 'main' panicked at src/lib.rs:6:1:\nassertion failed: false\n\n\nfailures:\n    src/lib.rs - add (line 3)\n    src/lib.rs - Parser::parse (line 20)\n\ntest result: FAILED. 1 passed; 2 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.21s\n\n";
+        let doctests = parse(stdout);
+        assert_eq!((doctests.tally.passed, doctests.tally.failed), (1, 2));
+        let [compile, run] = &doctests.failures[..] else { panic!() };
+        assert_eq!(
+            compile.rendered,
+            "error: doctest of `add` failed to compile\n --> src/lib.rs:3:1\n  error[E0425]: cannot find value `x` in this scope\n   --> src/lib.rs:5:1\n    |\n  3 | x\n    | ^ not found in this scope\n",
+        );
+        assert_eq!((compile.file.as_deref(), compile.line), (Some("src/lib.rs"), Some(3)));
+        assert_eq!(run.message, "doctest of `Parser::parse` failed");
+        assert!(run.rendered.ends_with("  thread 'main' panicked at src/lib.rs:6:1:\n  assertion failed: false\n"));
+    }
+
+    #[test]
+    fn test_parse_tests() {
+        let stdout = "\nrunning 2 tests\ntest tests::works ... ok\ntest tests::fails ... FAILED\n\nfailures:\n\n---- tests::fails stdout ----\n\nthread 'tests::fails' panicked at src/lib.rs:10:9:\nassertion failed: false\n\n\nfailures:\n    tests::fails\n\ntest result: FAILED. 1 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out; finished in 0.00s\n";
+        let results = parse(stdout);
+        let [failure] = &results.failures[..] else { panic!() };
+        assert_eq!(failure.message, "test tests::fails failed");
+        assert_eq!((failure.file.as_deref(), failure.line), (Some("src/lib.rs"), Some(10)));
+    }
+}
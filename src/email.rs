@@ -0,0 +1,153 @@
+use crate::notify::{self, BuildOutcome};
+use crate::settings::{EmailSettings, SmtpTls};
+use anyhow::{Result, Context};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use lettre::message::header::ContentType;
+use lettre::message::{Attachment, MultiPart, SinglePart};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{Message, SmtpTransport, Transport};
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+/// Plain-text body: the summary, followed by the log unless it is attached.
+fn body(outcome: &BuildOutcome, settings: &EmailSettings) -> Result<String> {
+    let mut body = format!(
+        "{}\n\nCommand: {}\nStarted: {}\n",
+        outcome.headline(),
+        outcome.command,
+        humantime::format_rfc3339_seconds(outcome.started_at),
+    );
+    for path in &outcome.log_paths {
+        body.push_str(&format!("Log:     {}\n", path));
+    }
+
+    match outcome.log_paths.first() {
+        Some(_) if settings.attach => body.push_str("\nThe error log is attached.\n"),
+        Some(path) => {
+            let (log, truncated) = notify::read_log(Path::new(path))?;
+            body.push('\n');
+            body.push_str(&log);
+            if truncated {
+                body.push_str("\n[log truncated]\n");
+            }
+        }
+        None => {}
+    }
+    Ok(body)
+}
+
+/// The first log as an attachment, gzipped if requested and not already compressed.
+fn attachment(path: &Path, compress: bool) -> Result<SinglePart> {
+    let content = std::fs::read(path)
+        .with_context(|| format!("Failed to read log: {}", path.display()))?;
+    let name = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+
+    if crate::logging::is_gzip_path(path) {
+        return Ok(Attachment::new(name).body(content, ContentType::parse("application/gzip")?));
+    }
+    if compress {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&content)?;
+        return Ok(Attachment::new(format!("{}.gz", name)).body(encoder.finish()?, ContentType::parse("application/gzip")?));
+    }
+    Ok(Attachment::new(name).body(content, ContentType::TEXT_PLAIN))
+}
+
+fn message(outcome: &BuildOutcome, settings: &EmailSettings) -> Result<Message> {
+    let mut builder = Message::builder()
+        .from(settings.from.parse().with_context(|| format!("Invalid sender address: {}", settings.from))?)
+        .subject(format!("cargo-builder: {}", outcome.headline()));
+    for to in &settings.to {
+        builder = builder.to(to.parse().with_context(|| format!("Invalid recipient address: {}", to))?);
+    }
+
+    let text = SinglePart::plain(body(outcome, settings)?);
+    let message = match outcome.log_paths.first() {
+        Some(path) if settings.attach => builder.multipart(
+            MultiPart::mixed()
+                .singlepart(text)
+                .singlepart(attachment(Path::new(path), settings.compress)?),
+        )?,
+        _ => builder.singlepart(text)?,
+    };
+    Ok(message)
+}
+
+/// Mail the build result to the configured recipients over SMTP.
+pub fn send(settings: &EmailSettings, outcome: &BuildOutcome, timeout: Duration) -> Result<()> {
+    let message = message(outcome, settings)?;
+
+    let server = settings.server.as_str();
+    let mut transport = match settings.tls {
+        SmtpTls::None => SmtpTransport::builder_dangerous(server),
+        SmtpTls::Starttls => SmtpTransport::starttls_relay(server)?,
+        SmtpTls::Tls => SmtpTransport::relay(server)?,
+    }
+    .port(settings.port.unwrap_or(settings.tls.default_port()))
+    .timeout(Some(timeout));
+
+    if let Some(username) = &settings.username {
+        let password = match &settings.password_env {
+            Some(var) => std::env::var(var)
+                .with_context(|| format!("SMTP password variable {} is not set", var))?,
+            None => String::new(),
+        };
+        transport = transport.credentials(Credentials::new(username.clone(), password));
+    }
+
+    transport.build().send(&message)
+        .with_context(|| format!("Failed to send mail via {}", server))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::test_outcome;
+    use crate::settings::NotifyOn;
+    use tempfile::TempDir;
+
+    fn settings(attach: bool, compress: bool) -> EmailSettings {
+        EmailSettings {
+            server: "localhost".to_string(),
+            port: None,
+            tls: SmtpTls::None,
+            username: None,
+            password_env: None,
+            from: "builds@example.com".to_string(),
+            to: vec!["dev@example.com".to_string(), "lead@example.com".to_string()],
+            attach,
+            compress,
+            on: NotifyOn::Failure,
+        }
+    }
+
+    #[test]
+    fn test_inline_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = temp_dir.path().join("build-errors.log");
+        std::fs::write(&log, "error[E0425]: cannot find value\n").unwrap();
+
+        let outcome = BuildOutcome { log_paths: vec![log.display().to_string()], ..test_outcome(&[]) };
+        let formatted = String::from_utf8(message(&outcome, &settings(false, false)).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("To: dev@example.com, lead@example.com"));
+        assert!(formatted.contains("Subject: cargo-builder: Build failed: 0 errors, 0 warnings in 4.2s"));
+        assert!(formatted.contains("error[E0425]: cannot find value"));
+    }
+
+    #[test]
+    fn test_compressed_attachment() {
+        let temp_dir = TempDir::new().unwrap();
+        let log = temp_dir.path().join("build-errors.log");
+        std::fs::write(&log, "error[E0425]: cannot find value\n").unwrap();
+
+        let outcome = BuildOutcome { log_paths: vec![log.display().to_string()], ..test_outcome(&[]) };
+        let formatted = String::from_utf8(message(&outcome, &settings(true, true)).unwrap().formatted()).unwrap();
+        assert!(formatted.contains("The error log is attached."));
+        assert!(formatted.contains("filename=\"build-errors.log.gz\""));
+        assert!(formatted.contains("Content-Type: application/gzip"));
+        assert!(!formatted.contains("cannot find value"));
+    }
+}
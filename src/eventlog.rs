@@ -0,0 +1,121 @@
+use crate::diagnostics::Diagnostic;
+use crate::logging::LogSink;
+use anyhow::Result;
+
+#[cfg_attr(not(windows), allow(dead_code))]
+const EVENT_SOURCE: &str = "cargo-builder";
+#[cfg_attr(not(windows), allow(dead_code))]
+const BUILD_FAILED_EVENT_ID: u32 = 1;
+
+/// Writes a single Windows Event Log entry summarizing a failed build,
+/// so fleet monitoring on build agents picks it up.
+pub struct EventLogSink {
+    command: String,
+    log_paths: Vec<String>,
+    errors: usize,
+    warnings: usize,
+}
+
+impl EventLogSink {
+    pub fn new(command: String, log_paths: Vec<String>) -> Result<Self> {
+        if !cfg!(windows) {
+            anyhow::bail!("The Windows Event Log sink is only available on Windows");
+        }
+        Ok(EventLogSink { command, log_paths, errors: 0, warnings: 0 })
+    }
+
+    fn message(&self) -> String {
+        let mut message = format!(
+            "Build failed with {} errors and {} warnings\r\nCommand: {}",
+            self.errors, self.warnings, self.command,
+        );
+        for path in &self.log_paths {
+            message.push_str(&format!("\r\nLog: {}", path));
+        }
+        message
+    }
+}
+
+impl LogSink for EventLogSink {
+    fn log_diagnostic(&mut self, diagnostic: &Diagnostic) -> Result<()> {
+        match diagnostic.level.as_str() {
+            "error" => self.errors += 1,
+            "warning" => self.warnings += 1,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn finalize(self: Box<Self>, build_success: bool) -> Result<()> {
+        if !build_success {
+            report_error(&self.message())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+fn report_error(message: &str) -> Result<()> {
+    use windows_sys::Win32::System::EventLog::{
+        DeregisterEventSource, RegisterEventSourceW, ReportEventW, EVENTLOG_ERROR_TYPE,
+    };
+
+    fn wide(text: &str) -> Vec<u16> {
+        text.encode_utf16().chain(std::iter::once(0)).collect()
+    }
+
+    let source = wide(EVENT_SOURCE);
+    let message = wide(message);
+    let strings = [message.as_ptr()];
+
+    // SAFETY: all pointers reference live, NUL-terminated UTF-16 buffers for
+    // the duration of the calls, and the handle is released before returning.
+    unsafe {
+        let handle = RegisterEventSourceW(std::ptr::null(), source.as_ptr());
+        if handle.is_null() {
+            anyhow::bail!("Failed to open the Windows Event Log: {}", std::io::Error::last_os_error());
+        }
+        let ok = ReportEventW(
+            handle,
+            EVENTLOG_ERROR_TYPE,
+            0,
+            BUILD_FAILED_EVENT_ID,
+            std::ptr::null_mut(),
+            strings.len() as u16,
+            0,
+            strings.as_ptr(),
+            std::ptr::null(),
+        );
+        let error = std::io::Error::last_os_error();
+        DeregisterEventSource(handle);
+        if ok == 0 {
+            anyhow::bail!("Failed to write to the Windows Event Log: {}", error);
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(windows))]
+fn report_error(_message: &str) -> Result<()> {
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_message_includes_counts_and_logs() {
+        let sink = EventLogSink {
+            command: "cargo build --release".to_string(),
+            log_paths: vec!["C:\\work\\target\\build-errors.log".to_string()],
+            errors: 3,
+            warnings: 1,
+        };
+        assert_eq!(
+            sink.message(),
+            "Build failed with 3 errors and 1 warnings\r\nCommand: cargo build --release\r\nLog: C:\\work\\target\\build-errors.log"
+        );
+    }
+}
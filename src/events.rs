@@ -0,0 +1,127 @@
+use anyhow::{Result, Context};
+use std::io::{BufRead, BufReader, ErrorKind, Read};
+use std::process::Child;
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+/// Longest wait for an event before the loop gets a `Tick`.
+pub const TICK: Duration = Duration::from_millis(200);
+
+/// Something the build loop reacts to, in the order it happened.
+#[derive(Debug)]
+pub enum Event {
+    /// A line of cargo's JSON messages
+    Stdout(std::io::Result<String>),
+    /// Progress and status messages as cargo wrote them, which may end mid-line
+    Stderr(Vec<u8>),
+    /// Nothing happened for a `TICK`, so timers can be checked
+    Tick,
+}
+
+enum Message {
+    Event(Event),
+    /// A pipe of the child was closed
+    Closed,
+}
+
+/// Events of a running cargo process. Every source runs on a thread of its own and
+/// sends to one channel, so a full pipe never stalls cargo while the loop waits
+/// on something else, and the loop never blocks longer than a `TICK`. Threads
+/// rather than an async runtime, as every library doing our I/O blocks; timeouts
+/// and signals are polled on each event, and notifications are sent after the
+/// loop, each on a thread of its own (see `notify::send_all`).
+pub struct Events {
+    receiver: Receiver<Message>,
+    open_pipes: usize,
+}
+
+impl Events {
+    /// Start reading stdout and stderr of `child`. The events end once both are closed.
+    pub fn spawn(child: &mut Child) -> Result<Self> {
+        let stdout = child.stdout.take().context("Failed to capture stdout")?;
+        let stderr = child.stderr.take().context("Failed to capture stderr")?;
+        let (sender, receiver) = mpsc::channel();
+
+        let stdout_sender = sender.clone();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let failed = line.is_err();
+                if stdout_sender.send(Message::Event(Event::Stdout(line))).is_err() || failed {
+                    break;
+                }
+            }
+            let _ = stdout_sender.send(Message::Closed);
+        });
+        thread::spawn(move || read_chunks(stderr, sender));
+
+        Ok(Events { receiver, open_pipes: 2 })
+    }
+}
+
+/// Pass on whatever arrives: progress bars end in `\r` rather than a newline.
+fn read_chunks(mut pipe: impl Read, sender: Sender<Message>) {
+    let mut buffer = [0; 8192];
+    loop {
+        match pipe.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => {
+                if sender.send(Message::Event(Event::Stderr(buffer[..n].to_vec()))).is_err() {
+                    return;
+                }
+            }
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(_) => break,
+        }
+    }
+    let _ = sender.send(Message::Closed);
+}
+
+impl Iterator for Events {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        while self.open_pipes > 0 {
+            match self.receiver.recv_timeout(TICK) {
+                Ok(Message::Event(event)) => return Some(event),
+                Ok(Message::Closed) => self.open_pipes -= 1,
+                Err(RecvTimeoutError::Timeout) => return Some(Event::Tick),
+                Err(RecvTimeoutError::Disconnected) => break,
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::process::{Command, Stdio};
+
+    #[test]
+    fn test_events_of_both_pipes() {
+        let mut child = Command::new("sh")
+            .args(["-c", "echo one; printf 'progress\\r' >&2; sleep 0.5; echo two"])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .unwrap();
+
+        let mut lines = Vec::new();
+        let mut stderr = Vec::new();
+        let mut ticks = 0;
+        for event in Events::spawn(&mut child).unwrap() {
+            match event {
+                Event::Stdout(line) => lines.push(line.unwrap()),
+                Event::Stderr(chunk) => stderr.extend(chunk),
+                Event::Tick => ticks += 1,
+            }
+        }
+        child.wait().unwrap();
+
+        assert_eq!(lines, ["one", "two"]);
+        assert_eq!(stderr, b"progress\r");
+        // The loop kept running while the child slept
+        assert!(ticks >= 1);
+    }
+}
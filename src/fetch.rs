@@ -0,0 +1,238 @@
+use crate::diagnostics::{strip_ansi_codes, Diagnostic};
+use crate::{retry, Config};
+use anyhow::{Context, Result};
+use regex::Regex;
+use std::process::Command;
+
+/// Flags of `cargo build` that `cargo fetch` takes as well, with a value and without.
+const PASSED_WITH_VALUES: [&str; 2] = ["--manifest-path", "--target"];
+const PASSED: [&str; 3] = ["--locked", "--frozen", "--offline"];
+
+lazy_static::lazy_static! {
+    /// The dependency an error is about, e.g. "failed to get `foo` as a dependency",
+    /// "no matching package named `foo`" or "the requirement `foo = \"^2\"`".
+    static ref DEPENDENCY: Regex = Regex::new(
+        r"(?:failed to get|dependency|package named|requirement|package) `([A-Za-z0-9_-]+)"
+    ).unwrap();
+    static ref AUTH: Regex = Regex::new(
+        r"(?i)authenticat|no token found|token rejected|got 40[13]\b|cargo login"
+    ).unwrap();
+    static ref GIT: Regex = Regex::new(
+        r"failed to load source for dependency|revspec|failed to find branch|failed to find tag|failed to clone|git repository"
+    ).unwrap();
+}
+
+/// Why fetching a dependency failed, and what to do about it.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    /// The dependency it's about, when cargo names one
+    pub dependency: Option<String>,
+    /// cargo's error, with its innermost cause
+    pub message: String,
+    /// What cargo adds, e.g. the versions it found instead
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Problem {
+    /// The problem as an error entry, rendered the way the compiler renders its own.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut rendered = format!("error: {}\n", self.message);
+        for note in &self.notes {
+            rendered.push_str(&format!("  = note: {}\n", note));
+        }
+        if let Some(help) = &self.help {
+            rendered.push_str(&format!("  = help: {}\n", help));
+        }
+        Diagnostic {
+            rendered,
+            ..Diagnostic::new("error", self.message.clone())
+        }
+    }
+}
+
+/// A `cargo fetch` that failed.
+pub struct Failure {
+    pub exit_code: i32,
+    pub problems: Vec<Problem>,
+}
+
+/// Run `cargo fetch` for the dependencies the build needs.
+pub fn run(config: &Config) -> Result<Option<Failure>> {
+    let mut cmd = Command::new(&config.cargo);
+    cmd.arg("fetch").args(args(&config.cargo_args)).env("CARGO_TERM_COLOR", "never");
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    let output = cmd.output().with_context(|| format!("Failed to run {} fetch", config.cargo))?;
+    if output.status.success() {
+        return Ok(None);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let mut found = problems(&stderr);
+    if found.is_empty() {
+        // Nothing cargo-builder recognizes; show cargo's own words
+        let message = strip_ansi_codes(&stderr).trim().lines().last().unwrap_or("cargo fetch failed").to_string();
+        found.push(Problem { dependency: None, message, notes: Vec::new(), help: None });
+    }
+    Ok(Some(Failure { exit_code: output.status.code().unwrap_or(1), problems: found }))
+}
+
+/// The cargo arguments that also apply to `cargo fetch`.
+fn args(cargo_args: &[String]) -> Vec<String> {
+    let mut passed = Vec::new();
+    let mut args = cargo_args.iter().take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if PASSED.contains(&arg.as_str()) {
+            passed.push(arg.clone());
+        } else if PASSED_WITH_VALUES.contains(&arg.as_str()) {
+            passed.push(arg.clone());
+            passed.extend(args.next().cloned());
+        } else if PASSED_WITH_VALUES.iter().any(|flag| arg.strip_prefix(flag).is_some_and(|rest| rest.starts_with('='))) {
+            passed.push(arg.clone());
+        }
+    }
+    passed
+}
+
+/// The errors in `cargo fetch`'s output. Each starts with an `error:` line and may
+/// go on with details and `Caused by:` sections, innermost last.
+fn problems(output: &str) -> Vec<Problem> {
+    let output = strip_ansi_codes(output);
+    let mut blocks: Vec<Vec<&str>> = Vec::new();
+    let mut in_error = false;
+    for line in output.lines() {
+        if line.starts_with("error:") {
+            blocks.push(vec![line]);
+            in_error = true;
+        } else if line.starts_with("warning:") {
+            in_error = false;
+        } else if let Some(block) = blocks.last_mut().filter(|_| in_error) {
+            block.push(line);
+        }
+    }
+    blocks.iter().map(|block| problem(block)).collect()
+}
+
+fn problem(block: &[&str]) -> Problem {
+    let headline = block[0].trim_start_matches("error:").trim();
+    let mut notes = Vec::new();
+    let mut cargo_help = None;
+    let mut causes = Vec::new();
+    let mut in_causes = false;
+    for line in &block[1..] {
+        let line = line.trim();
+        if line == "Caused by:" {
+            in_causes = true;
+        } else if line.is_empty() || line.starts_with("As a reminder, you're using offline mode") {
+            continue;
+        } else if in_causes {
+            causes.push(line);
+        } else if let Some(help) = line.strip_prefix("help:") {
+            cargo_help = Some(help.trim().to_string());
+        } else {
+            notes.push(line.to_string());
+        }
+    }
+
+    let text = block.join("\n");
+    let dependency = DEPENDENCY.captures(&text).map(|captures| captures[1].to_string());
+    let message = match causes.last() {
+        Some(cause) => format!("{}: {}", headline, cause),
+        None => headline.to_string(),
+    };
+    let help = hint(&text, dependency.as_deref()).or(cargo_help);
+    Problem { dependency, message, notes, help }
+}
+
+/// What usually fixes the failure `text` describes.
+fn hint(text: &str, dependency: Option<&str>) -> Option<String> {
+    let update = dependency.map_or("cargo update".to_string(), |name| format!("cargo update -p {}", name));
+    Some(if text.contains("is yanked") {
+        format!("the version was yanked from the registry; `{}` picks one that isn't, or require another version in Cargo.toml", update)
+    } else if AUTH.is_match(text) {
+        if GIT.is_match(text) {
+            "check the credentials git uses for the repository (ssh-agent or a credential helper); with `net.git-fetch-with-cli = true` cargo uses git's own".to_string()
+        } else {
+            "log in to the registry with `cargo login` (`--registry <name>` for one other than crates.io)".to_string()
+        }
+    } else if retry::network_cause(text).is_some() {
+        "check the network and proxy settings; --retry tries again, --offline-fallback builds with the dependencies already downloaded".to_string()
+    } else if GIT.is_match(text) {
+        "check the `git`, `branch`, `tag` and `rev` of the dependency in Cargo.toml".to_string()
+    } else if text.contains("--locked was passed") {
+        "Cargo.lock is missing or out of date: run `cargo update` or build once without --locked, and commit Cargo.lock".to_string()
+    } else if text.contains("no matching package named") {
+        "check the name of the dependency and its `registry` in Cargo.toml".to_string()
+    } else if text.contains("failed to select a version") {
+        if text.contains("offline mode") {
+            "no downloaded version matches; fetch once without --offline in case a matching one was published since".to_string()
+        } else {
+            format!("no published version matches the requirement; change it in Cargo.toml, or run `{}` if Cargo.lock pins an old one", update)
+        }
+    } else {
+        return None;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args_of(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_args() {
+        let cargo_args = args_of(&["--release", "--target", "wasm32-unknown-unknown", "--manifest-path=app/Cargo.toml", "--locked", "-p", "app", "--", "--offline"]);
+        assert_eq!(args(&cargo_args), args_of(&["--target", "wasm32-unknown-unknown", "--manifest-path=app/Cargo.toml", "--locked"]));
+    }
+
+    #[test]
+    fn test_problems() {
+        let git = "    Updating git repository `http://127.0.0.1:9/foo.git`\nwarning: spurious network error (1 try remaining): failed to connect to 127.0.0.1: Connection refused; class=Os (2)\nerror: failed to get `foo` as a dependency of package `app v0.1.0 (/tmp/app)`\n\nCaused by:\n  failed to load source for dependency `foo`\n\nCaused by:\n  failed to fetch into: /home/me/.cargo/git/db/foo-3f63\n\nCaused by:\n  failed to connect to 127.0.0.1: Connection refused; class=Os (2)\n";
+        let [problem] = &problems(git)[..] else { panic!() };
+        assert_eq!(problem.dependency.as_deref(), Some("foo"));
+        assert_eq!(
+            problem.message,
+            "failed to get `foo` as a dependency of package `app v0.1.0 (/tmp/app)`: failed to connect to 127.0.0.1: Connection refused; class=Os (2)",
+        );
+        assert!(problem.help.as_deref().unwrap().starts_with("check the network"));
+
+        let version = "error: failed to select a version for the requirement `humantime = \"^99\"`\ncandidate versions found which didn't match: 2.3.0, 2.2.0, ...\nlocation searched: crates.io index\nrequired by package `app v0.1.0 (/tmp/app)`\n";
+        let [problem] = &problems(version)[..] else { panic!() };
+        assert_eq!(problem.dependency.as_deref(), Some("humantime"));
+        assert_eq!(problem.notes[0], "candidate versions found which didn't match: 2.3.0, 2.2.0, ...");
+        assert!(problem.help.as_deref().unwrap().contains("`cargo update -p humantime`"));
+
+        let auth = "error: failed to get `private` as a dependency of package `app v0.1.0`\n\nCaused by:\n  failed to load source for dependency `private`\n\nCaused by:\n  failed to authenticate when downloading repository\n\n  * attempted ssh-agent authentication, but no usernames succeeded: `git`\n";
+        let [problem] = &problems(auth)[..] else { panic!() };
+        assert!(problem.help.as_deref().unwrap().contains("ssh-agent"));
+
+        let registry = "error: failed to download from `https://my-registry.example/api/v1/crates/x/1.0.0/download`\n\nCaused by:\n  no token found for `my-registry`, please run `cargo login --registry my-registry`\n";
+        let [problem] = &problems(registry)[..] else { panic!() };
+        assert!(problem.help.as_deref().unwrap().starts_with("log in"));
+
+        // cargo's own help is kept when there's nothing better
+        let lock = "error: cannot create the lock file /tmp/app/Cargo.lock because --locked was passed to prevent this\nhelp: to generate the lock file without accessing the network, remove the --locked flag and use --offline instead.\n";
+        let [problem] = &problems(lock)[..] else { panic!() };
+        assert!(problem.help.as_deref().unwrap().starts_with("Cargo.lock is missing or out of date"));
+        let other = "error: something new\nhelp: try this\n";
+        assert_eq!(problems(other)[0].help.as_deref(), Some("try this"));
+    }
+
+    #[test]
+    fn test_diagnostic() {
+        let problem = Problem {
+            dependency: Some("foo".to_string()),
+            message: "version 1.0.1 of `foo` is yanked".to_string(),
+            notes: vec!["required by package `app v0.1.0`".to_string()],
+            help: Some("pick another".to_string()),
+        };
+        assert_eq!(
+            problem.diagnostic().rendered,
+            "error: version 1.0.1 of `foo` is yanked\n  = note: required by package `app v0.1.0`\n  = help: pick another\n",
+        );
+    }
+}
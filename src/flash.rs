@@ -0,0 +1,88 @@
+use crate::settings::FlashSettings;
+use crate::steps::{self, Problem};
+use crate::{util, Config};
+use std::path::PathBuf;
+use std::process::Command;
+use std::time::Instant;
+
+const INSTALL: &str = "install it with `cargo install probe-rs-tools --locked`, or see https://probe.rs/docs/getting-started/installation/";
+
+/// Flash the program the build made with probe-rs and reset the chip to run it.
+pub fn run(config: &Config, settings: &FlashSettings, executables: &[PathBuf]) -> Option<Problem> {
+    let elf = match executables {
+        [elf] => elf,
+        [] => return Some(Problem::new(
+            "the build made no program to flash".to_string(),
+            "--flash writes a binary; build one with --bin <name> or --example <name>".to_string(),
+        )),
+        _ => {
+            let names: Vec<_> = executables.iter().filter_map(|path| path.file_name()).map(|name| name.to_string_lossy()).collect();
+            return Some(Problem::new(
+                format!("the build made {} programs to flash: {}", names.len(), names.join(", ")),
+                "pick the one to flash with --bin <name> or --example <name>".to_string(),
+            ));
+        }
+    };
+    let Some(chip) = config.flash_chip.as_ref().or(settings.chip.as_ref()) else {
+        return Some(Problem::new(
+            "--flash needs the chip to write to".to_string(),
+            "give it with --chip, or as `chip` under [flash] in cargo-builder.toml; `probe-rs chip list` lists the names".to_string(),
+        ));
+    };
+    let mut target = vec!["--chip".to_string(), chip.clone()];
+    if let Some(probe) = config.flash_probe.as_ref().or(settings.probe.as_ref()) {
+        target.extend(["--probe".to_string(), probe.clone()]);
+    }
+
+    let name = elf.file_name().map_or_else(|| elf.display().to_string(), |name| name.to_string_lossy().into_owned());
+    if !config.quiet {
+        eprintln!("cargo-builder: Flashing {} to {}...", name, chip);
+    }
+    let start = Instant::now();
+    let mut download = Command::new("probe-rs");
+    download.arg("download").args(&target).arg(elf);
+    if let Some(problem) = steps::run(download, "probe-rs", &name, INSTALL, hint) {
+        return Some(problem);
+    }
+    let mut reset = Command::new("probe-rs");
+    reset.arg("reset").args(&target);
+    if let Some(problem) = steps::run(reset, "probe-rs", chip, INSTALL, hint) {
+        return Some(problem);
+    }
+    if !config.quiet {
+        eprintln!("cargo-builder: Flashed {} to {} in {}", name, chip, util::format_duration(start.elapsed()));
+    }
+    None
+}
+
+/// What usually fixes the failure probe-rs printed.
+fn hint(output: &str) -> Option<String> {
+    let output = output.to_lowercase();
+    Some(if output.contains("no connected probes") || output.contains("no probe was found") || output.contains("probe was not found") {
+        "connect the debug probe; on Linux, install probe-rs's udev rules so it can be opened without root".to_string()
+    } else if output.contains("permission denied") || output.contains("access denied") {
+        "install probe-rs's udev rules (https://probe.rs/docs/getting-started/probe-setup/) so the probe can be opened without root".to_string()
+    } else if output.contains("multiple probes") || output.contains("more than a single probe") {
+        "pick one with --probe VID:PID[:SERIAL]; `probe-rs list` lists those connected".to_string()
+    } else if output.contains("chip") && (output.contains("not found") || output.contains("unknown") || output.contains("not supported")) {
+        "check the name given with --chip; `probe-rs chip list` lists the chips probe-rs knows".to_string()
+    } else if output.contains("attach") || output.contains("connect") {
+        "check the wiring and that the board is powered; firmware that turns off the debug port needs the chip held in reset while connecting".to_string()
+    } else {
+        return None;
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hint() {
+        assert!(hint("Error: No connected probes were found.").unwrap().starts_with("connect the debug probe"));
+        assert!(hint("Error: Failed to open probe: USB error: Access denied (insufficient permissions)").unwrap().starts_with("install probe-rs's udev rules"));
+        assert!(hint("Error: More than a single probe detected. Use the --probe argument to select which probe to use.").unwrap().starts_with("pick one"));
+        assert!(hint("Error: The chip 'STM32F999' was not found in the database.").unwrap().contains("probe-rs chip list"));
+        assert!(hint("Error: Failed to flash: erase failed").is_none());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::diagnostics::{self, Diagnostic};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// What cargo says at the end of a build with code a future Rust will reject, before
+/// the id of its report
+const NOTICE: &str = "future-incompatibilities --id ";
+
+/// The report cargo keeps in the target directory, for `cargo report future-incompatibilities`.
+#[derive(Deserialize)]
+struct OnDisk {
+    reports: Vec<OnDiskReport>,
+}
+
+#[derive(Deserialize)]
+struct OnDiskReport {
+    id: u64,
+    /// The lints each package triggers, as rustc rendered them, by `name@version`
+    per_package: BTreeMap<String, String>,
+}
+
+/// Finds the id of the report of this build in cargo's stderr. cargo only writes the
+/// report when it changed, so the file alone doesn't say whether it's this build's.
+#[derive(Default)]
+pub struct Notice {
+    partial: String,
+    id: Option<u64>,
+}
+
+impl Notice {
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.partial.push_str(&String::from_utf8_lossy(chunk));
+        while let Some(end) = self.partial.find(['\n', '\r']) {
+            let line: String = self.partial.drain(..=end).collect();
+            self.id = report_id(&diagnostics::strip_ansi_codes(&line)).or(self.id);
+        }
+    }
+
+    pub fn id(&self) -> Option<u64> {
+        self.id
+    }
+}
+
+fn report_id(line: &str) -> Option<u64> {
+    let rest = &line[line.find(NOTICE)? + NOTICE.len()..];
+    rest.split(|c: char| !c.is_ascii_digit()).next()?.parse().ok()
+}
+
+/// A warning per package of report `id`, with the lints it triggers.
+pub fn load(target_directory: &Path, id: u64) -> Vec<Diagnostic> {
+    let text = fs::read_to_string(target_directory.join(".future-incompat-report.json")).unwrap_or_default();
+    match serde_json::from_str::<OnDisk>(&text) {
+        Ok(on_disk) => on_disk.reports.into_iter().find(|report| report.id == id).map(|report| warnings(&report, id)).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+fn warnings(report: &OnDiskReport, id: u64) -> Vec<Diagnostic> {
+    report.per_package.iter().map(|(package, lints)| {
+        let (name, version) = package.split_once('@').unwrap_or((package, ""));
+        let message = format!("{} v{} contains code that will be rejected by a future version of Rust", name, version);
+        // The first line names the package again
+        let lints = lints.split_once('\n').map_or("", |(_, lints)| lints).trim_end();
+        Diagnostic {
+            rendered: format!(
+                "warning: {}\n{}\n  = note: `cargo report future-incompatibilities --id {}` shows the report again\n",
+                message, lints, id,
+            ),
+            package_id: Some(format!("{} {}", name, version)),
+            ..Diagnostic::new("warning", message)
+        }
+    }).collect()
+}
+
+/// The packages, e.g. ``syn v1.0.0, app v0.1.0 (see `cargo report future-incompatibilities --id 3`)``
+pub fn describe(diagnostics: &[Diagnostic], id: u64) -> String {
+    let packages: Vec<String> = diagnostics.iter()
+        .filter_map(|diagnostic| diagnostic.package_id.as_deref())
+        .map(|package| package.replacen(' ', " v", 1))
+        .collect();
+    format!("{} (see `cargo report future-incompatibilities --id {}`)", packages.join(", "), id)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_notice() {
+        let mut notice = Notice::default();
+        notice.feed(b"warning: the following packages contain code that will be rejected by a future version of Rust: syn v1.0.0\nnote: to see what the problems were, use the option `--future-incompat-report`, or run `cargo report future-incompat");
+        assert_eq!(notice.id(), None);
+        notice.feed(b"ibilities --id 12`\n");
+        assert_eq!(notice.id(), Some(12));
+    }
+
+    #[test]
+    fn test_warnings() {
+        let on_disk: OnDisk = serde_json::from_str(r#"{"version":0,"next_id":4,"reports":[
+            {"id":2,"suggestion_message":"","per_package":{"old@0.1.0":"The package `old v0.1.0` currently triggers the following future incompatibility lints:\n> warning: old\n"}},
+            {"id":3,"suggestion_message":"","per_package":{"syn@1.0.0":"The package `syn v1.0.0` currently triggers the following future incompatibility lints:\n> warning: this function depends on never type fallback being `()`\n>  --> src/lib.rs:3:1\n"}}
+        ]}"#).unwrap();
+        let found = warnings(&on_disk.reports[1], 3);
+        assert_eq!(found[0].message, "syn v1.0.0 contains code that will be rejected by a future version of Rust");
+        assert_eq!(found[0].rendered, "warning: syn v1.0.0 contains code that will be rejected by a future version of Rust\n> warning: this function depends on never type fallback being `()`\n>  --> src/lib.rs:3:1\n  = note: `cargo report future-incompatibilities --id 3` shows the report again\n");
+        assert_eq!(found[0].package_name(), Some("syn"));
+        assert_eq!(describe(&found, 3), "syn v1.0.0 (see `cargo report future-incompatibilities --id 3`)");
+    }
+}
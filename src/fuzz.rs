@@ -0,0 +1,107 @@
+use crate::steps::Problem;
+use crate::{buildstd, context, util, Config};
+use anyhow::{bail, Result};
+use std::env;
+use std::path::PathBuf;
+
+/// The sanitizers `--fuzz` can build with, as cargo-fuzz names them
+pub const SANITIZERS: [&str; 5] = ["address", "leak", "memory", "thread", "none"];
+
+/// The fuzz crate `cargo fuzz init` made: `fuzz/Cargo.toml` in the workspace, or the
+/// workspace itself when the build runs inside it.
+pub fn manifest(cargo_args: &[String]) -> Result<PathBuf> {
+    if let Some(manifest) = context::manifest_path(cargo_args) {
+        return Ok(manifest.to_path_buf());
+    }
+    let workspace = util::find_workspace_for(None)?;
+    let nested = workspace.root.join("fuzz").join("Cargo.toml");
+    if nested.is_file() {
+        return Ok(nested);
+    }
+    if workspace.root.file_name().is_some_and(|name| name == "fuzz") {
+        return Ok(workspace.root.join("Cargo.toml"));
+    }
+    bail!("--fuzz found no fuzz/Cargo.toml in {}; create the fuzz targets with `cargo fuzz init`", workspace.root.display())
+}
+
+/// The arguments `cargo fuzz build` would give cargo: the fuzz crate's manifest, the
+/// host as an explicit target so build scripts and proc macros aren't instrumented,
+/// the release profile and every fuzz target, unless given otherwise.
+pub fn cargo_args(cargo_args: &[String], manifest: &str, host: &str) -> Vec<String> {
+    let end = cargo_args.iter().position(|arg| arg == "--").unwrap_or(cargo_args.len());
+    let given = &cargo_args[..end];
+    let mut added = Vec::new();
+    if context::manifest_path(given).is_none() {
+        added.extend(["--manifest-path".to_string(), manifest.to_string()]);
+    }
+    if context::option_value(given, &["--target"]).is_none() {
+        added.extend(["--target".to_string(), host.to_string()]);
+    }
+    if !["--release", "-r"].iter().any(|flag| context::has_flag(given, flag)) && context::option_value(given, &["--profile"]).is_none() {
+        added.push("--release".to_string());
+    }
+    if context::option_value(given, &["--bin"]).is_none() && !context::has_flag(given, "--bins") {
+        added.push("--bins".to_string());
+    }
+    let mut args = given.to_vec();
+    args.extend(added);
+    args.extend_from_slice(&cargo_args[end..]);
+    args
+}
+
+/// The flags of cargo-fuzz's instrumented builds, after any `RUSTFLAGS` already set.
+pub fn rustflags(sanitizer: &str, host: &str) -> String {
+    let mut flags = env::var("RUSTFLAGS").unwrap_or_default();
+    flags.push_str(" -Cpasses=sancov-module -Cllvm-args=-sanitizer-coverage-level=4");
+    flags.push_str(" -Cllvm-args=-sanitizer-coverage-inline-8bit-counters -Cllvm-args=-sanitizer-coverage-pc-table");
+    flags.push_str(" -Cllvm-args=-sanitizer-coverage-trace-compares --cfg fuzzing -Clink-dead-code");
+    if host.contains("-linux-") {
+        flags.push_str(" -Cllvm-args=-sanitizer-coverage-stack-depth");
+    }
+    if sanitizer != "none" {
+        flags.push_str(&format!(" -Zsanitizer={}", sanitizer));
+    }
+    flags.trim_start().to_string()
+}
+
+/// A problem when the sanitizer needs a nightly toolchain the build doesn't use.
+pub fn check(config: &Config, sanitizer: &str) -> Option<Problem> {
+    let cargo = context::cargo_version(config)?;
+    if sanitizer == "none" || buildstd::is_nightly(cargo) || env::var_os("RUSTC_BOOTSTRAP").is_some() {
+        return None;
+    }
+    Some(Problem::new(
+        format!("fuzzing with the {} sanitizer needs a nightly toolchain, and this build uses {}", sanitizer, cargo),
+        "build with `--toolchain nightly`, or without a sanitizer with `--fuzz=none`".to_string(),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_cargo_args() {
+        let host = "x86_64-unknown-linux-gnu";
+        assert_eq!(
+            cargo_args(&args(&["-j4"]), "/app/fuzz/Cargo.toml", host),
+            args(&["-j4", "--manifest-path", "/app/fuzz/Cargo.toml", "--target", host, "--release", "--bins"]),
+        );
+        assert_eq!(
+            cargo_args(&args(&["--bin", "parse", "--profile", "fuzz", "--", "-Zextra"]), "/app/fuzz/Cargo.toml", host),
+            args(&["--bin", "parse", "--profile", "fuzz", "--manifest-path", "/app/fuzz/Cargo.toml", "--target", host, "--", "-Zextra"]),
+        );
+    }
+
+    #[test]
+    fn test_rustflags() {
+        let flags = rustflags("address", "x86_64-unknown-linux-gnu");
+        assert!(flags.contains("-Cpasses=sancov-module") && flags.contains("--cfg fuzzing"));
+        assert!(flags.ends_with("-Cllvm-args=-sanitizer-coverage-stack-depth -Zsanitizer=address"));
+        assert!(!rustflags("none", "aarch64-apple-darwin").contains("-Zsanitizer"));
+    }
+}
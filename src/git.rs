@@ -0,0 +1,313 @@
+use crate::diagnostics::Diagnostic;
+use anyhow::{Result, Context, bail};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{Duration, UNIX_EPOCH};
+
+/// Run git in `root` and return its trimmed output.
+pub fn output(root: &Path, args: &[&str]) -> Result<String> {
+    let output = Command::new("git")
+        .args(args)
+        .current_dir(root)
+        .output()
+        .context("Failed to run git")?;
+    if !output.status.success() {
+        bail!("`git {}` failed: {}", args.join(" "), String::from_utf8_lossy(&output.stderr).trim());
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Current branch of `root`, or `None` outside a repository or on a detached HEAD.
+pub fn branch(root: &Path) -> Option<String> {
+    let branch = output(root, &["rev-parse", "--abbrev-ref", "HEAD"]).ok()?;
+    (!branch.is_empty() && branch != "HEAD").then_some(branch)
+}
+
+/// The code a build ran against, so its log can be tied back to it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VcsState {
+    pub commit: String,
+    pub branch: Option<String>,
+    /// Whether tracked files were modified or untracked files added
+    pub dirty: bool,
+}
+
+impl VcsState {
+    /// State of the repository containing `root`, or `None` outside a repository
+    /// or before its first commit.
+    pub fn load(root: &Path) -> Option<Self> {
+        let commit = output(root, &["rev-parse", "--verify", "--quiet", "HEAD"]).ok()?;
+        let status = output(root, &["status", "--porcelain"]).ok()?;
+        Some(VcsState {
+            commit,
+            branch: branch(root),
+            dirty: !status.is_empty(),
+        })
+    }
+
+    /// e.g. `1a2b3c4d5e6f7a8b9c0d1a2b3c4d5e6f7a8b9c0d (main, dirty)`
+    pub fn describe(&self) -> String {
+        let mut notes = vec![self.branch.as_deref().unwrap_or("detached")];
+        if self.dirty {
+            notes.push("dirty");
+        }
+        format!("{} ({})", self.commit, notes.join(", "))
+    }
+}
+
+/// Files changed since the merge base of HEAD and a ref, relative to the workspace root.
+pub struct ChangedFiles {
+    /// Short hash of the merge base
+    pub base: String,
+    files: HashSet<String>,
+}
+
+impl ChangedFiles {
+    /// Compare the working tree, including untracked files, with the merge base of
+    /// HEAD and `reference`. `main` falls back to `master` for older repositories.
+    pub fn load(root: &Path, reference: &str) -> Result<Self> {
+        let reference = if reference == "main" && output(root, &["rev-parse", "--verify", "--quiet", "main"]).is_err() {
+            "master"
+        } else {
+            reference
+        };
+        let base = output(root, &["merge-base", "HEAD", reference])
+            .with_context(|| format!("--changed-only: no common ancestor of HEAD and `{}`", reference))?;
+
+        // `--relative` limits the diff to the workspace and makes paths relative to it, like cargo's
+        let changed = output(root, &["diff", "--name-only", "--relative", &base])?;
+        let untracked = output(root, &["ls-files", "--others", "--exclude-standard"])?;
+        let files = changed.lines()
+            .chain(untracked.lines())
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect();
+
+        Ok(ChangedFiles {
+            base: base.chars().take(10).collect(),
+            files,
+        })
+    }
+
+    pub fn count(&self) -> usize {
+        self.files.len()
+    }
+
+    /// Whether the primary span of `diagnostic` is in a changed file. Diagnostics
+    /// without a file, such as linker errors, always count as changed.
+    pub fn contains(&self, diagnostic: &Diagnostic) -> bool {
+        diagnostic.file.as_ref().is_none_or(|file| self.files.contains(&file.replace('\\', "/")))
+    }
+}
+
+/// Who last changed a line, from `git blame`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlameLine {
+    pub commit: String,
+    pub author: String,
+    pub email: String,
+    /// Seconds since the Unix epoch
+    pub time: u64,
+    pub summary: String,
+}
+
+impl BlameLine {
+    fn is_uncommitted(&self) -> bool {
+        self.commit.bytes().all(|b| b == b'0')
+    }
+
+    /// e.g. `1a2b3c4d Jane Doe <jane@example.com>, 2024-05-01: Add parser`
+    pub fn describe(&self) -> String {
+        if self.is_uncommitted() {
+            return "not committed yet".to_string();
+        }
+        let date = humantime::format_rfc3339_seconds(UNIX_EPOCH + Duration::from_secs(self.time)).to_string();
+        format!(
+            "{} {} {}, {}: {}",
+            &self.commit[..self.commit.len().min(8)], self.author, self.email, &date[..10], self.summary,
+        )
+    }
+}
+
+/// Lines of `git blame --line-porcelain` output by line number.
+fn parse_blame(porcelain: &str) -> HashMap<u64, BlameLine> {
+    let mut lines = HashMap::new();
+    let mut current: Option<(u64, BlameLine)> = None;
+    for line in porcelain.lines() {
+        if line.starts_with('\t') {
+            // The content line ends each entry
+            if let Some((number, blame)) = current.take() {
+                lines.insert(number, blame);
+            }
+            continue;
+        }
+        let Some((key, value)) = line.split_once(' ') else {
+            continue;
+        };
+        match &mut current {
+            None => {
+                // Header: <commit> <original line> <final line> [<lines in group>]
+                let number = value.split(' ').nth(1).and_then(|n| n.parse().ok());
+                if let Some(number) = number {
+                    current = Some((number, BlameLine {
+                        commit: key.to_string(),
+                        author: String::new(),
+                        email: String::new(),
+                        time: 0,
+                        summary: String::new(),
+                    }));
+                }
+            }
+            Some((_, blame)) => match key {
+                "author" => blame.author = value.to_string(),
+                "author-mail" => blame.email = value.to_string(),
+                "author-time" => blame.time = value.parse().unwrap_or(0),
+                "summary" => blame.summary = value.to_string(),
+                _ => {}
+            },
+        }
+    }
+    lines
+}
+
+/// Blames the lines diagnostics point at, running `git blame` once per file.
+pub struct Blamer {
+    root: PathBuf,
+    /// `None` for files git cannot blame, e.g. outside the repository
+    files: HashMap<String, Option<HashMap<u64, BlameLine>>>,
+}
+
+impl Blamer {
+    pub fn new(root: &Path) -> Self {
+        Blamer { root: root.to_path_buf(), files: HashMap::new() }
+    }
+
+    pub fn blame(&mut self, file: &str, line: u64) -> Option<&BlameLine> {
+        let root = &self.root;
+        self.files.entry(file.to_string())
+            .or_insert_with(|| output(root, &["blame", "--line-porcelain", "--", file]).ok().map(|out| parse_blame(&out)))
+            .as_ref()?
+            .get(&line)
+    }
+
+    /// Add a `= blame:` note for the diagnostic's primary line after its rendered text.
+    pub fn annotate(&mut self, diagnostic: &mut Diagnostic) {
+        let (Some(file), Some(line)) = (&diagnostic.file, diagnostic.line) else {
+            return;
+        };
+        let Some(blame) = self.blame(file, line) else {
+            return;
+        };
+        let note = format!("  \x1b[1m\x1b[94m=\x1b[0m \x1b[1mblame\x1b[0m: {}", blame.describe());
+        let rendered = diagnostic.rendered.trim_end_matches('\n');
+        let trailing = &diagnostic.rendered[rendered.len()..];
+        diagnostic.rendered = format!("{}\n{}{}", rendered, note, if trailing.is_empty() { "\n" } else { trailing });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    fn git(root: &Path, args: &[&str]) {
+        let mut all = vec!["-c", "user.name=test", "-c", "user.email=test@example.com"];
+        all.extend_from_slice(args);
+        output(root, &all).unwrap();
+    }
+
+    #[test]
+    fn test_changed_files_since_merge_base() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        let workspace = repo.join("ws");
+        fs::create_dir_all(workspace.join("src")).unwrap();
+        fs::write(workspace.join("src/lib.rs"), "").unwrap();
+        fs::write(workspace.join("src/old.rs"), "").unwrap();
+        fs::write(repo.join("README"), "").unwrap();
+        git(repo, &["init", "-q", "-b", "master"]);
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-q", "-m", "init"]);
+        git(repo, &["checkout", "-q", "-b", "feature"]);
+
+        fs::write(workspace.join("src/lib.rs"), "mod new;").unwrap();
+        fs::write(workspace.join("src/new.rs"), "").unwrap();
+        fs::write(repo.join("README"), "changed outside the workspace").unwrap();
+
+        // `main` doesn't exist, so the merge base with `master` is used
+        let changed = ChangedFiles::load(&workspace, "main").unwrap();
+        assert_eq!(changed.count(), 2);
+        assert!(changed.contains(&Diagnostic::new("warning", "unused variable: `x`").at("src/lib.rs", 1, 1)));
+        assert!(changed.contains(&Diagnostic::new("warning", "unused variable: `x`").at("src/new.rs", 1, 1)));
+        assert!(!changed.contains(&Diagnostic::new("warning", "unused variable: `x`").at("src/old.rs", 1, 1)));
+        assert!(changed.contains(&Diagnostic::new("warning", "unused variable: `x`")));
+        assert_eq!(branch(&workspace).as_deref(), Some("feature"));
+
+        let vcs = VcsState::load(&workspace).unwrap();
+        assert_eq!(vcs.commit, output(repo, &["rev-parse", "HEAD"]).unwrap());
+        assert!(vcs.dirty);
+        assert_eq!(vcs.describe(), format!("{} (feature, dirty)", vcs.commit));
+        git(repo, &["add", "."]);
+        git(repo, &["commit", "-q", "-m", "feature"]);
+        assert!(!VcsState::load(&workspace).unwrap().dirty);
+    }
+
+    #[test]
+    fn test_parse_blame() {
+        let porcelain = "\
+1a2b3c4d5e6f7a8b9c0d1a2b3c4d5e6f7a8b9c0d 1 1 2
+author Jane Doe
+author-mail <jane@example.com>
+author-time 1714521600
+author-tz +0000
+summary Add parser
+filename src/lib.rs
+\tfn parse() {
+1a2b3c4d5e6f7a8b9c0d1a2b3c4d5e6f7a8b9c0d 2 2
+author Jane Doe
+author-mail <jane@example.com>
+author-time 1714521600
+author-tz +0000
+summary Add parser
+filename src/lib.rs
+\t    let x = 1;
+0000000000000000000000000000000000000000 3 3 1
+author Not Committed Yet
+author-mail <not.committed.yet>
+author-time 1714600000
+author-tz +0000
+summary Version of src/lib.rs from src/lib.rs
+filename src/lib.rs
+\t}
+";
+        let lines = parse_blame(porcelain);
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[&2].describe(), "1a2b3c4d Jane Doe <jane@example.com>, 2024-05-01: Add parser");
+        assert_eq!(lines[&3].describe(), "not committed yet");
+    }
+
+    #[test]
+    fn test_annotate() {
+        let temp_dir = TempDir::new().unwrap();
+        let repo = temp_dir.path();
+        fs::write(repo.join("lib.rs"), "fn f() {\n    let x = 1;\n}\n").unwrap();
+        git(repo, &["init", "-q"]);
+        git(repo, &["add", "."]);
+        git(repo, &["-c", "user.name=Jane Doe", "-c", "user.email=jane@example.com", "commit", "-q", "-m", "Add f"]);
+
+        let mut diagnostic = Diagnostic::new("warning", "unused variable: `x`")
+            .at("lib.rs", 2, 1)
+            .with_rendered("warning: unused variable: `x`\n --> lib.rs:2:9\n\n");
+        let mut blamer = Blamer::new(repo);
+        blamer.annotate(&mut diagnostic);
+
+        let lines: Vec<&str> = diagnostic.rendered.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert!(lines[2].contains("blame\x1b[0m: "));
+        assert!(lines[2].contains(" Jane Doe <jane@example.com>, "));
+        assert!(diagnostic.rendered.ends_with(": Add f\n\n"));
+        assert!(blamer.blame("missing.rs", 1).is_none());
+    }
+}
@@ -0,0 +1,227 @@
+use crate::diagnostics::{self, Diagnostic};
+use crate::notify::{self, BuildOutcome};
+use anyhow::{Result, Context};
+use serde_json::{json, Value};
+use std::env;
+use std::time::Duration;
+
+/// GitHub rejects comments over 65536 characters; leave room for the footer
+const BODY_LIMIT: usize = 60_000;
+const PAGE_SIZE: usize = 100;
+
+/// A pull request given with `--github-pr`; the repository defaults to `$GITHUB_REPOSITORY`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PullRequest {
+    pub repo: Option<String>,
+    pub number: u64,
+}
+
+/// Parse `123` or `owner/repo#123`.
+pub fn parse_pull_request(text: &str) -> std::result::Result<PullRequest, String> {
+    let (repo, number) = match text.rsplit_once('#') {
+        Some((repo, number)) if repo.split('/').count() == 2 => (Some(repo.to_string()), number),
+        Some(_) => return Err(format!("expected OWNER/REPO#NUMBER, got `{}`", text)),
+        None => (None, text),
+    };
+    let number = number.parse()
+        .map_err(|_| format!("invalid pull request number `{}`", number))?;
+    Ok(PullRequest { repo, number })
+}
+
+/// The pull request number of a GitHub Actions event payload.
+fn event_pull_request(event: &Value) -> Option<u64> {
+    event["pull_request"]["number"].as_u64()
+}
+
+/// The pull request number from a `refs/pull/<n>/merge` ref.
+fn ref_pull_request(git_ref: &str) -> Option<u64> {
+    git_ref.strip_prefix("refs/pull/")?.split('/').next()?.parse().ok()
+}
+
+/// The repository and pull request to comment on: explicit, or detected from GitHub Actions.
+fn resolve(explicit: Option<&PullRequest>) -> Result<(String, u64)> {
+    let repo = explicit.and_then(|pr| pr.repo.clone())
+        .or_else(|| env::var("GITHUB_REPOSITORY").ok())
+        .context("No repository to comment on; pass --github-pr OWNER/REPO#NUMBER")?;
+    if let Some(pr) = explicit {
+        return Ok((repo, pr.number));
+    }
+
+    let from_event = env::var("GITHUB_EVENT_PATH").ok()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+        .and_then(|event| event_pull_request(&event));
+    let number = from_event
+        .or_else(|| env::var("GITHUB_REF").ok().and_then(|r| ref_pull_request(&r)))
+        .context("Not running for a pull request; pass --github-pr NUMBER")?;
+    Ok((repo, number))
+}
+
+/// Hidden first line identifying our comment, per command so matrix jobs don't overwrite each other.
+fn marker(command: &str) -> String {
+    format!("<!-- cargo-builder: {} -->", command.replace("--", "- -"))
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// A collapsible section with the rendered diagnostic.
+fn details(diagnostic: &Diagnostic) -> String {
+    format!(
+        "<details><summary>{}</summary>\n\n````text\n{}\n````\n\n</details>\n",
+        escape_html(&diagnostic.summary()),
+        diagnostics::strip_ansi_codes(&diagnostic.rendered).trim_end(),
+    )
+}
+
+/// Markdown comment: the headline, the command, and each error then warning in a collapsible block.
+fn comment_body(outcome: &BuildOutcome, run_url: Option<&str>) -> String {
+    let mut body = format!(
+        "{}\n### {} {}\n\n`{}`\n\n",
+        marker(&outcome.command),
+        if outcome.success { "✅" } else { "❌" },
+        outcome.headline(),
+        outcome.command,
+    );
+
+    let ordered = outcome.errors()
+        .chain(outcome.diagnostics.iter().filter(|d| d.level == "warning"));
+    let total = outcome.error_count() + outcome.warning_count();
+    let mut shown = 0;
+    for diagnostic in ordered {
+        let section = details(diagnostic);
+        if body.len() + section.len() > BODY_LIMIT {
+            break;
+        }
+        body.push_str(&section);
+        shown += 1;
+    }
+    if shown < total {
+        body.push_str(&format!("\n_… and {} more not shown._\n", total - shown));
+    }
+
+    if let Some(url) = run_url {
+        body.push_str(&format!("\n[Workflow run]({})\n", url));
+    }
+    body
+}
+
+/// Link to the current GitHub Actions run, if any.
+fn run_url() -> Option<String> {
+    let server = env::var("GITHUB_SERVER_URL").ok()?;
+    let repo = env::var("GITHUB_REPOSITORY").ok()?;
+    let run_id = env::var("GITHUB_RUN_ID").ok()?;
+    Some(format!("{}/{}/actions/runs/{}", server, repo, run_id))
+}
+
+/// Post the build summary on the pull request, editing our earlier comment if there is one.
+pub fn comment(pr: Option<&PullRequest>, outcome: &BuildOutcome, timeout: Duration) -> Result<()> {
+    let (repo, number) = resolve(pr)?;
+    let token = env::var("GITHUB_TOKEN").or_else(|_| env::var("GH_TOKEN"))
+        .context("GITHUB_TOKEN is not set")?;
+    let api = env::var("GITHUB_API_URL").unwrap_or_else(|_| "https://api.github.com".to_string());
+    let api = api.trim_end_matches('/');
+
+    let prepare = |request: ureq::Request| {
+        request
+            .set("Accept", "application/vnd.github+json")
+            .set("Authorization", &format!("Bearer {}", token))
+            .set("X-GitHub-Api-Version", "2022-11-28")
+    };
+
+    let body = comment_body(outcome, run_url().as_deref());
+    let marker = marker(&outcome.command);
+    let existing = find_comment(api, &repo, number, &marker, timeout, &prepare)?;
+
+    let payload = json!({ "body": body }).to_string();
+    let prepare_json = |request| prepare(request).set("Content-Type", "application/json");
+    match existing {
+        Some(id) => notify::send_with_retry(
+            "PATCH", &format!("{}/repos/{}/issues/comments/{}", api, repo, id), Some(&payload), timeout, prepare_json,
+        )?,
+        None => notify::send_with_retry(
+            "POST", &format!("{}/repos/{}/issues/{}/comments", api, repo, number), Some(&payload), timeout, prepare_json,
+        )?,
+    };
+    Ok(())
+}
+
+/// The id of the pull request comment starting with `marker`.
+fn find_comment(
+    api: &str,
+    repo: &str,
+    number: u64,
+    marker: &str,
+    timeout: Duration,
+    prepare: &impl Fn(ureq::Request) -> ureq::Request,
+) -> Result<Option<u64>> {
+    for page in 1.. {
+        let url = format!("{}/repos/{}/issues/{}/comments?per_page={}&page={}", api, repo, number, PAGE_SIZE, page);
+        let response = notify::send_with_retry("GET", &url, None, timeout, prepare)?;
+        let comments: Vec<Value> = serde_json::from_str(&response)
+            .with_context(|| format!("Unexpected response from {}", url))?;
+
+        let found = comments.iter()
+            .find(|c| c["body"].as_str().is_some_and(|b| b.starts_with(marker)))
+            .map(|c| c["id"].as_u64().context("Comment without an id"));
+        if let Some(id) = found {
+            return id.map(Some);
+        }
+        if comments.len() < PAGE_SIZE {
+            break;
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::test_outcome;
+
+    #[test]
+    fn test_parse_pull_request() {
+        assert_eq!(parse_pull_request("42"), Ok(PullRequest { repo: None, number: 42 }));
+        assert_eq!(
+            parse_pull_request("rust-lang/cargo#7"),
+            Ok(PullRequest { repo: Some("rust-lang/cargo".to_string()), number: 7 }),
+        );
+        assert!(parse_pull_request("cargo#7").is_err());
+        assert!(parse_pull_request("seven").is_err());
+    }
+
+    #[test]
+    fn test_detect_pull_request() {
+        assert_eq!(event_pull_request(&json!({"pull_request": {"number": 12}})), Some(12));
+        assert_eq!(event_pull_request(&json!({"ref": "refs/heads/main"})), None);
+        assert_eq!(ref_pull_request("refs/pull/34/merge"), Some(34));
+        assert_eq!(ref_pull_request("refs/heads/main"), None);
+    }
+
+    #[test]
+    fn test_comment_body() {
+        let diagnostics = [
+            Diagnostic::new("warning", "unused variable").at("src/lib.rs", 1, 1).with_rendered("\x1b[1mwarning\x1b[0m: unused variable\n"),
+            Diagnostic::new("error", "expected `Vec<u8>`").at("src/lib.rs", 1, 1).with_rendered("\x1b[1merror\x1b[0m: expected `Vec<u8>`\n"),
+        ];
+        let body = comment_body(&BuildOutcome { command: "cargo build --release".to_string(), ..test_outcome(&diagnostics) }, Some("https://github.com/o/r/actions/runs/1"));
+
+        assert!(body.starts_with("<!-- cargo-builder: cargo build - -release -->\n### ❌ Build failed: 1 error, 1 warning"));
+        // Errors come first, with HTML in the summary escaped
+        let error = body.find("<summary>error: expected `Vec&lt;u8&gt;`").unwrap();
+        assert!(error < body.find("<summary>warning: unused variable").unwrap());
+        assert!(body.contains("````text\nerror: expected `Vec<u8>`\n````"));
+        assert!(body.ends_with("[Workflow run](https://github.com/o/r/actions/runs/1)\n"));
+    }
+
+    #[test]
+    fn test_comment_body_limit() {
+        let long = "x".repeat(10_000);
+        let error = Diagnostic::new("error", &long).at("src/lib.rs", 1, 1).with_rendered(format!("\x1b[1merror\x1b[0m: {}\n", long));
+        let diagnostics = vec![error; 10];
+        let body = comment_body(&test_outcome(&diagnostics), None);
+        assert!(body.len() < BODY_LIMIT + 100);
+        assert!(body.contains("… and 8 more not shown."));
+    }
+}
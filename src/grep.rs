@@ -0,0 +1,185 @@
+use crate::{diagnostics, store, util, ColorChoice};
+use crate::diagnostics::Diagnostic;
+use crate::search::Matcher;
+use crate::store::DiagnosticQuery;
+use anyhow::Result;
+use clap::{Arg, ArgAction, ArgGroup, Command};
+use std::collections::BTreeMap;
+
+/// Criteria a diagnostic of a past run must meet; all given ones must match.
+/// The store answers `query`, the pattern is matched here.
+struct Filter {
+    matcher: Option<Matcher>,
+    query: DiagnosticQuery,
+}
+
+impl Filter {
+    fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        self.matcher.as_ref().is_none_or(|m| m.matches_diagnostic(diagnostic))
+            && self.query.matches(diagnostic)
+    }
+}
+
+pub fn run(args: &[String]) -> Result<i32> {
+    let matches = Command::new("cargo-builder grep")
+        .about("Search the diagnostics of all recorded builds")
+        .arg(
+            Arg::new("pattern")
+                .value_name("PATTERN")
+                .help("Regex matched against messages, error codes and file paths")
+        )
+        .arg(
+            Arg::new("code")
+                .long("code")
+                .value_name("CODE")
+                .help("Only diagnostics with this error code, e.g. E0277")
+        )
+        .arg(
+            Arg::new("file")
+                .long("file")
+                .value_name("PATH")
+                .help("Only diagnostics whose file path contains PATH")
+        )
+        .group(ArgGroup::new("filter").args(["pattern", "code", "file"]).required(true).multiple(true))
+        .arg(
+            Arg::new("ignore-case")
+                .short('i')
+                .long("ignore-case")
+                .action(ArgAction::SetTrue)
+                .help("Match PATTERN case-insensitively")
+        )
+        .arg(
+            Arg::new("level")
+                .long("level")
+                .value_name("LEVEL")
+                .help("Only show diagnostics of this level (error, warning, ...)")
+        )
+        .arg(
+            Arg::new("first")
+                .long("first")
+                .action(ArgAction::SetTrue)
+                .help("Only show the earliest run with a match")
+        )
+        .arg(
+            Arg::new("rendered")
+                .long("rendered")
+                .action(ArgAction::SetTrue)
+                .help("Print the full rendered diagnostics instead of one line each")
+        )
+        .arg(
+            Arg::new("terminal-color")
+                .long("terminal-color")
+                .value_name("CHOICE")
+                .value_parser(["auto", "never", "always"])
+                .default_value("auto")
+                .help("Color control for terminal output")
+        )
+        .try_get_matches_from(std::iter::once("cargo-builder grep".to_string()).chain(args.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+
+    let filter = Filter {
+        matcher: matches.get_one::<String>("pattern")
+            .map(|pattern| Matcher::new(pattern, false, matches.get_flag("ignore-case")))
+            .transpose()?,
+        query: DiagnosticQuery {
+            code: matches.get_one::<String>("code").cloned(),
+            level: matches.get_one::<String>("level").cloned(),
+            file: matches.get_one::<String>("file").cloned(),
+        },
+    };
+    let color: ColorChoice = matches.get_one::<String>("terminal-color").unwrap().parse()?;
+
+    let workspace = util::find_workspace()?;
+    let history = store::load_history(&workspace)?;
+    if history.is_empty() {
+        eprintln!("cargo-builder: No builds recorded yet; run a build first");
+        return Ok(1);
+    }
+
+    let mut by_run: BTreeMap<u64, Vec<Diagnostic>> = BTreeMap::new();
+    for (id, diagnostic) in store::query_diagnostics(&workspace, &filter.query)? {
+        if filter.matches(&diagnostic) {
+            by_run.entry(id).or_default().push(diagnostic);
+        }
+    }
+
+    let mut total = 0;
+    let mut matched_runs = Vec::new();
+    for run in &history {
+        let Some(found) = by_run.get(&run.id) else {
+            continue;
+        };
+
+        let started = humantime::format_rfc3339_seconds(run.started_at());
+        for diagnostic in found {
+            if matches.get_flag("rendered") {
+                eprintln!("#{} {}  {}", run.id, started, run.command);
+                eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &color));
+            } else {
+                eprintln!("#{:<5} {}  {}", run.id, started, diagnostic.summary());
+            }
+        }
+        total += found.len();
+        matched_runs.push(run);
+
+        if matches.get_flag("first") {
+            break;
+        }
+    }
+
+    match (matched_runs.first(), matched_runs.last()) {
+        (Some(first), Some(last)) => {
+            eprintln!(
+                "cargo-builder: {} matches in {} of {} runs; first seen in #{} ({}), last in #{} ({})",
+                total,
+                matched_runs.len(),
+                history.len(),
+                first.id,
+                humantime::format_rfc3339_seconds(first.started_at()),
+                last.id,
+                humantime::format_rfc3339_seconds(last.started_at()),
+            );
+            Ok(0)
+        }
+        _ => {
+            eprintln!("cargo-builder: No matches in {} runs", history.len());
+            Ok(1)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_filter_requires_every_criterion() {
+        let filter = Filter {
+            matcher: None,
+            query: DiagnosticQuery {
+                code: Some("E0277".to_string()),
+                level: None,
+                file: Some("src/net.rs".to_string()),
+            },
+        };
+        let trait_bound = Diagnostic::new("error", "the trait bound is not satisfied");
+        assert!(filter.matches(&trait_bound.clone().with_code("E0277").at("crates/io/src/net.rs", 1, 1)));
+        assert!(!filter.matches(&trait_bound.clone().with_code("E0277").at("src/main.rs", 1, 1)));
+        assert!(!filter.matches(&trait_bound.clone().with_code("E0308").at("src/net.rs", 1, 1)));
+        assert!(!filter.matches(&trait_bound.at("src/net.rs", 1, 1)));
+    }
+
+    #[test]
+    fn test_filter_pattern_and_level() {
+        let filter = Filter {
+            matcher: Some(Matcher::new("trait bound", false, false).unwrap()),
+            query: DiagnosticQuery {
+                code: None,
+                level: Some("warning".to_string()),
+                file: None,
+            },
+        };
+        let trait_bound = Diagnostic::new("error", "the trait bound is not satisfied").with_code("E0277");
+        assert!(!filter.matches(&trait_bound.at("src/net.rs", 1, 1)));
+    }
+}
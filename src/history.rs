@@ -0,0 +1,157 @@
+use crate::{diagnostics, store, util, ColorChoice};
+use crate::store::RunRecord;
+use anyhow::{Result, bail};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+
+pub fn run(args: &[String]) -> Result<i32> {
+    let matches = Command::new("cargo-builder history")
+        .about("List recent builds, or re-render the diagnostics of one")
+        .arg(
+            Arg::new("limit")
+                .short('n')
+                .long("limit")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("20")
+                .help("Number of runs to list")
+        )
+        .arg(
+            Arg::new("failed")
+                .long("failed")
+                .action(ArgAction::SetTrue)
+                .help("Only list failed runs")
+        )
+        .subcommand(
+            Command::new("show")
+                .about("Print the diagnostics of a recorded run")
+                .arg(
+                    Arg::new("id")
+                        .value_name("ID")
+                        .default_value("last")
+                        .help("Run number as listed by `history`, or `last`")
+                )
+                .arg(
+                    Arg::new("level")
+                        .long("level")
+                        .value_name("LEVEL")
+                        .help("Only show diagnostics of this level (error, warning, ...)")
+                )
+                .arg(
+                    Arg::new("terminal-color")
+                        .long("terminal-color")
+                        .value_name("CHOICE")
+                        .value_parser(["auto", "never", "always"])
+                        .default_value("auto")
+                        .help("Color control for terminal output")
+                )
+        )
+        .try_get_matches_from(std::iter::once("cargo-builder history".to_string()).chain(args.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+
+    let workspace = util::find_workspace()?;
+    let history = store::load_history(&workspace)?;
+    if history.is_empty() {
+        eprintln!("cargo-builder: No builds recorded yet; run a build first");
+        return Ok(1);
+    }
+
+    if let Some(("show", show)) = matches.subcommand() {
+        return show_run(&workspace, &history, show);
+    }
+
+    let limit = *matches.get_one::<usize>("limit").unwrap();
+    let listed = recent(&history, limit, matches.get_flag("failed"));
+    for run in &listed {
+        eprintln!("{}", run.format_row());
+    }
+    eprintln!("cargo-builder: {} of {} runs shown", listed.len(), history.len());
+    Ok(0)
+}
+
+/// Up to `limit` runs, newest first.
+fn recent(history: &[RunRecord], limit: usize, failed_only: bool) -> Vec<&RunRecord> {
+    history.iter()
+        .rev()
+        .filter(|run| !failed_only || !run.success)
+        .take(limit)
+        .collect()
+}
+
+/// The run `id` names: a run number, optionally prefixed with `#`, or `last`.
+pub fn find_run<'a>(history: &'a [RunRecord], id: &str) -> Result<&'a RunRecord> {
+    if id == "last" {
+        return Ok(history.last().expect("history is not empty"));
+    }
+    let Ok(number) = id.trim_start_matches('#').parse::<u64>() else {
+        bail!("Invalid run `{}`; expected a run number or `last`", id);
+    };
+    match history.iter().find(|run| run.id == number) {
+        Some(run) => Ok(run),
+        None => bail!("No run #{} in the history (it may have been dropped)", number),
+    }
+}
+
+fn show_run(workspace: &util::Workspace, history: &[RunRecord], matches: &ArgMatches) -> Result<i32> {
+    let run = find_run(history, matches.get_one::<String>("id").unwrap())?;
+    let level = matches.get_one::<String>("level");
+    let color: ColorChoice = matches.get_one::<String>("terminal-color").unwrap().parse()?;
+
+    eprintln!("{}", run.format_row());
+    if let Some(commit) = &run.commit {
+        eprintln!("Commit: {}{}", commit, if run.dirty { " with uncommitted changes" } else { "" });
+    }
+    let diagnostics = store::load_run_diagnostics(workspace, run.id)?;
+    let shown: Vec<_> = diagnostics.iter()
+        .filter(|d| level.is_none_or(|level| &d.level == level))
+        .collect();
+    for diagnostic in &shown {
+        eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &color));
+    }
+    eprintln!("cargo-builder: {} of {} diagnostics shown", shown.len(), diagnostics.len());
+    Ok(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn history() -> Vec<RunRecord> {
+        (1..=5)
+            .map(|id| RunRecord {
+                id,
+                timestamp: 1_700_000_000 + id,
+                command: "cargo build".to_string(),
+                profile: None,
+                duration_ms: 1000,
+                success: id % 2 == 0,
+                errors: 0,
+                warnings: 0,
+                fingerprints: Vec::new(),
+                branch: None,
+                commit: None,
+                dirty: false,
+                warnings_counted: false,
+                package_warnings: Default::default(),
+                binary_sizes: Default::default(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_recent_is_newest_first() {
+        let history = history();
+        let ids: Vec<u64> = recent(&history, 3, false).iter().map(|r| r.id).collect();
+        assert_eq!(ids, [5, 4, 3]);
+        let failed: Vec<u64> = recent(&history, 10, true).iter().map(|r| r.id).collect();
+        assert_eq!(failed, [5, 3, 1]);
+    }
+
+    #[test]
+    fn test_find_run() {
+        let history = history();
+        assert_eq!(find_run(&history, "last").unwrap().id, 5);
+        assert_eq!(find_run(&history, "#2").unwrap().id, 2);
+        assert!(find_run(&history, "9").unwrap_err().to_string().contains("No run #9"));
+        assert!(find_run(&history, "latest").is_err());
+    }
+}
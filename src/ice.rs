@@ -0,0 +1,201 @@
+use crate::context::BuildContext;
+use crate::diagnostics::{self, Diagnostic};
+use anyhow::{Context, Result};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Lines of rustc's output kept, enough for a backtrace with `RUST_BACKTRACE=full`
+const KEPT: usize = 1000;
+
+/// Where the compiler's bugs are reported
+const ISSUES: &str = "https://github.com/rust-lang/rust/issues/new?labels=C-bug%2C+I-ICE%2C+T-compiler&template=ice.md";
+
+/// An internal compiler error: rustc panicked, printed its backtrace, notes and query
+/// stack on its stderr, which cargo passes on, and cargo said which crate failed.
+#[derive(Debug, Default)]
+pub struct Ice {
+    /// What rustc printed, from its panic to the end of the query stack
+    output: Vec<String>,
+    /// What it panicked with, the line after `panicked at`
+    panic: Option<String>,
+    /// `rustc 1.80.0 (051478957 2024-07-21) running on x86_64-unknown-linux-gnu`
+    rustc: Option<String>,
+    /// The file rustc wrote the backtrace to, named in its notes
+    ice_file: Option<PathBuf>,
+    /// The crate being compiled, as cargo names it, e.g. `app` (bin "app")
+    target: Option<String>,
+    /// The rustc command cargo ran
+    command: Option<String>,
+}
+
+/// Picks the ICEs out of cargo's stderr.
+#[derive(Default)]
+pub struct Detector {
+    partial: String,
+    ices: Vec<Ice>,
+    capturing: bool,
+}
+
+impl Detector {
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.partial.push_str(&String::from_utf8_lossy(chunk));
+        while let Some(end) = self.partial.find('\n') {
+            let line: String = self.partial.drain(..=end).collect();
+            self.line(diagnostics::strip_ansi_codes(&line).trim_end());
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        // `thread 'rustc' (1234) panicked at compiler/...`, without the thread's id in older releases
+        if line.starts_with("thread 'rustc'") && line.contains(" panicked at ") {
+            self.ices.push(Ice::default());
+            self.capturing = true;
+        }
+        let Some(ice) = self.ices.last_mut() else { return };
+        if let Some(target) = line.strip_prefix("error: could not compile ") {
+            self.capturing = false;
+            if ice.target.is_none() {
+                ice.target = Some(target.split(" due to ").next().unwrap_or(target).to_string());
+            }
+            return;
+        }
+        if let Some(command) = line.trim_start().strip_prefix("process didn't exit successfully: `") {
+            if ice.command.is_none() {
+                ice.command = command.rsplit_once("` (").map(|(command, _)| command.to_string());
+            }
+            return;
+        }
+        if !self.capturing {
+            return;
+        }
+        if ice.output.len() == 1 {
+            ice.panic = Some(line.to_string());
+        }
+        if let Some(version) = line.strip_prefix("note: rustc ") {
+            ice.rustc = Some(format!("rustc {}", version));
+        }
+        if let Some(file) = line.strip_prefix("note: please attach the file at `").and_then(|rest| rest.split_once('`')) {
+            ice.ice_file = Some(PathBuf::from(file.0));
+        }
+        if ice.output.len() < KEPT {
+            ice.output.push(line.to_string());
+        }
+        if line == "end of query stack" {
+            self.capturing = false;
+        }
+    }
+
+    pub fn finish(self) -> Vec<Ice> {
+        self.ices
+    }
+}
+
+impl Ice {
+    /// Write the report for a bug report into `dir`, with what's known of the build.
+    pub fn write(&self, dir: &Path, context: &BuildContext, started_at: SystemTime) -> Result<PathBuf> {
+        fs::create_dir_all(dir).with_context(|| format!("Failed to create {}", dir.display()))?;
+        let stamp = humantime::format_rfc3339_seconds(started_at).to_string().replace(':', "");
+        let name = self.target.as_deref().and_then(|target| target.split('`').nth(1)).unwrap_or("rustc");
+        let mut path = dir.join(format!("ice-{}-{}.md", stamp, name));
+        let mut n = 1;
+        while path.exists() {
+            n += 1;
+            path = dir.join(format!("ice-{}-{}-{}.md", stamp, name, n));
+        }
+        fs::write(&path, self.report(context)).with_context(|| format!("Failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    fn report(&self, context: &BuildContext) -> String {
+        let unknown = || "unknown".to_string();
+        let mut report = format!("# Internal compiler error compiling {}\n\n", self.target.as_deref().unwrap_or("a crate"));
+        report.push_str(&format!("- Compiler: {}\n", self.rustc.clone().or_else(|| context.rustc_version.clone()).unwrap_or_else(unknown)));
+        report.push_str(&format!("- Cargo: {}\n", context.cargo_version.clone().unwrap_or_else(unknown)));
+        if let Some(toolchain) = &context.toolchain {
+            report.push_str(&format!("- Toolchain: {}\n", toolchain));
+        }
+        report.push_str(&format!("- Built: {}\n\n", context.timestamp));
+        report.push_str(&format!("## Reproduction\n\nThe build ran:\n\n```\n{}\n```\n\n", context.command));
+        if let Some(command) = &self.command {
+            report.push_str(&format!("cargo ran rustc as:\n\n```\n{}\n```\n\n", command));
+        }
+        report.push_str(&format!("## Compiler output\n\n```\n{}\n```\n", self.output.join("\n")));
+        if let Some((file, contents)) = self.ice_file.as_ref().and_then(|file| Some((file, fs::read_to_string(file).ok()?))) {
+            report.push_str(&format!("\n## rustc's own report, {}\n\n```\n{}\n```\n", file.display(), contents.trim_end()));
+        }
+        report
+    }
+
+    /// The error shown and logged in place of the ICE, pointing to its report.
+    pub fn diagnostic(&self, report: &Path) -> Diagnostic {
+        let message = format!(
+            "the compiler crashed compiling {}{}",
+            self.target.as_deref().unwrap_or("a crate"),
+            self.panic.as_deref().map(|panic| format!(": {}", panic)).unwrap_or_default(),
+        );
+        Diagnostic {
+            rendered: format!(
+                "error: {}\n  = note: an internal compiler error; its backtrace, the compiler's version and how to reproduce it are in {}\n  = note: report it at {}\n\n",
+                message, report.display(), ISSUES,
+            ),
+            package_id: self.target.as_deref().and_then(|target| target.split('`').nth(1)).map(str::to_string),
+            ..Diagnostic::new("error", message)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STDERR: &str = "   Compiling app v0.1.0 (/work/app)
+
+thread 'rustc' (32274) panicked at compiler/rustc_errors/src/lib.rs:1531:17:
+aborting due to `-Z treat-err-as-bug=1`
+stack backtrace:
+   0: __rustc::rust_begin_unwind
+note: Some details are omitted, run with `RUST_BACKTRACE=full` for a verbose backtrace.
+
+error: the compiler unexpectedly panicked. This is a bug
+
+note: please attach the file at `/nonexistent/rustc-ice-2024-07-21T10_00_00-1.txt` to your bug report
+
+note: rustc 1.95.0 (59807616e 2026-04-14) running on x86_64-unknown-linux-gnu
+
+query stack during panic:
+#0 [typeck] type-checking `main`
+end of query stack
+error: could not compile `app` (bin \"app\")
+
+Caused by:
+  process didn't exit successfully: `rustc --crate-name app --edition=2024 src/main.rs -Ztreat-err-as-bug=1` (exit status: 101)
+";
+
+    #[test]
+    fn test_detector() {
+        let mut detector = Detector::default();
+        for chunk in STDERR.as_bytes().chunks(100) {
+            detector.feed(chunk);
+        }
+        let ices = detector.finish();
+        assert_eq!(ices.len(), 1);
+        let ice = &ices[0];
+        assert_eq!(ice.panic.as_deref(), Some("aborting due to `-Z treat-err-as-bug=1`"));
+        assert_eq!(ice.rustc.as_deref(), Some("rustc 1.95.0 (59807616e 2026-04-14) running on x86_64-unknown-linux-gnu"));
+        assert_eq!(ice.ice_file.as_deref(), Some(Path::new("/nonexistent/rustc-ice-2024-07-21T10_00_00-1.txt")));
+        assert_eq!(ice.target.as_deref(), Some("`app` (bin \"app\")"));
+        assert_eq!(ice.command.as_deref(), Some("rustc --crate-name app --edition=2024 src/main.rs -Ztreat-err-as-bug=1"));
+        assert_eq!(ice.output.first().map(String::as_str), Some("thread 'rustc' (32274) panicked at compiler/rustc_errors/src/lib.rs:1531:17:"));
+        assert_eq!(ice.output.last().map(String::as_str), Some("end of query stack"));
+
+        let diagnostic = ice.diagnostic(Path::new("target/cargo-builder/ice/ice.md"));
+        assert_eq!(diagnostic.message, "the compiler crashed compiling `app` (bin \"app\"): aborting due to `-Z treat-err-as-bug=1`");
+        assert_eq!(diagnostic.package_name(), Some("app"));
+
+        // A build script's panic, which cargo indents, is not the compiler's
+        let mut detector = Detector::default();
+        detector.feed(b"  --- stderr\n  thread 'main' panicked at build.rs:1:13:\n  thread 'rustc' panicked at nothing\nerror: could not compile `app`\n");
+        assert!(detector.finish().is_empty());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::diagnostics::{self, Diagnostic};
+use crate::logging::LogSink;
+use anyhow::Result;
+
+const SYSLOG_IDENTIFIER: &str = "cargo-builder";
+
+/// Encode journal fields in the native protocol. Values containing newlines use
+/// the binary form: name, newline, little-endian u64 length, value, newline.
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn encode_fields(fields: &[(&str, String)]) -> Vec<u8> {
+    let mut buf = Vec::new();
+    for (name, value) in fields {
+        buf.extend_from_slice(name.as_bytes());
+        if value.contains('\n') {
+            buf.push(b'\n');
+            buf.extend_from_slice(&(value.len() as u64).to_le_bytes());
+            buf.extend_from_slice(value.as_bytes());
+        } else {
+            buf.push(b'=');
+            buf.extend_from_slice(value.as_bytes());
+        }
+        buf.push(b'\n');
+    }
+    buf
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn priority(level: &str) -> u8 {
+    match level {
+        "error" => 3,
+        "warning" => 4,
+        _ => 5,
+    }
+}
+
+#[cfg_attr(not(target_os = "linux"), allow(dead_code))]
+fn fields(diagnostic: &Diagnostic) -> Vec<(&'static str, String)> {
+    let mut fields = vec![
+        ("MESSAGE", diagnostic.summary()),
+        ("PRIORITY", priority(&diagnostic.level).to_string()),
+        ("SYSLOG_IDENTIFIER", SYSLOG_IDENTIFIER.to_string()),
+        ("RENDERED", diagnostics::strip_ansi_codes(&diagnostic.rendered)),
+    ];
+    if let Some(code) = &diagnostic.code {
+        fields.push(("ERROR_CODE", code.clone()));
+    }
+    if let Some(file) = &diagnostic.file {
+        fields.push(("CODE_FILE", file.clone()));
+    }
+    if let Some(line) = diagnostic.line {
+        fields.push(("CODE_LINE", line.to_string()));
+    }
+    if let Some(package_id) = &diagnostic.package_id {
+        fields.push(("PACKAGE_ID", package_id.clone()));
+    }
+    fields
+}
+
+/// Sends logged diagnostics to systemd-journald as structured entries, so they
+/// show up under `journalctl -t cargo-builder`.
+#[cfg(target_os = "linux")]
+pub struct JournaldSink {
+    socket: std::os::unix::net::UnixDatagram,
+}
+
+#[cfg(target_os = "linux")]
+impl JournaldSink {
+    pub fn connect() -> Result<Self> {
+        Self::connect_to(std::path::Path::new("/run/systemd/journal/socket"))
+    }
+
+    fn connect_to(path: &std::path::Path) -> Result<Self> {
+        use anyhow::Context;
+
+        let socket = std::os::unix::net::UnixDatagram::unbound()?;
+        socket.connect(path)
+            .with_context(|| format!("Failed to connect to journald at {}", path.display()))?;
+        Ok(JournaldSink { socket })
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+pub struct JournaldSink;
+
+#[cfg(not(target_os = "linux"))]
+impl JournaldSink {
+    pub fn connect() -> Result<Self> {
+        anyhow::bail!("The journald sink is only available on Linux")
+    }
+}
+
+impl LogSink for JournaldSink {
+    #[cfg(target_os = "linux")]
+    fn log_diagnostic(&mut self, diagnostic: &Diagnostic) -> Result<()> {
+        use anyhow::Context;
+
+        self.socket.send(&encode_fields(&fields(diagnostic)))
+            .context("Failed to send entry to journald")?;
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn log_diagnostic(&mut self, _diagnostic: &Diagnostic) -> Result<()> {
+        Ok(())
+    }
+
+    fn finalize(self: Box<Self>, _build_success: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encode_fields() {
+        let encoded = encode_fields(&[
+            ("MESSAGE", "hello".to_string()),
+            ("RENDERED", "a\nb".to_string()),
+        ]);
+        let mut expected = b"MESSAGE=hello\nRENDERED\n".to_vec();
+        expected.extend_from_slice(&3u64.to_le_bytes());
+        expected.extend_from_slice(b"a\nb\n");
+        assert_eq!(encoded, expected);
+    }
+
+    #[test]
+    fn test_fields_use_primary_span() {
+        let fields = fields(&Diagnostic::new("warning", "unused variable: `x`").at("src/main.rs", 2, 9));
+        assert!(fields.contains(&("PRIORITY", "4".to_string())));
+        assert!(fields.contains(&("CODE_FILE", "src/main.rs".to_string())));
+        assert!(fields.contains(&("CODE_LINE", "2".to_string())));
+        assert!(fields.contains(&("SYSLOG_IDENTIFIER", "cargo-builder".to_string())));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_sends_to_socket() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let path = temp_dir.path().join("journal.sock");
+        let server = std::os::unix::net::UnixDatagram::bind(&path).unwrap();
+
+        let mut sink = JournaldSink::connect_to(&path).unwrap();
+        sink.log_diagnostic(&Diagnostic::new("warning", "unused variable: `x`").at("src/main.rs", 2, 9)).unwrap();
+
+        let mut buf = [0u8; 4096];
+        let len = server.recv(&mut buf).unwrap();
+        assert!(buf[..len].starts_with(b"MESSAGE=warning: unused variable: `x` (src/main.rs:2:9)\n"));
+    }
+}
@@ -0,0 +1,113 @@
+use crate::diagnostics::{self, Diagnostic};
+use regex::Regex;
+
+/// Symbols and libraries named in each line
+const LISTED: usize = 10;
+
+lazy_static::lazy_static! {
+    /// Undefined symbols, as lld and mold, GNU ld, ld64 (under "Undefined symbols for
+    /// architecture") and link.exe report them
+    static ref SYMBOL: Regex = Regex::new(concat!(
+        r"undefined symbol: (.+)$",
+        r"|undefined reference to [`'‘](.+?)['’]",
+        r#"|^\s*"(.+)", referenced from:"#,
+        r"|unresolved external symbol (\S+)",
+    )).unwrap();
+    /// Libraries passed with `-l` the linker didn't find
+    static ref LIBRARY: Regex = Regex::new(concat!(
+        r"unable to find library -l(\S+)",
+        r"|cannot find -l([^:\s]+)",
+        r"|library not found for -l(\S+)",
+        r"|library '([^']+)' not found",
+        r"|cannot open input file '([^']+)\.lib'",
+    )).unwrap();
+}
+
+/// A short form of a failed link: what the linker didn't find, in place of the command
+/// line and everything it printed, which stay in the log. `None` for other errors, and
+/// for linker failures about something else, which are shown as rustc rendered them.
+pub fn simplify(diagnostic: &Diagnostic) -> Option<String> {
+    if !(diagnostic.message.starts_with("linking with `") && diagnostic.message.contains(" failed")) {
+        return None;
+    }
+    let rendered = diagnostics::strip_ansi_codes(&diagnostic.rendered);
+    let mut symbols: Vec<&str> = Vec::new();
+    let mut libraries: Vec<&str> = Vec::new();
+    for line in rendered.lines() {
+        // The linker's output follows rustc's "= note: ", indented to line up with it
+        let line = line.trim_start().trim_start_matches("= note: ");
+        if let Some(found) = SYMBOL.captures(line).and_then(|c| c.iter().skip(1).flatten().next()) {
+            if !symbols.contains(&found.as_str()) {
+                symbols.push(found.as_str());
+            }
+        }
+        if let Some(found) = missing_library(line) {
+            if !libraries.contains(&found) {
+                libraries.push(found);
+            }
+        }
+    }
+    if symbols.is_empty() && libraries.is_empty() {
+        return None;
+    }
+    let mut short = format!("{}\n", diagnostic.rendered.lines().next().unwrap_or_default());
+    if !symbols.is_empty() {
+        short.push_str(&format!("  = note: undefined {}: {}\n", if symbols.len() == 1 { "symbol" } else { "symbols" }, list(&symbols)));
+    }
+    if !libraries.is_empty() {
+        short.push_str(&format!("  = note: {} not found: {}\n", if libraries.len() == 1 { "library" } else { "libraries" }, list(&libraries)));
+    }
+    short.push_str("  = note: the linker command and its full output are in the log\n\n");
+    Some(short)
+}
+
+/// The library of `-l` a line of the linker's output says it didn't find.
+pub fn missing_library(line: &str) -> Option<&str> {
+    LIBRARY.captures(line)?.iter().skip(1).flatten().next().map(|found| found.as_str())
+}
+
+fn list(names: &[&str]) -> String {
+    let shown: Vec<String> = names.iter().take(LISTED).map(|name| format!("`{}`", name)).collect();
+    match names.len().saturating_sub(LISTED) {
+        0 => shown.join(", "),
+        more => format!("{} and {} more", shown.join(", "), more),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link_error(output: &str) -> Diagnostic {
+        Diagnostic {
+            package_id: Some("path+file:///work/app#0.1.0".to_string()),
+            target_kind: vec!["bin".to_string()],
+            rendered: format!(
+                "\x1b[0m\x1b[1m\x1b[38;5;9merror\x1b[0m: linking with `cc` failed: exit status: 1\n  |\n  = note:  \"cc\" \"-m64\" \"-lfoo\"\n  = note: some arguments are omitted. use `--verbose` to show all linker arguments\n  = note: {}\n\n",
+                output.replace('\n', "\n          "),
+            ),
+            ..Diagnostic::new("error", "linking with `cc` failed: exit status: 1")
+        }
+    }
+
+    #[test]
+    fn test_simplify() {
+        let lld = link_error("rust-lld: error: undefined symbol: missing_one\n>>> referenced by main.rs:3 (src/main.rs:3)\n\nrust-lld: error: undefined symbol: missing_two\n>>> referenced by main.rs:3 (src/main.rs:3)\nrust-lld: error: unable to find library -lssl\ncollect2: error: ld returned 1 exit status");
+        assert_eq!(simplify(&lld).unwrap(), "\x1b[0m\x1b[1m\x1b[38;5;9merror\x1b[0m: linking with `cc` failed: exit status: 1\n  = note: undefined symbols: `missing_one`, `missing_two`\n  = note: library not found: `ssl`\n  = note: the linker command and its full output are in the log\n\n");
+
+        let gnu = link_error("/usr/bin/ld: main.o: in function `app::main':\nmain.rs:3: undefined reference to `missing_one'\nmain.rs:4: undefined reference to `missing_one'\n/usr/bin/ld: cannot find -lasound: No such file or directory");
+        assert!(simplify(&gnu).unwrap().contains("undefined symbol: `missing_one`\n  = note: library not found: `asound`\n"));
+
+        let ld64 = link_error("Undefined symbols for architecture arm64:\n  \"_missing_one\", referenced from:\n      app::main in app.o\nld: library not found for -lz");
+        assert!(simplify(&ld64).unwrap().contains("undefined symbol: `_missing_one`\n  = note: library not found: `z`\n"));
+
+        let msvc = link_error("app.o : error LNK2019: unresolved external symbol missing_one referenced in function main\nLINK : fatal error LNK1181: cannot open input file 'ssl.lib'");
+        assert!(simplify(&msvc).unwrap().contains("undefined symbol: `missing_one`\n  = note: library not found: `ssl`\n"));
+
+        // Linker failures about something else, and other errors, stay as they are
+        assert!(simplify(&link_error("/usr/bin/ld: final link failed: No space left on device")).is_none());
+        let mut other = link_error("undefined symbol: missing_one");
+        other.message = "cannot find value `x` in this scope".to_string();
+        assert!(simplify(&other).is_none());
+    }
+}
@@ -0,0 +1,46 @@
+/// CPUs `--auto-jobs` keeps free for whatever else runs on the machine.
+const RESERVED_CPUS: usize = 1;
+
+/// The 1-minute load average: runnable processes, averaged.
+#[cfg(unix)]
+pub fn load_average() -> Option<f64> {
+    let mut loads = [0.0; 3];
+    // SAFETY: the pointer and count describe `loads`
+    let samples = unsafe { libc::getloadavg(loads.as_mut_ptr(), 1) };
+    (samples >= 1).then_some(loads[0])
+}
+
+/// Windows has no load average, so only the CPU count is taken into account.
+#[cfg(not(unix))]
+pub fn load_average() -> Option<f64> {
+    None
+}
+
+/// Jobs for cargo that fit into the CPUs the current load leaves idle, keeping
+/// `RESERVED_CPUS` free. Always at least one.
+pub fn auto_jobs(cpus: usize, load: Option<f64>) -> usize {
+    let idle = (cpus as f64 - load.unwrap_or(0.0)).floor().max(0.0) as usize;
+    idle.min(cpus.saturating_sub(RESERVED_CPUS)).max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_auto_jobs() {
+        // An idle machine
+        assert_eq!(auto_jobs(8, Some(0.3)), 7);
+        assert_eq!(auto_jobs(8, None), 7);
+        // A busy one
+        assert_eq!(auto_jobs(8, Some(5.5)), 2);
+        assert_eq!(auto_jobs(8, Some(20.0)), 1);
+        assert_eq!(auto_jobs(1, Some(0.0)), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_load_average() {
+        assert!(load_average().is_some_and(|load| load >= 0.0));
+    }
+}
@@ -0,0 +1,204 @@
+use crate::diagnostics::strip_ansi_codes;
+use crate::util;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+const BLOCKING: &str = "Blocking waiting for file lock on ";
+
+/// What cargo blocks on while another process holds one of its file locks.
+pub struct Wait {
+    /// e.g. `build directory` or `package cache`
+    pub what: String,
+    pub since: Instant,
+    /// The process holding the lock, when it could be found
+    pub holder: Option<String>,
+    /// Whole seconds reported last, so a status line is only redrawn when they change
+    shown_secs: Option<u64>,
+}
+
+impl Wait {
+    /// `cargo` is the blocked cargo process, which holds other locks of its own.
+    pub fn new(what: String, target_dir: &Path, cargo: u32, now: Instant) -> Self {
+        let holder = holder(&what, target_dir, cargo);
+        Wait { what, since: now, holder, shown_secs: None }
+    }
+
+    pub fn elapsed(&self, now: Instant) -> Duration {
+        now.saturating_duration_since(self.since)
+    }
+
+    /// e.g. `Waiting for the file lock on build directory, held by pid 4242: cargo check`
+    pub fn describe(&self, now: Instant) -> String {
+        let mut text = format!("Waiting for the file lock on {}", self.what);
+        let elapsed = self.elapsed(now);
+        if elapsed >= Duration::from_secs(1) {
+            text.push_str(&format!(" for {}", util::format_duration(elapsed)));
+        }
+        if let Some(holder) = &self.holder {
+            text.push_str(&format!(", held by {}", holder));
+        }
+        text
+    }
+
+    /// Whether the wait is due to be reported: once, or every second in a live status line.
+    pub fn report_due(&mut self, now: Instant, live: bool) -> bool {
+        let secs = self.elapsed(now).as_secs();
+        let due = match self.shown_secs {
+            None => true,
+            Some(shown) => live && shown != secs,
+        };
+        self.shown_secs = Some(secs);
+        due
+    }
+}
+
+/// Puts cargo's stderr back together into lines, as cargo writes the status
+/// (`    Blocking`) and the rest of a line separately.
+#[derive(Default)]
+pub struct Lines {
+    partial: String,
+}
+
+impl Lines {
+    /// What cargo started waiting for, if a line `chunk` completes says it blocks on a lock.
+    pub fn blocked_on(&mut self, chunk: &[u8]) -> Option<String> {
+        self.partial.push_str(&String::from_utf8_lossy(chunk));
+        let mut blocked = None;
+        // Progress bars end in `\r`
+        while let Some(end) = self.partial.find(['\n', '\r']) {
+            let line: String = self.partial.drain(..=end).collect();
+            blocked = blocked_on(&line).or(blocked);
+        }
+        blocked
+    }
+}
+
+fn blocked_on(line: &str) -> Option<String> {
+    let line = strip_ansi_codes(line);
+    let what = line[line.find(BLOCKING)? + BLOCKING.len()..].trim();
+    Some(if what.is_empty() { "build directory".to_string() } else { what.to_string() })
+}
+
+/// The process holding the lock on `what`: the package cache, or else a build or
+/// artifact directory in the target directory.
+#[cfg(target_os = "linux")]
+fn holder(what: &str, target_dir: &Path, cargo: u32) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let mut files = Vec::new();
+    if what.contains("package cache") {
+        if let Some(cargo_home) = util::cargo_home() {
+            files.push(cargo_home.join(".package-cache"));
+            files.push(cargo_home.join(".package-cache-mutate"));
+        }
+    } else {
+        // In `<target>/<profile>` or `<target>/<triple>/<profile>`
+        for dir in subdirs(target_dir).into_iter().flat_map(|dir| std::iter::once(dir.clone()).chain(subdirs(&dir))) {
+            files.push(dir.join(".cargo-lock"));
+            files.push(dir.join(".cargo-artifact-lock"));
+        }
+    }
+    let inodes: Vec<u64> = files.iter().filter_map(|file| std::fs::metadata(file).ok()).map(|meta| meta.ino()).collect();
+
+    let locks = std::fs::read_to_string("/proc/locks").ok()?;
+    let pid = parse_locks(&locks).into_iter().find(|(pid, inode)| *pid != cargo && inodes.contains(inode))?.0;
+    let cmdline = std::fs::read(format!("/proc/{}/cmdline", pid)).unwrap_or_default();
+    let mut args = cmdline.split(|&b| b == 0).filter(|arg| !arg.is_empty()).map(String::from_utf8_lossy);
+    let mut command = args.next().map(|program| program.rsplit('/').next().unwrap_or_default().to_string()).unwrap_or_default();
+    for arg in args {
+        command.push(' ');
+        command.push_str(&arg);
+    }
+    Some(if command.is_empty() { format!("pid {}", pid) } else { format!("pid {}: {}", pid, truncate(&command, 80)) })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn holder(_what: &str, _target_dir: &Path, _cargo: u32) -> Option<String> {
+    None
+}
+
+#[cfg(target_os = "linux")]
+fn subdirs(dir: &Path) -> Vec<std::path::PathBuf> {
+    std::fs::read_dir(dir)
+        .map(|entries| entries.flatten().map(|entry| entry.path()).filter(|path| path.is_dir()).collect())
+        .unwrap_or_default()
+}
+
+/// Holders and inodes of the granted locks in `/proc/locks`, e.g.
+/// `1: FLOCK  ADVISORY  WRITE 4242 00:2a:1234567 0 EOF`. Waiters are marked with `->`.
+#[cfg(target_os = "linux")]
+fn parse_locks(locks: &str) -> Vec<(u32, u64)> {
+    locks.lines().filter_map(|line| {
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        if fields.get(1) == Some(&"->") {
+            return None;
+        }
+        let pid = fields.get(4)?.parse().ok()?;
+        let inode = fields.get(5)?.rsplit(':').next()?.parse().ok()?;
+        Some((pid, inode))
+    }).collect()
+}
+
+#[cfg(target_os = "linux")]
+fn truncate(text: &str, max: usize) -> String {
+    match text.char_indices().nth(max) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_blocked_on() {
+        let mut lines = Lines::default();
+        assert_eq!(lines.blocked_on(b"    Blocking waiting for file lock on build directory\n").as_deref(), Some("build directory"));
+        // As cargo writes it
+        assert_eq!(lines.blocked_on(b"\x1b[1m\x1b[36m    Blocking\x1b[0m"), None);
+        assert_eq!(lines.blocked_on(b" waiting for file lock on package cache\n").as_deref(), Some("package cache"));
+        assert_eq!(lines.blocked_on(b"   Compiling serde v1.0.0\n"), None);
+        assert_eq!(lines.blocked_on(b"    Building [===>  ] 3/10\r"), None);
+    }
+
+    #[test]
+    fn test_describe() {
+        let start = Instant::now();
+        let mut wait = Wait { what: "build directory".to_string(), since: start, holder: None, shown_secs: None };
+        assert_eq!(wait.describe(start), "Waiting for the file lock on build directory");
+        wait.holder = Some("pid 4242: cargo check".to_string());
+        assert_eq!(
+            wait.describe(start + Duration::from_secs(12)),
+            "Waiting for the file lock on build directory for 12.0s, held by pid 4242: cargo check",
+        );
+        assert!(wait.report_due(start, true));
+        assert!(!wait.report_due(start + Duration::from_millis(400), true));
+        assert!(wait.report_due(start + Duration::from_secs(1), true));
+        // Without a status line, only once
+        assert!(!wait.report_due(start + Duration::from_secs(2), false));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_locks() {
+        let locks = "1: FLOCK  ADVISORY  WRITE 4242 00:2a:1234567 0 EOF\n1: -> FLOCK  ADVISORY  WRITE 4300 00:2a:1234567 0 EOF\n2: POSIX  ADVISORY  READ 99 fd:01:77 0 EOF\n";
+        assert_eq!(parse_locks(locks), [(4242, 1234567), (99, 77)]);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_holder() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let profile = dir.path().join("debug");
+        std::fs::create_dir(&profile).unwrap();
+        let file = std::fs::File::create(profile.join(".cargo-lock")).unwrap();
+        // SAFETY: the descriptor stays open for the whole call
+        assert_eq!(unsafe { libc::flock(std::os::unix::io::AsRawFd::as_raw_fd(&file), libc::LOCK_EX) }, 0);
+
+        let holder = holder("build directory", dir.path(), 0).unwrap();
+        assert!(holder.starts_with(&format!("pid {}: ", std::process::id())), "{}", holder);
+        // The blocked cargo's own locks don't count
+        assert_eq!(super::holder("build directory", dir.path(), std::process::id()), None);
+    }
+}
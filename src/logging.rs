@@ -1,98 +1,785 @@
-use crate::{Config, diagnostics};
-use anyhow::{Result, Context};
+use crate::{Config, context, diagnostics};
+use crate::diagnostics::Diagnostic;
+use crate::redact::Redactor;
+use anyhow::{Result, Context, bail};
+use flate2::Compression;
+use flate2::write::GzEncoder;
+use std::collections::VecDeque;
+use std::collections::btree_map::{BTreeMap, Entry};
 use std::fs::{File, OpenOptions};
 use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    /// Rendered diagnostics for humans
+    Text,
+    /// One JSON diagnostic per line for machines
+    JsonLines,
+}
+
+/// One `--log` destination, written as `[text:|jsonl:]PATH`. Without a prefix
+/// the format follows the extension: `.jsonl`/`.json` (optionally `.gz`) is JSON lines.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogTarget {
+    pub format: LogFormat,
+    pub path: PathBuf,
+}
+
+impl LogTarget {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let (format, path) = if let Some(path) = spec.strip_prefix("text:") {
+            (LogFormat::Text, path)
+        } else if let Some(path) = spec.strip_prefix("jsonl:") {
+            (LogFormat::JsonLines, path)
+        } else {
+            let inner = spec.strip_suffix(".gz").unwrap_or(spec);
+            let format = if inner.ends_with(".jsonl") || inner.ends_with(".json") {
+                LogFormat::JsonLines
+            } else {
+                LogFormat::Text
+            };
+            (format, spec)
+        };
+
+        if path.is_empty() {
+            bail!("Empty log path in --log {}", spec);
+        }
+        Ok(LogTarget { format, path: PathBuf::from(path) })
+    }
+}
+
+/// A destination for logged diagnostics.
+pub trait LogSink {
+    fn log_diagnostic(&mut self, diagnostic: &Diagnostic) -> Result<()>;
+
+    /// Flush and close the sink; file sinks remove their output after a
+    /// successful build unless configured to keep it.
+    fn finalize(self: Box<Self>, build_success: bool) -> Result<()>;
+
+    /// Main output location, if the sink writes to a file.
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    /// Additional files written by the sink, e.g. per-target logs.
+    fn extra_paths(&self) -> Vec<&Path> {
+        Vec::new()
+    }
+}
+
+/// Fans each logged diagnostic out to every configured sink.
 pub struct Logger {
+    sinks: Vec<Box<dyn LogSink>>,
+    /// Sinks sending to other services, like syslog, which can't fail the build
+    remote: Vec<Box<dyn LogSink>>,
+    redactor: Option<Redactor>,
+    // Released when the logger is dropped, after every sink is finalized
+    _locks: Vec<LogLock>,
+}
+
+impl Logger {
+    pub fn new(targets: &[LogTarget], config: &Config) -> Result<Self> {
+        let mut locks = Vec::new();
+        let mut locked = Vec::new();
+        for target in targets {
+            if !locked.contains(&&target.path) {
+                locks.push(LogLock::acquire(&target.path, config.wait, config.quiet)?);
+                locked.push(&target.path);
+            }
+        }
+
+        let mut sinks: Vec<Box<dyn LogSink>> = Vec::new();
+        for target in targets {
+            let path = target.path.display().to_string();
+            match target.format {
+                LogFormat::Text => sinks.push(Box::new(TextLog::new(&path, config)?)),
+                LogFormat::JsonLines => sinks.push(Box::new(JsonLinesLog::new(&path, config)?)),
+            }
+        }
+        let redactor = Redactor::from_config(config)?;
+        Ok(Logger { sinks, remote: Vec::new(), redactor, _locks: locks })
+    }
+
+    /// Add a sink sending to another service. When it fails, from connecting on, that's
+    /// a warning and the sink is turned off; only the log files can fail the build.
+    pub fn add_remote_sink<S: LogSink + 'static>(&mut self, sink: Result<S>) {
+        match sink {
+            Ok(sink) => self.remote.push(Box::new(sink)),
+            Err(e) => warn_remote(&e),
+        }
+    }
+
+    pub fn log_diagnostic(&mut self, diagnostic: &Diagnostic) -> Result<()> {
+        let redacted = self.redactor.as_ref().map(|r| r.redact_diagnostic(diagnostic));
+        let diagnostic = redacted.as_ref().unwrap_or(diagnostic);
+        for sink in &mut self.sinks {
+            sink.log_diagnostic(diagnostic)?;
+        }
+        self.remote.retain_mut(|sink| sink.log_diagnostic(diagnostic).map_err(|e| warn_remote(&e)).is_ok());
+        Ok(())
+    }
+
+    /// What `--redact` and friends hide, for what's sent elsewhere after the logs.
+    pub fn redactor(&self) -> Option<&Redactor> {
+        self.redactor.as_ref()
+    }
+
+    pub fn paths(&self) -> Vec<&Path> {
+        self.sinks.iter().filter_map(|sink| sink.path()).collect()
+    }
+
+    pub fn extra_paths(&self) -> Vec<&Path> {
+        self.sinks.iter().flat_map(|sink| sink.extra_paths()).collect()
+    }
+
+    pub fn finalize(self, build_success: bool) -> Result<()> {
+        for sink in self.sinks {
+            sink.finalize(build_success)?;
+        }
+        for sink in self.remote {
+            if let Err(e) = sink.finalize(build_success) {
+                warn_remote(&e);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn warn_remote(e: &anyhow::Error) {
+    eprintln!("cargo-builder: Warning: {:#}; not logging there for the rest of the build", e);
+}
+
+/// Advisory lock on `<log>.lock`, held for the whole run so a concurrent build
+/// cannot rotate or truncate a log that is still being written. The file is removed
+/// with the lock, so per-run logs don't leave one behind each.
+pub struct LogLock {
+    _file: File,
+    path: PathBuf,
+}
+
+impl LogLock {
+    pub fn acquire(log_path: &Path, wait: bool, quiet: bool) -> Result<Self> {
+        let path = lock_path(log_path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .with_context(|| format!("Failed to create log directory: {}", parent.display()))?;
+        }
+        loop {
+            let file = OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .write(true)
+                .open(&path)
+                .with_context(|| format!("Failed to create lock file: {}", path.display()))?;
+
+            match file.try_lock() {
+                Ok(()) => {}
+                Err(std::fs::TryLockError::WouldBlock) if wait => {
+                    if !quiet {
+                        eprintln!("cargo-builder: Waiting for another build writing {}...", log_path.display());
+                    }
+                    file.lock()
+                        .with_context(|| format!("Failed to lock {}", path.display()))?;
+                }
+                Err(std::fs::TryLockError::WouldBlock) => bail!(
+                    "Another build is writing this log: {} (pass --wait to wait for it)",
+                    log_path.display()
+                ),
+                Err(std::fs::TryLockError::Error(e)) => {
+                    return Err(e).with_context(|| format!("Failed to lock {}", path.display()));
+                }
+            }
+
+            // The build we waited for removed the file as it let go, and a lock on a
+            // removed file keeps no one out: start over on whatever is there now
+            if is_same_file(&file, &path) {
+                return Ok(LogLock { _file: file, path });
+            }
+        }
+    }
+}
+
+impl Drop for LogLock {
+    fn drop(&mut self) {
+        // Removed while still locked, so no build can lock it in between
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Whether `file` is still the file at `path`, rather than one removed since it was
+/// opened (and possibly replaced by another build's lock file).
+#[cfg(unix)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    match (file.metadata(), std::fs::metadata(path)) {
+        (Ok(locked), Ok(current)) => locked.dev() == current.dev() && locked.ino() == current.ino(),
+        _ => false,
+    }
+}
+
+#[cfg(windows)]
+fn is_same_file(file: &File, path: &Path) -> bool {
+    use std::os::windows::io::AsRawHandle;
+    use windows_sys::Win32::Storage::FileSystem::{GetFileInformationByHandle, BY_HANDLE_FILE_INFORMATION};
+
+    fn identity(file: &File) -> Option<(u32, u32, u32)> {
+        let mut info: BY_HANDLE_FILE_INFORMATION = unsafe { std::mem::zeroed() };
+        if unsafe { GetFileInformationByHandle(file.as_raw_handle(), &mut info) } == 0 {
+            return None;
+        }
+        Some((info.dwVolumeSerialNumber, info.nFileIndexHigh, info.nFileIndexLow))
+    }
+
+    match (identity(file), File::open(path).ok().as_ref().and_then(identity)) {
+        (Some(locked), Some(current)) => locked == current,
+        _ => false,
+    }
+}
+
+fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".lock");
+    PathBuf::from(name)
+}
+
+/// Destination of the log; paths ending in `.gz` are written through a gzip encoder.
+enum LogWriter {
+    Plain(File),
+    Gzip(GzEncoder<File>),
+}
+
+impl LogWriter {
+    /// Open `path` for a new run, rotating previous logs unless appending.
+    /// Returns the writer and whether the file started out empty.
+    fn open(path: &Path, config: &Config) -> Result<(Self, bool)> {
+        if let Some(parent) = path.parent() {
+            if !parent.exists() {
+                std::fs::create_dir_all(parent)
+                    .with_context(|| format!("Failed to create log directory: {}", parent.display()))?;
+            }
+        }
+
+        let append = config.log_append;
+        if !append {
+            rotate(path, config.log_keep)?;
+        }
+        let is_new = !append || std::fs::metadata(path).map_or(true, |m| m.len() == 0);
+
+        let file = OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(append)
+            .truncate(!append) // Overwrite existing file unless appending
+            .open(path)
+            .with_context(|| format!("Failed to create log file: {}", path.display()))?;
+
+        // Appending to a gzip file adds a new gzip member, which decompressors concatenate
+        let writer = if is_gzip_path(path) {
+            LogWriter::Gzip(GzEncoder::new(file, Compression::default()))
+        } else {
+            LogWriter::Plain(file)
+        };
+        Ok((writer, is_new))
+    }
+
+    fn finish(self) -> Result<()> {
+        if let LogWriter::Gzip(encoder) = self {
+            encoder.finish()?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        match self {
+            LogWriter::Plain(file) => file.write(buf),
+            LogWriter::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        match self {
+            LogWriter::Plain(file) => file.flush(),
+            LogWriter::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+/// Close `writer` and delete the log if the build succeeded and we're not keeping it.
+/// An appended log also holds earlier runs, so it is never removed.
+fn close_log(writer: Option<LogWriter>, path: &Path, has_written: bool, build_success: bool, config: &Config) -> Result<()> {
+    if let Some(writer) = writer {
+        writer.finish()?;
+    }
+
+    if build_success && !config.log_on_success && !config.log_append && has_written && path.exists() {
+        std::fs::remove_file(path)
+            .with_context(|| format!("Failed to remove log file: {}", path.display()))?;
+    }
+
+    Ok(())
+}
+
+/// Shift existing logs up by one (`.log` -> `.1.log` -> `.2.log` ...), dropping
+/// anything beyond `keep`.
+fn rotate(path: &Path, keep: usize) -> Result<()> {
+    if keep == 0 || !path.exists() {
+        return Ok(());
+    }
+
+    for n in (1..keep).rev() {
+        let from = rotated_path(path, n);
+        if from.exists() {
+            std::fs::rename(&from, rotated_path(path, n + 1))
+                .with_context(|| format!("Failed to rotate log file: {}", from.display()))?;
+        }
+    }
+    std::fs::rename(path, rotated_path(path, 1))
+        .with_context(|| format!("Failed to rotate log file: {}", path.display()))?;
+
+    Ok(())
+}
+
+/// Human-readable log of rendered diagnostics.
+pub struct TextLog {
     log_path: PathBuf,
-    file: Option<File>,
+    file: Option<LogWriter>,
     config: Config,
     has_written: bool,
+    /// Per-target-category logs written with `--log-split`
+    split: BTreeMap<&'static str, TextLog>,
+    /// Bytes of diagnostics written directly to the file during this run
+    bytes_written: u64,
+    /// With `--log-max-size`, the most recent entries once the head budget is used up
+    tail: VecDeque<String>,
+    tail_bytes: u64,
+    truncated_entries: usize,
+    truncated_bytes: u64,
 }
 
-impl Logger {
+impl TextLog {
     pub fn new(log_path: &str, config: &Config) -> Result<Self> {
-        Ok(Logger {
+        Ok(TextLog {
             log_path: PathBuf::from(log_path),
             file: None,
             config: config.clone(),
             has_written: false,
+            split: BTreeMap::new(),
+            bytes_written: 0,
+            tail: VecDeque::new(),
+            tail_bytes: 0,
+            truncated_entries: 0,
+            truncated_bytes: 0,
         })
     }
 
+    /// Paths of the per-target logs written so far.
+    pub fn split_paths(&self) -> Vec<&Path> {
+        self.split.values().map(|log| log.log_path.as_path()).collect()
+    }
+
     pub fn log_error(&mut self, rendered: &str) -> Result<()> {
         // Initialize file on first error
         if self.file.is_none() {
-            self.ensure_parent_dir()?;
-            
-            let file = OpenOptions::new()
-                .create(true)
-                .write(true)
-                .truncate(true) // Overwrite existing file
-                .open(&self.log_path)
-                .with_context(|| format!("Failed to create log file: {}", self.log_path.display()))?;
-            
-            self.file = Some(file);
-            
+            let (mut writer, is_new) = LogWriter::open(&self.log_path, &self.config)?;
+
             // Write header
-            if let Some(ref mut f) = self.file {
-                writeln!(f, "cargo-builder error log")?;
-                writeln!(f, "======================")?;
-                writeln!(f)?;
+            let context = context::get(&self.config);
+            if is_new {
+                writeln!(writer, "cargo-builder error log")?;
+                writeln!(writer, "======================")?;
+                writeln!(writer)?;
             }
+            if self.config.log_append {
+                // Separate runs accumulating in the same file
+                writeln!(writer, "--- {} {} ---", context.timestamp, context.command)?;
+            }
+            for line in context.header_lines() {
+                writeln!(writer, "{}", line)?;
+            }
+            writeln!(writer)?;
+
+            self.file = Some(writer);
         }
 
-        // Format the message for the log file
-        let log_content = diagnostics::format_for_log(rendered, &self.config);
+        // Format the message for the log file, with a blank line between errors
+        let entry = format!("{}\n\n", diagnostics::format_for_log(rendered, &self.config));
+
+        // Once half of --log-max-size is used, keep only the most recent entries
+        // in memory; they are written after a truncation marker in `finalize`.
+        if let Some(max_size) = self.config.log_max_size {
+            let budget = max_size / 2;
+            if self.bytes_written + entry.len() as u64 > budget {
+                self.push_tail(entry, budget);
+                self.has_written = true;
+                return Ok(());
+            }
+        }
 
         if let Some(ref mut file) = self.file {
-            writeln!(file, "{}", log_content)?;
-            writeln!(file)?; // Add blank line between errors
+            file.write_all(entry.as_bytes())?;
             file.flush()?;
+            self.bytes_written += entry.len() as u64;
             self.has_written = true;
         }
 
         Ok(())
     }
 
-    pub fn finalize(self, build_success: bool) -> Result<()> {
-        // Drop the file handle first
-        drop(self.file);
-
-        // Delete the log file if build succeeded and we're not keeping it
-        if build_success && !self.config.log_on_success && self.has_written {
-            if self.log_path.exists() {
-                std::fs::remove_file(&self.log_path)
-                    .with_context(|| format!("Failed to remove log file: {}", self.log_path.display()))?;
+    fn push_tail(&mut self, mut entry: String, budget: u64) {
+        // A single oversized entry keeps only its end
+        if entry.len() as u64 > budget {
+            let mut cut = entry.len() - budget as usize;
+            while !entry.is_char_boundary(cut) {
+                cut += 1;
             }
+            self.truncated_bytes += cut as u64;
+            entry.drain(..cut);
         }
 
-        Ok(())
+        self.tail_bytes += entry.len() as u64;
+        self.tail.push_back(entry);
+        while self.tail_bytes > budget {
+            if let Some(dropped) = self.tail.pop_front() {
+                self.tail_bytes -= dropped.len() as u64;
+                self.truncated_bytes += dropped.len() as u64;
+                self.truncated_entries += 1;
+            }
+        }
     }
 
-    fn ensure_parent_dir(&self) -> Result<()> {
-        if let Some(parent) = self.log_path.parent() {
-            if !parent.exists() {
-                std::fs::create_dir_all(parent)
-                    .with_context(|| format!("Failed to create log directory: {}", parent.display()))?;
+    fn write_tail(&mut self) -> Result<()> {
+        if let Some(ref mut file) = self.file {
+            if self.truncated_bytes > 0 {
+                writeln!(
+                    file,
+                    "[... {} bytes ({} diagnostics) truncated by --log-max-size ...]",
+                    self.truncated_bytes, self.truncated_entries,
+                )?;
+                writeln!(file)?;
             }
+            for entry in &self.tail {
+                file.write_all(entry.as_bytes())?;
+            }
+            file.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn finalize(mut self, build_success: bool) -> Result<()> {
+        for log in std::mem::take(&mut self.split).into_values() {
+            log.finalize(build_success)?;
+        }
+
+        self.write_tail()?;
+        close_log(self.file.take(), &self.log_path, self.has_written, build_success, &self.config)
+    }
+}
+
+impl LogSink for TextLog {
+    /// Log a diagnostic to the main log and, with `--log-split`, to the log
+    /// for its target category (`build-errors.lib.log`, `build-errors.test.log`, ...).
+    fn log_diagnostic(&mut self, diagnostic: &Diagnostic) -> Result<()> {
+        self.log_error(&diagnostic.rendered)?;
+
+        if self.config.log_split {
+            let category = diagnostic.target_category();
+            let log = match self.split.entry(category) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => {
+                    let path = suffixed_path(&self.log_path, category);
+                    entry.insert(TextLog::new(&path.display().to_string(), &self.config)?)
+                }
+            };
+            log.log_error(&diagnostic.rendered)?;
+        }
+
+        Ok(())
+    }
+
+    fn finalize(self: Box<Self>, build_success: bool) -> Result<()> {
+        TextLog::finalize(*self, build_success)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.log_path)
+    }
+
+    fn extra_paths(&self) -> Vec<&Path> {
+        self.split_paths()
+    }
+}
+
+/// Machine-readable log with one serialized `Diagnostic` per line.
+pub struct JsonLinesLog {
+    log_path: PathBuf,
+    file: Option<LogWriter>,
+    config: Config,
+    has_written: bool,
+}
+
+impl JsonLinesLog {
+    pub fn new(log_path: &str, config: &Config) -> Result<Self> {
+        Ok(JsonLinesLog {
+            log_path: PathBuf::from(log_path),
+            file: None,
+            config: config.clone(),
+            has_written: false,
+        })
+    }
+}
+
+impl LogSink for JsonLinesLog {
+    fn log_diagnostic(&mut self, diagnostic: &Diagnostic) -> Result<()> {
+        if self.file.is_none() {
+            self.file = Some(LogWriter::open(&self.log_path, &self.config)?.0);
+        }
+
+        if let Some(ref mut file) = self.file {
+            let mut diagnostic = diagnostic.clone();
+            diagnostic.rendered = diagnostics::format_for_log(&diagnostic.rendered, &self.config);
+            serde_json::to_writer(&mut *file, &diagnostic)?;
+            writeln!(file)?;
+            file.flush()?;
+            self.has_written = true;
         }
+
         Ok(())
     }
+
+    fn finalize(mut self: Box<Self>, build_success: bool) -> Result<()> {
+        close_log(self.file.take(), &self.log_path, self.has_written, build_success, &self.config)
+    }
+
+    fn path(&self) -> Option<&Path> {
+        Some(&self.log_path)
+    }
+}
+
+/// `build-errors.log` -> `build-errors.<n>.log`
+pub fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    suffixed_path(path, &n.to_string())
+}
+
+/// `build-errors.log` -> `build-errors.<suffix>.log`
+pub fn suffixed_path(path: &Path, suffix: &str) -> PathBuf {
+    // Keep the suffix in front of the compression extension: `errors.log.gz` -> `errors.1.log.gz`
+    if is_gzip_path(path) {
+        let inner = suffixed_path(&path.with_extension(""), suffix);
+        return with_gzip_extension(&inner);
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(ext) => format!("{}.{}.{}", stem, suffix, ext.to_string_lossy()),
+        None => format!("{}.{}", stem, suffix),
+    };
+    path.with_file_name(name)
 }
 
-// Need to implement Clone for Config to use it in Logger
+pub fn is_gzip_path(path: &Path) -> bool {
+    path.extension().is_some_and(|ext| ext == "gz")
+}
+
+/// `build-errors.log` -> `build-errors.log.gz`
+pub fn with_gzip_extension(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".gz");
+    PathBuf::from(name)
+}
+
+/// Name of the default log of a build with `profile`: `build-errors.log` for the dev
+/// profile and `build-errors-<profile>.log` for others, so a release build doesn't
+/// replace the errors of a debug one.
+pub fn default_log_name(profile: &str) -> String {
+    if profile == "dev" {
+        "build-errors.log".to_string()
+    } else {
+        format!("build-errors-{}.log", profile)
+    }
+}
+
+/// Unique log path for a run started at `started_at`, e.g.
+/// `<dir>/logs/build-errors-2024-06-01T093012Z.log`.
+pub fn per_run_log_path(dir: &Path, started_at: SystemTime, compress: bool) -> PathBuf {
+    let stamp = humantime::format_rfc3339_seconds(started_at).to_string().replace(':', "");
+    let with_compression = |path: PathBuf| if compress { with_gzip_extension(&path) } else { path };
+
+    let logs = dir.join("logs");
+    let mut path = with_compression(logs.join(format!("build-errors-{}.log", stamp)));
+    // Two runs within the same second get distinct logs
+    let mut n = 1;
+    while path.exists() {
+        n += 1;
+        path = with_compression(logs.join(format!("build-errors-{}-{}.log", stamp, n)));
+    }
+    path
+}
+
+/// Delete rotated logs of `targets` and per-run logs in `per_run_dir` that were
+/// last written more than `retention` ago. The newest per-run log is always kept
+/// so `latest.log` keeps pointing at something. Returns the number of files removed.
+/// Called with the logs of `targets` locked, so no other build is rotating them.
+pub fn prune_logs(targets: &[LogTarget], per_run_dir: &Path, retention: Duration) -> Result<usize> {
+    let Some(cutoff) = SystemTime::now().checked_sub(retention) else {
+        return Ok(0);
+    };
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|m| m.modified()).ok();
+
+    let mut candidates = Vec::new();
+    for target in targets {
+        let mut n = 1;
+        while rotated_path(&target.path, n).exists() {
+            candidates.push(rotated_path(&target.path, n));
+            n += 1;
+        }
+    }
+    if let Ok(entries) = std::fs::read_dir(per_run_dir) {
+        let mut per_run: Vec<(SystemTime, PathBuf)> = entries.flatten()
+            .map(|entry| entry.path())
+            // Not lock files, nor the log this build is writing
+            .filter(|path| path.is_file() && is_log_name(path))
+            .filter(|path| !targets.iter().any(|target| &target.path == path))
+            .filter_map(|path| Some((modified(&path)?, path)))
+            .collect();
+        per_run.sort();
+        per_run.pop();
+        candidates.extend(per_run.into_iter().map(|(_, path)| path));
+    }
+
+    let mut removed = 0;
+    for path in candidates {
+        if modified(&path).is_some_and(|m| m < cutoff) {
+            std::fs::remove_file(&path)
+                .with_context(|| format!("Failed to remove old log: {}", path.display()))?;
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+fn is_log_name(path: &Path) -> bool {
+    path.file_name().and_then(|name| name.to_str())
+        .is_some_and(|name| name.ends_with(".log") || name.ends_with(".log.gz"))
+}
+
+/// Point `latest` at `log`: a relative symlink on Unix, a copy elsewhere.
+pub fn update_latest(latest: &Path, log: &Path) -> Result<()> {
+    if latest.symlink_metadata().is_ok() {
+        std::fs::remove_file(latest)
+            .with_context(|| format!("Failed to replace {}", latest.display()))?;
+    }
+
+    #[cfg(unix)]
+    {
+        let target = latest.parent()
+            .and_then(|dir| log.strip_prefix(dir).ok())
+            .unwrap_or(log);
+        std::os::unix::fs::symlink(target, latest)
+            .with_context(|| format!("Failed to link {} to {}", latest.display(), log.display()))?;
+    }
+    #[cfg(not(unix))]
+    {
+        std::fs::copy(log, latest)
+            .with_context(|| format!("Failed to copy {} to {}", log.display(), latest.display()))?;
+    }
+
+    Ok(())
+}
+
+// Need to implement Clone for Config to use it in the log sinks
 impl Clone for crate::Config {
     fn clone(&self) -> Self {
         Self {
-            log_path: self.log_path.clone(),
+            log_paths: self.log_paths.clone(),
             log_on_success: self.log_on_success,
+            log_keep: self.log_keep,
+            log_append: self.log_append,
+            log_split: self.log_split,
+            log_max_size: self.log_max_size,
+            log_compress: self.log_compress,
+            syslog: self.syslog.clone(),
+            syslog_facility: self.syslog_facility.clone(),
+            journald: self.journald,
+            event_log: self.event_log,
+            redact: self.redact.clone(),
+            redact_env: self.redact_env.clone(),
+            redact_home: self.redact_home,
+            wait: self.wait,
+            log_per_run: self.log_per_run,
+            capture_json: self.capture_json.clone(),
+            paths: self.paths,
+            path_maps: self.path_maps.clone(),
             log_color: self.log_color.clone(),
             terminal_color: self.terminal_color.clone(),
             include_warnings: self.include_warnings,
             show_build_output: self.show_build_output,
             quiet: self.quiet,
+            bell: self.bell,
+            webhooks: self.webhooks.clone(),
+            webhook_include_log: self.webhook_include_log,
+            webhook_timeout: self.webhook_timeout,
+            notify_threshold: self.notify_threshold,
+            github_comment: self.github_comment,
+            github_pr: self.github_pr.clone(),
+            statsd: self.statsd.clone(),
+            diff_last: self.diff_last,
+            fail_on_warning_increase: self.fail_on_warning_increase,
+            crate_timings: self.crate_timings,
+            timings: self.timings,
+            changed_only: self.changed_only.clone(),
+            blame: self.blame,
+            against: self.against.clone(),
+            baseline: self.baseline.clone(),
+            view: self.view,
+            viewer: self.viewer.clone(),
+            ci: self.ci,
+            annotations: self.annotations,
+            exit_code: self.exit_code,
+            timeout: self.timeout,
+            lock_timeout: self.lock_timeout,
+            max_memory: self.max_memory,
+            retry: self.retry,
+            offline_fallback: self.offline_fallback,
+            fetch: self.fetch,
+            label: self.label.clone(),
+            feature_matrix: self.feature_matrix,
+            feature_depth: self.feature_depth,
+            per_member: self.per_member,
+            member_jobs: self.member_jobs,
+            auto_jobs: self.auto_jobs,
+            nice: self.nice,
+            toolchain: self.toolchain.clone(),
+            cargo: self.cargo.clone(),
+            cross: self.cross,
+            container: self.container.clone(),
+            container_engine: self.container_engine.clone(),
+            target_wasm: self.target_wasm.clone(),
+            wasm_bindgen: self.wasm_bindgen.clone(),
+            wasm_opt: self.wasm_opt.clone(),
+            nextest: self.nextest,
+            doctests: self.doctests,
+            miri: self.miri.clone(),
+            audit: self.audit,
+            deny_check: self.deny_check.clone(),
+            udeps: self.udeps,
+            sizes: self.sizes,
+            bloat: self.bloat,
+            list_artifacts: self.list_artifacts.clone(),
+            out_dir: self.out_dir.clone(),
+            out_name: self.out_name.clone(),
+            checksums: self.checksums,
+            verify_reproducible: self.verify_reproducible,
+            coverage: self.coverage.clone(),
+            fuzz: self.fuzz.clone(),
+            flash: self.flash,
+            flash_chip: self.flash_chip.clone(),
+            flash_probe: self.flash_probe.clone(),
+            directory: self.directory.clone(),
+            short_messages: self.short_messages,
             cargo_args: self.cargo_args.clone(),
         }
     }
@@ -106,13 +793,92 @@ mod tests {
 
     fn create_test_config() -> Config {
         Config {
-            log_path: None,
+            log_paths: vec![],
             log_on_success: false,
+            log_keep: 0,
+            log_append: false,
+            log_split: false,
+            log_max_size: None,
+            log_compress: false,
+            syslog: None,
+            syslog_facility: "user".to_string(),
+            journald: false,
+            event_log: false,
+            redact: vec![],
+            redact_env: vec![],
+            redact_home: false,
+            wait: false,
+            log_per_run: false,
+            capture_json: None,
+            paths: None,
+            path_maps: vec![],
             log_color: crate::ColorChoice::Never,
             terminal_color: crate::ColorChoice::Auto,
             include_warnings: false,
             show_build_output: false,
             quiet: false,
+            bell: false,
+            webhooks: vec![],
+            webhook_include_log: false,
+            webhook_timeout: std::time::Duration::from_secs(10),
+            notify_threshold: None,
+            github_comment: false,
+            github_pr: None,
+            statsd: None,
+            diff_last: false,
+            fail_on_warning_increase: false,
+            crate_timings: false,
+            timings: false,
+            changed_only: None,
+            blame: false,
+            against: None,
+            baseline: None,
+            view: false,
+            viewer: None,
+            ci: None,
+            annotations: false,
+            exit_code: crate::ExitCodePolicy::Detailed,
+            timeout: None,
+            lock_timeout: None,
+            max_memory: None,
+            retry: 0,
+            offline_fallback: false,
+            fetch: false,
+            label: None,
+            feature_matrix: None,
+            feature_depth: None,
+            per_member: false,
+            member_jobs: 1,
+            auto_jobs: false,
+            nice: None,
+            toolchain: None,
+            cargo: "cargo".to_string(),
+            cross: false,
+            container: None,
+            container_engine: None,
+            target_wasm: None,
+            wasm_bindgen: None,
+            wasm_opt: None,
+            nextest: false,
+            doctests: false,
+            miri: None,
+            audit: None,
+            deny_check: None,
+            udeps: false,
+            sizes: false,
+            bloat: None,
+            list_artifacts: None,
+            out_dir: None,
+            out_name: None,
+            checksums: false,
+            verify_reproducible: false,
+            coverage: None,
+            fuzz: None,
+            flash: false,
+            flash_chip: None,
+            flash_probe: None,
+            directory: None,
+            short_messages: false,
             cargo_args: vec![],
         }
     }
@@ -123,16 +889,18 @@ mod tests {
         let log_path = temp_dir.path().join("test.log");
         let config = create_test_config();
         
-        let mut logger = Logger::new(log_path.to_str().unwrap(), &config).unwrap();
+        let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
         
         assert!(!log_path.exists());
         
-        logger.log_error("Test error message").unwrap();
+        log.log_error("Test error message").unwrap();
         
         assert!(log_path.exists());
         let content = fs::read_to_string(&log_path).unwrap();
         assert!(content.contains("Test error message"));
         assert!(content.contains("cargo-builder error log"));
+        assert!(content.contains("Command:  cargo build"));
+        assert!(content.contains("Profile:  dev"));
     }
 
     #[test]
@@ -141,13 +909,13 @@ mod tests {
         let log_path = temp_dir.path().join("test.log");
         let config = create_test_config();
         
-        let mut logger = Logger::new(log_path.to_str().unwrap(), &config).unwrap();
-        logger.log_error("Test error").unwrap();
+        let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+        log.log_error("Test error").unwrap();
         
         assert!(log_path.exists());
         
         // Finalize with success - should remove file
-        logger.finalize(true).unwrap();
+        log.finalize(true).unwrap();
         
         assert!(!log_path.exists());
     }
@@ -158,17 +926,205 @@ mod tests {
         let log_path = temp_dir.path().join("test.log");
         let config = create_test_config();
         
-        let mut logger = Logger::new(log_path.to_str().unwrap(), &config).unwrap();
-        logger.log_error("Test error").unwrap();
+        let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+        log.log_error("Test error").unwrap();
         
         assert!(log_path.exists());
         
         // Finalize with failure - should keep file
-        logger.finalize(false).unwrap();
+        log.finalize(false).unwrap();
         
         assert!(log_path.exists());
     }
 
+    #[test]
+    fn test_log_target_parse() {
+        assert_eq!(LogTarget::parse("errors.log").unwrap().format, LogFormat::Text);
+        assert_eq!(LogTarget::parse("errors.jsonl.gz").unwrap().format, LogFormat::JsonLines);
+        let target = LogTarget::parse("jsonl:out/errors.txt").unwrap();
+        assert_eq!(target.format, LogFormat::JsonLines);
+        assert_eq!(target.path, Path::new("out/errors.txt"));
+        assert_eq!(LogTarget::parse("text:errors.json").unwrap().format, LogFormat::Text);
+        assert!(LogTarget::parse("text:").is_err());
+    }
+
+    #[test]
+    fn test_logger_writes_every_sink() {
+        let temp_dir = TempDir::new().unwrap();
+        let text_path = temp_dir.path().join("errors.log");
+        let json_path = temp_dir.path().join("errors.jsonl");
+        let targets = vec![
+            LogTarget { format: LogFormat::Text, path: text_path.clone() },
+            LogTarget { format: LogFormat::JsonLines, path: json_path.clone() },
+        ];
+        let config = create_test_config();
+
+        let mut logger = Logger::new(&targets, &config).unwrap();
+        logger.log_diagnostic(&Diagnostic {
+            code: Some("E0425".to_string()),
+            rendered: "\x1b[31merror\x1b[0m[E0425]: cannot find value".to_string(),
+            ..Diagnostic::new("error", "cannot find value")
+        }).unwrap();
+        assert_eq!(logger.paths().len(), 2);
+        logger.finalize(false).unwrap();
+
+        assert!(fs::read_to_string(&text_path).unwrap().contains("error[E0425]"));
+        let stored = crate::store::read_diagnostics(&json_path).unwrap();
+        assert_eq!(stored.len(), 1);
+        assert_eq!(stored[0].rendered, "error[E0425]: cannot find value");
+    }
+
+    /// A remote sink whose service went away
+    struct Unreachable(std::rc::Rc<std::cell::Cell<usize>>);
+
+    impl LogSink for Unreachable {
+        fn log_diagnostic(&mut self, _diagnostic: &Diagnostic) -> Result<()> {
+            self.0.set(self.0.get() + 1);
+            bail!("Failed to send message to syslog: Connection refused")
+        }
+
+        fn finalize(self: Box<Self>, _build_success: bool) -> Result<()> {
+            bail!("Failed to flush")
+        }
+    }
+
+    #[test]
+    fn test_logger_turns_off_failing_remote_sink() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("errors.log");
+        let config = create_test_config();
+        let mut logger = Logger::new(&[LogTarget { format: LogFormat::Text, path: path.clone() }], &config).unwrap();
+        let attempts = std::rc::Rc::new(std::cell::Cell::new(0));
+        logger.add_remote_sink(Ok(Unreachable(attempts.clone())));
+        logger.add_remote_sink::<Unreachable>(Err(anyhow::anyhow!("Failed to connect to the local syslog daemon at /dev/log")));
+
+        let diagnostic = Diagnostic { rendered: "error: cannot find value\n".to_string(), ..Diagnostic::new("error", "cannot find value") };
+        logger.log_diagnostic(&diagnostic).unwrap();
+        logger.log_diagnostic(&diagnostic).unwrap();
+        assert_eq!(attempts.get(), 1);
+        logger.finalize(false).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap().matches("cannot find value").count(), 2);
+    }
+
+    #[test]
+    fn test_rotated_path() {
+        assert_eq!(rotated_path(Path::new("/t/build-errors.log"), 2), Path::new("/t/build-errors.2.log"));
+        assert_eq!(rotated_path(Path::new("/t/errors"), 1), Path::new("/t/errors.1"));
+    }
+
+    #[test]
+    fn test_logger_rotates_previous_logs() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log");
+        let mut config = create_test_config();
+        config.log_keep = 2;
+
+        for run in 1..=3 {
+            let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+            log.log_error(&format!("error from run {}", run)).unwrap();
+            log.finalize(false).unwrap();
+        }
+
+        assert!(fs::read_to_string(&log_path).unwrap().contains("run 3"));
+        assert!(fs::read_to_string(temp_dir.path().join("test.1.log")).unwrap().contains("run 2"));
+        assert!(fs::read_to_string(temp_dir.path().join("test.2.log")).unwrap().contains("run 1"));
+        assert!(!temp_dir.path().join("test.3.log").exists());
+    }
+
+    #[test]
+    fn test_logger_splits_by_target_kind() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log");
+        let mut config = create_test_config();
+        config.log_split = true;
+
+        let diagnostic = |kind: &str, text: &str| Diagnostic {
+            target_kind: vec![kind.to_string()],
+            rendered: text.to_string(),
+            ..Diagnostic::new("error", text)
+        };
+
+        let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+        log.log_diagnostic(&diagnostic("lib", "lib error")).unwrap();
+        log.log_diagnostic(&diagnostic("test", "test error")).unwrap();
+        assert_eq!(log.split_paths().len(), 2);
+        log.finalize(false).unwrap();
+
+        let main = fs::read_to_string(&log_path).unwrap();
+        assert!(main.contains("lib error") && main.contains("test error"));
+        let test_log = fs::read_to_string(temp_dir.path().join("test.test.log")).unwrap();
+        assert!(test_log.contains("test error") && !test_log.contains("lib error"));
+    }
+
+    #[test]
+    fn test_logger_truncates_middle_over_max_size() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log");
+        let mut config = create_test_config();
+        config.log_max_size = Some(200);
+
+        let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+        for i in 0..20 {
+            log.log_error(&format!("error number {:02}", i)).unwrap();
+        }
+        log.finalize(false).unwrap();
+
+        let content = fs::read_to_string(&log_path).unwrap();
+        assert!(content.contains("error number 00"));
+        assert!(content.contains("error number 19"));
+        assert!(!content.contains("error number 10"));
+        assert!(content.contains("truncated by --log-max-size"));
+    }
+
+    #[test]
+    fn test_gzip_paths() {
+        assert!(is_gzip_path(Path::new("errors.log.gz")));
+        assert_eq!(with_gzip_extension(Path::new("/t/errors.log")), Path::new("/t/errors.log.gz"));
+        assert_eq!(rotated_path(Path::new("/t/errors.log.gz"), 1), Path::new("/t/errors.1.log.gz"));
+    }
+
+    #[test]
+    fn test_logger_writes_gzip() {
+        use flate2::read::MultiGzDecoder;
+        use std::io::Read;
+
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log.gz");
+        let mut config = create_test_config();
+        config.log_append = true;
+
+        for run in 1..=2 {
+            let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+            log.log_error(&format!("compressed error {}", run)).unwrap();
+            log.finalize(false).unwrap();
+        }
+
+        let mut content = String::new();
+        MultiGzDecoder::new(File::open(&log_path).unwrap()).read_to_string(&mut content).unwrap();
+        assert!(content.contains("compressed error 1"));
+        assert!(content.contains("compressed error 2"));
+    }
+
+    #[test]
+    fn test_logger_appends_runs() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log");
+        let mut config = create_test_config();
+        config.log_append = true;
+
+        for run in 1..=2 {
+            let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+            log.log_error(&format!("error from run {}", run)).unwrap();
+            // Even a successful run must not delete earlier runs' output
+            log.finalize(true).unwrap();
+        }
+
+        let content = fs::read_to_string(&log_path).unwrap();
+        assert_eq!(content.matches("cargo-builder error log").count(), 1);
+        assert_eq!(content.matches("--- ").count(), 2);
+        assert!(content.contains("run 1") && content.contains("run 2"));
+    }
+
     #[test]
     fn test_logger_keeps_file_with_log_on_success() {
         let temp_dir = TempDir::new().unwrap();
@@ -176,14 +1132,143 @@ mod tests {
         let mut config = create_test_config();
         config.log_on_success = true;
         
-        let mut logger = Logger::new(log_path.to_str().unwrap(), &config).unwrap();
-        logger.log_error("Test error").unwrap();
+        let mut log = TextLog::new(log_path.to_str().unwrap(), &config).unwrap();
+        log.log_error("Test error").unwrap();
         
         assert!(log_path.exists());
         
         // Finalize with success but log_on_success=true - should keep file
-        logger.finalize(true).unwrap();
+        log.finalize(true).unwrap();
         
         assert!(log_path.exists());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_per_run_log_path_is_unique() {
+        let temp_dir = TempDir::new().unwrap();
+        let started_at = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_717_234_212);
+
+        let first = per_run_log_path(temp_dir.path(), started_at, false);
+        assert_eq!(first, temp_dir.path().join("logs/build-errors-2024-06-01T093012Z.log"));
+
+        fs::create_dir_all(first.parent().unwrap()).unwrap();
+        fs::write(&first, "run 1").unwrap();
+        let second = per_run_log_path(temp_dir.path(), started_at, false);
+        assert_eq!(second, temp_dir.path().join("logs/build-errors-2024-06-01T093012Z-2.log"));
+
+        let compressed = per_run_log_path(temp_dir.path(), started_at, true);
+        assert!(is_gzip_path(&compressed));
+    }
+
+    #[test]
+    fn test_update_latest_follows_newest_log() {
+        let temp_dir = TempDir::new().unwrap();
+        let latest = temp_dir.path().join("latest.log");
+        fs::create_dir(temp_dir.path().join("logs")).unwrap();
+        let old = temp_dir.path().join("logs/old.log");
+        let new = temp_dir.path().join("logs/new.log");
+        fs::write(&old, "old").unwrap();
+        fs::write(&new, "new").unwrap();
+
+        update_latest(&latest, &old).unwrap();
+        update_latest(&latest, &new).unwrap();
+        assert_eq!(fs::read_to_string(&latest).unwrap(), "new");
+    }
+
+    #[test]
+    fn test_log_lock_rejects_concurrent_writer() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log");
+
+        let held = LogLock::acquire(&log_path, false, true).unwrap();
+        let err = LogLock::acquire(&log_path, false, true).err().unwrap();
+        assert!(err.to_string().contains("Another build is writing this log"));
+
+        drop(held);
+        assert!(!lock_path(&log_path).exists());
+        assert!(LogLock::acquire(&log_path, false, true).is_ok());
+    }
+
+    #[test]
+    fn test_log_lock_waiter_keeps_others_out() {
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log");
+
+        let held = LogLock::acquire(&log_path, false, true).unwrap();
+        let waiter = {
+            let log_path = log_path.clone();
+            std::thread::spawn(move || LogLock::acquire(&log_path, true, true).unwrap())
+        };
+        std::thread::sleep(std::time::Duration::from_millis(100));
+        drop(held);
+
+        // The waiter locked the file now there, not the one removed
+        let _waited = waiter.join().unwrap();
+        assert!(LogLock::acquire(&log_path, false, true).is_err());
+    }
+
+    #[test]
+    fn test_log_lock_three_builds_never_overlap() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        use std::sync::Arc;
+
+        let temp_dir = TempDir::new().unwrap();
+        let log_path = temp_dir.path().join("test.log");
+        let writing = Arc::new(AtomicUsize::new(0));
+
+        // Each lock is let go (and its file removed) while the others wait on it or
+        // are about to create a new one
+        let builds: Vec<_> = (0..3)
+            .map(|_| {
+                let log_path = log_path.clone();
+                let writing = Arc::clone(&writing);
+                std::thread::spawn(move || {
+                    for _ in 0..200 {
+                        let _lock = LogLock::acquire(&log_path, true, true).unwrap();
+                        assert_eq!(writing.fetch_add(1, Ordering::SeqCst), 0, "two builds hold the lock");
+                        std::thread::yield_now();
+                        writing.fetch_sub(1, Ordering::SeqCst);
+                    }
+                })
+            })
+            .collect();
+        for build in builds {
+            build.join().unwrap();
+        }
+    }
+
+    #[test]
+    fn test_prune_logs_by_age() {
+        let temp_dir = TempDir::new().unwrap();
+        let per_run_dir = temp_dir.path().join("logs");
+        fs::create_dir(&per_run_dir).unwrap();
+        let target = LogTarget { format: LogFormat::Text, path: temp_dir.path().join("build-errors.log") };
+
+        let old = SystemTime::now() - Duration::from_secs(10 * 24 * 3600);
+        let touch = |path: &Path, modified: SystemTime| {
+            fs::write(path, "error").unwrap();
+            File::options().write(true).open(path).unwrap().set_modified(modified).unwrap();
+        };
+        touch(&target.path, old);
+        touch(&rotated_path(&target.path, 1), SystemTime::now());
+        touch(&rotated_path(&target.path, 2), old);
+        touch(&per_run_dir.join("a.log"), old - Duration::from_secs(60));
+        touch(&per_run_dir.join("b.log"), old);
+        touch(&per_run_dir.join("a.log.lock"), old);
+        touch(&per_run_dir.join("notes.txt"), old);
+        // The log being written doesn't count as the newest to keep
+        let current = LogTarget { format: LogFormat::Text, path: per_run_dir.join("c.log") };
+        touch(&current.path, SystemTime::now());
+
+        let removed = prune_logs(&[target.clone(), current], &per_run_dir, Duration::from_secs(7 * 24 * 3600)).unwrap();
+        assert_eq!(removed, 2);
+        // The current log is not touched, the newest per-run log is kept
+        assert!(target.path.exists());
+        assert!(rotated_path(&target.path, 1).exists());
+        assert!(!rotated_path(&target.path, 2).exists());
+        assert!(!per_run_dir.join("a.log").exists());
+        assert!(per_run_dir.join("b.log").exists());
+        assert!(per_run_dir.join("a.log.lock").exists());
+        assert!(per_run_dir.join("notes.txt").exists());
+    }
+}
@@ -1,22 +1,253 @@
 mod runner;
+mod against;
+mod alias;
+mod artifacts;
+mod audit;
+mod baseline;
+mod buildscript;
+mod buildstd;
+mod chat;
+mod ci;
+mod clean;
+mod container;
+mod context;
+mod coverage;
+mod cross;
+mod dashboard;
+mod deny;
 mod diagnostics; 
+mod diff;
+mod doctest;
+mod email;
+mod events;
+mod eventlog;
+mod fetch;
+mod flash;
+mod futureincompat;
+mod fuzz;
+mod git;
+mod github;
+mod grep;
+mod history;
+mod ice;
+mod journald;
+mod linker;
+mod load;
+mod lock;
 mod logging;
+mod matrix;
+mod members;
+mod memory;
+mod miri;
+mod nextest;
+mod notify;
+mod ntfy;
+mod paths;
+mod process;
+mod rebuilds;
+mod redact;
+mod reproducible;
+mod retry;
+mod rustflags;
+mod search;
+mod settings;
+mod signals;
+mod sizes;
+mod sqlite;
+mod statsd;
+mod steps;
+mod store;
+mod suppress;
+mod syslibs;
+mod syslog;
+mod tail;
+mod timings;
+mod trend;
 mod term;
+mod tui;
+mod udeps;
 mod util;
+mod wasm;
 
 use clap::{Arg, ArgAction, Command};
-use anyhow::Result;
+use anyhow::{Context, Result};
 use std::env;
 
 #[derive(Debug)]
 pub struct Config {
-    pub log_path: Option<String>,
+    /// `--log`: the logs to write, each prefixed with its format if not text
+    pub log_paths: Vec<String>,
+    /// `--log-on-success`: keep the log of a successful build too; on in CI
     pub log_on_success: bool,
+    /// `--log-keep`: how many previous logs to rotate
+    pub log_keep: usize,
+    /// `--log-append`: add to the log instead of replacing it
+    pub log_append: bool,
+    /// `--log-split`: also write a log per target kind
+    pub log_split: bool,
+    /// `--log-max-size` in bytes, keeping the head and tail of what a run logs
+    pub log_max_size: Option<u64>,
+    /// `--log-compress`: gzip the log
+    pub log_compress: bool,
+    /// `--syslog`: `local`, or the host:port of a syslog server
+    pub syslog: Option<String>,
+    /// `--syslog-facility` of the messages sent there
+    pub syslog_facility: String,
+    /// `--journald`: also log to systemd-journald
+    pub journald: bool,
+    /// `--event-log`: write a Windows Event Log entry when the build fails
+    pub event_log: bool,
+    /// `--redact`: patterns to hide in logs and everything sent elsewhere
+    pub redact: Vec<String>,
+    /// `--redact-env`: environment variables whose values are hidden the same way
+    pub redact_env: Vec<String>,
+    /// `--redact-home`: show the home directory as `~`
+    pub redact_home: bool,
+    /// `--wait`: wait for another build writing the same log rather than fail
+    pub wait: bool,
+    /// `--log-per-run`: a timestamped log of its own for each run
+    pub log_per_run: bool,
+    /// `--capture-json`: where to copy cargo's JSON messages, unfiltered
+    pub capture_json: Option<String>,
+    /// `--paths`: how file paths in diagnostics are shown
+    pub paths: Option<paths::PathStyle>,
+    /// `--path-map`: path prefixes to rewrite, from and to
+    pub path_maps: Vec<(String, String)>,
+    /// `--log-color`: colors in the log
     pub log_color: ColorChoice,
+    /// `--terminal-color`: colors on the terminal; never in CI unless asked for
     pub terminal_color: ColorChoice,
+    /// `--include-warnings`: show warnings instead of dropping them
     pub include_warnings: bool,
+    /// `--show-build-output`: pass all of cargo's output through
     pub show_build_output: bool,
+    /// `--quiet`: print as little of cargo-builder's own as possible
     pub quiet: bool,
+    /// `--bell`: ring the terminal bell when the build fails; never in CI
+    pub bell: bool,
+    /// `--webhook`: URLs to post a summary of the build to
+    pub webhooks: Vec<String>,
+    /// `--webhook-include-log`: put the log in that summary
+    pub webhook_include_log: bool,
+    /// `--webhook-timeout` of each notification request
+    pub webhook_timeout: std::time::Duration,
+    /// `--notify-threshold`: only notify of builds that took at least this long
+    pub notify_threshold: Option<std::time::Duration>,
+    /// `--github-comment`: comment a summary on the pull request being built
+    pub github_comment: bool,
+    /// `--github-pr`: the pull request to comment on instead of the detected one
+    pub github_pr: Option<github::PullRequest>,
+    /// `--statsd`: the agent to send the build's metrics to
+    pub statsd: Option<String>,
+    /// `--diff-last`: list what is new or fixed since the previous build
+    pub diff_last: bool,
+    /// `--fail-on-warning-increase`: fail when there are more warnings than the last time
+    pub fail_on_warning_increase: bool,
+    /// `--crate-timings`: list the crates that took longest to compile
+    pub crate_timings: bool,
+    /// `--timings`: have cargo write its timings report, and summarize it
+    pub timings: bool,
+    /// `--changed-only`: only show diagnostics in files changed since this ref
+    pub changed_only: Option<String>,
+    /// `--blame`: note who last changed each diagnostic's line
+    pub blame: bool,
+    /// `--against`: only report diagnostics this ref's build doesn't have
+    pub against: Option<String>,
+    /// `--baseline`: the file of known diagnostics to hide
+    pub baseline: Option<String>,
+    /// `--view`: open the log in a viewer when the build fails; never in CI
+    pub view: bool,
+    /// `--viewer` to open it with
+    pub viewer: Option<String>,
+    /// The CI the build runs in, unless `--no-ci`
+    pub ci: Option<ci::Provider>,
+    /// `--exit-code`: what the exit code says
+    pub exit_code: ExitCodePolicy,
+    /// `--timeout`: stop the build when it takes longer than this
+    pub timeout: Option<std::time::Duration>,
+    /// `--lock-timeout`: stop the build when cargo waits longer than this for a file lock
+    pub lock_timeout: Option<std::time::Duration>,
+    /// `--max-memory` in bytes
+    pub max_memory: Option<u64>,
+    /// `--retry`: how often a build that failed for a transient reason is started again
+    pub retry: u32,
+    /// `--offline-fallback`: build offline when fetching dependencies fails on a network error
+    pub offline_fallback: bool,
+    /// `--fetch`: run `cargo fetch` first and explain what keeps dependencies from being fetched
+    pub fetch: bool,
+    /// Which build of a matrix this is, e.g. a target triple; its diagnostics are prefixed with it
+    pub label: Option<String>,
+    /// `--feature-matrix`: which feature sets to build the package with
+    pub feature_matrix: Option<matrix::FeatureMatrix>,
+    /// `--feature-depth`: most features combined by `--feature-matrix powerset`
+    pub feature_depth: Option<usize>,
+    /// `--per-member`: build each workspace member with a cargo-builder of its own
+    pub per_member: bool,
+    /// `--member-jobs`: how many members `--per-member` builds at once
+    pub member_jobs: usize,
+    /// `--auto-jobs`: pass cargo a `-j` that fits the idle CPUs
+    pub auto_jobs: bool,
+    /// `--nice` niceness for cargo and everything it starts
+    pub nice: Option<u8>,
+    /// rustup toolchain from `--toolchain` or a leading `+toolchain`
+    pub toolchain: Option<String>,
+    /// The cargo to run: `--cargo-path`, `$CARGO`, or `cargo` from PATH
+    pub cargo: String,
+    /// `--cross`: cargo runs in cross's container, whose paths are mapped back to the host's
+    pub cross: bool,
+    /// `--container IMAGE`: cargo runs in a container of this image
+    pub container: Option<String>,
+    /// `--container-engine`: docker, podman or the like; else whichever is on PATH
+    pub container_engine: Option<String>,
+    /// `--target-wasm`: the wasm target, also passed to cargo as its `--target`
+    pub target_wasm: Option<String>,
+    /// `--wasm-bindgen`: run wasm-bindgen with this `--target` on the modules built
+    pub wasm_bindgen: Option<String>,
+    /// `--wasm-opt`: run wasm-opt at this optimization level, e.g. `s` for -Os
+    pub wasm_opt: Option<String>,
+    /// `--nextest`: run the tests with cargo-nextest instead of building
+    pub nextest: bool,
+    /// `cargo builder miri test|run`: run the tests or the program under Miri instead of building
+    pub miri: Option<String>,
+    /// `--audit`: run cargo audit after a successful build; vulnerabilities at or above this severity are errors
+    pub audit: Option<audit::Severity>,
+    /// `--fuzz`: build the fuzz targets with cargo-fuzz's instrumentation and this sanitizer
+    pub fuzz: Option<String>,
+    /// `--coverage`: run the tests with cargo-llvm-cov and write a report in this format
+    pub coverage: Option<String>,
+    /// `--deny-check`: run these comma-separated cargo-deny checks after a successful build
+    pub deny_check: Option<String>,
+    /// `--udeps`: look for unused dependencies after a successful build
+    pub udeps: bool,
+    /// `--sizes`: print the size of the programs and libraries built, and how it changed
+    pub sizes: bool,
+    /// `--list-artifacts`: print the programs and libraries built, as text or json
+    pub list_artifacts: Option<String>,
+    /// `--out-dir`: copy the programs and libraries built here, over `[artifacts]` in cargo-builder.toml
+    pub out_dir: Option<String>,
+    /// `--out-name`: the template of their names there
+    pub out_name: Option<String>,
+    /// `--checksums`: compute the SHA-256 of the programs and libraries built
+    pub checksums: bool,
+    /// `--verify-reproducible`: build again from scratch and compare what both builds produced
+    pub verify_reproducible: bool,
+    /// `--bloat`: break down the size of each program by crate with cargo-bloat, this many crates
+    pub bloat: Option<usize>,
+    /// Run the doctests with `cargo test --doc` after `--nextest`, unless `--no-doctests`
+    pub doctests: bool,
+    /// `--flash`: write the program built to a chip with probe-rs
+    pub flash: bool,
+    /// `--chip` for `--flash`, over the one in cargo-builder.toml
+    pub flash_chip: Option<String>,
+    /// `--probe` for `--flash`, over the one in cargo-builder.toml
+    pub flash_probe: Option<String>,
+    /// `-C`: the directory to build in instead of the current one
+    pub directory: Option<String>,
+    /// `--message-format short`: diagnostics on one line each
+    pub short_messages: bool,
+    /// `--annotations`: print a GitHub Actions workflow command per diagnostic; on in Actions
+    pub annotations: bool,
+    /// What is passed on to cargo
     pub cargo_args: Vec<String>,
 }
 
@@ -40,31 +271,286 @@ impl std::str::FromStr for ColorChoice {
     }
 }
 
-fn main() -> Result<()> {
-    let config = parse_args()?;
+/// Exit codes of `--exit-code detailed`, one per kind of failure.
+pub mod exit_code {
+    pub const COMPILE_ERRORS: i32 = 1;
+    /// Invalid arguments, as clap reports them
+    pub const USAGE: i32 = 2;
+    /// `--baseline`, `--against` or `--fail-on-warning-increase` found new warnings
+    pub const WARNING_CHECK: i32 = 3;
+    /// `--nextest` ran tests that failed
+    pub const TESTS_FAILED: i32 = 4;
+    /// cargo failed without a compiler error, e.g. a missing toolchain or an unknown feature
+    pub const CARGO_FAILED: i32 = 5;
+    /// cargo-builder itself failed
+    pub const INTERNAL: i32 = 6;
+    /// The build ran past `--timeout`, or waited past `--lock-timeout`, and was stopped
+    pub const TIMED_OUT: i32 = 7;
+    /// The build went over `--max-memory` and was stopped
+    pub const MEMORY_LIMIT: i32 = 8;
+    /// Stopped by Ctrl-C, as shells report a process ended by SIGINT
+    pub const INTERRUPTED: i32 = 130;
+}
+
+/// How the exit code is chosen, selected with `--exit-code`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExitCodePolicy {
+    /// A distinct code per kind of failure, see `exit_code`
+    Detailed,
+    /// cargo's exit code, or 1 when a warning check fails
+    Cargo,
+    /// 1 when errors were shown, cargo failed without saying why, or a warning check failed
+    Errors,
+    /// Always 0, for wrappers that only want the log
+    Never,
+}
+
+impl std::str::FromStr for ExitCodePolicy {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "detailed" => Ok(ExitCodePolicy::Detailed),
+            "cargo" => Ok(ExitCodePolicy::Cargo),
+            "errors" => Ok(ExitCodePolicy::Errors),
+            "never" => Ok(ExitCodePolicy::Never),
+            _ => Err(anyhow::anyhow!("Invalid exit code policy: {}", s)),
+        }
+    }
+}
+
+fn main() {
+    let code = match run() {
+        Ok(code) => code,
+        Err(e) => match e.downcast::<clap::Error>() {
+            // Help and version requests exit with 0, invalid arguments with 2
+            Ok(e) => e.exit(),
+            Err(e) => {
+                eprintln!("cargo-builder: Error: {:#}", e);
+                exit_code::INTERNAL
+            }
+        },
+    };
+    std::process::exit(code);
+}
+
+fn run() -> Result<i32> {
+    let raw_args = raw_args();
+
+    // Subcommands operate on results of previous builds and never run cargo themselves
+    if let Some(subcommand) = raw_args.first() {
+        let code = match subcommand.as_str() {
+            "search" => Some(search::run(&raw_args[1..])?),
+            "dashboard" => Some(dashboard::run(&raw_args[1..])?),
+            "tail" => Some(tail::run(&raw_args[1..])?),
+            "grep" => Some(grep::run(&raw_args[1..])?),
+            "history" => Some(history::run(&raw_args[1..])?),
+            "diff" => Some(diff::run(&raw_args[1..])?),
+            "clean" => Some(clean::run(&raw_args[1..])?),
+            _ => None,
+        };
+        if let Some(code) = code {
+            return Ok(code);
+        }
+    }
+
+    // `bless` runs a build with warnings enabled and records what it finds as the baseline
+    let bless = raw_args.first().is_some_and(|arg| arg == "bless");
+    let raw_args = if bless { raw_args[1..].to_vec() } else { raw_args };
+    // `miri` runs `cargo miri test` or `cargo miri run` in place of the build, the tests by default
+    let miri = raw_args.first().is_some_and(|arg| arg == "miri").then(|| match raw_args.get(1).map(String::as_str) {
+        Some(command @ ("test" | "run")) => (command.to_string(), 2),
+        _ => ("test".to_string(), 1),
+    });
+    let (miri, raw_args) = match miri {
+        Some((command, skip)) => (Some(command), raw_args[skip..].to_vec()),
+        None => (None, raw_args),
+    };
+    let (tool_args, _) = separate_arguments(&raw_args);
+    let mut config = parse_args(raw_args)?;
+    config.miri = miri;
+    // Nothing is hidden while the baseline is being recorded
+    let baseline_path = if bless { config.baseline.take() } else { None };
+    if bless {
+        config.include_warnings = true;
+    }
+    // Like `cargo -C`, so the workspace, the repository and relative paths are all found from there
+    if let Some(directory) = &config.directory {
+        env::set_current_dir(directory)
+            .with_context(|| format!("Failed to change to directory: {}", directory))?;
+    }
+    // Found from the configuration in the directory the build runs in
+    config.cargo_args = match alias::expand(&config) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("cargo-builder: Error: {:#}", e);
+            return Ok(exit_code::USAGE);
+        }
+    };
     
     if !config.quiet {
         eprintln!("cargo-builder: Running build with errors-only output...");
+        if let Some(provider) = config.ci {
+            eprintln!("cargo-builder: Detected {}; color and interactive features are off, the log is kept", provider.name());
+        }
     }
     
-    let exit_code = runner::run_build(&config)?;
-    std::process::exit(exit_code);
+    if config.miri.is_some() && (config.nextest || config.coverage.is_some() || config.fuzz.is_some() || config.flash || config.target_wasm.is_some()) {
+        eprintln!("cargo-builder: Error: miri interprets the program itself; leave out --nextest, --coverage, --fuzz, --flash and --target-wasm");
+        return Ok(exit_code::USAGE);
+    }
+    if config.verify_reproducible && (config.miri.is_some() || context::option_value(&config.cargo_args, &["--target-dir"]).is_some()) {
+        eprintln!("cargo-builder: Error: --verify-reproducible builds with cargo build into a target directory of its own; leave out miri and --target-dir");
+        return Ok(exit_code::USAGE);
+    }
+    // Found from the directory the build runs in, like the aliases
+    if config.fuzz.is_some() {
+        let manifest = match fuzz::manifest(&config.cargo_args) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                eprintln!("cargo-builder: Error: {:#}", e);
+                return Ok(exit_code::USAGE);
+            }
+        };
+        let Some(host) = context::host(&config) else {
+            eprintln!("cargo-builder: Error: --fuzz needs the host target, which `rustc -vV` didn't report");
+            return Ok(exit_code::USAGE);
+        };
+        config.cargo_args = fuzz::cargo_args(&config.cargo_args, &manifest.display().to_string(), host);
+    }
+
+    let targets = matrix::targets(&config.cargo_args);
+    if bless && (!targets.is_empty() || config.feature_matrix.is_some() || config.per_member) {
+        eprintln!("cargo-builder: Error: bless records the baseline of one build, not a matrix");
+        return Ok(exit_code::USAGE);
+    }
+    let mut exit_code = if config.per_member {
+        if !targets.is_empty() || config.feature_matrix.is_some() {
+            eprintln!("cargo-builder: Error: --per-member builds each member once; leave out --feature-matrix and extra --target");
+            return Ok(exit_code::USAGE);
+        }
+        members::run(&config, &tool_args)?
+    } else if let Some(mode) = config.feature_matrix {
+        if !targets.is_empty() {
+            eprintln!("cargo-builder: Error: --feature-matrix builds a single --target");
+            return Ok(exit_code::USAGE);
+        }
+        let feature_sets = match matrix::feature_sets(&config.cargo_args, mode, config.feature_depth) {
+            Ok(feature_sets) => feature_sets,
+            Err(e) => {
+                eprintln!("cargo-builder: Error: {:#}", e);
+                return Ok(exit_code::USAGE);
+            }
+        };
+        matrix::run(&config, "Feature set", feature_sets, true)?
+    } else if !targets.is_empty() {
+        matrix::run(&config, "Target", targets, false)?
+    } else {
+        runner::run_build(&config, None)?.exit_code
+    };
+    if bless {
+        exit_code = baseline::bless(baseline_path.as_deref(), context::manifest_path(&config.cargo_args), exit_code)?;
+    }
+    Ok(exit_code)
 }
 
 fn separate_arguments(args: &[String]) -> (Vec<String>, Vec<String>) {
     // Define our tool's flags that take values
     let tool_flags_with_values = [
         "--log",
+        "--log-keep",
+        "--log-max-size",
         "--log-color", 
+        "--paths",
+        "--path-map",
         "--terminal-color",
+        "--viewer",
+        "--syslog",
+        "--syslog-facility",
+        "--capture-json",
+        "--webhook",
+        "--webhook-timeout",
+        "--notify-threshold",
+        "--github-pr",
+        "--statsd",
+        "--baseline",
+        "--against",
+        "--exit-code",
+        "--timeout",
+        "--lock-timeout",
+        "--max-memory",
+        "--retry",
+        "--redact",
+        "--redact-env",
+        "--toolchain",
+        "--cargo-path",
+        "--container",
+        "--container-engine",
+        "--chip",
+        "--probe",
+        "-C",
+        "--feature-matrix",
+        "--feature-depth",
+        "--member-jobs",
+        "--label",
+        "--out-dir",
+        "--out-name",
+    ];
+
+    // Define our tool's flags whose value is optional; it is taken from the next
+    // argument only if that isn't a flag, since `cargo build` has no positional arguments
+    let tool_flags_with_optional_values = [
+        "--changed-only",
+        "--nice",
+        "--target-wasm",
+        "--wasm-bindgen",
+        "--wasm-opt",
+        "--audit",
+        "--deny-check",
+        "--coverage",
+        "--fuzz",
+        "--bloat",
+        "--list-artifacts",
     ];
 
     // Define our tool's boolean flags
     let tool_boolean_flags = [
         "--log-on-success",
+        "--log-append",
+        "--log-split",
+        "--log-compress",
+        "--log-per-run",
+        "--journald",
+        "--event-log",
+        "--wait",
+        "--redact-home",
         "--include-warnings", 
         "--show-build-output",
         "--quiet", "-q",
+        "--view",
+        "--bell",
+        "--webhook-include-log",
+        "--github-comment",
+        "--diff-last",
+        "--fail-on-warning-increase",
+        "--crate-timings",
+        // Only the bare flag; `--timings=json` and the like go to cargo as given
+        "--timings",
+        "--blame",
+        "--annotations",
+        "--no-ci",
+        "--auto-jobs",
+        "--offline-fallback",
+        "--fetch",
+        "--per-member",
+        "--cross",
+        "--flash",
+        "--nextest",
+        "--no-doctests",
+        "--udeps",
+        "--sizes",
+        "--checksums",
+        "--verify-reproducible",
         "--help", "-h",
         "--version", "-V",
     ];
@@ -73,6 +559,13 @@ fn separate_arguments(args: &[String]) -> (Vec<String>, Vec<String>) {
     let mut cargo_args = Vec::new();
     let mut i = 0;
 
+    // `+nightly` picks the toolchain as it does for cargo, but only as the first argument
+    if let Some(toolchain) = args.first().and_then(|arg| arg.strip_prefix('+')) {
+        tool_args.push("--toolchain".to_string());
+        tool_args.push(toolchain.to_string());
+        i += 1;
+    }
+
     while i < args.len() {
         let arg = &args[i];
         
@@ -87,6 +580,13 @@ fn separate_arguments(args: &[String]) -> (Vec<String>, Vec<String>) {
         if tool_boolean_flags.contains(&arg.as_str()) {
             tool_args.push(arg.clone());
             i += 1;
+        } else if tool_flags_with_optional_values.iter().any(|flag| arg == flag || arg.starts_with(&format!("{}=", flag))) {
+            tool_args.push(arg.clone());
+            i += 1;
+            if !arg.contains('=') && i < args.len() && !args[i].starts_with('-') {
+                tool_args.push(args[i].clone());
+                i += 1;
+            }
         } else if tool_flags_with_values.contains(&arg.as_str()) {
             tool_args.push(arg.clone());
             // Take the next argument as the value
@@ -108,29 +608,70 @@ fn separate_arguments(args: &[String]) -> (Vec<String>, Vec<String>) {
     (tool_args, cargo_args)
 }
 
-fn parse_args() -> Result<Config> {
+/// Take `--message-format` out of the cargo arguments, as cargo-builder picks the format
+/// itself and cargo rejects a second kind. `short` is kept as a display preference;
+/// JSON formats would leave nothing for cargo-builder to show.
+fn take_message_format(cargo_args: &mut Vec<String>) -> std::result::Result<bool, clap::Error> {
+    let mut short = false;
+    let mut i = 0;
+    while i < cargo_args.len() && cargo_args[i] != "--" {
+        let value = if cargo_args[i] == "--message-format" {
+            let value = cargo_args.get(i + 1).cloned().unwrap_or_default();
+            cargo_args.drain(i..(i + 2).min(cargo_args.len()));
+            value
+        } else if let Some(value) = cargo_args[i].strip_prefix("--message-format=") {
+            let value = value.to_string();
+            cargo_args.remove(i);
+            value
+        } else {
+            i += 1;
+            continue;
+        };
+        for format in value.split(',').map(str::trim) {
+            match format {
+                "human" => short = false,
+                "short" => short = true,
+                format if format.starts_with("json") => return Err(clap::Error::raw(
+                    clap::error::ErrorKind::ArgumentConflict,
+                    format!("--message-format {} can't be passed to cargo, as cargo-builder reads cargo's JSON messages itself; use --capture-json <PATH> to save them\n", format),
+                )),
+                format => return Err(clap::Error::raw(
+                    clap::error::ErrorKind::InvalidValue,
+                    format!("invalid --message-format '{}'; cargo-builder accepts human or short\n", format),
+                )),
+            }
+        }
+    }
+    Ok(short)
+}
+
+fn raw_args() -> Vec<String> {
     // Handle cargo subcommand - when called as "cargo builder", the first arg is "builder"
     let args: Vec<String> = env::args().collect();
-    let raw_args = if args.len() > 1 && args[1] == "builder" {
+    if args.len() > 1 && args[1] == "builder" {
         // Skip the "builder" subcommand argument
         args[2..].to_vec()
     } else {
         args[1..].to_vec()
-    };
+    }
+}
 
+fn parse_args(raw_args: Vec<String>) -> Result<Config> {
     // Separate our tool flags from cargo flags
-    let (tool_args, cargo_args) = separate_arguments(&raw_args);
+    let (tool_args, mut cargo_args) = separate_arguments(&raw_args);
+    let short_messages = take_message_format(&mut cargo_args)?;
 
     // Parse our tool's arguments
     let matches = Command::new("cargo-builder")
         .about("A Cargo build wrapper that shows errors-only output with optional logging")
-        .long_about("A Cargo build wrapper that shows errors-only output with optional logging.\n\nUsage:\n  cargo builder [OPTIONS] [cargo-build-args...]\n  cargo-builder [OPTIONS] [cargo-build-args...]")
+        .long_about("A Cargo build wrapper that shows errors-only output with optional logging.\n\nUsage:\n  cargo builder [OPTIONS] [cargo-build-args...]\n  cargo-builder [OPTIONS] [cargo-build-args...]\n\nSubcommands:\n  search <PATTERN>  Search the diagnostics collected by the last build\n  dashboard         Browse previous builds and their diagnostics\n  tail              Follow the error log while a build writes it\n  grep [PATTERN]    Search the diagnostics of all recorded builds\n  history [show ID] List recent builds, or re-render one's diagnostics\n  diff [RUN_A] [RUN_B]  Compare the diagnostics of two recorded builds\n  bless [OPTIONS] [cargo-build-args...]  Build and write the diagnostics found to the --baseline file\n  miri [test|run] [OPTIONS] [cargo-args...]  Run the tests (by default) or the program under Miri, reporting undefined behavior like errors")
         .version("0.1.0")
         .arg(
             Arg::new("log")
                 .long("log")
                 .value_name("PATH")
-                .help("Target log file path")
+                .action(ArgAction::Append)
+                .help("Target log file path; repeat for several logs, prefix with text: or jsonl: to pick the format")
         )
         .arg(
             Arg::new("log-on-success")
@@ -138,6 +679,119 @@ fn parse_args() -> Result<Config> {
                 .action(ArgAction::SetTrue)
                 .help("Keep the log file even on success")
         )
+        .arg(
+            Arg::new("log-keep")
+                .long("log-keep")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("0")
+                .help("Keep the N previous logs as <name>.1.log ... <name>.N.log")
+        )
+        .arg(
+            Arg::new("log-append")
+                .long("log-append")
+                .action(ArgAction::SetTrue)
+                .help("Append to the log with a per-run separator instead of overwriting it")
+        )
+        .arg(
+            Arg::new("log-split")
+                .long("log-split")
+                .action(ArgAction::SetTrue)
+                .help("Also write separate logs per target kind (lib, bin, test, bench, ...)")
+        )
+        .arg(
+            Arg::new("log-max-size")
+                .long("log-max-size")
+                .value_name("SIZE")
+                .value_parser(util::parse_size)
+                .help("Cap the log size per run, keeping its head and tail (e.g. 5MB)")
+        )
+        .arg(
+            Arg::new("log-compress")
+                .long("log-compress")
+                .action(ArgAction::SetTrue)
+                .help("Gzip the log file (implied by a --log path ending in .gz)")
+        )
+        .arg(
+            Arg::new("log-per-run")
+                .long("log-per-run")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["log", "log-append"])
+                .help("Write each run to its own timestamped log under target/cargo-builder/logs, linked as latest.log")
+        )
+        .arg(
+            Arg::new("syslog")
+                .long("syslog")
+                .value_name("TARGET")
+                .help("Also send logged diagnostics to syslog: \"local\" or a UDP host:port")
+        )
+        .arg(
+            Arg::new("syslog-facility")
+                .long("syslog-facility")
+                .value_name("FACILITY")
+                .value_parser(syslog::FACILITIES)
+                .default_value("user")
+                .help("Syslog facility for --syslog")
+        )
+        .arg(
+            Arg::new("journald")
+                .long("journald")
+                .action(ArgAction::SetTrue)
+                .help("Also send logged diagnostics to systemd-journald (Linux)")
+        )
+        .arg(
+            Arg::new("event-log")
+                .long("event-log")
+                .action(ArgAction::SetTrue)
+                .help("Write a Windows Event Log entry when the build fails (Windows)")
+        )
+        .arg(
+            Arg::new("redact")
+                .long("redact")
+                .value_name("REGEX")
+                .action(ArgAction::Append)
+                .help("Replace text matching REGEX with [REDACTED] in logs and remote sinks")
+        )
+        .arg(
+            Arg::new("redact-env")
+                .long("redact-env")
+                .value_name("VAR")
+                .action(ArgAction::Append)
+                .help("Replace the value of environment variable VAR with $VAR in logs and remote sinks")
+        )
+        .arg(
+            Arg::new("redact-home")
+                .long("redact-home")
+                .action(ArgAction::SetTrue)
+                .help("Replace the home directory with ~ in logs and remote sinks")
+        )
+        .arg(
+            Arg::new("wait")
+                .long("wait")
+                .action(ArgAction::SetTrue)
+                .help("Wait for other builds writing the same log instead of failing")
+        )
+        .arg(
+            Arg::new("capture-json")
+                .long("capture-json")
+                .value_name("PATH")
+                .help("Copy every JSON message cargo emits to PATH, unfiltered")
+        )
+        .arg(
+            Arg::new("paths")
+                .long("paths")
+                .value_name("STYLE")
+                .value_parser(["workspace-relative", "absolute"])
+                .help("Show file paths in diagnostics relative to the workspace root or as absolute paths")
+        )
+        .arg(
+            Arg::new("path-map")
+                .long("path-map")
+                .value_name("FROM=TO")
+                .value_parser(paths::parse_path_map)
+                .action(ArgAction::Append)
+                .help("Rewrite paths starting with FROM to start with TO, e.g. /container/src=/home/me/src")
+        )
         .arg(
             Arg::new("log-color")
                 .long("log-color")
@@ -172,23 +826,614 @@ fn parse_args() -> Result<Config> {
                 .action(ArgAction::SetTrue)
                 .help("Minimize plugin output")
         )
+        .arg(
+            Arg::new("bell")
+                .long("bell")
+                .action(ArgAction::SetTrue)
+                .help("Ring the terminal bell (or run notify.sound_command) when the build fails")
+        )
+        .arg(
+            Arg::new("webhook")
+                .long("webhook")
+                .value_name("URL")
+                .action(ArgAction::Append)
+                .help("POST a JSON summary of the build to URL when it finishes")
+        )
+        .arg(
+            Arg::new("webhook-include-log")
+                .long("webhook-include-log")
+                .action(ArgAction::SetTrue)
+                .help("Include the error log's contents in --webhook payloads")
+        )
+        .arg(
+            Arg::new("webhook-timeout")
+                .long("webhook-timeout")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration)
+                .default_value("10s")
+                .help("Timeout for each notification request")
+        )
+        .arg(
+            Arg::new("notify-threshold")
+                .long("notify-threshold")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration)
+                .help("Only notify (and ring --bell) for builds that take at least DURATION")
+        )
+        .arg(
+            Arg::new("github-comment")
+                .long("github-comment")
+                .action(ArgAction::SetTrue)
+                .help("Post or update a summary comment on the pull request being built in GitHub Actions")
+        )
+        .arg(
+            Arg::new("github-pr")
+                .long("github-pr")
+                .value_name("[OWNER/REPO#]NUMBER")
+                .value_parser(github::parse_pull_request)
+                .help("Comment on this pull request instead of the detected one (implies --github-comment)")
+        )
+        .arg(
+            Arg::new("statsd")
+                .long("statsd")
+                .value_name("HOST:PORT")
+                .help("Send build duration and error/warning counts to this StatsD agent")
+        )
+        .arg(
+            Arg::new("baseline")
+                .long("baseline")
+                .value_name("FILE")
+                .help("Hide diagnostics listed in this baseline file; only the rest are reported and fail the build")
+        )
+        .arg(
+            Arg::new("diff-last")
+                .long("diff-last")
+                .action(ArgAction::SetTrue)
+                .help("After the build, list diagnostics that are new or fixed since the previous build")
+        )
+        .arg(
+            Arg::new("fail-on-warning-increase")
+                .long("fail-on-warning-increase")
+                .action(ArgAction::SetTrue)
+                .help("Fail when there are more warnings than in the last run of the same command on this branch")
+        )
+        .arg(
+            Arg::new("crate-timings")
+                .long("crate-timings")
+                .action(ArgAction::SetTrue)
+                .help("After the build, list the crates that took longest to compile")
+        )
+        .arg(
+            Arg::new("changed-only")
+                .long("changed-only")
+                .value_name("REF")
+                .num_args(0..=1)
+                .default_missing_value("main")
+                .help("Only show diagnostics in files changed since the merge base with REF (default: main, or master)")
+        )
+        .arg(
+            Arg::new("against")
+                .long("against")
+                .value_name("REF")
+                .help("Also build REF (or reuse its cached build) and only report diagnostics it doesn't have")
+        )
+        .arg(
+            Arg::new("blame")
+                .long("blame")
+                .action(ArgAction::SetTrue)
+                .help("Note the author and commit of each diagnostic's line, from git blame")
+        )
+        .arg(
+            Arg::new("timings")
+                .long("timings")
+                .action(ArgAction::SetTrue)
+                .help("Have cargo write a --timings report, keep it with the logs and summarize it")
+        )
+        .arg(
+            Arg::new("view")
+                .long("view")
+                .action(ArgAction::SetTrue)
+                .help("Open the error log in a viewer when the build fails")
+        )
+        .arg(
+            Arg::new("viewer")
+                .long("viewer")
+                .value_name("COMMAND")
+                .help("Viewer for --view (default: $PAGER, then $EDITOR)")
+        )
+        .arg(
+            Arg::new("exit-code")
+                .long("exit-code")
+                .value_name("POLICY")
+                .value_parser(["detailed", "cargo", "errors", "never"])
+                .default_value("detailed")
+                .help("Exit with a distinct code per kind of failure, with cargo's code, with 1 only if errors were shown (after filters and baselines), or always with 0")
+        )
+        .arg(
+            Arg::new("timeout")
+                .long("timeout")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration)
+                .help("Stop cargo and everything it started when the build takes longer than DURATION, e.g. 15m")
+        )
+        .arg(
+            Arg::new("lock-timeout")
+                .long("lock-timeout")
+                .value_name("DURATION")
+                .value_parser(humantime::parse_duration)
+                .help("Stop the build when cargo waits longer than DURATION for a file lock another process holds")
+        )
+        .arg(
+            Arg::new("max-memory")
+                .long("max-memory")
+                .value_name("SIZE")
+                .value_parser(util::parse_size)
+                .help("Stop the build when cargo and everything it started use more than SIZE of memory, e.g. 8G")
+        )
+        .arg(
+            Arg::new("retry")
+                .long("retry")
+                .value_name("N")
+                .value_parser(clap::value_parser!(u32))
+                .help("Start the build again up to N times when it fails on a network blip or a lock (default: 2 in CI, otherwise 0)")
+        )
+        .arg(
+            Arg::new("offline-fallback")
+                .long("offline-fallback")
+                .action(ArgAction::SetTrue)
+                .help("Build once more with --offline when fetching dependencies fails on a network error")
+        )
+        .arg(
+            Arg::new("feature-matrix")
+                .long("feature-matrix")
+                .value_name("MODE")
+                .value_parser(["each-feature", "powerset"])
+                .help("Build the package once per feature set: with each feature on its own, or with every combination")
+        )
+        .arg(
+            Arg::new("feature-depth")
+                .long("feature-depth")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Combine at most N features with --feature-matrix powerset")
+        )
+        .arg(
+            Arg::new("per-member")
+                .long("per-member")
+                .action(ArgAction::SetTrue)
+                .help("Build each workspace member (or each chosen with -p) on its own, with a table of their progress")
+        )
+        .arg(
+            Arg::new("member-jobs")
+                .long("member-jobs")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1")
+                .help("Build up to N members at once with --per-member, each in a target directory of its own")
+        )
+        .arg(
+            // Set by --per-member for the build of each member
+            Arg::new("label")
+                .long("label")
+                .value_name("LABEL")
+                .hide(true)
+        )
+        .arg(
+            Arg::new("fetch")
+                .long("fetch")
+                .action(ArgAction::SetTrue)
+                .help("Run cargo fetch before building and explain why dependencies can't be fetched")
+        )
+        .arg(
+            Arg::new("auto-jobs")
+                .long("auto-jobs")
+                .action(ArgAction::SetTrue)
+                .help("Pass cargo a -j that fits the CPUs the system load leaves idle, keeping one free")
+        )
+        .arg(
+            Arg::new("nice")
+                .long("nice")
+                .value_name("N")
+                .num_args(0..=1)
+                .default_missing_value("10")
+                .value_parser(clap::value_parser!(u8).range(1..=19))
+                .help("Run cargo and everything it starts at lower CPU and I/O priority (niceness N, default 10; below normal on Windows)")
+        )
+        .arg(
+            Arg::new("toolchain")
+                .long("toolchain")
+                .value_name("NAME")
+                .help("Build with this rustup toolchain, e.g. nightly-2024-06-01; `+NAME` as the first argument does the same")
+        )
+        .arg(
+            Arg::new("cargo-path")
+                .long("cargo-path")
+                .value_name("PROGRAM")
+                .help("Run this cargo instead of $CARGO or cargo from PATH, e.g. cross or a wrapper script")
+        )
+        .arg(
+            Arg::new("cross")
+                .long("cross")
+                .action(ArgAction::SetTrue)
+                .conflicts_with("cargo-path")
+                .help("Build with cross from PATH and show paths in its containers as the host's")
+        )
+        .arg(
+            Arg::new("container")
+                .long("container")
+                .value_name("IMAGE")
+                .conflicts_with_all(["cargo-path", "cross"])
+                .help("Build in a container of IMAGE with the workspace mounted, showing its paths as the host's")
+        )
+        .arg(
+            Arg::new("container-engine")
+                .long("container-engine")
+                .value_name("PROGRAM")
+                .requires("container")
+                .help("Run --container with PROGRAM instead of docker or podman from PATH")
+        )
+        .arg(
+            Arg::new("target-wasm")
+                .long("target-wasm")
+                .value_name("TARGET")
+                .num_args(0..=1)
+                .default_missing_value(wasm::DEFAULT_TARGET)
+                .help("Build for a wasm target, wasm32-unknown-unknown by default, checking first that it's installed")
+        )
+        .arg(
+            Arg::new("wasm-bindgen")
+                .long("wasm-bindgen")
+                .value_name("TARGET")
+                .num_args(0..=1)
+                .default_missing_value("bundler")
+                .value_parser(["bundler", "web", "nodejs", "no-modules", "deno", "experimental-nodejs-module"])
+                .requires("target-wasm")
+                .help("After a successful build, run wasm-bindgen --target TARGET on the modules built, into bindgen/ next to them")
+        )
+        .arg(
+            Arg::new("wasm-opt")
+                .long("wasm-opt")
+                .value_name("LEVEL")
+                .num_args(0..=1)
+                .default_missing_value("s")
+                .value_parser(["0", "1", "2", "3", "4", "s", "z"])
+                .requires("target-wasm")
+                .help("After a successful build, optimize the modules built with wasm-opt -O<LEVEL> (s by default)")
+        )
+        .arg(
+            Arg::new("nextest")
+                .long("nextest")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["flash", "wasm-bindgen", "wasm-opt"])
+                .help("Run the tests with cargo nextest run, showing compile errors and failed tests together")
+        )
+        .arg(
+            Arg::new("coverage")
+                .long("coverage")
+                .value_name("FORMAT")
+                .num_args(0..=1)
+                .default_missing_value("html")
+                .value_parser(coverage::FORMATS)
+                .conflicts_with_all(["nextest", "flash", "target-wasm"])
+                .help("Run the tests with cargo llvm-cov, then print the coverage and write a detailed report (html by default)")
+        )
+        .arg(
+            Arg::new("fuzz")
+                .long("fuzz")
+                .value_name("SANITIZER")
+                .num_args(0..=1)
+                .default_missing_value("address")
+                .value_parser(fuzz::SANITIZERS)
+                .conflicts_with_all(["nextest", "coverage", "flash", "target-wasm"])
+                .help("Build the fuzz targets in fuzz/ as cargo fuzz build does, with the address sanitizer by default")
+        )
+        .arg(
+            Arg::new("no-doctests")
+                .long("no-doctests")
+                .action(ArgAction::SetTrue)
+                .requires("nextest")
+                .help("Skip the doctests --nextest runs with cargo test --doc afterwards")
+        )
+        .arg(
+            Arg::new("audit")
+                .long("audit")
+                .value_name("SEVERITY")
+                .num_args(0..=1)
+                .default_missing_value("low")
+                .value_parser(["low", "medium", "high", "critical"])
+                .help("After a successful build, run cargo audit on Cargo.lock; vulnerabilities at or above SEVERITY (low by default) are errors, the rest warnings")
+        )
+        .arg(
+            Arg::new("deny-check")
+                .long("deny-check")
+                .value_name("CHECKS")
+                .num_args(0..=1)
+                .default_missing_value("all")
+                .value_parser(deny::parse_checks)
+                .help("After a successful build, run these cargo deny checks (advisories,bans,licenses,sources; all by default), showing what they find like compiler diagnostics")
+        )
+        .arg(
+            Arg::new("udeps")
+                .long("udeps")
+                .action(ArgAction::SetTrue)
+                .help("After a successful build, warn of dependencies that look unused, with cargo udeps if it's installed")
+        )
+        .arg(
+            Arg::new("sizes")
+                .long("sizes")
+                .action(ArgAction::SetTrue)
+                .help("After a successful build, print the size of each program and library built and how it changed since the last build")
+        )
+        .arg(
+            Arg::new("bloat")
+                .long("bloat")
+                .value_name("N")
+                .num_args(0..=1)
+                .default_missing_value("10")
+                .value_parser(clap::value_parser!(usize))
+                .help("Like --sizes, and break down each program's size by crate with cargo bloat, the N largest (10 by default)")
+        )
+        .arg(
+            Arg::new("out-dir")
+                .long("out-dir")
+                .value_name("PATH")
+                .help("After a successful build, copy the programs and libraries built from the workspace into PATH")
+        )
+        .arg(
+            Arg::new("out-name")
+                .long("out-name")
+                .value_name("TEMPLATE")
+                .value_parser(artifacts::parse_template)
+                .help("Name the copies of --out-dir after TEMPLATE, with {name}, {file}, {stem}, {ext}, {kind}, {version}, {target} and {profile}")
+        )
+        .arg(
+            Arg::new("checksums")
+                .long("checksums")
+                .action(ArgAction::SetTrue)
+                .help("After a successful build, print the SHA-256 of each program and library built, and add it to summary.json")
+        )
+        .arg(
+            Arg::new("verify-reproducible")
+                .long("verify-reproducible")
+                .action(ArgAction::SetTrue)
+                .conflicts_with_all(["nextest", "coverage", "fuzz", "cross", "container"])
+                .help("After a successful build, build again from scratch in another directory and fail if any program or library differs")
+        )
+        .arg(
+            Arg::new("list-artifacts")
+                .long("list-artifacts")
+                .value_name("FORMAT")
+                .num_args(0..=1)
+                .default_missing_value("text")
+                .value_parser(["text", "json"])
+                .help("After a successful build, print the programs and libraries of the workspace it produced, with their paths, to stdout")
+        )
+        .arg(
+            Arg::new("flash")
+                .long("flash")
+                .action(ArgAction::SetTrue)
+                .help("After a successful build, flash the program built with probe-rs and reset the chip")
+        )
+        .arg(
+            Arg::new("chip")
+                .long("chip")
+                .value_name("NAME")
+                .help("The chip --flash writes to, as probe-rs names it (or `chip` under [flash] in cargo-builder.toml)")
+        )
+        .arg(
+            Arg::new("probe")
+                .long("probe")
+                .value_name("VID:PID[:SERIAL]")
+                .help("The debug probe --flash uses when several are connected")
+        )
+        .arg(
+            Arg::new("directory")
+                .short('C')
+                .value_name("DIR")
+                .help("Change to DIR before doing anything, as if cargo-builder was started there")
+        )
+        .arg(
+            Arg::new("annotations")
+                .long("annotations")
+                .action(ArgAction::SetTrue)
+                .help("Print GitHub Actions ::error/::warning commands for each diagnostic (on by default in Actions)")
+        )
+        .arg(
+            Arg::new("no-ci")
+                .long("no-ci")
+                .action(ArgAction::SetTrue)
+                .help("Keep the local defaults when a CI environment is detected")
+        )
         .try_get_matches_from(std::iter::once("cargo-builder".to_string()).chain(tool_args))?;
 
+    // A --target like any other from here on
+    let target_wasm = matches.get_one::<String>("target-wasm").cloned();
+    if let Some(target) = &target_wasm {
+        if context::option_value(&cargo_args, &["--target"]).is_some() {
+            anyhow::bail!("--target-wasm can't be combined with --target");
+        }
+        let end = cargo_args.iter().position(|arg| arg == "--").unwrap_or(cargo_args.len());
+        cargo_args.splice(end..end, ["--target".to_string(), target.clone()]);
+    }
+
+    // CI logs aren't terminals and nobody is there to look at a pager
+    let ci = if matches.get_flag("no-ci") { None } else { ci::detect() };
+
     let config = Config {
-        log_path: matches.get_one::<String>("log").cloned(),
-        log_on_success: matches.get_flag("log-on-success"),
+        log_paths: matches.get_many::<String>("log").map(|v| v.cloned().collect()).unwrap_or_default(),
+        log_on_success: matches.get_flag("log-on-success") || ci.is_some(),
+        log_keep: *matches.get_one::<usize>("log-keep").unwrap(),
+        log_append: matches.get_flag("log-append"),
+        log_split: matches.get_flag("log-split"),
+        log_max_size: matches.get_one::<u64>("log-max-size").copied(),
+        log_compress: matches.get_flag("log-compress"),
+        syslog: matches.get_one::<String>("syslog").cloned(),
+        syslog_facility: matches.get_one::<String>("syslog-facility").unwrap().clone(),
+        journald: matches.get_flag("journald"),
+        event_log: matches.get_flag("event-log"),
+        redact: matches.get_many::<String>("redact").map(|v| v.cloned().collect()).unwrap_or_default(),
+        redact_env: matches.get_many::<String>("redact-env").map(|v| v.cloned().collect()).unwrap_or_default(),
+        redact_home: matches.get_flag("redact-home"),
+        wait: matches.get_flag("wait"),
+        log_per_run: matches.get_flag("log-per-run"),
+        capture_json: matches.get_one::<String>("capture-json").cloned(),
+        paths: matches.get_one::<String>("paths")
+            .map(|s| s.parse())
+            .transpose()?,
+        path_maps: matches.get_many::<(String, String)>("path-map").map(|v| v.cloned().collect()).unwrap_or_default(),
         log_color: matches.get_one::<String>("log-color")
             .unwrap()
             .parse()?,
         terminal_color: matches.get_one::<String>("terminal-color")
             .map(|s| s.parse())
             .transpose()?
-            .unwrap_or(ColorChoice::Auto),
-        include_warnings: matches.get_flag("include-warnings"),
+            .unwrap_or(if ci.is_some() { ColorChoice::Never } else { ColorChoice::Auto }),
+        // The trend needs the warnings compiled in to count them
+        include_warnings: matches.get_flag("include-warnings") || matches.get_flag("fail-on-warning-increase"),
         show_build_output: matches.get_flag("show-build-output"),
         quiet: matches.get_flag("quiet"),
+        bell: matches.get_flag("bell") && ci.is_none(),
+        webhooks: matches.get_many::<String>("webhook").map(|v| v.cloned().collect()).unwrap_or_default(),
+        webhook_include_log: matches.get_flag("webhook-include-log"),
+        webhook_timeout: *matches.get_one::<std::time::Duration>("webhook-timeout").unwrap(),
+        notify_threshold: matches.get_one::<std::time::Duration>("notify-threshold").copied(),
+        github_comment: matches.get_flag("github-comment"),
+        github_pr: matches.get_one::<github::PullRequest>("github-pr").cloned(),
+        statsd: matches.get_one::<String>("statsd").cloned(),
+        diff_last: matches.get_flag("diff-last"),
+        fail_on_warning_increase: matches.get_flag("fail-on-warning-increase"),
+        crate_timings: matches.get_flag("crate-timings"),
+        timings: matches.get_flag("timings"),
+        changed_only: matches.get_one::<String>("changed-only").cloned(),
+        blame: matches.get_flag("blame"),
+        against: matches.get_one::<String>("against").cloned(),
+        baseline: matches.get_one::<String>("baseline").cloned(),
+        view: matches.get_flag("view") && ci.is_none(),
+        viewer: matches.get_one::<String>("viewer").cloned(),
+        annotations: matches.get_flag("annotations") || ci.is_some_and(|provider| provider.workflow_commands()),
+        ci,
+        exit_code: matches.get_one::<String>("exit-code")
+            .unwrap()
+            .parse()?,
+        timeout: matches.get_one::<std::time::Duration>("timeout").copied(),
+        lock_timeout: matches.get_one::<std::time::Duration>("lock-timeout").copied(),
+        max_memory: matches.get_one::<u64>("max-memory").copied(),
+        retry: matches.get_one::<u32>("retry").copied()
+            .unwrap_or(if ci.is_some() { retry::CI_RETRIES } else { 0 }),
+        offline_fallback: matches.get_flag("offline-fallback"),
+        fetch: matches.get_flag("fetch"),
+        label: matches.get_one::<String>("label").cloned(),
+        feature_matrix: matches.get_one::<String>("feature-matrix")
+            .map(|s| s.parse())
+            .transpose()?,
+        feature_depth: matches.get_one::<usize>("feature-depth").copied(),
+        per_member: matches.get_flag("per-member"),
+        member_jobs: *matches.get_one::<usize>("member-jobs").unwrap(),
+        auto_jobs: matches.get_flag("auto-jobs"),
+        nice: matches.get_one::<u8>("nice").copied(),
+        toolchain: matches.get_one::<String>("toolchain").cloned(),
+        // `cargo builder` sets $CARGO to the cargo that ran it, which a `--toolchain` must not bypass
+        cargo: matches.get_one::<String>("cargo-path").cloned()
+            .or_else(|| matches.get_flag("cross").then(|| "cross".to_string()))
+            .or_else(|| env::var("CARGO").ok().filter(|_| !matches.contains_id("toolchain")))
+            .unwrap_or_else(|| "cargo".to_string()),
+        cross: matches.get_flag("cross"),
+        container: matches.get_one::<String>("container").cloned(),
+        container_engine: matches.get_one::<String>("container-engine").cloned(),
+        target_wasm,
+        wasm_bindgen: matches.get_one::<String>("wasm-bindgen").cloned(),
+        wasm_opt: matches.get_one::<String>("wasm-opt").cloned(),
+        nextest: matches.get_flag("nextest"),
+        doctests: matches.get_flag("nextest") && !matches.get_flag("no-doctests"),
+        miri: None,
+        coverage: matches.get_one::<String>("coverage").cloned(),
+        fuzz: matches.get_one::<String>("fuzz").cloned(),
+        deny_check: matches.get_one::<String>("deny-check").cloned(),
+        udeps: matches.get_flag("udeps"),
+        sizes: matches.get_flag("sizes") || matches.contains_id("bloat"),
+        bloat: matches.get_one::<usize>("bloat").copied(),
+        list_artifacts: matches.get_one::<String>("list-artifacts").cloned(),
+        out_dir: matches.get_one::<String>("out-dir").cloned(),
+        out_name: matches.get_one::<String>("out-name").cloned(),
+        checksums: matches.get_flag("checksums"),
+        verify_reproducible: matches.get_flag("verify-reproducible"),
+        audit: matches.get_one::<String>("audit").map(|severity| severity.parse()).transpose()?,
+        flash: matches.get_flag("flash"),
+        flash_chip: matches.get_one::<String>("chip").cloned(),
+        flash_probe: matches.get_one::<String>("probe").cloned(),
+        directory: matches.get_one::<String>("directory").cloned(),
+        short_messages,
         cargo_args,
     };
 
     Ok(config)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_separate_toolchain() {
+        let (tool, cargo) = separate_arguments(&args(&["+nightly-2024-06-01", "--release", "--quiet"]));
+        assert_eq!(tool, args(&["--toolchain", "nightly-2024-06-01", "--quiet"]));
+        assert_eq!(cargo, args(&["--release"]));
+        // Only the first argument, as for cargo
+        let (tool, cargo) = separate_arguments(&args(&["--release", "+nightly"]));
+        assert!(tool.is_empty());
+        assert_eq!(cargo, args(&["--release", "+nightly"]));
+
+        let config = parse_args(args(&["--toolchain", "stable", "-p", "app"])).unwrap();
+        assert_eq!(config.toolchain.as_deref(), Some("stable"));
+        assert_eq!(runner::format_command(&config), "cargo +stable build -p app");
+        assert_eq!(config.cargo, "cargo");
+    }
+
+    #[test]
+    fn test_target_wasm() {
+        let config = parse_args(args(&["--target-wasm", "--release"])).unwrap();
+        assert_eq!(config.target_wasm.as_deref(), Some("wasm32-unknown-unknown"));
+        assert_eq!(config.cargo_args, args(&["--release", "--target", "wasm32-unknown-unknown"]));
+
+        let config = parse_args(args(&["--target-wasm", "wasm32-wasip1", "--wasm-opt", "--wasm-bindgen=web"])).unwrap();
+        assert_eq!(config.cargo_args, args(&["--target", "wasm32-wasip1"]));
+        assert_eq!(config.wasm_opt.as_deref(), Some("s"));
+        assert_eq!(config.wasm_bindgen.as_deref(), Some("web"));
+
+        assert!(parse_args(args(&["--target-wasm", "--target", "x86_64-unknown-linux-gnu"])).is_err());
+        assert!(parse_args(args(&["--wasm-opt"])).is_err());
+    }
+
+    #[test]
+    fn test_take_message_format() {
+        let mut cargo_args = args(&["--release", "--message-format", "short", "-p", "app"]);
+        assert!(take_message_format(&mut cargo_args).unwrap());
+        assert_eq!(cargo_args, args(&["--release", "-p", "app"]));
+
+        let mut cargo_args = args(&["--message-format=short", "--message-format=human"]);
+        assert!(!take_message_format(&mut cargo_args).unwrap());
+        assert!(cargo_args.is_empty());
+
+        assert!(take_message_format(&mut args(&["--message-format=json-render-diagnostics"])).is_err());
+        assert!(take_message_format(&mut args(&["--message-format", "xml"])).is_err());
+        // Arguments after `--` aren't cargo's
+        assert!(!take_message_format(&mut args(&["--", "--message-format=json"])).unwrap());
+    }
+
+    #[test]
+    fn test_cargo_path() {
+        let config = parse_args(args(&["--cargo-path", "/usr/local/bin/cross", "--target", "aarch64-unknown-linux-gnu"])).unwrap();
+        assert_eq!(config.cargo, "/usr/local/bin/cross");
+        assert_eq!(runner::format_command(&config), "cross build --target aarch64-unknown-linux-gnu");
+
+        let config = parse_args(args(&["--cross", "--target", "aarch64-unknown-linux-gnu"])).unwrap();
+        assert_eq!(config.cargo, "cross");
+        assert!(parse_args(args(&["--cross", "--cargo-path", "cross"])).is_err());
+        assert!(parse_args(args(&["--container", "rust:1.80", "--cross"])).is_err());
+        assert!(parse_args(args(&["--container-engine", "podman"])).is_err());
+    }
+}
@@ -0,0 +1,357 @@
+use crate::diagnostics::Diagnostic;
+use crate::{context, runner, signals, util, Config};
+use anyhow::{bail, Context, Result};
+use cargo_metadata::MetadataCommand;
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+/// More feature combinations than this take a `--feature-depth` to build.
+const MAX_COMBINATIONS: usize = 64;
+
+/// Which feature sets `--feature-matrix` builds, as cargo-hack's options of the same names.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeatureMatrix {
+    /// No features, each feature on its own, and all features
+    EachFeature,
+    /// Every combination of features, up to `--feature-depth` at a time
+    Powerset,
+}
+
+impl std::str::FromStr for FeatureMatrix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "each-feature" => Ok(FeatureMatrix::EachFeature),
+            "powerset" => Ok(FeatureMatrix::Powerset),
+            _ => Err(anyhow::anyhow!("Invalid feature matrix: {}", s)),
+        }
+    }
+}
+
+/// Diagnostics earlier builds of a matrix showed. Later builds hide them, so an error
+/// that every feature set has is shown once.
+#[derive(Default)]
+pub struct Seen {
+    locations: HashSet<String>,
+}
+
+impl Seen {
+    pub fn contains(&self, diagnostic: &Diagnostic) -> bool {
+        self.locations.contains(&Self::key(diagnostic))
+    }
+
+    /// Remember what a build showed, once it's done; diagnostics alike in one build all count.
+    pub fn add(&mut self, diagnostics: &[Diagnostic]) {
+        self.locations.extend(diagnostics.iter().map(Self::key));
+    }
+
+    /// The fingerprint leaves out the line, which tells apart the same error twice in a file.
+    fn key(diagnostic: &Diagnostic) -> String {
+        format!("{}:{}:{}", diagnostic.fingerprint(), diagnostic.line.unwrap_or(0), diagnostic.column.unwrap_or(0))
+    }
+}
+
+/// One build of a matrix, e.g. for one target triple.
+#[derive(Debug, PartialEq)]
+pub struct Variant {
+    /// Prefixes the build's diagnostics and names its row in the summary
+    pub label: String,
+    pub cargo_args: Vec<String>,
+}
+
+/// How one build of the matrix went.
+struct Row {
+    label: String,
+    outcome: runner::Outcome,
+    duration: Duration,
+}
+
+/// A build per `--target` when more than one is given; none otherwise, as cargo
+/// builds a single target as usual.
+pub fn targets(args: &[String]) -> Vec<Variant> {
+    let targets = context::option_values(args, &["--target"]);
+    if targets.len() < 2 {
+        return Vec::new();
+    }
+    let rest = without_option(args, "--target");
+    targets.into_iter().map(|target| {
+        let mut cargo_args = rest.clone();
+        cargo_args.insert(0, format!("--target={}", target));
+        Variant { label: target.to_string(), cargo_args }
+    }).collect()
+}
+
+/// A build per feature set of the package being built: the one chosen with `-p`, or
+/// the one in the current directory.
+pub fn feature_sets(args: &[String], mode: FeatureMatrix, depth: Option<usize>) -> Result<Vec<Variant>> {
+    let mut metadata = MetadataCommand::new();
+    metadata.no_deps();
+    if let Some(manifest_path) = context::manifest_path(args) {
+        metadata.manifest_path(manifest_path);
+    }
+    let metadata = metadata.exec().context("Failed to get cargo metadata")?;
+    let packages = context::option_values(args, &["-p", "--package"]);
+    let package = match packages[..] {
+        [] => metadata.root_package().context("--feature-matrix needs a package; pick one with -p")?,
+        [name] => metadata.workspace_packages().into_iter().find(|package| package.name == name)
+            .with_context(|| format!("No package `{}` in the workspace", name))?,
+        _ => bail!("--feature-matrix builds one package; pick it with a single -p"),
+    };
+    let features: Vec<&str> = package.features.iter()
+        // Optional dependencies as features of their own, which cargo adds implicitly
+        .filter(|(name, enables)| **enables != [format!("dep:{}", name)])
+        .map(|(name, _)| name.as_str())
+        .collect();
+
+    // Counted before making them: a powerset of a few dozen features doesn't fit in memory
+    let count = combination_count(features.len(), mode, depth);
+    if count > MAX_COMBINATIONS {
+        bail!(
+            "{} has {} feature combinations; limit them with --feature-depth or use --feature-matrix each-feature",
+            package.name, count,
+        );
+    }
+    let sets = combinations(&features, mode, depth);
+    let mut rest = args.to_vec();
+    for option in ["--features", "-F"] {
+        rest = without_option(&rest, option);
+    }
+    rest.retain(|arg| arg != "--all-features" && arg != "--no-default-features");
+    Ok(sets.into_iter().map(|set| {
+        let (label, mut cargo_args) = match set {
+            None => ("all features".to_string(), vec!["--all-features".to_string()]),
+            Some(set) if set.is_empty() => ("no features".to_string(), vec!["--no-default-features".to_string()]),
+            Some(set) => {
+                let set = set.join(",");
+                (set.clone(), vec!["--no-default-features".to_string(), format!("--features={}", set)])
+            }
+        };
+        cargo_args.extend(rest.iter().cloned());
+        Variant { label, cargo_args }
+    }).collect())
+}
+
+/// The feature sets to build, `None` for `--all-features`. Smaller sets come first, so
+/// sets that share most features follow each other and cargo rebuilds little in between.
+fn combinations<'a>(features: &[&'a str], mode: FeatureMatrix, depth: Option<usize>) -> Vec<Option<Vec<&'a str>>> {
+    let mut features = features.to_vec();
+    features.sort_unstable();
+    match mode {
+        FeatureMatrix::EachFeature => std::iter::once(Some(Vec::new()))
+            .chain(features.iter().map(|feature| Some(vec![*feature])))
+            .chain([None])
+            .collect(),
+        FeatureMatrix::Powerset => {
+            let depth = depth.unwrap_or(features.len()).min(features.len());
+            let mut sets: Vec<Vec<&str>> = vec![Vec::new()];
+            for size in 1..=depth {
+                let mut next = Vec::new();
+                for set in sets.iter().filter(|set| set.len() == size - 1) {
+                    let after = set.last().map_or(0, |last| features.iter().position(|f| f == last).unwrap() + 1);
+                    for feature in &features[after..] {
+                        let mut set = set.clone();
+                        set.push(*feature);
+                        next.push(set);
+                    }
+                }
+                sets.extend(next);
+            }
+            sets.into_iter().map(Some).collect()
+        }
+    }
+}
+
+/// How many sets `combinations` makes of `features` features, saturating at `usize::MAX`.
+fn combination_count(features: usize, mode: FeatureMatrix, depth: Option<usize>) -> usize {
+    match mode {
+        FeatureMatrix::EachFeature => features.saturating_add(2),
+        FeatureMatrix::Powerset => {
+            let depth = depth.unwrap_or(features).min(features);
+            // Sets of each size k: C(n, k) = C(n, k - 1) * (n - k + 1) / k, from C(n, 0) = 1
+            let mut sets_of_size: u128 = 1;
+            let mut count: u128 = 1;
+            for size in 1..=depth {
+                sets_of_size = match sets_of_size.checked_mul((features - size + 1) as u128) {
+                    Some(product) => product / size as u128,
+                    None => return usize::MAX,
+                };
+                count = count.saturating_add(sets_of_size);
+            }
+            usize::try_from(count).unwrap_or(usize::MAX)
+        }
+    }
+}
+
+/// `args` without any `--name value` or `--name=value` before `--`.
+pub fn without_option(args: &[String], name: &str) -> Vec<String> {
+    let mut kept = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            kept.push(arg.clone());
+            kept.extend(iter.by_ref().cloned());
+        } else if arg == name {
+            iter.next();
+        } else if !arg.strip_prefix(name).is_some_and(|rest| rest.starts_with('=')) {
+            kept.push(arg.clone());
+        }
+    }
+    kept
+}
+
+/// Build every variant one after another, then show a table of how each went.
+/// The log collects all of them; the exit code is that of the first that failed.
+/// With `dedup`, diagnostics an earlier build showed aren't shown again.
+pub fn run(config: &Config, heading: &str, variants: Vec<Variant>, dedup: bool) -> Result<i32> {
+    let mut seen = dedup.then(Seen::default);
+    let mut rows = Vec::new();
+    let count = variants.len();
+    for (i, variant) in variants.into_iter().enumerate() {
+        if !config.quiet {
+            eprintln!("cargo-builder: {} {}/{}: {}", heading, i + 1, count, variant.label);
+        }
+        let mut build = config.clone();
+        build.cargo_args = variant.cargo_args;
+        build.label = Some(variant.label.clone());
+        // Later builds add to the log of the first rather than replacing it
+        build.log_append |= i > 0;
+        let start = Instant::now();
+        let outcome = runner::run_build(&build, seen.as_mut())?;
+        rows.push(Row { label: variant.label, outcome, duration: start.elapsed() });
+        if signals::interrupts() > 0 {
+            break;
+        }
+    }
+
+    if !config.quiet {
+        eprintln!("cargo-builder: {} matrix:", heading);
+        for line in table(&rows) {
+            eprintln!("  {}", line);
+        }
+        let failed = rows.iter().filter(|row| row.outcome.exit_code != 0).count();
+        if rows.len() < count {
+            eprintln!("cargo-builder: Interrupted; {} of {} not built", count - rows.len(), count);
+        } else if failed > 0 {
+            eprintln!("cargo-builder: {} of {} failed", failed, count);
+        }
+    }
+    Ok(rows.iter().map(|row| row.outcome.exit_code).find(|code| *code != 0).unwrap_or(0))
+}
+
+/// e.g. `wasm32-unknown-unknown    failed  2 errors, 1 warning  3.1s`
+fn table(rows: &[Row]) -> Vec<String> {
+    let width = rows.iter().map(|row| row.label.len()).max().unwrap_or(0);
+    let counts: Vec<String> = rows.iter().map(|row| counts(&row.outcome)).collect();
+    let counts_width = counts.iter().map(String::len).max().unwrap_or(0);
+    rows.iter().zip(&counts).map(|(row, counts)| {
+        let status = if row.outcome.exit_code == 0 { "ok" } else { "failed" };
+        let mut line = format!("{:<width$}  {:<6}  ", row.label, status, width = width);
+        if counts_width > 0 {
+            line.push_str(&format!("{:<width$}  ", counts, width = counts_width));
+        }
+        line.push_str(&util::format_duration(row.duration));
+        line
+    }).collect()
+}
+
+fn counts(outcome: &runner::Outcome) -> String {
+    let plural = |n: usize, what: &str| format!("{} {}{}", n, what, if n == 1 { "" } else { "s" });
+    let mut counts = Vec::new();
+    if outcome.errors > 0 {
+        counts.push(plural(outcome.errors, "error"));
+    }
+    if outcome.warnings > 0 {
+        counts.push(plural(outcome.warnings, "warning"));
+    }
+    if outcome.repeated > 0 {
+        counts.push(format!("{} shown before", outcome.repeated));
+    }
+    counts.join(", ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_targets() {
+        assert!(targets(&args(&["--target", "wasm32-unknown-unknown", "--release"])).is_empty());
+        let variants = targets(&args(&["--target", "x86_64-unknown-linux-gnu", "-p", "app", "--target=wasm32-unknown-unknown", "--", "--target", "x"]));
+        assert_eq!(variants, [
+            Variant {
+                label: "x86_64-unknown-linux-gnu".to_string(),
+                cargo_args: args(&["--target=x86_64-unknown-linux-gnu", "-p", "app", "--", "--target", "x"]),
+            },
+            Variant {
+                label: "wasm32-unknown-unknown".to_string(),
+                cargo_args: args(&["--target=wasm32-unknown-unknown", "-p", "app", "--", "--target", "x"]),
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_combinations() {
+        let features = ["std", "alloc", "serde"];
+        let labels = |sets: Vec<Option<Vec<&str>>>| -> Vec<String> {
+            sets.into_iter().map(|set| set.map_or("all".to_string(), |set| set.join(","))).collect()
+        };
+        assert_eq!(labels(combinations(&features, FeatureMatrix::EachFeature, None)), ["", "alloc", "serde", "std", "all"]);
+        assert_eq!(
+            labels(combinations(&features, FeatureMatrix::Powerset, None)),
+            ["", "alloc", "serde", "std", "alloc,serde", "alloc,std", "serde,std", "alloc,serde,std"],
+        );
+        assert_eq!(combinations(&features, FeatureMatrix::Powerset, Some(1)).len(), 4);
+    }
+
+    #[test]
+    fn test_combination_count() {
+        let features = ["std", "alloc", "serde", "derive", "rc"];
+        for mode in [FeatureMatrix::EachFeature, FeatureMatrix::Powerset] {
+            for depth in [None, Some(0), Some(2), Some(9)] {
+                assert_eq!(combination_count(features.len(), mode, depth), combinations(&features, mode, depth).len());
+            }
+        }
+
+        // Large enough that making the sets would never finish
+        assert_eq!(combination_count(40, FeatureMatrix::Powerset, None), 1 << 40);
+        assert_eq!(combination_count(40, FeatureMatrix::Powerset, Some(1)), 41);
+        assert_eq!(combination_count(200, FeatureMatrix::Powerset, None), usize::MAX);
+    }
+
+    #[test]
+    fn test_without_option() {
+        let cargo_args = args(&["--features", "a", "-p", "app", "--features=b,c", "--", "--features", "x"]);
+        assert_eq!(without_option(&cargo_args, "--features"), args(&["-p", "app", "--", "--features", "x"]));
+    }
+
+    #[test]
+    fn test_seen() {
+        let error = |line| Diagnostic::new("error", "cannot find value `x` in this scope").with_code("E0425").at("src/lib.rs", line, 5);
+        let mut seen = Seen::default();
+        assert!(!seen.contains(&error(3)));
+        seen.add(&[error(3)]);
+        assert!(seen.contains(&error(3)));
+        assert!(!seen.contains(&error(7)));
+    }
+
+    #[test]
+    fn test_table() {
+        let rows = [
+            Row { label: "x86_64-unknown-linux-gnu".to_string(), outcome: runner::Outcome::default(), duration: Duration::from_millis(1200) },
+            Row {
+                label: "wasm32-unknown-unknown".to_string(),
+                outcome: runner::Outcome { exit_code: 1, errors: 2, warnings: 1, repeated: 0 },
+                duration: Duration::from_millis(3100),
+            },
+        ];
+        assert_eq!(table(&rows), [
+            "x86_64-unknown-linux-gnu  ok                           1.2s",
+            "wasm32-unknown-unknown    failed  2 errors, 1 warning  3.1s",
+        ]);
+    }
+}
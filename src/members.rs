@@ -0,0 +1,340 @@
+use crate::diagnostics::strip_ansi_codes;
+use crate::{ci, context, exit_code, logging, matrix, signals, term, util, ColorChoice, Config};
+use anyhow::{bail, Context, Result};
+use cargo_metadata::MetadataCommand;
+use std::process::{Command, Output, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// How often the live table is redrawn while members build.
+const REDRAW: Duration = Duration::from_millis(250);
+
+/// Cargo options that choose packages; each member build gets its own `-p` instead.
+const PACKAGE_OPTIONS: [&str; 3] = ["-p", "--package", "--exclude"];
+const PACKAGE_FLAGS: [&str; 2] = ["--workspace", "--all"];
+
+/// Where a member's build is.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum State {
+    Pending,
+    Building(Instant),
+    Done { ok: bool, duration: Duration },
+}
+
+struct Member {
+    name: String,
+    state: State,
+    exit_code: i32,
+    errors: usize,
+}
+
+/// Build each workspace member with a cargo-builder of its own, `--member-jobs` at a
+/// time, showing a table of where each one is. `tool_args` are cargo-builder's own
+/// arguments, passed on to every member build.
+pub fn run(config: &Config, tool_args: &[String]) -> Result<i32> {
+    let jobs = config.member_jobs.max(1);
+    let workspace = util::find_workspace_for(context::manifest_path(&config.cargo_args))?;
+    let names = match members(&config.cargo_args) {
+        Ok(names) => names,
+        Err(e) => {
+            eprintln!("cargo-builder: Error: {:#}", e);
+            return Ok(exit_code::USAGE);
+        }
+    };
+    let logs = log_targets(config, &workspace)?;
+    let exe = std::env::current_exe().context("Failed to find the cargo-builder executable")?;
+    let color = match config.terminal_color {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => term::should_use_color(),
+    };
+    let grouped = config.ci.is_some_and(|provider| provider.workflow_commands());
+    let live = !grouped && !config.quiet && atty::is(atty::Stream::Stderr);
+
+    let _handlers = signals::install().context("Failed to install the Ctrl-C handler")?;
+    let mut members: Vec<Member> = names.into_iter()
+        .map(|name| Member { name, state: State::Pending, exit_code: 0, errors: 0 })
+        .collect();
+    let (done, finished) = mpsc::channel::<(usize, std::io::Result<Output>)>();
+    let mut running = 0;
+    let mut drawn = 0;
+    loop {
+        // Members build one after another by default: cargo locks the target directory for a whole build
+        while running < jobs && signals::interrupts() == 0 {
+            let Some(index) = members.iter().position(|member| member.state == State::Pending) else { break };
+            let member = &mut members[index];
+            let mut cmd = Command::new(&exe);
+            cmd.args(member_args(tool_args, &config.cargo_args, config.short_messages, &member.name, &logs))
+               .env("CARGO_TERM_COLOR", if color { "always" } else { "never" })
+               // cargo's progress would only end up in the captured output
+               .env("CARGO_TERM_QUIET", "true")
+               .stdin(Stdio::null());
+            if jobs > 1 {
+                cmd.env("CARGO_TARGET_DIR", workspace.target_directory.join("members").join(&member.name));
+            }
+            member.state = State::Building(Instant::now());
+            if !live && !config.quiet {
+                eprintln!("cargo-builder: Building {}", member.name);
+            }
+            let done = done.clone();
+            std::thread::spawn(move || {
+                let _ = done.send((index, cmd.output()));
+            });
+            running += 1;
+        }
+        if running == 0 {
+            break;
+        }
+        if live {
+            drawn = redraw(&table(&members, Instant::now()), drawn);
+        }
+
+        let Ok((index, output)) = finished.recv_timeout(REDRAW) else { continue };
+        running -= 1;
+        let member = &mut members[index];
+        let State::Building(started) = member.state else { unreachable!() };
+        let duration = started.elapsed();
+        let text = match output {
+            Ok(output) => {
+                member.exit_code = output.status.code().unwrap_or(exit_code::INTERRUPTED);
+                let mut text = String::from_utf8_lossy(&output.stderr).into_owned();
+                text.push_str(&String::from_utf8_lossy(&output.stdout));
+                text
+            }
+            Err(e) => {
+                member.exit_code = exit_code::INTERNAL;
+                format!("cargo-builder: Failed to run {}: {}\n", exe.display(), e)
+            }
+        };
+        member.errors = count_errors(&text, &member.name);
+        member.state = State::Done { ok: member.exit_code == 0, duration };
+
+        // The output goes above the table, which is drawn again below it
+        if live {
+            drawn = redraw(&[], drawn);
+        }
+        if !text.trim().is_empty() {
+            if grouped {
+                ci::print_group(&format!("{}: {}", member.name, status(member)), &text);
+            } else {
+                eprint!("{}", text);
+            }
+        }
+        if !live && !config.quiet {
+            eprintln!("cargo-builder: {} {} in {}", member.name, status(member), util::format_duration(duration));
+        }
+    }
+
+    let built = members.iter().filter(|member| matches!(member.state, State::Done { .. })).count();
+    if !config.quiet {
+        if !live {
+            eprintln!("cargo-builder: Members:");
+        }
+        let lines = table(&members, Instant::now());
+        if live {
+            redraw(&lines, drawn);
+        } else {
+            for line in &lines {
+                eprintln!("{}", line);
+            }
+        }
+        let failed: Vec<&Member> = members.iter().filter(|member| member.exit_code != 0).collect();
+        if built < members.len() {
+            eprintln!("cargo-builder: Interrupted; {} of {} members not built", members.len() - built, members.len());
+        } else if !failed.is_empty() {
+            eprintln!("cargo-builder: {} of {} members failed", failed.len(), members.len());
+        }
+        for member in failed {
+            let paths: Vec<String> = logs.iter().map(|log| logging::suffixed_path(&log.path, &member.name).display().to_string()).collect();
+            eprintln!("cargo-builder: Errors of {} written to: {}", member.name, paths.join(", "));
+        }
+    }
+    if built < members.len() {
+        return Ok(exit_code::INTERRUPTED);
+    }
+    Ok(members.iter().map(|member| member.exit_code).find(|code| *code != 0).unwrap_or(0))
+}
+
+/// The workspace members to build, as cargo picks them: those chosen with `-p`,
+/// all of them with `--workspace`, or else the default members, less `--exclude`.
+fn members(args: &[String]) -> Result<Vec<String>> {
+    let mut metadata = MetadataCommand::new();
+    metadata.no_deps();
+    if let Some(manifest_path) = context::manifest_path(args) {
+        metadata.manifest_path(manifest_path);
+    }
+    let metadata = metadata.exec().context("Failed to get cargo metadata")?;
+    let names = |packages: Vec<&cargo_metadata::Package>| packages.iter().map(|package| package.name.to_string()).collect();
+    // `default-members`, or the package of the current directory; cargo before 1.71 doesn't say
+    let defaults = (!cargo_metadata::workspace_default_members_is_missing(&metadata.workspace_default_members))
+        .then(|| names(metadata.workspace_default_packages()));
+    select(names(metadata.workspace_packages()), defaults, args)
+}
+
+fn select(all: Vec<String>, defaults: Option<Vec<String>>, args: &[String]) -> Result<Vec<String>> {
+    let chosen = context::option_values(args, &["-p", "--package"]);
+    for name in &chosen {
+        if !all.iter().any(|member| member == name) {
+            bail!("No package `{}` in the workspace", name);
+        }
+    }
+    let whole = context::has_flag(args, "--workspace") || context::has_flag(args, "--all");
+    let candidates = match defaults {
+        Some(defaults) if chosen.is_empty() && !whole => defaults,
+        _ => all,
+    };
+    let excluded = context::option_values(args, &["--exclude"]);
+    Ok(candidates.into_iter()
+        .filter(|name| chosen.is_empty() || chosen.contains(&name.as_str()))
+        .filter(|name| !excluded.contains(&name.as_str()))
+        .collect())
+}
+
+/// Where the members' logs go: the `--log` destinations, or the default log, each
+/// with the member's name added, e.g. `build-errors.app.log`.
+fn log_targets(config: &Config, workspace: &util::Workspace) -> Result<Vec<logging::LogTarget>> {
+    if config.log_paths.is_empty() {
+        let name = logging::default_log_name(&context::profile(&config.cargo_args));
+        return Ok(vec![logging::LogTarget { format: logging::LogFormat::Text, path: workspace.target_directory.join(name) }]);
+    }
+    config.log_paths.iter().map(|spec| logging::LogTarget::parse(spec)).collect()
+}
+
+/// The arguments of the cargo-builder that builds `member`.
+fn member_args(tool_args: &[String], cargo_args: &[String], short_messages: bool, member: &str, logs: &[logging::LogTarget]) -> Vec<String> {
+    let mut args = tool_args.to_vec();
+    // The directory was changed to already, and the logs are the member's own
+    for option in ["-C", "--log", "--member-jobs"] {
+        args = matrix::without_option(&args, option);
+    }
+    args.retain(|arg| !["--per-member", "--quiet", "-q"].contains(&arg.as_str()));
+    args.extend(["--quiet".to_string(), "--label".to_string(), member.to_string()]);
+    for log in logs {
+        let format = match log.format {
+            logging::LogFormat::Text => "text",
+            logging::LogFormat::JsonLines => "jsonl",
+        };
+        args.push("--log".to_string());
+        args.push(format!("{}:{}", format, logging::suffixed_path(&log.path, member).display()));
+    }
+
+    args.extend(["-p".to_string(), member.to_string()]);
+    if short_messages {
+        args.push("--message-format=short".to_string());
+    }
+    let mut rest = cargo_args.to_vec();
+    for option in PACKAGE_OPTIONS {
+        rest = matrix::without_option(&rest, option);
+    }
+    let end = rest.iter().position(|arg| arg == "--").unwrap_or(rest.len());
+    let (flags, trailing) = rest.split_at(end);
+    args.extend(flags.iter().filter(|arg| !PACKAGE_FLAGS.contains(&arg.as_str())).cloned());
+    args.extend(trailing.iter().cloned());
+    args
+}
+
+/// Errors in a member's output, which its cargo-builder labeled with the member's name.
+fn count_errors(output: &str, member: &str) -> usize {
+    let label = format!("[{}] error", member);
+    strip_ansi_codes(output).lines().filter(|line| line.starts_with(&label)).count()
+}
+
+fn status(member: &Member) -> String {
+    match member.state {
+        State::Pending => "pending".to_string(),
+        State::Building(_) => "building".to_string(),
+        State::Done { ok: true, .. } => "ok".to_string(),
+        State::Done { ok: false, .. } if member.errors > 0 => {
+            format!("failed ({} {})", member.errors, if member.errors == 1 { "error" } else { "errors" })
+        }
+        State::Done { ok: false, .. } => format!("failed (exit {})", member.exit_code),
+    }
+}
+
+/// e.g. `  app    building  12.3s`
+fn table(members: &[Member], now: Instant) -> Vec<String> {
+    let width = members.iter().map(|member| member.name.len()).max().unwrap_or(0);
+    let statuses: Vec<String> = members.iter().map(status).collect();
+    let status_width = statuses.iter().map(String::len).max().unwrap_or(0);
+    members.iter().zip(&statuses).map(|(member, status)| {
+        let duration = match member.state {
+            State::Pending => None,
+            State::Building(started) => Some(now.saturating_duration_since(started)),
+            State::Done { duration, .. } => Some(duration),
+        };
+        match duration {
+            Some(duration) => format!("  {:<width$}  {:<status_width$}  {}", member.name, status, util::format_duration(duration)),
+            None => format!("  {:<width$}  {}", member.name, status),
+        }
+    }).collect()
+}
+
+/// Replace the `drawn` lines printed last with `lines`. Returns how many are drawn now.
+fn redraw(lines: &[String], drawn: usize) -> usize {
+    use crossterm::{cursor, execute, terminal};
+    let mut stderr = std::io::stderr();
+    if drawn > 0 {
+        let _ = execute!(stderr, cursor::MoveUp(drawn as u16), cursor::MoveToColumn(0), terminal::Clear(terminal::ClearType::FromCursorDown));
+    }
+    for line in lines {
+        eprintln!("{}", line);
+    }
+    lines.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(list: &[&str]) -> Vec<String> {
+        list.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_select() {
+        let all = args(&["core", "app", "cli"]);
+        let defaults = Some(args(&["app", "cli"]));
+        assert_eq!(select(all.clone(), defaults.clone(), &[]).unwrap(), ["app", "cli"]);
+        assert_eq!(select(all.clone(), defaults.clone(), &args(&["--workspace", "--exclude", "cli"])).unwrap(), ["core", "app"]);
+        assert_eq!(select(all.clone(), defaults.clone(), &args(&["-p", "core"])).unwrap(), ["core"]);
+        // Cargo older than 1.71
+        assert_eq!(select(all.clone(), None, &[]).unwrap(), ["core", "app", "cli"]);
+        assert!(select(all, defaults, &args(&["-p", "web"])).is_err());
+    }
+
+    #[test]
+    fn test_member_args() {
+        let tool_args = args(&["--per-member", "--member-jobs", "4", "-C", "ws", "--include-warnings", "--log=all.log", "-q"]);
+        let cargo_args = args(&["--workspace", "--exclude", "old", "-p", "app", "--release", "--", "-p"]);
+        let logs = [logging::LogTarget { format: logging::LogFormat::JsonLines, path: "target/errors.jsonl".into() }];
+        assert_eq!(
+            member_args(&tool_args, &cargo_args, true, "core", &logs),
+            args(&[
+                "--include-warnings", "--quiet", "--label", "core", "--log", "jsonl:target/errors.core.jsonl",
+                "-p", "core", "--message-format=short", "--release", "--", "-p",
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_count_errors() {
+        let output = "\x1b[1m[core] \x1b[91merror[E0425]\x1b[0m: cannot find value\n[core] warning: unused\n[core] error: aborting\nerror: could not compile `core`\n";
+        assert_eq!(count_errors(output, "core"), 2);
+    }
+
+    #[test]
+    fn test_table() {
+        let now = Instant::now();
+        let member = |name: &str, state, exit_code, errors| Member { name: name.to_string(), state, exit_code, errors };
+        let members = [
+            member("app", State::Done { ok: false, duration: Duration::from_millis(2500) }, 1, 2),
+            member("core", State::Building(now - Duration::from_secs(3)), 0, 0),
+            member("cli", State::Pending, 0, 0),
+        ];
+        assert_eq!(table(&members, now), [
+            "  app   failed (2 errors)  2.5s",
+            "  core  building           3.0s",
+            "  cli   pending",
+        ]);
+    }
+}
@@ -0,0 +1,202 @@
+use crate::process::Group;
+use crate::util;
+use anyhow::Result;
+use std::time::{Duration, Instant};
+
+/// How often the build's memory is measured, which takes a scan of all processes.
+const SAMPLE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// A process of the build, as far as the limit is concerned.
+#[cfg(unix)]
+#[derive(Debug, PartialEq)]
+struct Process {
+    /// Resident memory in bytes
+    rss: u64,
+    /// The crate it compiles, for rustc
+    crate_name: Option<String>,
+    /// The program, e.g. `cc1plus` in a build script
+    program: String,
+}
+
+/// What the build used when it went over `--max-memory`.
+#[derive(Debug)]
+pub struct Exceeded {
+    pub limit: u64,
+    pub used: u64,
+    /// The crate, or else the program, using the most memory
+    pub culprit: Option<String>,
+}
+
+impl Exceeded {
+    pub fn describe(&self) -> String {
+        let during = self.culprit.as_ref().map(|culprit| format!(" while {}", culprit)).unwrap_or_default();
+        format!("memory limit of {} exceeded{} ({} in use)", util::format_size(self.limit), during, util::format_size(self.used))
+    }
+}
+
+/// Watches the memory of cargo and everything it started.
+pub struct Monitor {
+    limit: u64,
+    next_at: Instant,
+}
+
+impl Monitor {
+    pub fn new(limit: u64, now: Instant) -> Self {
+        Monitor { limit, next_at: now }
+    }
+
+    /// Measure the build if it's time to; call this regularly while it runs.
+    pub fn check(&mut self, group: &Group, now: Instant) -> Result<Option<Exceeded>> {
+        if now < self.next_at {
+            return Ok(None);
+        }
+        self.next_at = now + SAMPLE_INTERVAL;
+
+        let (used, culprit) = measure(group)?;
+        Ok((used > self.limit).then_some(Exceeded { limit: self.limit, used, culprit }))
+    }
+}
+
+/// The memory of the whole tree, and what uses most of it.
+#[cfg(unix)]
+fn measure(group: &Group) -> Result<(u64, Option<String>)> {
+    let processes = group_processes(group.id())?;
+    let used = processes.iter().map(|process| process.rss).sum();
+    let culprit = processes.into_iter().max_by_key(|process| process.rss).map(|process| match process.crate_name {
+        Some(name) => format!("compiling {}", name),
+        None => format!("running {}", process.program),
+    });
+    Ok((used, culprit))
+}
+
+/// Job objects only account for the memory of the whole job, at its peak.
+#[cfg(windows)]
+fn measure(group: &Group) -> Result<(u64, Option<String>)> {
+    Ok((group.peak_memory()?, None))
+}
+
+#[cfg(target_os = "linux")]
+fn group_processes(group: libc::pid_t) -> Result<Vec<Process>> {
+    use anyhow::Context;
+
+    // SAFETY: sysconf has no memory safety requirements
+    let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) }.max(0) as u64;
+    let mut processes = Vec::new();
+    for entry in std::fs::read_dir("/proc").context("Failed to read /proc")? {
+        let path = entry.context("Failed to read /proc")?.path();
+        // Processes may end at any time, so anything unreadable is skipped
+        let Ok(stat) = std::fs::read_to_string(path.join("stat")) else { continue };
+        let Some((pgrp, pages)) = parse_stat(&stat) else { continue };
+        if pgrp != group {
+            continue;
+        }
+        let Ok(cmdline) = std::fs::read(path.join("cmdline")) else { continue };
+        let args: Vec<String> = cmdline.split(|&b| b == 0)
+            .filter(|arg| !arg.is_empty())
+            .map(|arg| String::from_utf8_lossy(arg).into_owned())
+            .collect();
+        processes.push(process(pages * page_size, &args));
+    }
+    Ok(processes)
+}
+
+/// The process group and resident pages from `/proc/<pid>/stat`.
+#[cfg(target_os = "linux")]
+fn parse_stat(stat: &str) -> Option<(libc::pid_t, u64)> {
+    // The command in parentheses may contain spaces and parentheses itself
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    Some((fields.get(2)?.parse().ok()?, fields.get(21)?.parse().ok()?))
+}
+
+#[cfg(all(unix, not(target_os = "linux")))]
+fn group_processes(group: libc::pid_t) -> Result<Vec<Process>> {
+    use anyhow::Context;
+
+    let output = std::process::Command::new("ps")
+        .args(["-A", "-ww", "-o", "pgid=,rss=,args="])
+        .output()
+        .context("Failed to run ps")?;
+    Ok(parse_ps(&String::from_utf8_lossy(&output.stdout), group))
+}
+
+/// `ps -o pgid=,rss=,args=` lines of the group; rss is in kilobytes.
+#[cfg(all(unix, not(target_os = "linux")))]
+fn parse_ps(output: &str, group: libc::pid_t) -> Vec<Process> {
+    output.lines().filter_map(|line| {
+        let mut fields = line.split_whitespace();
+        let pgid: libc::pid_t = fields.next()?.parse().ok()?;
+        let rss: u64 = fields.next()?.parse().ok()?;
+        let args: Vec<String> = fields.map(str::to_string).collect();
+        (pgid == group).then(|| process(rss * 1024, &args))
+    }).collect()
+}
+
+#[cfg(unix)]
+fn process(rss: u64, args: &[String]) -> Process {
+    let program = args.first()
+        .map(|program| program.rsplit(['/', '\\']).next().unwrap_or(program).to_string())
+        .unwrap_or_default();
+    let crate_name = args.iter()
+        .position(|arg| arg == "--crate-name")
+        .and_then(|i| args.get(i + 1))
+        .cloned();
+    Process { rss, crate_name, program }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split(' ').map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_process() {
+        let rustc = process(1 << 30, &args("/home/me/.rustup/toolchains/stable/bin/rustc --crate-name serde --edition=2018 src/lib.rs"));
+        assert_eq!(rustc.crate_name.as_deref(), Some("serde"));
+        assert_eq!(rustc.program, "rustc");
+        assert_eq!(process(0, &args("/usr/lib/gcc/cc1plus -O2")).crate_name, None);
+    }
+
+    #[test]
+    fn test_exceeded() {
+        let exceeded = Exceeded { limit: 8 << 30, used: 9_771_000_000, culprit: Some("compiling serde".to_string()) };
+        assert_eq!(exceeded.describe(), "memory limit of 8G exceeded while compiling serde (9.1G in use)");
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_parse_stat() {
+        let stat = "4242 (rustc (x)) R 4200 4100 4100 0 -1 4194304 9 0 0 0 1 0 0 0 20 0 3 0 123 1000000 2560 18446744073709551615";
+        assert_eq!(parse_stat(stat), Some((4100, 2560)));
+        assert_eq!(parse_stat("garbage"), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_group_processes() {
+        let mut cmd = std::process::Command::new("sleep");
+        cmd.arg("30");
+        let (mut child, group) = crate::process::spawn_group(&mut cmd, None).unwrap();
+        // Right after spawning the child may still be cargo-builder's own image
+        std::thread::sleep(Duration::from_millis(100));
+        let processes = group_processes(group.id()).unwrap();
+        child.kill().unwrap();
+        child.wait().unwrap();
+
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].program, "sleep");
+        assert!(processes[0].rss > 0);
+    }
+
+    #[cfg(all(unix, not(target_os = "linux")))]
+    #[test]
+    fn test_parse_ps() {
+        let output = "  100  2048 /bin/rustc --crate-name tokio src/lib.rs\n  200  512 /bin/sh\n";
+        let processes = parse_ps(output, 100);
+        assert_eq!(processes.len(), 1);
+        assert_eq!(processes[0].rss, 2048 * 1024);
+        assert_eq!(processes[0].crate_name.as_deref(), Some("tokio"));
+    }
+}
@@ -0,0 +1,129 @@
+use crate::diagnostics::{strip_ansi_codes, Diagnostic};
+use crate::steps::Problem;
+use crate::{buildstd, context, Config};
+use regex::Regex;
+use std::env;
+use std::path::Path;
+
+lazy_static::lazy_static! {
+    /// The first line of a report of what Miri stopped the program for
+    static ref REPORT_START: Regex = Regex::new(
+        r"^error: (Undefined Behavior|unsupported operation|resource exhaustion|memory leaked|abnormal termination|deadlock|the main thread terminated|post-monomorphization error)"
+    ).unwrap();
+    static ref LOCATION: Regex = Regex::new(r"^\s*--> ([^\s:]+):(\d+):(\d+)").unwrap();
+}
+
+/// A problem when Miri can't run: it only comes with nightly toolchains, as a
+/// rustup component.
+pub fn check(config: &Config) -> Option<Problem> {
+    let cargo = context::cargo_version(config)?;
+    if !buildstd::is_nightly(cargo) {
+        return Some(Problem::new(
+            format!("Miri runs on a nightly toolchain, and this build uses {}", cargo),
+            "build with `--toolchain nightly`, after `rustup toolchain install nightly --component miri`".to_string(),
+        ));
+    }
+    let sysroot = context::sysroot(config)?;
+    if Path::new(&sysroot).join("bin").join(format!("cargo-miri{}", env::consts::EXE_SUFFIX)).is_file() {
+        return None;
+    }
+    let toolchain = config.toolchain.as_deref().or_else(|| context::active_toolchain(config));
+    Some(Problem::new(
+        format!("Miri isn't installed in {}", sysroot),
+        format!("run `rustup component add miri{}`", toolchain.map(|toolchain| format!(" --toolchain {}", toolchain)).unwrap_or_default()),
+    ))
+}
+
+/// Picks Miri's reports out of what cargo writes to stderr, where the programs it
+/// interprets print them as text. Everything else is passed on as it arrives.
+#[derive(Default)]
+pub struct Reports {
+    /// The end of the last chunk, when it wasn't a whole line
+    pending: Vec<u8>,
+    /// The lines of the report being read
+    report: Option<Vec<String>>,
+}
+
+impl Reports {
+    /// Take a chunk of stderr, returning what to pass on and the reports it finished.
+    pub fn feed(&mut self, chunk: &[u8]) -> (Vec<u8>, Vec<Diagnostic>) {
+        self.pending.extend_from_slice(chunk);
+        let mut through = Vec::new();
+        let mut finished = Vec::new();
+        while let Some(end) = self.pending.iter().position(|&byte| byte == b'\n') {
+            let raw: Vec<u8> = self.pending.drain(..=end).collect();
+            let line = strip_ansi_codes(String::from_utf8_lossy(&raw).trim_end());
+            if REPORT_START.is_match(&line) {
+                finished.extend(self.report.replace(vec![line]).and_then(|lines| diagnostic(&lines)));
+            } else if line.starts_with("error: aborting due to") {
+                finished.extend(self.report.take().and_then(|lines| diagnostic(&lines)));
+            } else if let Some(report) = &mut self.report {
+                report.push(line);
+            } else {
+                through.extend_from_slice(&raw);
+            }
+        }
+        // Progress bars never end their lines; only what could start a report waits for more
+        let held = String::from_utf8_lossy(&self.pending);
+        let held = strip_ansi_codes(&held);
+        if self.report.is_none() && !held.starts_with("error:") && !"error:".starts_with(held.as_str()) {
+            through.append(&mut self.pending);
+        }
+        (through, finished)
+    }
+
+    /// A report cut off when the program was stopped, once cargo is done.
+    pub fn finish(&mut self) -> Option<Diagnostic> {
+        self.report.take().and_then(|lines| diagnostic(&lines))
+    }
+}
+
+/// A report as an error entry at the first place it points to, which is where
+/// the program went wrong.
+fn diagnostic(lines: &[String]) -> Option<Diagnostic> {
+    let message = lines.first()?.strip_prefix("error: ")?.to_string();
+    let location = lines.iter().find_map(|line| LOCATION.captures(line));
+    let end = lines.iter().rposition(|line| !line.is_empty())?;
+    let mut rendered = lines[..=end].join("\n");
+    rendered.push('\n');
+    Some(Diagnostic {
+        file: location.as_ref().map(|location| location[1].to_string()),
+        line: location.as_ref().and_then(|location| location[2].parse().ok()),
+        column: location.as_ref().and_then(|location| location[3].parse().ok()),
+        target_kind: vec!["miri".to_string()],
+        rendered,
+        ..Diagnostic::new("error", message)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reports() {
+        let mut reports = Reports::default();
+        let (through, found) = reports.feed(b"     Running unittests src/lib.rs (target/miri/debug/deps/app-1f2e)\nerror: Undefined Behavior: memory access failed: alloc1 has been freed, so this pointer is dangling\n --> src/lib.rs:5:14\n");
+        assert_eq!(through, b"     Running unittests src/lib.rs (target/miri/debug/deps/app-1f2e)\n");
+        assert!(found.is_empty());
+
+        let (through, found) = reports.feed(b"  |\n5 |     unsafe { *p }\n  |              ^^ Undefined Behavior occurred here\n\nnote: some details are omitted\n\nerror: aborting due to 1 previous error\n\nerror: test failed, to rerun pass `--lib`\n    Building [=====>  ] 3/4");
+        assert_eq!(String::from_utf8_lossy(&through), "\nerror: test failed, to rerun pass `--lib`\n    Building [=====>  ] 3/4");
+        let [report] = &found[..] else { panic!() };
+        assert_eq!(report.message, "Undefined Behavior: memory access failed: alloc1 has been freed, so this pointer is dangling");
+        assert_eq!((report.file.as_deref(), report.line, report.column), (Some("src/lib.rs"), Some(5), Some(14)));
+        assert!(report.rendered.starts_with("error: Undefined Behavior: memory access failed"));
+        assert!(report.rendered.ends_with("Undefined Behavior occurred here\n\nnote: some details are omitted\n"));
+    }
+
+    #[test]
+    fn test_leaks() {
+        let mut reports = Reports::default();
+        let (_, found) = reports.feed(b"error: memory leaked: alloc7 (Rust heap, size: 4, align: 4), allocated here:\n --> src/main.rs:2:5\nerror: memory leaked: alloc9 (Rust heap, size: 8, align: 8), allocated here:\n --> src/main.rs:3:5\n\nnote: set `MIRIFLAGS=-Zmiri-ignore-leaks` to disable this check\n\nerr");
+        assert_eq!(found.len(), 1);
+        let (through, found) = reports.feed(b"or: aborting due to 2 previous errors\n");
+        assert!(through.is_empty());
+        assert_eq!(found[0].line, Some(3));
+        assert!(reports.finish().is_none());
+    }
+}
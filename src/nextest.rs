@@ -0,0 +1,131 @@
+use crate::diagnostics::Diagnostic;
+use regex::Regex;
+use serde_json::Value;
+
+lazy_static::lazy_static! {
+    /// Where a test panicked: `panicked at src/lib.rs:10:9:`, or before Rust 1.73
+    /// `panicked at 'message', src/lib.rs:10:9`
+    static ref PANIC_LOCATION: Regex = Regex::new(r"panicked at (?:'.*', )?([^\s:]+):(\d+):(\d+)").unwrap();
+}
+
+/// A test finishing, from nextest's libtest-json output.
+#[derive(Debug, PartialEq)]
+pub enum TestEvent {
+    Passed,
+    Failed {
+        /// e.g. `tests::it_works`
+        name: String,
+        /// The test binary, e.g. `my-crate::my_crate`
+        binary: Option<String>,
+        /// What the test printed, with its panic message
+        output: String,
+    },
+    Ignored,
+}
+
+/// A line of libtest-json output about a finished test; `None` for anything else,
+/// including cargo's own messages.
+pub fn parse_event(line: &str) -> Option<TestEvent> {
+    let json: Value = serde_json::from_str(line.trim()).ok()?;
+    if json.get("type")?.as_str()? != "test" {
+        return None;
+    }
+    // nextest names tests `<binary id>$<test name>`
+    let (binary, name) = match json.get("name")?.as_str()?.rsplit_once('$') {
+        Some((binary, name)) => (Some(binary.to_string()), name.to_string()),
+        None => (None, json.get("name")?.as_str()?.to_string()),
+    };
+    match json.get("event")?.as_str()? {
+        "ok" => Some(TestEvent::Passed),
+        "ignored" => Some(TestEvent::Ignored),
+        "failed" | "timeout" => {
+            let output = json.get("stdout").and_then(|stdout| stdout.as_str()).unwrap_or_default().trim_end().to_string();
+            Some(TestEvent::Failed { name, binary, output })
+        }
+        _ => None,
+    }
+}
+
+/// A failed test as an error entry, pointing at where it panicked.
+pub fn diagnostic(name: &str, binary: Option<&str>, output: &str) -> Diagnostic {
+    let message = format!("test {} failed", name);
+    let location = PANIC_LOCATION.captures(output);
+    let mut rendered = match binary {
+        Some(binary) => format!("error: {} ({})\n", message, binary),
+        None => format!("error: {}\n", message),
+    };
+    if let Some(location) = &location {
+        rendered.push_str(&format!(" --> {}:{}:{}\n", &location[1], &location[2], &location[3]));
+    }
+    for line in output.lines() {
+        if line.is_empty() {
+            rendered.push('\n');
+        } else {
+            rendered.push_str(&format!("  {}\n", line));
+        }
+    }
+    Diagnostic {
+        file: location.as_ref().map(|location| location[1].to_string()),
+        line: location.as_ref().and_then(|location| location[2].parse().ok()),
+        column: location.as_ref().and_then(|location| location[3].parse().ok()),
+        target_kind: vec!["test".to_string()],
+        rendered,
+        ..Diagnostic::new("error", message)
+    }
+}
+
+/// How many tests passed, failed and were skipped.
+#[derive(Debug, Default)]
+pub struct Tally {
+    pub passed: usize,
+    pub failed: usize,
+    pub ignored: usize,
+}
+
+impl Tally {
+    pub fn record(&mut self, event: &TestEvent) {
+        match event {
+            TestEvent::Passed => self.passed += 1,
+            TestEvent::Failed { .. } => self.failed += 1,
+            TestEvent::Ignored => self.ignored += 1,
+        }
+    }
+
+    pub fn describe(&self) -> String {
+        let mut text = format!("{} passed, {} failed", self.passed, self.failed);
+        if self.ignored > 0 {
+            text.push_str(&format!(", {} ignored", self.ignored));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_event() {
+        assert_eq!(parse_event(r#"{"type":"test","event":"ok","name":"app::app$tests::works","exec_time":0.001}"#), Some(TestEvent::Passed));
+        assert_eq!(parse_event(r#"{"type":"test","event":"started","name":"app::app$tests::works"}"#), None);
+        assert_eq!(parse_event(r#"{"type":"suite","event":"failed","passed":1,"failed":1}"#), None);
+        assert_eq!(parse_event(r#"{"reason":"build-finished","success":true}"#), None);
+
+        let failed = r#"{"type":"test","event":"failed","name":"app::app$tests::fails","exec_time":0.001,"stdout":"\nthread 'tests::fails' panicked at src/lib.rs:10:9:\nassertion `left == right` failed\n  left: 1\n right: 2\n"}"#;
+        let Some(TestEvent::Failed { name, binary, output }) = parse_event(failed) else { panic!() };
+        assert_eq!((name.as_str(), binary.as_deref()), ("tests::fails", Some("app::app")));
+        let diagnostic = diagnostic(&name, binary.as_deref(), &output);
+        assert_eq!((diagnostic.file.as_deref(), diagnostic.line, diagnostic.column), (Some("src/lib.rs"), Some(10), Some(9)));
+        assert_eq!(
+            diagnostic.rendered,
+            "error: test tests::fails failed (app::app)\n --> src/lib.rs:10:9\n\n  thread 'tests::fails' panicked at src/lib.rs:10:9:\n  assertion `left == right` failed\n    left: 1\n   right: 2\n",
+        );
+    }
+
+    #[test]
+    fn test_old_panic_location() {
+        let diagnostic = diagnostic("it_fails", None, "thread 'it_fails' panicked at 'boom', tests/api.rs:4:5");
+        assert_eq!((diagnostic.file.as_deref(), diagnostic.line), (Some("tests/api.rs"), Some(4)));
+        assert!(diagnostic.rendered.starts_with("error: test it_fails failed\n --> tests/api.rs:4:5\n"));
+    }
+}
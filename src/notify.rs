@@ -0,0 +1,430 @@
+use crate::{artifacts, chat, coverage, email, git, github, logging, ntfy, util, Config};
+use crate::diagnostics::{self, Diagnostic};
+use crate::redact::Redactor;
+use crate::settings::NotifySettings;
+use anyhow::{Result, Context, bail};
+use flate2::read::MultiGzDecoder;
+use serde_json::{json, Value};
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+/// Attempts per notification before giving up
+const ATTEMPTS: u32 = 3;
+/// Most of the log included in a notification
+const MAX_LOG_BYTES: usize = 256 * 1024;
+
+/// What notifications report about a finished build.
+pub struct BuildOutcome<'a> {
+    pub success: bool,
+    pub command: String,
+    pub started_at: SystemTime,
+    pub duration: Duration,
+    /// Errors and warnings of the build
+    pub diagnostics: Cow<'a, [Diagnostic]>,
+    pub log_paths: Vec<String>,
+    /// Commit the build ran against, if the workspace is in a git repository
+    pub vcs: Option<git::VcsState>,
+    pub artifacts: Artifacts,
+    /// With `--coverage`, once the tests passed
+    pub coverage: Option<&'a coverage::Report>,
+    /// The programs and libraries of a successful build, where they ended up
+    pub produced: &'a [artifacts::Artifact],
+}
+
+/// Crates cargo compiled during the build or reused from an earlier one, from its artifact messages.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Artifacts {
+    pub compiled: usize,
+    pub fresh: usize,
+}
+
+impl Artifacts {
+    pub fn record(&mut self, fresh: bool) {
+        if fresh {
+            self.fresh += 1;
+        } else {
+            self.compiled += 1;
+        }
+    }
+
+    /// e.g. `12 crates compiled (3.1/s), 40 fresh`, or `None` if cargo got to no crate.
+    pub fn describe(&self, duration: Duration) -> Option<String> {
+        if self.compiled + self.fresh == 0 {
+            return None;
+        }
+        let mut text = format!("{} {} compiled", self.compiled, if self.compiled == 1 { "crate" } else { "crates" });
+        if self.compiled > 0 && duration.as_secs_f64() > 0.0 {
+            text.push_str(&format!(" ({:.1}/s)", self.compiled as f64 / duration.as_secs_f64()));
+        }
+        text.push_str(&format!(", {} fresh", self.fresh));
+        Some(text)
+    }
+}
+
+impl BuildOutcome<'_> {
+    /// The outcome with what the logs hide hidden too. The log paths stay as they are,
+    /// for reading the log.
+    pub fn redact(self, redactor: Option<&Redactor>) -> Self {
+        let Some(redactor) = redactor else { return self };
+        BuildOutcome {
+            command: redactor.redact(&self.command),
+            diagnostics: self.diagnostics.iter().map(|d| redactor.redact_diagnostic(d)).collect(),
+            ..self
+        }
+    }
+
+    pub fn errors(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter().filter(|d| d.level == "error")
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors().count()
+    }
+
+    pub fn warning_count(&self) -> usize {
+        self.diagnostics.iter().filter(|d| d.level == "warning").count()
+    }
+
+    /// One-line description, e.g. `Build failed: 2 errors, 1 warning in 4.2s`.
+    pub fn headline(&self) -> String {
+        format!(
+            "Build {}: {} {}, {} {} in {}",
+            if self.success { "succeeded" } else { "failed" },
+            self.error_count(),
+            if self.error_count() == 1 { "error" } else { "errors" },
+            self.warning_count(),
+            if self.warning_count() == 1 { "warning" } else { "warnings" },
+            util::format_duration(self.duration),
+        )
+    }
+}
+
+/// Send every configured notification, reporting failures without failing the build.
+/// Each is sent on a thread of its own, so one slow or retried service doesn't hold
+/// up the others; this returns once all are done.
+pub fn send_all(config: &Config, settings: &NotifySettings, outcome: &BuildOutcome) {
+    fn report(what: &str, result: Result<()>) {
+        if let Err(e) = result {
+            eprintln!("cargo-builder: {} notification failed: {:#}", what, e);
+        }
+    }
+
+    thread::scope(|scope| {
+        for url in &config.webhooks {
+            scope.spawn(move || report("Webhook", send_webhook(url, outcome, config.webhook_include_log, config.webhook_timeout)));
+        }
+        if let Some(slack) = settings.slack.as_ref().filter(|s| s.wants(outcome.success)) {
+            scope.spawn(move || report("Slack", post_json(&slack.webhook, &chat::slack_payload(outcome, slack.mention.as_deref()), config.webhook_timeout)));
+        }
+        if let Some(discord) = settings.discord.as_ref().filter(|s| s.wants(outcome.success)) {
+            scope.spawn(move || report("Discord", post_json(&discord.webhook, &chat::discord_payload(outcome, discord.mention.as_deref()), config.webhook_timeout)));
+        }
+        if let Some(email) = settings.email.as_ref().filter(|s| s.wants(outcome.success)) {
+            scope.spawn(move || report("Email", email::send(email, outcome, config.webhook_timeout)));
+        }
+        if let Some(settings) = settings.ntfy.as_ref().filter(|s| s.wants(outcome.success)) {
+            scope.spawn(move || report("ntfy", ntfy::send(settings, outcome, config.webhook_timeout)));
+        }
+        // Sent for every build so the comment also turns green once the build is fixed
+        if config.github_comment || config.github_pr.is_some() {
+            scope.spawn(move || report("GitHub", github::comment(config.github_pr.as_ref(), outcome, config.webhook_timeout)));
+        }
+    });
+}
+
+/// Signal a failed build: run the configured sound command, or ring the terminal bell.
+pub fn ring_bell(sound_command: Option<&str>) -> Result<()> {
+    let Some(command) = sound_command.filter(|c| !c.trim().is_empty()) else {
+        let mut stderr = std::io::stderr();
+        stderr.write_all(b"\x07")?;
+        stderr.flush()?;
+        return Ok(());
+    };
+
+    let mut parts = command.split_whitespace();
+    let program = parts.next().context("Empty sound command")?;
+    // Don't hold up the exit while the sound plays
+    Command::new(program)
+        .args(parts)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .spawn()
+        .with_context(|| format!("Failed to run sound command: {}", command))?;
+
+    Ok(())
+}
+
+/// JSON payload of `--webhook`.
+fn webhook_payload(outcome: &BuildOutcome, log: Option<(String, bool)>) -> Value {
+    let mut payload = json!({
+        "status": if outcome.success { "success" } else { "failure" },
+        "command": outcome.command,
+        "errors": outcome.error_count(),
+        "warnings": outcome.warning_count(),
+        "started_at": humantime::format_rfc3339_seconds(outcome.started_at).to_string(),
+        "duration_ms": outcome.duration.as_millis() as u64,
+        "crates_compiled": outcome.artifacts.compiled,
+        "crates_fresh": outcome.artifacts.fresh,
+        "first_error": outcome.errors().next().map(|d| json!({
+            "code": d.code,
+            "message": d.message,
+            "file": d.file,
+            "line": d.line,
+            "column": d.column,
+            "rendered": diagnostics::strip_ansi_codes(&d.rendered),
+        })),
+        "log_paths": outcome.log_paths,
+        "git": outcome.vcs.as_ref().map(|vcs| json!({
+            "commit": vcs.commit,
+            "branch": vcs.branch,
+            "dirty": vcs.dirty,
+        })),
+        "coverage": outcome.coverage.map(coverage::Report::to_json),
+        "artifacts": outcome.produced,
+    });
+    if let Some((content, truncated)) = log {
+        payload["log"] = json!(content);
+        payload["log_truncated"] = json!(truncated);
+    }
+    payload
+}
+
+/// Write what `--webhook` would send, without the log, to `summary.json`.
+pub fn write_summary(path: &Path, outcome: &BuildOutcome) -> Result<()> {
+    let summary = serde_json::to_string_pretty(&webhook_payload(outcome, None))?;
+    std::fs::write(path, summary).with_context(|| format!("Failed to write {}", path.display()))
+}
+
+pub fn send_webhook(url: &str, outcome: &BuildOutcome, include_log: bool, timeout: Duration) -> Result<()> {
+    let log = if include_log {
+        outcome.log_paths.first().map(|path| read_log(Path::new(path))).transpose()?
+    } else {
+        None
+    };
+    post_json(url, &webhook_payload(outcome, log), timeout)
+}
+
+/// The log's text (decompressed if needed), cut to `MAX_LOG_BYTES`, and whether it was cut.
+pub fn read_log(path: &Path) -> Result<(String, bool)> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open log: {}", path.display()))?;
+    let mut reader: Box<dyn Read> = if logging::is_gzip_path(path) {
+        Box::new(MultiGzDecoder::new(file))
+    } else {
+        Box::new(file)
+    };
+
+    let mut bytes = Vec::new();
+    reader.by_ref().take(MAX_LOG_BYTES as u64).read_to_end(&mut bytes)
+        .with_context(|| format!("Failed to read log: {}", path.display()))?;
+    let truncated = reader.read(&mut [0u8])? > 0;
+    Ok((String::from_utf8_lossy(&bytes).into_owned(), truncated))
+}
+
+/// POST `body` as JSON.
+pub fn post_json(url: &str, body: &Value, timeout: Duration) -> Result<()> {
+    send_with_retry("POST", url, Some(&body.to_string()), timeout, |request| {
+        request.set("Content-Type", "application/json")
+    })?;
+    Ok(())
+}
+
+/// Send a request after `prepare` has added headers to it, returning the response body.
+/// Connection failures, rate limits and server errors are retried with a
+/// growing delay; other client errors are not.
+pub fn send_with_retry(
+    method: &str,
+    url: &str,
+    body: Option<&str>,
+    timeout: Duration,
+    prepare: impl Fn(ureq::Request) -> ureq::Request,
+) -> Result<String> {
+    let agent = ureq::AgentBuilder::new().timeout(timeout).build();
+
+    let mut attempt = 1;
+    loop {
+        let request = prepare(agent.request(method, url));
+        let result = match body {
+            Some(body) => request.send_string(body),
+            None => request.call(),
+        };
+        let error = match result {
+            Ok(response) => {
+                return response.into_string()
+                    .with_context(|| format!("Failed to read the response from {}", url));
+            }
+            Err(ureq::Error::Status(code, _)) if code != 429 && code < 500 => {
+                bail!("{} rejected the notification with HTTP {}", url, code)
+            }
+            Err(e) => e,
+        };
+        if attempt == ATTEMPTS {
+            return Err(error).with_context(|| format!("Failed to notify {} after {} attempts", url, ATTEMPTS));
+        }
+        thread::sleep(Duration::from_secs(1 << (attempt - 1)));
+        attempt += 1;
+    }
+}
+
+/// A failed `cargo build` with `diagnostics`, for tests of the notifiers to change as they need.
+#[cfg(test)]
+pub fn test_outcome(diagnostics: &[Diagnostic]) -> BuildOutcome<'_> {
+    BuildOutcome {
+        success: false,
+        command: "cargo build".to_string(),
+        started_at: SystemTime::UNIX_EPOCH,
+        duration: Duration::from_millis(4200),
+        diagnostics: diagnostics.into(),
+        log_paths: vec!["target/build-errors.log".to_string()],
+        vcs: None,
+        artifacts: Default::default(),
+        coverage: None,
+        produced: &[],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+
+    /// Answer each incoming request with the next status, returning the request bodies.
+    fn serve(statuses: &'static [u16]) -> (String, thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let handle = thread::spawn(move || {
+            let mut bodies = Vec::new();
+            for status in statuses {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut length = 0;
+                loop {
+                    let mut line = String::new();
+                    reader.read_line(&mut line).unwrap();
+                    if line.trim().is_empty() {
+                        break;
+                    }
+                    if let Some((name, value)) = line.split_once(':') {
+                        if name.eq_ignore_ascii_case("content-length") {
+                            length = value.trim().parse().unwrap();
+                        }
+                    }
+                }
+                let mut body = vec![0; length];
+                reader.read_exact(&mut body).unwrap();
+                bodies.push(String::from_utf8(body).unwrap());
+                write!(reader.get_mut(), "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status).unwrap();
+            }
+            bodies
+        });
+        (url, handle)
+    }
+
+    #[test]
+    fn test_headline() {
+        let diagnostics = [Diagnostic::new("error", ""), Diagnostic::new("error", ""), Diagnostic::new("warning", "")];
+        assert_eq!(test_outcome(&diagnostics).headline(), "Build failed: 2 errors, 1 warning in 4.2s");
+    }
+
+    #[test]
+    fn test_describe_artifacts() {
+        let mut artifacts = Artifacts::default();
+        assert_eq!(artifacts.describe(Duration::from_secs(2)), None);
+        artifacts.record(true);
+        assert_eq!(artifacts.describe(Duration::from_secs(2)).unwrap(), "0 crates compiled, 1 fresh");
+        for _ in 0..5 {
+            artifacts.record(false);
+        }
+        assert_eq!(artifacts.describe(Duration::from_secs(2)).unwrap(), "5 crates compiled (2.5/s), 1 fresh");
+    }
+
+    #[test]
+    fn test_webhook_payload() {
+        let error = Diagnostic::new("error", "cannot find value `x` in this scope")
+            .with_code("E0425")
+            .at("src/main.rs", 2, 5)
+            .with_rendered("\x1b[1merror[E0425]\x1b[0m: cannot find value");
+        let diagnostics = [Diagnostic::new("warning", "unused variable: `y`"), error.clone(), error];
+        let payload = webhook_payload(&test_outcome(&diagnostics), Some(("log".to_string(), false)));
+        assert_eq!(payload["status"], "failure");
+        assert_eq!(payload["errors"], 2);
+        assert_eq!(payload["duration_ms"], 4200);
+        assert_eq!(payload["started_at"], "1970-01-01T00:00:00Z");
+        assert_eq!(payload["first_error"]["code"], "E0425");
+        assert_eq!(payload["first_error"]["rendered"], "error[E0425]: cannot find value");
+        assert_eq!(payload["log"], "log");
+        assert!(payload["git"].is_null());
+        assert_eq!(payload["crates_compiled"], 0);
+        assert_eq!(payload["artifacts"], json!([]));
+
+        assert!(webhook_payload(&test_outcome(&[]), None).get("log").is_none());
+
+        let vcs = git::VcsState { commit: "1a2b3c4d".to_string(), branch: None, dirty: true };
+        let payload = webhook_payload(&BuildOutcome { vcs: Some(vcs), ..test_outcome(&diagnostics) }, None);
+        assert_eq!(payload["git"]["commit"], "1a2b3c4d");
+        assert!(payload["git"]["branch"].is_null());
+        assert_eq!(payload["git"]["dirty"], true);
+    }
+
+    #[test]
+    fn test_redacted_webhook_payload() {
+        let config = crate::parse_args(vec!["--redact".to_string(), "tok_[0-9a-f]+".to_string()]).unwrap();
+        let redactor = Redactor::from_config(&config).unwrap();
+        let diagnostics = [Diagnostic::new("error", "invalid token").with_rendered("error: invalid token `tok_5ec7e7`")];
+        let outcome = BuildOutcome {
+            command: "cargo build --config registry.token=tok_5ec7e7".to_string(),
+            ..test_outcome(&diagnostics)
+        };
+        let payload = webhook_payload(&outcome.redact(redactor.as_ref()), None);
+        assert!(!payload.to_string().contains("tok_5ec7e7"));
+        assert_eq!(payload["command"], "cargo build --config registry.token=[REDACTED]");
+        assert_eq!(payload["first_error"]["rendered"], "error: invalid token `[REDACTED]`");
+    }
+
+    #[test]
+    fn test_send_all_doesnt_wait_on_a_stalled_service() {
+        // Answers only once told to
+        let stalled = TcpListener::bind("127.0.0.1:0").unwrap();
+        let stalled_url = format!("http://{}/hook", stalled.local_addr().unwrap());
+        let (release, released) = std::sync::mpsc::channel::<()>();
+        let stalled = thread::spawn(move || {
+            let (mut stream, _) = stalled.accept().unwrap();
+            released.recv().unwrap();
+            write!(stream, "HTTP/1.1 200 OK\r\nContent-Length: 0\r\nConnection: close\r\n\r\n").unwrap();
+        });
+        let (url, server) = serve(&[200]);
+        let config = crate::parse_args(["--webhook", &stalled_url, "--webhook", &url, "--webhook-timeout", "10s"]
+            .iter().map(|arg| arg.to_string()).collect()).unwrap();
+
+        let started = std::time::Instant::now();
+        let sender = thread::spawn(move || send_all(&config, &NotifySettings::default(), &test_outcome(&[])));
+        // The second webhook is sent while the first still waits for its answer
+        assert_eq!(server.join().unwrap().len(), 1);
+        assert!(started.elapsed() < Duration::from_secs(5));
+        release.send(()).unwrap();
+        stalled.join().unwrap();
+        sender.join().unwrap();
+    }
+
+    #[test]
+    fn test_post_json_retries_server_errors() {
+        let (url, server) = serve(&[503, 200]);
+        post_json(&url, &json!({"status": "failure"}), Duration::from_secs(5)).unwrap();
+        assert_eq!(server.join().unwrap(), [r#"{"status":"failure"}"#; 2]);
+    }
+
+    #[test]
+    fn test_post_json_does_not_retry_client_errors() {
+        let (url, server) = serve(&[404]);
+        let err = post_json(&url, &json!({}), Duration::from_secs(5)).unwrap_err();
+        assert!(err.to_string().contains("HTTP 404"));
+        assert_eq!(server.join().unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,110 @@
+use crate::notify::{self, BuildOutcome};
+use crate::settings::NtfySettings;
+use anyhow::{Result, Context};
+use std::time::Duration;
+
+/// Errors listed in a push message
+const LISTED_ERRORS: usize = 5;
+/// ntfy turns messages over 4096 bytes into attachments
+const MESSAGE_LIMIT: usize = 4096;
+
+/// Cut `text` to at most `max` bytes on a character boundary, marking the cut with an ellipsis.
+fn truncate_bytes(text: &str, max: usize) -> String {
+    if text.len() <= max {
+        return text.to_string();
+    }
+    let mut end = max.saturating_sub('…'.len_utf8());
+    while !text.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}…", &text[..end])
+}
+
+/// Push body: the command and a one-line summary per error.
+fn message(outcome: &BuildOutcome) -> String {
+    let mut message = outcome.command.clone();
+    for d in outcome.errors().take(LISTED_ERRORS) {
+        message.push('\n');
+        message.push_str(&d.summary());
+    }
+    let more = outcome.error_count().saturating_sub(LISTED_ERRORS);
+    if more > 0 {
+        message.push_str(&format!("\n… and {} more", more));
+    }
+    truncate_bytes(&message, MESSAGE_LIMIT)
+}
+
+/// Headers describing the push: title, priority and emoji tags.
+fn headers(outcome: &BuildOutcome) -> [(&'static str, String); 3] {
+    let (priority, tags) = if outcome.success {
+        ("default", "white_check_mark")
+    } else {
+        ("high", "rotating_light")
+    };
+    [
+        ("Title", outcome.headline()),
+        ("Priority", priority.to_string()),
+        ("Tags", tags.to_string()),
+    ]
+}
+
+/// Publish the build result to the configured ntfy topic.
+pub fn send(settings: &NtfySettings, outcome: &BuildOutcome, timeout: Duration) -> Result<()> {
+    let url = format!("{}/{}", settings.server.trim_end_matches('/'), settings.topic);
+    let token = match &settings.token_env {
+        Some(var) => Some(std::env::var(var)
+            .with_context(|| format!("ntfy token variable {} is not set", var))?),
+        None => None,
+    };
+    let headers = headers(outcome);
+
+    notify::send_with_retry("POST", &url, Some(&message(outcome)), timeout, |request| {
+        let mut request = request.set("Content-Type", "text/plain; charset=utf-8");
+        for (name, value) in &headers {
+            request = request.set(name, value);
+        }
+        if let Some(token) = &token {
+            request = request.set("Authorization", &format!("Bearer {}", token));
+        }
+        request
+    })?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diagnostics::Diagnostic;
+    use crate::notify::test_outcome;
+
+    #[test]
+    fn test_truncate_bytes() {
+        assert_eq!(truncate_bytes("short", 10), "short");
+        // Never split the two-byte `é`
+        assert_eq!(truncate_bytes("aéééé", 5), "a…");
+        assert!(truncate_bytes(&"x".repeat(5000), MESSAGE_LIMIT).len() <= MESSAGE_LIMIT);
+    }
+
+    #[test]
+    fn test_message() {
+        let errors: Vec<_> = (1..=7)
+            .map(|n| Diagnostic::new("error", format!("cannot find value `x{}` in this scope", n)).with_code("E0425").at("src/main.rs", n, 5))
+            .collect();
+        let message = message(&test_outcome(&errors));
+        let lines: Vec<_> = message.lines().collect();
+        assert_eq!(lines[0], "cargo build");
+        assert_eq!(lines[1], "error[E0425]: cannot find value `x1` in this scope (src/main.rs:1:5)");
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[6], "… and 2 more");
+    }
+
+    #[test]
+    fn test_headers() {
+        let errors = [Diagnostic::new("error", "cannot find value `x1` in this scope").with_code("E0425").at("src/main.rs", 1, 5)];
+        let [title, priority, tags] = headers(&test_outcome(&errors));
+        assert_eq!(title.1, "Build failed: 1 error, 0 warnings in 4.2s");
+        assert_eq!(priority.1, "high");
+        assert_eq!(tags.1, "rotating_light");
+        assert_eq!(headers(&BuildOutcome { success: true, ..test_outcome(&[]) })[1].1, "default");
+    }
+}
@@ -0,0 +1,162 @@
+use crate::diagnostics::Diagnostic;
+use crate::util::Workspace;
+use anyhow::Result;
+use regex::{Captures, Regex};
+use std::path::{Path, PathBuf};
+
+lazy_static::lazy_static! {
+    // `--> src/main.rs:3:18` and `::: /path/to/dep.rs:10:5`, possibly wrapped in color codes
+    static ref LOCATION_REGEX: Regex =
+        Regex::new(r"((?:-->|:::) (?:\x1b\[[0-9;]*m)*)([^\n\x1b]+?)(:\d+:\d+)").unwrap();
+}
+
+/// How file paths are shown, selected with `--paths`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PathStyle {
+    WorkspaceRelative,
+    Absolute,
+}
+
+impl std::str::FromStr for PathStyle {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "workspace-relative" => Ok(PathStyle::WorkspaceRelative),
+            "absolute" => Ok(PathStyle::Absolute),
+            _ => Err(anyhow::anyhow!("Invalid path style: {}", s)),
+        }
+    }
+}
+
+/// Parse a `--path-map FROM=TO` value.
+pub fn parse_path_map(text: &str) -> std::result::Result<(String, String), String> {
+    match text.split_once('=') {
+        Some((from, to)) if !from.is_empty() => Ok((from.to_string(), to.to_string())),
+        _ => Err(format!("expected FROM=TO, got `{}`", text)),
+    }
+}
+
+/// `--path-map`s that turn paths in a container into the host's, given where it mounts the
+/// workspace, its target directory and the cargo home, e.g. `/cargo/registry/src/...` for
+/// the sources of a dependency.
+pub fn mount_maps(
+    workspace: &Workspace,
+    workspace_mount: &str,
+    target_mount: &str,
+    cargo_home_mount: &str,
+    cargo_home: Option<&Path>,
+) -> Vec<(String, String)> {
+    let mut maps = vec![
+        (workspace_mount.to_string(), workspace.root.display().to_string()),
+        (target_mount.to_string(), workspace.target_directory.display().to_string()),
+    ];
+    if let Some(cargo_home) = cargo_home {
+        maps.push((cargo_home_mount.to_string(), cargo_home.display().to_string()));
+    }
+    maps
+}
+
+/// Rewrites file paths in diagnostics according to `--paths` and `--path-map`.
+pub struct PathRewriter {
+    root: PathBuf,
+    style: Option<PathStyle>,
+    maps: Vec<(String, String)>,
+}
+
+impl PathRewriter {
+    /// `None` when paths are left as cargo reports them.
+    pub fn new(root: &Path, style: Option<PathStyle>, maps: &[(String, String)]) -> Option<Self> {
+        if style.is_none() && maps.is_empty() {
+            return None;
+        }
+        Some(PathRewriter { root: root.to_path_buf(), style, maps: maps.to_vec() })
+    }
+
+    pub fn rewrite(&self, path: &str) -> String {
+        let mut path = match self.style {
+            Some(PathStyle::Absolute) if Path::new(path).is_relative() => {
+                self.root.join(path).display().to_string()
+            }
+            Some(PathStyle::WorkspaceRelative) => match Path::new(path).strip_prefix(&self.root) {
+                Ok(relative) => relative.display().to_string(),
+                Err(_) => path.to_string(),
+            },
+            _ => path.to_string(),
+        };
+
+        // First matching prefix wins; only whole path components match
+        for (from, to) in &self.maps {
+            if let Ok(rest) = Path::new(&path).strip_prefix(from) {
+                path = Path::new(to).join(rest).display().to_string();
+                break;
+            }
+        }
+        path
+    }
+
+    pub fn rewrite_diagnostic(&self, mut diagnostic: Diagnostic) -> Diagnostic {
+        if let Some(file) = &diagnostic.file {
+            let rewritten = self.rewrite(file);
+            if rewritten != *file {
+                diagnostic.cargo_file = diagnostic.file.replace(rewritten);
+            }
+        }
+        diagnostic.rendered = LOCATION_REGEX
+            .replace_all(&diagnostic.rendered, |caps: &Captures| {
+                format!("{}{}{}", &caps[1], self.rewrite(&caps[2]), &caps[3])
+            })
+            .into_owned();
+        diagnostic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rewriter(style: Option<PathStyle>, maps: &[(&str, &str)]) -> PathRewriter {
+        let maps: Vec<_> = maps.iter().map(|(f, t)| (f.to_string(), t.to_string())).collect();
+        PathRewriter::new(Path::new("/work/app"), style, &maps).unwrap()
+    }
+
+    #[test]
+    fn test_path_styles() {
+        let absolute = rewriter(Some(PathStyle::Absolute), &[]);
+        assert_eq!(absolute.rewrite("src/main.rs"), "/work/app/src/main.rs");
+        assert_eq!(absolute.rewrite("/other/lib.rs"), "/other/lib.rs");
+
+        let relative = rewriter(Some(PathStyle::WorkspaceRelative), &[]);
+        assert_eq!(relative.rewrite("/work/app/src/main.rs"), "src/main.rs");
+        assert_eq!(relative.rewrite("/work/application/x.rs"), "/work/application/x.rs");
+    }
+
+    #[test]
+    fn test_path_map() {
+        let mapped = rewriter(Some(PathStyle::Absolute), &[("/work/app", "/home/me/src/app/")]);
+        assert_eq!(mapped.rewrite("src/main.rs"), "/home/me/src/app/src/main.rs");
+        assert_eq!(mapped.rewrite("/work/app"), "/home/me/src/app/");
+        // Prefixes only match whole components
+        assert_eq!(mapped.rewrite("/work/application/x.rs"), "/work/application/x.rs");
+
+        assert_eq!(parse_path_map("/a=/b"), Ok(("/a".to_string(), "/b".to_string())));
+        assert!(parse_path_map("/a").is_err());
+    }
+
+    #[test]
+    fn test_rewrites_rendered_locations() {
+        let diagnostic = Diagnostic::new("error", "mismatched types")
+            .at("src/main.rs", 3, 18)
+            .with_rendered("error: mismatched types\n \x1b[1m\x1b[94m--> \x1b[0msrc/main.rs:3:18\n    ::: /work/app/src/lib.rs:1:1\n");
+        let fingerprint = diagnostic.fingerprint();
+        let rewritten = rewriter(Some(PathStyle::Absolute), &[]).rewrite_diagnostic(diagnostic);
+        assert_eq!(rewritten.file.as_deref(), Some("/work/app/src/main.rs"));
+        // Baselines and history still match it by the path cargo reported
+        assert_eq!(rewritten.workspace_file(), Some("src/main.rs"));
+        assert_eq!(rewritten.fingerprint(), fingerprint);
+        assert_eq!(
+            rewritten.rendered,
+            "error: mismatched types\n \x1b[1m\x1b[94m--> \x1b[0m/work/app/src/main.rs:3:18\n    ::: /work/app/src/lib.rs:1:1\n"
+        );
+    }
+}
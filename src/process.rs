@@ -0,0 +1,404 @@
+use anyhow::{Result, Context};
+use std::process::{Child, Command};
+use std::time::{Duration, Instant};
+
+/// How long cargo gets to stop after being asked before it is killed.
+const GRACE: Duration = Duration::from_secs(10);
+
+/// cargo together with the rustc and build script processes it starts, so they
+/// can be stopped as a whole: a process group on Unix, a job object on Windows.
+pub struct Group {
+    #[cfg(unix)]
+    id: libc::pid_t,
+    /// Closing the job when cargo-builder exits, even when it is killed, ends
+    /// everything still running in it
+    #[cfg(windows)]
+    job: job::Job,
+}
+
+/// Start `cmd` in a group of its own, at lower priority with a `nice`.
+#[cfg(unix)]
+pub fn spawn_group(cmd: &mut Command, nice: Option<u8>) -> std::io::Result<(Child, Group)> {
+    use std::os::unix::process::CommandExt;
+    cmd.process_group(0);
+    if let Some(nice) = nice {
+        lower_priority(cmd, nice);
+    }
+    let child = cmd.spawn()?;
+    // The group has the id of cargo, its leader
+    let id = child.id() as libc::pid_t;
+    Ok((child, Group { id }))
+}
+
+/// Start `cmd` in a job object of its own, at lower priority with a `nice`. cargo
+/// starts suspended and only runs once it is in the job, so everything it starts is too.
+#[cfg(windows)]
+pub fn spawn_group(cmd: &mut Command, nice: Option<u8>) -> std::io::Result<(Child, Group)> {
+    use std::os::windows::process::CommandExt;
+    use windows_sys::Win32::System::Threading::{BELOW_NORMAL_PRIORITY_CLASS, CREATE_SUSPENDED};
+
+    let job = job::Job::new()?;
+    // The flags replace any set before, so the priority is set here too
+    cmd.creation_flags(CREATE_SUSPENDED | if nice.is_some() { BELOW_NORMAL_PRIORITY_CLASS } else { 0 });
+    let mut child = cmd.spawn()?;
+    if let Err(e) = job.assign(&child).and_then(|()| job::resume(&child)) {
+        let _ = child.kill();
+        let _ = child.wait();
+        return Err(e);
+    }
+    Ok((child, Group { job }))
+}
+
+#[cfg(unix)]
+impl Group {
+    /// The process group id, which is cargo's process id.
+    pub fn id(&self) -> libc::pid_t {
+        self.id
+    }
+
+    /// Ask cargo and its children to stop, as Ctrl-C would.
+    pub fn interrupt(&self) -> Result<()> {
+        self.signal(libc::SIGINT)
+    }
+
+    /// Ask cargo and its children to stop.
+    pub fn terminate(&self) -> Result<()> {
+        self.signal(libc::SIGTERM)
+    }
+
+    /// Stop cargo and its children at once.
+    pub fn kill(&self) -> Result<()> {
+        self.signal(libc::SIGKILL)
+    }
+
+    fn signal(&self, signal: libc::c_int) -> Result<()> {
+        // SAFETY: kill only sends a signal and has no memory safety requirements
+        if unsafe { libc::kill(-self.id, signal) } != 0 {
+            let error = std::io::Error::last_os_error();
+            // The build ended on its own in the meantime
+            if error.raw_os_error() != Some(libc::ESRCH) {
+                return Err(error).context("Failed to signal the cargo process group");
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Group {
+    /// The most memory the job committed at once so far.
+    pub fn peak_memory(&self) -> Result<u64> {
+        self.job.peak_memory().context("Failed to query the cargo job object")
+    }
+
+    /// The console already delivers Ctrl-C to every process attached to it, cargo included.
+    pub fn interrupt(&self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Windows console programs can't be asked to stop, so the job is ended at once.
+    pub fn terminate(&self) -> Result<()> {
+        self.kill()
+    }
+
+    pub fn kill(&self) -> Result<()> {
+        self.job.terminate().context("Failed to end the cargo job object")
+    }
+}
+
+#[cfg(windows)]
+mod job {
+    use std::io;
+    use std::os::windows::io::AsRawHandle;
+    use std::process::Child;
+    use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
+    use windows_sys::Win32::System::Diagnostics::ToolHelp::{CreateToolhelp32Snapshot, Thread32First, Thread32Next, TH32CS_SNAPTHREAD, THREADENTRY32};
+    use windows_sys::Win32::System::Threading::{OpenThread, ResumeThread, THREAD_SUSPEND_RESUME};
+    use windows_sys::Win32::System::JobObjects::{
+        AssignProcessToJobObject, CreateJobObjectW, JobObjectExtendedLimitInformation, QueryInformationJobObject,
+        SetInformationJobObject, TerminateJobObject, JOBOBJECT_EXTENDED_LIMIT_INFORMATION, JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE,
+    };
+
+    pub struct Job(HANDLE);
+
+    impl Job {
+        /// An unnamed job that ends its processes once its last handle is closed.
+        pub fn new() -> io::Result<Self> {
+            // SAFETY: null attributes and name are allowed and create an unnamed job
+            let handle = unsafe { CreateJobObjectW(std::ptr::null(), std::ptr::null()) };
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let job = Job(handle);
+
+            // SAFETY: the structure is plain data, for which all zeroes means no limits
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            info.BasicLimitInformation.LimitFlags = JOB_OBJECT_LIMIT_KILL_ON_JOB_CLOSE;
+            // SAFETY: the pointer and size describe `info`, which outlives the call
+            let set = unsafe {
+                SetInformationJobObject(
+                    job.0,
+                    JobObjectExtendedLimitInformation,
+                    &info as *const _ as *const std::ffi::c_void,
+                    std::mem::size_of_val(&info) as u32,
+                )
+            };
+            if set == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(job)
+        }
+
+        pub fn assign(&self, child: &Child) -> io::Result<()> {
+            // SAFETY: both handles are valid while `self` and `child` are alive
+            if unsafe { AssignProcessToJobObject(self.0, child.as_raw_handle() as HANDLE) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+
+        pub fn peak_memory(&self) -> io::Result<u64> {
+            // SAFETY: as in `new`
+            let mut info: JOBOBJECT_EXTENDED_LIMIT_INFORMATION = unsafe { std::mem::zeroed() };
+            // SAFETY: the pointer and size describe `info`, which outlives the call
+            let queried = unsafe {
+                QueryInformationJobObject(
+                    self.0,
+                    JobObjectExtendedLimitInformation,
+                    &mut info as *mut _ as *mut std::ffi::c_void,
+                    std::mem::size_of_val(&info) as u32,
+                    std::ptr::null_mut(),
+                )
+            };
+            if queried == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(info.PeakJobMemoryUsed as u64)
+        }
+
+        pub fn terminate(&self) -> io::Result<()> {
+            // SAFETY: the handle is valid while `self` is alive
+            if unsafe { TerminateJobObject(self.0, 1) } == 0 {
+                return Err(io::Error::last_os_error());
+            }
+            Ok(())
+        }
+    }
+
+    impl Drop for Job {
+        fn drop(&mut self) {
+            // SAFETY: the handle was created by `new` and is closed only here
+            unsafe { CloseHandle(self.0) };
+        }
+    }
+
+    /// Start a process created suspended. `Child` keeps no handle to its main thread,
+    /// its only one until it runs, so that is found among the system's threads.
+    pub fn resume(child: &Child) -> io::Result<()> {
+        // SAFETY: takes a snapshot, with no requirements on the arguments
+        let snapshot = unsafe { CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0) };
+        if snapshot == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the structure is plain data; its size is set as the calls require
+        let mut entry: THREADENTRY32 = unsafe { std::mem::zeroed() };
+        entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+        let mut result = Err(io::Error::new(io::ErrorKind::NotFound, "cargo's main thread was not found"));
+        // SAFETY: the snapshot is valid until closed below and `entry` outlives the calls
+        let mut found = unsafe { Thread32First(snapshot, &mut entry) } != 0;
+        while found {
+            if entry.th32OwnerProcessID == child.id() {
+                result = resume_thread(entry.th32ThreadID);
+                break;
+            }
+            // SAFETY: as for `Thread32First`
+            found = unsafe { Thread32Next(snapshot, &mut entry) } != 0;
+        }
+        // SAFETY: the snapshot was opened above and is closed only here
+        unsafe { CloseHandle(snapshot) };
+        result
+    }
+
+    fn resume_thread(id: u32) -> io::Result<()> {
+        // SAFETY: opens a thread by id, with no requirements on the arguments
+        let thread = unsafe { OpenThread(THREAD_SUSPEND_RESUME, 0, id) };
+        if thread.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        // SAFETY: the handle was opened above and is valid until closed
+        let resumed = unsafe { ResumeThread(thread) };
+        // SAFETY: as above, closed only here
+        unsafe { CloseHandle(thread) };
+        if resumed == u32::MAX {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+/// Start `cmd` at lower priority: niceness `nice` and the lowest best-effort I/O
+/// priority. Processes it starts inherit this; on Windows, `spawn_group` starts it
+/// below normal priority instead.
+#[cfg(unix)]
+fn lower_priority(cmd: &mut Command, nice: u8) {
+    use std::os::unix::process::CommandExt;
+    // SAFETY: the closure only makes system calls, which is allowed between fork and exec
+    unsafe {
+        cmd.pre_exec(move || {
+            // Both are best effort: a build at normal priority is better than none
+            libc::setpriority(libc::PRIO_PROCESS as _, 0, nice.into());
+            #[cfg(target_os = "linux")]
+            {
+                const IOPRIO_WHO_PROCESS: libc::c_int = 1;
+                const IOPRIO_CLASS_BE: libc::c_int = 2;
+                const IOPRIO_CLASS_SHIFT: libc::c_int = 13;
+                libc::syscall(libc::SYS_ioprio_set, IOPRIO_WHO_PROCESS, 0, (IOPRIO_CLASS_BE << IOPRIO_CLASS_SHIFT) | 7);
+            }
+            Ok(())
+        });
+    }
+}
+
+/// Why a build is being stopped.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Stop {
+    /// Ctrl-C or SIGTERM reached cargo-builder
+    Interrupted,
+    /// The build ran past `--timeout`
+    TimedOut,
+    /// cargo waited for a file lock past `--lock-timeout`
+    LockTimeout,
+    /// The build went over `--max-memory`, which can't wait for a grace period
+    MemoryLimit,
+}
+
+/// Stops a running build: first asking cargo to stop, then killing it once
+/// the grace period is over.
+#[derive(Default)]
+pub struct Stopper {
+    reason: Option<Stop>,
+    killing_at: Option<Instant>,
+    killed: bool,
+}
+
+impl Stopper {
+    /// Why the build was stopped, if it was.
+    pub fn reason(&self) -> Option<Stop> {
+        self.reason
+    }
+
+    /// Ask the build to stop. The first reason sticks.
+    pub fn stop(&mut self, group: &Group, reason: Stop, now: Instant) -> Result<()> {
+        if self.reason.is_some() {
+            return Ok(());
+        }
+        match reason {
+            Stop::Interrupted => group.interrupt()?,
+            Stop::TimedOut | Stop::LockTimeout => group.terminate()?,
+            Stop::MemoryLimit => {
+                group.kill()?;
+                self.killed = true;
+            }
+        }
+        self.reason = Some(reason);
+        self.killing_at = Some(now + GRACE);
+        Ok(())
+    }
+
+    /// Kill the build without waiting for the grace period, e.g. on a second Ctrl-C.
+    pub fn kill_now(&mut self, group: &Group) -> Result<()> {
+        if !self.killed {
+            group.kill()?;
+            self.killed = true;
+        }
+        Ok(())
+    }
+
+    /// Kill the build if it outlasted the grace period; call this regularly while it runs.
+    pub fn check(&mut self, group: &Group, now: Instant) -> Result<()> {
+        match self.killing_at {
+            Some(killing_at) if now >= killing_at => self.kill_now(group),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// When a build runs past `--timeout`.
+pub struct Deadline {
+    limit: Duration,
+    at: Instant,
+}
+
+impl Deadline {
+    pub fn new(start: Instant, limit: Duration) -> Self {
+        Deadline { limit, at: start + limit }
+    }
+
+    pub fn limit(&self) -> Duration {
+        self.limit
+    }
+
+    pub fn passed(&self, now: Instant) -> bool {
+        now >= self.at
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::Read;
+    use std::process::Stdio;
+
+    fn sleeping_group() -> (Child, Group) {
+        // `sleep` holds the pipe open, so reading it to the end also waits for
+        // the shell's child, which would keep running if only the shell were signalled
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "sleep 30 & wait"]).stdout(Stdio::piped());
+        spawn_group(&mut cmd, None).unwrap()
+    }
+
+    fn wait_for_group(mut child: Child) {
+        child.stdout.take().unwrap().read_to_end(&mut Vec::new()).unwrap();
+        child.wait().unwrap();
+    }
+
+    #[test]
+    fn test_lower_priority() {
+        // `nice` prints the niceness it runs at
+        let mut cmd = Command::new("nice");
+        lower_priority(&mut cmd, 19);
+        let output = cmd.output().unwrap();
+        assert_eq!(String::from_utf8_lossy(&output.stdout).trim(), "19");
+    }
+
+    #[test]
+    fn test_stopper_stops_the_process_group() {
+        let (child, group) = sleeping_group();
+        let start = Instant::now();
+        let deadline = Deadline::new(start, Duration::from_secs(60));
+        assert!(!deadline.passed(start));
+        assert!(deadline.passed(start + Duration::from_secs(60)));
+
+        let mut stopper = Stopper::default();
+        stopper.stop(&group, Stop::TimedOut, start).unwrap();
+        // A later Ctrl-C doesn't change why the build stopped
+        stopper.stop(&group, Stop::Interrupted, start).unwrap();
+        assert_eq!(stopper.reason(), Some(Stop::TimedOut));
+
+        wait_for_group(child);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_stopper_kills_after_grace() {
+        // SIGINT is ignored by background jobs of a non-interactive shell
+        let (child, group) = sleeping_group();
+        let start = Instant::now();
+        let mut stopper = Stopper::default();
+        stopper.stop(&group, Stop::Interrupted, start).unwrap();
+        stopper.check(&group, start + GRACE).unwrap();
+
+        wait_for_group(child);
+        assert!(start.elapsed() < Duration::from_secs(10));
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{clean, diagnostics};
+use crate::timings::CrateTimings;
+use crate::util;
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// Crates named in each line
+const LISTED: usize = 5;
+
+/// The crates a build compiled instead of reusing, and the dependencies among them that
+/// the same profile directory had a fingerprint of from an earlier build. Those weren't
+/// edited; something around them changed, like RUSTFLAGS, and cargo threw away what it
+/// had compiled.
+pub struct Rebuilds {
+    /// Profile directories, and the packages with a fingerprint in each before the build
+    before: HashSet<(PathBuf, String)>,
+    timings: CrateTimings,
+    /// Package ids of the dependencies compiled again, once each
+    rebuilt: Vec<String>,
+}
+
+impl Rebuilds {
+    pub fn new(target_directory: &Path, started: Instant) -> Self {
+        let mut before = HashSet::new();
+        for dir in clean::profile_dirs(target_directory) {
+            for entry in fs::read_dir(dir.join(".fingerprint")).into_iter().flatten().flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                if let (Some(_), Some((package, _))) = (clean::unit_key(&name), name.rsplit_once('-')) {
+                    before.insert((dir.clone(), package.to_string()));
+                }
+            }
+        }
+        Rebuilds { before, timings: CrateTimings::new(started), rebuilt: Vec::new() }
+    }
+
+    /// A `compiler-artifact` message arrived at `now`.
+    pub fn artifact(&mut self, package_id: &str, fresh: bool, filenames: &[String], now: Instant) {
+        self.timings.artifact(package_id, fresh, now);
+        if fresh || is_local(package_id) || self.rebuilt.iter().any(|id| id == package_id) {
+            return;
+        }
+        let name = diagnostics::package_name(package_id);
+        if filenames.iter().filter_map(|file| profile_dir(Path::new(file))).any(|dir| self.before.contains(&(dir.to_path_buf(), name.to_string()))) {
+            self.rebuilt.push(package_id.to_string());
+        }
+    }
+
+    /// A `timing-info` message with the exact time of a unit.
+    pub fn timing_info(&mut self, package_id: &str, duration: Duration) {
+        self.timings.timing_info(package_id, duration);
+    }
+
+    /// The slowest crates compiled, and how many of them are dependencies compiled
+    /// again. Nothing when only local crates were compiled, as after an edit, or
+    /// everything was, as in a first build.
+    pub fn describe(&self, fresh: usize) -> Vec<String> {
+        let ranking = self.timings.ranking();
+        if !ranking.iter().any(|time| !is_local(&time.package_id)) || (fresh == 0 && self.rebuilt.is_empty()) {
+            return Vec::new();
+        }
+        let slowest: Vec<String> = ranking.iter().take(LISTED)
+            .map(|time| format!("{} ({})", time.label(), util::format_duration(time.duration)))
+            .collect();
+        let more = ranking.len().saturating_sub(LISTED);
+        let mut lines = vec![format!(
+            "Recompiled {} {}, {} fresh; the slowest: {}{}",
+            ranking.len(),
+            if ranking.len() == 1 { "crate" } else { "crates" },
+            fresh,
+            slowest.join(", "),
+            if more > 0 { format!(" and {} more", more) } else { String::new() },
+        )];
+        if !self.rebuilt.is_empty() {
+            let names: Vec<&str> = self.rebuilt.iter().take(LISTED).map(|id| diagnostics::package_name(id)).collect();
+            lines.push(format!(
+                "{} of them {} an earlier build had compiled for this profile ({}{}); cargo compiles a dependency again when RUSTFLAGS, the toolchain, its version or features, or a variable its build script watches changed",
+                self.rebuilt.len(),
+                if self.rebuilt.len() == 1 { "is a dependency" } else { "are dependencies" },
+                names.join(", "),
+                if self.rebuilt.len() > LISTED { ", ..." } else { "" },
+            ));
+        }
+        lines
+    }
+}
+
+/// The directory of the profile a file was built for, from `deps/`, `examples/` or
+/// `build/<unit>/` in it
+fn profile_dir(file: &Path) -> Option<&Path> {
+    let parent = file.parent()?;
+    let dir = match parent.file_name()?.to_str()? {
+        "deps" | "examples" => parent,
+        _ if parent.parent()?.file_name()? == "build" => parent.parent()?,
+        _ => return None,
+    };
+    dir.parent()
+}
+
+/// A package of the workspace or next to it, whose sources are edited
+fn is_local(package_id: &str) -> bool {
+    package_id.contains("path+file://")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SERDE: &str = "registry+https://github.com/rust-lang/crates.io-index#serde@1.0.200";
+    const SYN: &str = "registry+https://github.com/rust-lang/crates.io-index#syn@2.0.60";
+    const APP: &str = "path+file:///work/app#0.1.0";
+
+    fn files(list: &[&str]) -> Vec<String> {
+        list.iter().map(|file| file.to_string()).collect()
+    }
+
+    #[test]
+    fn test_describe() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("debug/.fingerprint/serde-1111111111111111")).unwrap();
+        fs::create_dir_all(dir.path().join("release/.fingerprint/syn-2222222222222222")).unwrap();
+        let target = dir.path().display();
+        let start = Instant::now();
+        let mut rebuilds = Rebuilds::new(dir.path(), start);
+        rebuilds.artifact(SYN, false, &files(&[&format!("{}/debug/deps/libsyn-4444444444444444.rlib", target)]), start + Duration::from_secs(4));
+        rebuilds.artifact(SERDE, false, &files(&[&format!("{}/debug/build/serde-5555555555555555/build_script_build-5555555555555555", target)]), start + Duration::from_secs(5));
+        rebuilds.artifact(SERDE, false, &files(&[&format!("{}/debug/deps/libserde-6666666666666666.rlib", target)]), start + Duration::from_secs(6));
+        rebuilds.artifact(APP, false, &files(&["/work/target/debug/app", "/work/target/debug/deps/app-3333333333333333"]), start + Duration::from_secs(7));
+        assert_eq!(rebuilds.describe(10), [
+            "Recompiled 3 crates, 10 fresh; the slowest: syn v2.0.60 (4.0s), serde v1.0.200 (2.0s), app v0.1.0 (1.0s)",
+            "1 of them is a dependency an earlier build had compiled for this profile (serde); cargo compiles a dependency again when RUSTFLAGS, the toolchain, its version or features, or a variable its build script watches changed",
+        ]);
+
+        // Only the workspace's own crates, or everything from scratch
+        let mut edit = Rebuilds::new(dir.path(), start);
+        edit.artifact(APP, false, &files(&["/work/target/debug/deps/app-3333333333333333"]), start + Duration::from_secs(1));
+        assert!(edit.describe(10).is_empty());
+        let mut first = Rebuilds::new(Path::new("/nonexistent"), start);
+        first.artifact(SYN, false, &files(&["/work/target/debug/deps/libsyn-2222222222222222.rlib"]), start + Duration::from_secs(4));
+        assert!(first.describe(0).is_empty());
+    }
+}
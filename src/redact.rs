@@ -0,0 +1,106 @@
+use crate::Config;
+use crate::diagnostics::Diagnostic;
+use anyhow::{Result, Context};
+use regex::Regex;
+use std::env;
+
+const REDACTED: &str = "[REDACTED]";
+
+/// Scrubs sensitive text from diagnostics before they reach logs, remote sinks or notifications.
+#[derive(Clone)]
+pub struct Redactor {
+    /// Literal values and their replacements, longest first so that e.g. a token
+    /// containing the home directory is replaced as a whole
+    literals: Vec<(String, String)>,
+    patterns: Vec<Regex>,
+}
+
+impl Redactor {
+    /// Build a redactor from `--redact`, `--redact-env` and `--redact-home`;
+    /// `None` when nothing is to be redacted.
+    pub fn from_config(config: &Config) -> Result<Option<Self>> {
+        let mut literals = Vec::new();
+        for name in &config.redact_env {
+            if let Ok(value) = env::var(name) {
+                literals.push((value, format!("${}", name)));
+            }
+        }
+        if config.redact_home {
+            if let Some(home) = home_dir() {
+                literals.push((home, "~".to_string()));
+            }
+        }
+
+        let patterns = config.redact.iter()
+            .map(|pattern| Regex::new(pattern)
+                .with_context(|| format!("Invalid --redact pattern: {}", pattern)))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self::new(literals, patterns))
+    }
+
+    fn new(mut literals: Vec<(String, String)>, patterns: Vec<Regex>) -> Option<Self> {
+        literals.retain(|(value, _)| !value.is_empty());
+        if literals.is_empty() && patterns.is_empty() {
+            return None;
+        }
+        literals.sort_by_key(|(value, _)| std::cmp::Reverse(value.len()));
+        Some(Redactor { literals, patterns })
+    }
+
+    pub fn redact(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (value, replacement) in &self.literals {
+            if text.contains(value.as_str()) {
+                text = text.replace(value.as_str(), replacement);
+            }
+        }
+        for pattern in &self.patterns {
+            text = pattern.replace_all(&text, REDACTED).into_owned();
+        }
+        text
+    }
+
+    pub fn redact_diagnostic(&self, diagnostic: &Diagnostic) -> Diagnostic {
+        Diagnostic {
+            message: self.redact(&diagnostic.message),
+            file: diagnostic.file.as_deref().map(|file| self.redact(file)),
+            package_id: diagnostic.package_id.as_deref().map(|id| self.redact(id)),
+            rendered: self.redact(&diagnostic.rendered),
+            ..diagnostic.clone()
+        }
+    }
+}
+
+fn home_dir() -> Option<String> {
+    env::var("HOME").ok()
+        .or_else(|| env::var("USERPROFILE").ok())
+        .map(|home| home.trim_end_matches(['/', '\\']).to_string())
+        .filter(|home| !home.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_redacts_literals_and_patterns() {
+        let redactor = Redactor::new(
+            vec![
+                ("/home/me".to_string(), "~".to_string()),
+                ("s3cr3t".to_string(), "$API_TOKEN".to_string()),
+            ],
+            vec![Regex::new(r"ghp_[A-Za-z0-9]+").unwrap()],
+        ).unwrap();
+
+        assert_eq!(
+            redactor.redact("--> /home/me/src/main.rs uses s3cr3t and ghp_abc123"),
+            "--> ~/src/main.rs uses $API_TOKEN and [REDACTED]"
+        );
+    }
+
+    #[test]
+    fn test_nothing_to_redact() {
+        assert!(Redactor::new(vec![(String::new(), "$EMPTY".to_string())], vec![]).is_none());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::artifacts::{self, Artifact};
+use crate::diagnostics::{self, CargoMessage};
+use crate::steps::Problem;
+use crate::util::Workspace;
+use crate::{runner, Config};
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::Command;
+
+/// Build the workspace again from scratch in a target directory of its own and compare
+/// what both builds produced, byte for byte. The second build runs in another time zone
+/// and locale, with its target directory mapped back to the first one's in the paths
+/// rustc writes, so only what really varies between builds shows. Returns how many
+/// artifacts were compared.
+pub fn verify(config: &Config, workspace: &Workspace, built: &[Artifact]) -> Result<usize, Problem> {
+    let mut first: Vec<Artifact> = built.iter().filter(|artifact| artifact.is_local() && artifact.is_shipped()).cloned().collect();
+    if first.is_empty() {
+        return Ok(0);
+    }
+    // Left in place afterwards, for diffoscope
+    let scratch = workspace.state_dir().join("reproducible");
+    if scratch.exists() {
+        fs::remove_dir_all(&scratch)
+            .map_err(|e| Problem { message: format!("failed to remove {}: {}", scratch.display(), e), output: None, help: None })?;
+    }
+    let mut cmd = Command::new(&config.cargo);
+    cmd.args(["build", "--message-format=json"]).args(&config.cargo_args);
+    runner::setup_environment(&mut cmd, config).map_err(|e| Problem { message: format!("{:#}", e), output: None, help: None })?;
+    cmd.env("CARGO_TARGET_DIR", &scratch)
+        .env("CARGO_TERM_COLOR", "never")
+        .env("TZ", "UTC")
+        .env("LC_ALL", "C")
+        .env("LANG", "C");
+    remap(&mut cmd, &scratch, &workspace.target_directory);
+    let output = cmd.output().map_err(|e| Problem { message: format!("failed to run cargo build: {}", e), output: None, help: None })?;
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+        return Err(Problem {
+            message: "the second build of --verify-reproducible failed".to_string(),
+            output: Some(stderr).filter(|stderr| !stderr.is_empty()),
+            help: None,
+        });
+    }
+    let mut second = Vec::new();
+    for line in String::from_utf8_lossy(&output.stdout).lines() {
+        if let Ok(Some(CargoMessage::CompilerArtifact { package_id, filenames, executable, target_name, target_kind, test, .. })) = diagnostics::parse_cargo_message(line) {
+            second.extend(Artifact::from_message(&package_id, &target_name, &target_kind, test, &filenames, executable.as_deref()));
+        }
+    }
+    artifacts::checksum(&mut first)?;
+    artifacts::checksum(&mut second)?;
+    let differences = compare(&first, &second);
+    if differences.is_empty() {
+        return Ok(first.len());
+    }
+    Err(Problem {
+        message: format!("the build isn't reproducible: {} of {} artifacts differ between two builds", differences.len(), first.len()),
+        output: Some(differences.join("\n")),
+        help: Some(format!(
+            "the second build is in {}; look for timestamps, absolute paths, hash map order and build scripts that read the environment, or compare the files with diffoscope",
+            scratch.display(),
+        )),
+    })
+}
+
+/// A line per artifact of the first build that the second one built differently, or
+/// didn't build at all. Both have their checksums.
+fn compare(first: &[Artifact], second: &[Artifact]) -> Vec<String> {
+    first.iter().filter_map(|artifact| {
+        let again = second.iter().find(|other| {
+            other.package_id == artifact.package_id && other.kind == artifact.kind && other.path.file_name() == artifact.path.file_name()
+        });
+        match again {
+            Some(again) if again.sha256 == artifact.sha256 => None,
+            Some(again) => Some(format!(
+                "{}: {} in {}, {} in {}",
+                artifact.name(),
+                artifact.sha256.as_deref().unwrap_or("?"),
+                artifact.path.display(),
+                again.sha256.as_deref().unwrap_or("?"),
+                again.path.display(),
+            )),
+            None => Some(format!("{}: not built the second time", artifact.name())),
+        }
+    }).collect()
+}
+
+/// Map the scratch target directory to the real one in the paths rustc writes, adding to
+/// the rustflags cargo would use rather than replacing them. `target.<triple>.rustflags`
+/// in cargo's configuration take the place of `build.rustflags`, and the paths stay as
+/// they are then.
+fn remap(cmd: &mut Command, from: &Path, to: &Path) {
+    let flag = format!("--remap-path-prefix={}={}", from.display(), to.display());
+    if let Ok(flags) = env::var("CARGO_ENCODED_RUSTFLAGS") {
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", if flags.is_empty() { flag } else { format!("{}\x1f{}", flags, flag) });
+    } else if let Ok(flags) = env::var("RUSTFLAGS") {
+        // Encoded, so a path with spaces stays one flag
+        let flags: Vec<&str> = flags.split_whitespace().chain([flag.as_str()]).collect();
+        cmd.env("CARGO_ENCODED_RUSTFLAGS", flags.join("\x1f"));
+    } else {
+        // Merged with the configured list, as arrays from different sources are
+        let value = serde_json::to_string(&flag).expect("a string serializes");
+        cmd.arg("--config").arg(format!("build.rustflags=[{}]", value));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn artifact(kind: &'static str, path: &str, sha256: &str) -> Artifact {
+        Artifact {
+            kind,
+            target_name: "app".to_string(),
+            package_id: "path+file:///work/app#0.1.0".to_string(),
+            path: PathBuf::from(path),
+            sha256: Some(sha256.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_compare() {
+        let first = [
+            artifact("bin", "/work/target/release/app", "aa"),
+            artifact("cdylib", "/work/target/release/libapp.so", "bb"),
+            artifact("staticlib", "/work/target/release/libapp.a", "cc"),
+        ];
+        let second = [
+            artifact("staticlib", "/work/target/cargo-builder/reproducible/release/libapp.a", "cc"),
+            artifact("bin", "/work/target/cargo-builder/reproducible/release/app", "ab"),
+        ];
+        assert_eq!(compare(&first, &second), [
+            "app: aa in /work/target/release/app, ab in /work/target/cargo-builder/reproducible/release/app",
+            "libapp.so: not built the second time",
+        ]);
+        assert!(compare(&first[2..], &second).is_empty());
+    }
+}
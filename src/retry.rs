@@ -0,0 +1,96 @@
+use crate::diagnostics::strip_ansi_codes;
+use regex::Regex;
+use std::time::Duration;
+
+/// Retries `--retry` defaults to in CI, where a network blip fails a whole job.
+pub const CI_RETRIES: u32 = 2;
+
+/// Backoff before the first retry, doubled for each one after it.
+const FIRST_BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+lazy_static::lazy_static! {
+    /// What cargo prints when fetching failed for reasons a second try may not hit.
+    static ref NETWORK: Regex = Regex::new(concat!(
+        // Registry and download failures, including the curl errors cargo quotes
+        r"spurious network error|failed to download|failed to query replaced source registry|",
+        r"download of \S+ failed|\[(6|7|28|35|52|55|56)\] |HTTP/2 stream \d+ was not closed cleanly|",
+        r"failed to get successful HTTP response from .*, got (408|429|5\d\d)|",
+        // git dependencies and git-based registries
+        r"failed to fetch into|failed to clone into|network failure seems to have happened|",
+        r"early EOF|[Cc]onnection (reset|refused|timed out)|Could not resolve host",
+    )).unwrap();
+
+    /// Locks held by another cargo, or by a virus scanner on Windows.
+    static ref LOCK: Regex = Regex::new(concat!(
+        r"failed to lock file|could not acquire package cache lock|",
+        r"being used by another process|Resource temporarily unavailable",
+    )).unwrap();
+}
+
+/// The line of cargo's output that shows the failure is transient, if it is. The
+/// last one is the innermost cause, after cargo's own retries and their warnings.
+pub fn transient_cause(output: &str) -> Option<String> {
+    last_match(output, |line| NETWORK.is_match(line) || LOCK.is_match(line))
+}
+
+/// Like `transient_cause`, but only for network failures, which `--offline` gets around.
+pub fn network_cause(output: &str) -> Option<String> {
+    last_match(output, |line| NETWORK.is_match(line))
+}
+
+fn last_match(output: &str, matches: impl Fn(&str) -> bool) -> Option<String> {
+    strip_ansi_codes(output)
+        .lines()
+        .map(str::trim)
+        .rfind(|line| matches(line))
+        .map(str::to_string)
+}
+
+/// How long to wait before the `retry`th retry, counting from 1.
+pub fn backoff(retry: u32) -> Duration {
+    FIRST_BACKOFF.saturating_mul(1 << retry.saturating_sub(1).min(16)).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_transient_cause() {
+        let registry = "    Updating crates.io index\nerror: failed to get `serde` as a dependency of package `app v0.1.0`\n\nCaused by:\n  download of config.json failed\n\nCaused by:\n  [28] Timeout was reached (Connection timed out after 30001 milliseconds)\n";
+        assert_eq!(
+            transient_cause(registry).as_deref(),
+            Some("[28] Timeout was reached (Connection timed out after 30001 milliseconds)"),
+        );
+
+        let git = "\x1b[1m\x1b[31merror\x1b[0m: failed to load source for dependency `foo`\n\nCaused by:\n  failed to fetch into: /home/me/.cargo/git/db/foo-1234\n";
+        assert_eq!(transient_cause(git).as_deref(), Some("failed to fetch into: /home/me/.cargo/git/db/foo-1234"));
+
+        let http = "error: failed to get successful HTTP response from `https://index.crates.io/se/rd/serde` (13.32.1.2), got 503\n";
+        assert!(transient_cause(http).is_some());
+
+        // A missing version fails the same way every time
+        let missing = "error: failed to select a version for the requirement `serde = \"^99\"`\ncandidate versions found which didn't match: 1.0.219\n";
+        assert_eq!(transient_cause(missing), None);
+        let not_found = "error: failed to get successful HTTP response from `https://index.crates.io/no/ne/nonexistent`, got 404\n";
+        assert_eq!(transient_cause(not_found), None);
+    }
+
+    #[test]
+    fn test_network_cause() {
+        let git = "error: failed to get `foo` as a dependency of package `app v0.1.0`\n\nCaused by:\n  failed to clone into: /home/me/.cargo/git/db/foo-1234\n";
+        assert_eq!(network_cause(git).as_deref(), Some("failed to clone into: /home/me/.cargo/git/db/foo-1234"));
+        let lock = "error: failed to lock file: /home/me/.cargo/.package-cache\n";
+        assert!(transient_cause(lock).is_some());
+        assert_eq!(network_cause(lock), None);
+    }
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), Duration::from_secs(2));
+        assert_eq!(backoff(2), Duration::from_secs(4));
+        assert_eq!(backoff(3), Duration::from_secs(8));
+        assert_eq!(backoff(10), MAX_BACKOFF);
+    }
+}
@@ -1,126 +1,1227 @@
-use crate::{Config, ColorChoice, diagnostics, logging, util};
+use crate::{Config, ColorChoice, ExitCodePolicy, exit_code, against, artifacts, audit, ci, baseline, buildscript, buildstd, container, context, coverage, cross, deny, diagnostics, diff, doctest, eventlog, events, fetch, flash, futureincompat, fuzz, git, ice, journald, linker, load, lock, logging, matrix, memory, miri, nextest, notify, paths, process, rebuilds, reproducible, retry, rustflags, settings, signals, sizes, statsd, store, suppress, syslibs, syslog, term, timings, trend, udeps, util, wasm};
 use anyhow::{Result, Context};
+use crate::events::Event;
+use std::collections::BTreeMap;
 use std::process::{Command, Stdio};
 use std::env;
-use std::io::{BufRead, BufReader};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
-pub fn run_build(config: &Config) -> Result<i32> {
-    let workspace = util::find_workspace()?;
-    let log_path = config.log_path.clone()
-        .unwrap_or_else(|| workspace.target_directory.join("build-errors.log").display().to_string());
+/// Run one build. `seen` holds what earlier builds of a matrix showed, which isn't shown again.
+pub fn run_build(config: &Config, seen: Option<&mut matrix::Seen>) -> Result<Outcome> {
+    let started_at = SystemTime::now();
+    let start = Instant::now();
+    let workspace = util::find_workspace_for(context::manifest_path(&config.cargo_args))?;
+    let profile = context::profile(&config.cargo_args);
+    let mut log_targets = if config.log_per_run {
+        vec![logging::LogTarget {
+            format: logging::LogFormat::Text,
+            path: logging::per_run_log_path(&workspace.state_dir(), started_at, config.log_compress),
+        }]
+    } else if config.log_paths.is_empty() {
+        vec![logging::LogTarget {
+            format: logging::LogFormat::Text,
+            path: workspace.target_directory.join(logging::default_log_name(&profile)),
+        }]
+    } else {
+        config.log_paths.iter()
+            .map(|spec| logging::LogTarget::parse(spec))
+            .collect::<Result<Vec<_>>>()?
+    };
+    if config.log_compress {
+        for target in &mut log_targets {
+            if !logging::is_gzip_path(&target.path) {
+                target.path = logging::with_gzip_extension(&target.path);
+            }
+        }
+    }
 
-    let mut cmd = Command::new("cargo");
-    cmd.arg("build")
-       .arg("--message-format=json-diagnostic-rendered-ansi");
+    let settings = settings::load(&workspace)?.for_profile(&profile);
+    // Set up logging before cargo starts, so a log locked by another build fails fast
+    let mut logger = logging::Logger::new(&log_targets, config)?;
+    // Only once the logs are locked, so no other build is rotating them
+    if let Some(retention) = settings.log.retention {
+        let removed = logging::prune_logs(&log_targets, &workspace.state_dir().join("logs"), retention)?;
+        if removed > 0 && !config.quiet {
+            eprintln!("cargo-builder: Removed {} logs older than {}", removed, humantime::format_duration(retention));
+        }
+    }
+    if let Some(target) = &config.syslog {
+        logger.add_remote_sink(syslog::SyslogSink::connect(target, &config.syslog_facility));
+    }
+    if config.journald {
+        logger.add_remote_sink(journald::JournaldSink::connect());
+    }
+    if config.event_log {
+        let paths = log_targets.iter().map(|target| target.path.display().to_string()).collect();
+        logger.add_remote_sink(eventlog::EventLogSink::new(format_command(config), paths));
+    }
+    // Taken before cargo starts, as edits made during the build aren't in it
+    let vcs = git::VcsState::load(&workspace.root);
+    let mut capture = config.capture_json.as_deref().map(open_capture).transpose()?;
+    let baseline = config.baseline.as_deref()
+        .map(|path| baseline::Baseline::load(Path::new(path)))
+        .transpose()?;
+    let mut suppressions = suppress::Suppressions::load(&workspace)?;
+    let changed = config.changed_only.as_deref()
+        .map(|reference| git::ChangedFiles::load(&workspace.root, reference))
+        .transpose()?;
+    if let (Some(changed), false) = (&changed, config.quiet) {
+        eprintln!("cargo-builder: Showing diagnostics in {} files changed since {}", changed.count(), changed.base);
+    }
+    let against = config.against.as_deref()
+        .map(|reference| against::load(&workspace, reference, config))
+        .transpose()?;
+
+    let mut cmd = Command::new(&config.cargo);
+    if config.nextest {
+        // cargo's messages and the tests' results both arrive as JSON on stdout
+        let format = if config.short_messages { "json-diagnostic-short" } else { "json-diagnostic-rendered-ansi" };
+        cmd.args(["nextest", "run", "--cargo-message-format", format, "--message-format", "libtest-json"])
+           .env("NEXTEST_EXPERIMENTAL_LIBTEST_JSON", "1");
+    } else {
+        match (&config.miri, &config.coverage) {
+            (Some(command), _) => cmd.args(["miri", command]),
+            // The report is made once the tests pass
+            (None, Some(_)) => cmd.args(["llvm-cov", "--no-report"]),
+            (None, None) => cmd.arg("build"),
+        };
+        cmd.arg("--message-format=json-diagnostic-rendered-ansi");
+        if config.short_messages {
+            // Combines with the format above: still JSON, rendered on one line
+            cmd.arg("--message-format=json-diagnostic-short");
+        }
+    }
+    if config.timings {
+        cmd.arg("--timings");
+    }
 
     // Add user's cargo args
     for arg in &config.cargo_args {
         cmd.arg(arg);
     }
 
+    // Jobs chosen with -j or in cargo's configuration are left alone; nextest's -j is for tests
+    let jobs_flag = if config.nextest { "--build-jobs" } else { "--jobs" };
+    let jobs_given = config.cargo_args.iter().take_while(|arg| *arg != "--")
+        .any(|arg| arg.starts_with(jobs_flag) || (!config.nextest && arg.starts_with("-j")));
+    if config.auto_jobs && !jobs_given && env::var_os("CARGO_BUILD_JOBS").is_none() {
+        let cpus = std::thread::available_parallelism().map_or(1, |cpus| cpus.get());
+        let load = load::load_average();
+        let jobs = load::auto_jobs(cpus, load);
+        if !config.quiet {
+            let load = load.map(|load| format!("Load {:.2} on ", load)).unwrap_or_default();
+            eprintln!("cargo-builder: {}{} {}; building with -j {}", load, cpus, if cpus == 1 { "CPU" } else { "CPUs" }, jobs);
+        }
+        cmd.arg(jobs_flag).arg(jobs.to_string());
+    }
+
     // Set up environment
     setup_environment(&mut cmd, config)?;
 
     // Configure stdio
+    // Collapsible sections keep cargo's progress out of the way in an Actions log
+    let grouped = config.ci.is_some_and(|provider| provider.workflow_commands());
+    // Show ALL output as it arrives with --show-build-output
+    let group_cargo_output = grouped && !config.show_build_output;
     cmd.stdout(Stdio::piped());
-    if config.show_build_output {
-        // Show ALL output including warnings
-        cmd.stderr(Stdio::inherit());
-    } else {
-        // Show build progress but capture for fallback error handling
-        cmd.stderr(Stdio::inherit());
+    // Passed through as it arrives, or kept for a group once the build finishes
+    cmd.stderr(Stdio::piped());
+    // cargo only draws its progress bar on a terminal, and its stderr is now a pipe
+    if !group_cargo_output && atty::is(atty::Stream::Stderr) && env::var_os("CARGO_TERM_PROGRESS_WHEN").is_none() {
+        if let Ok((width, _)) = crossterm::terminal::size() {
+            cmd.env("CARGO_TERM_PROGRESS_WHEN", "always")
+               .env("CARGO_TERM_PROGRESS_WIDTH", width.to_string());
+        }
+    }
+    if let Some(image) = &config.container {
+        // Everything above is for cargo; the engine passes its arguments and environment on
+        let engine = container::engine(config.container_engine.as_deref())?;
+        if !config.quiet {
+            eprintln!("cargo-builder: Building in {} with {}", image, engine);
+        }
+        cmd = container::command(&cmd, &engine, image, &workspace)?;
+        cmd.stdout(Stdio::piped()).stderr(Stdio::piped());
     }
 
     if !config.quiet {
-        eprintln!("cargo-builder: Starting build...");
+        if let Some(warning) = rustflags::overridden(config) {
+            eprintln!("cargo-builder: Warning: {}", warning);
+        }
     }
 
-    let mut child = cmd.spawn()
-        .context("Failed to spawn cargo build process")?;
+    // Builds in containers have toolchains of their own
+    if !config.cross && config.container.is_none() {
+        if let Some(problem) = config.target_wasm.as_deref().and_then(|target| wasm::check_target(config, target)) {
+            return fail_before_build(config, logger, "Checking the wasm target", &[problem.diagnostic()], exit_code::CARGO_FAILED);
+        }
+        if let Some(problem) = buildstd::check(config) {
+            return fail_before_build(config, logger, "Checking -Zbuild-std", &[problem.diagnostic()], exit_code::CARGO_FAILED);
+        }
+        if let Some(problem) = config.fuzz.as_ref().and_then(|sanitizer| fuzz::check(config, sanitizer)) {
+            return fail_before_build(config, logger, "Checking the fuzz toolchain", &[problem.diagnostic()], exit_code::CARGO_FAILED);
+        }
+        if let Some(problem) = config.miri.as_ref().and_then(|_| miri::check(config)) {
+            return fail_before_build(config, logger, "Checking Miri", &[problem.diagnostic()], exit_code::CARGO_FAILED);
+        }
+    }
 
-    let stdout = child.stdout.take()
-        .context("Failed to capture stdout")?;
+    if config.fetch {
+        if !config.quiet {
+            eprintln!("cargo-builder: Fetching dependencies...");
+        }
+        if let Some(failure) = fetch::run(config)? {
+            let diagnostics: Vec<_> = failure.problems.iter().map(fetch::Problem::diagnostic).collect();
+            return fail_before_build(config, logger, "Fetching dependencies", &diagnostics, failure.exit_code);
+        }
+    }
 
-    let mut logger = logging::Logger::new(&log_path, config)?;
+    if !config.quiet {
+        eprintln!("cargo-builder: Starting build...");
+    }
+
+    let handlers = signals::install().context("Failed to install the Ctrl-C handler")?;
+    let deadline = config.timeout.map(|limit| process::Deadline::new(Instant::now(), limit));
+    let mut stopper = process::Stopper::default();
+    let mut memory = config.max_memory.map(|limit| memory::Monitor::new(limit, Instant::now()));
+    let mut exceeded = None;
+    // Why the build gave up waiting for a file lock, with --lock-timeout
+    let mut lock_gave_up = None;
+    // The status line of a lock wait is redrawn in place
+    let live_status = !group_cargo_output && atty::is(atty::Stream::Stderr);
+    let mut interrupts = signals::interrupts();
+    let mut retries = 0;
+    // The network error that made --offline-fallback build offline
+    let mut offline_after = None;
+    let offline = ["--offline", "--frozen"].iter().any(|flag| context::has_flag(&config.cargo_args, flag))
+        || env::var("CARGO_NET_OFFLINE").is_ok_and(|value| value == "true");
+    // Ctrl-C while waiting to retry
+    let mut interrupted_waiting = false;
+    let mut cargo_output = Vec::new();
+    let mut crate_timings = config.crate_timings.then(|| timings::CrateTimings::new(Instant::now()));
+    let mut rebuilds = rebuilds::Rebuilds::new(&workspace.target_directory, Instant::now());
+    let mut future_incompat = futureincompat::Notice::default();
+    let mut missing = syslibs::Missing::default();
+    let mut ices = ice::Detector::default();
+    let mut script_failures = buildscript::Failures::default();
+
+    // Maps given with --path-map come first, as the first one that matches wins
+    let mut path_maps = config.path_maps.clone();
+    if config.cross {
+        path_maps.extend(cross::path_maps(&workspace, config));
+    }
+    if config.container.is_some() {
+        path_maps.extend(container::path_maps(&workspace));
+    }
+    let path_rewriter = paths::PathRewriter::new(&workspace.root, config.paths, &path_maps);
     let mut build_success = None;
     let mut has_errors = false;
+    let mut collected = Vec::new();
+    let mut blamer = config.blame.then(|| git::Blamer::new(&workspace.root));
+    let mut baselined = 0;
+    let mut unchanged = 0;
+    let mut preexisting = 0;
+    let mut repeated = 0;
+    let mut grouped_warnings = String::new();
+    let mut artifacts = notify::Artifacts::default();
+    let mut wasm_modules = Vec::new();
+    let mut executables = Vec::new();
+    // What the build produced, by path; fresh units report theirs again on every build
+    let mut built = BTreeMap::new();
+    let mut tests = nextest::Tally::default();
+    let mut miri_reports = config.miri.as_ref().map(|_| miri::Reports::default());
+    let mut test_output = String::new();
+
+    let exit_status = loop {
+        // A group of its own can be stopped as a whole; Ctrl-C is passed on from our handler
+        let (mut child, group) = match process::spawn_group(&mut cmd, config.nice) {
+            Ok(spawned) => spawned,
+            Err(e) => {
+                eprintln!("cargo-builder: Failed to run {}: {}", config.cargo, e);
+                let status = BuildStatus { cargo_exit_code: exit_code::CARGO_FAILED, ..Default::default() };
+                return Ok(Outcome { exit_code: apply_exit_code_policy(config.exit_code, &status), ..Default::default() });
+            }
+        };
+
+        let events = events::Events::spawn(&mut child)?;
+        // cargo's output until it compiles something, to tell whether a failure is transient
+        let mut early_output = Vec::new();
+        let mut compiling = false;
+        let mut lock_lines = lock::Lines::default();
+        let mut lock_wait: Option<lock::Wait> = None;
+
+        // Process JSON messages on stdout, passing cargo's progress on stderr through in between
+        for event in events {
+            let now = Instant::now();
+            if signals::interrupts() > interrupts {
+                interrupts = signals::interrupts();
+                if stopper.reason() == Some(process::Stop::Interrupted) {
+                    // Pressed again: no more waiting
+                    stopper.kill_now(&group)?;
+                } else {
+                    clear_progress_line(group_cargo_output);
+                    eprintln!("cargo-builder: Interrupted; stopping cargo (press Ctrl-C again to kill it)");
+                    stopper.stop(&group, process::Stop::Interrupted, now)?;
+                }
+            }
+            if let Some(deadline) = deadline.as_ref().filter(|deadline| deadline.passed(now) && stopper.reason().is_none()) {
+                eprintln!("cargo-builder: Build timed out after {}; stopping cargo", humantime::format_duration(deadline.limit()));
+                stopper.stop(&group, process::Stop::TimedOut, now)?;
+            }
+            if let Some(monitor) = memory.as_mut().filter(|_| stopper.reason().is_none()) {
+                if let Some(over) = monitor.check(&group, now)? {
+                    clear_progress_line(group_cargo_output);
+                    eprintln!("cargo-builder: {}; stopping cargo", over.describe());
+                    stopper.stop(&group, process::Stop::MemoryLimit, now)?;
+                    exceeded = Some(over);
+                }
+            }
+            stopper.check(&group, now)?;
+            if !matches!(event, Event::Tick) {
+                // Whatever cargo does next ends a wait for a lock
+                if lock_wait.take().is_some() {
+                    clear_progress_line(!live_status);
+                }
+                let blocked = match &event {
+                    Event::Stderr(chunk) => lock_lines.blocked_on(chunk),
+                    _ => None,
+                };
+                if let Some(what) = blocked {
+                    lock_wait = Some(lock::Wait::new(what, &workspace.target_directory, child.id(), now));
+                }
+            }
+            // Reported on ticks, once cargo finished writing the line that started the wait
+            if let (Event::Tick, Some(wait)) = (&event, lock_wait.as_mut().filter(|_| stopper.reason().is_none())) {
+                if config.lock_timeout.is_some_and(|limit| wait.elapsed(now) >= limit) {
+                    clear_progress_line(!live_status);
+                    let holder = wait.holder.as_ref().map(|holder| format!(", held by {}", holder)).unwrap_or_default();
+                    let lock = format!("the file lock on {} after {}{}", wait.what, util::format_duration(wait.elapsed(now)), holder);
+                    eprintln!("cargo-builder: Gave up waiting for {}; stopping cargo", lock);
+                    stopper.stop(&group, process::Stop::LockTimeout, now)?;
+                    lock_gave_up = Some(format!("gave up waiting for {}", lock));
+                } else if live_status && wait.report_due(now, true) {
+                    show_status_line(&format!("cargo-builder: {}", wait.describe(now)));
+                } else if !live_status && !config.quiet && wait.report_due(now, false) {
+                    eprintln!("cargo-builder: {}", wait.describe(now));
+                }
+            }
+            if let (Event::Stderr(chunk), false) = (&event, compiling) {
+                early_output.extend_from_slice(chunk);
+            }
+            if let Event::Stderr(chunk) = &event {
+                future_incompat.feed(chunk);
+                missing.feed(chunk);
+                ices.feed(chunk);
+                script_failures.feed(chunk);
+            }
+            // Miri's reports arrive as text among cargo's progress
+            let event = match (event, miri_reports.as_mut()) {
+                (Event::Stderr(chunk), Some(reports)) => {
+                    let (through, found) = reports.feed(&chunk);
+                    for diagnostic in found {
+                        collected.push(show_run_failure(config, &mut logger, path_rewriter.as_ref(), diagnostic)?);
+                        has_errors = true;
+                    }
+                    Event::Stderr(through)
+                }
+                (event, _) => event,
+            };
+            let line = match event {
+                Event::Stdout(line) => line.context("Failed to read stdout line")?,
+                Event::Stderr(chunk) if group_cargo_output => {
+                    cargo_output.extend_from_slice(&chunk);
+                    continue;
+                }
+                Event::Stderr(chunk) => {
+                    let mut stderr = std::io::stderr();
+                    stderr.write_all(&chunk).context("Failed to write cargo output")?;
+                    stderr.flush().context("Failed to write cargo output")?;
+                    continue;
+                }
+                Event::Tick => continue,
+            };
+            if let Some(capture) = &mut capture {
+                writeln!(capture, "{}", line).context("Failed to write captured JSON")?;
+            }
 
-    // Process stdout (JSON messages)
-    let stdout_reader = BufReader::new(stdout);
-    for line in stdout_reader.lines() {
-        let line = line.context("Failed to read stdout line")?;
-        
-        match diagnostics::parse_cargo_message(&line)? {
-            Some(diagnostics::CargoMessage::CompilerMessage { level, rendered }) => {
-                match level.as_str() {
+            if let Some(event) = config.nextest.then(|| nextest::parse_event(&line)).flatten() {
+                compiling = true;
+                tests.record(&event);
+                if let nextest::TestEvent::Failed { name, binary, output } = event {
+                    let diagnostic = nextest::diagnostic(&name, binary.as_deref(), &output);
+                    collected.push(show_run_failure(config, &mut logger, path_rewriter.as_ref(), diagnostic)?);
+                    has_errors = true;
+                }
+                continue;
+            }
+
+            // The tests' own output comes between cargo's messages
+            if config.coverage.is_some() && !line.starts_with('{') {
+                test_output.push_str(&line);
+                test_output.push('\n');
+                continue;
+            }
+
+            let message = diagnostics::parse_cargo_message(&line)?;
+            if matches!(message, Some(diagnostics::CargoMessage::CompilerMessage(_) | diagnostics::CargoMessage::CompilerArtifact { .. })) {
+                compiling = true;
+            }
+            // What build scripts printed goes the way of rustc's diagnostics
+            let (message, reported) = match message {
+                Some(diagnostics::CargoMessage::CompilerMessage(diagnostic)) => (None, vec![diagnostic]),
+                Some(diagnostics::CargoMessage::BuildScriptExecuted { package_id, out_dir }) => (None, buildscript::messages(&package_id, &out_dir)),
+                message => (message, Vec::new()),
+            };
+            for mut diagnostic in reported {
+                // Dropped before anything sees them, as if rustc hadn't reported them
+                if diagnostic.level == "warning" && !config.include_warnings {
+                    continue;
+                }
+                // Rules are written against the workspace-relative paths cargo reports
+                if suppressions.as_mut().is_some_and(|s| s.suppresses(&diagnostic)) {
+                    continue;
+                }
+                if changed.as_ref().is_some_and(|c| !c.contains(&diagnostic)) {
+                    unchanged += 1;
+                    continue;
+                }
+                if against.as_ref().is_some_and(|a| a.contains(&diagnostic)) {
+                    preexisting += 1;
+                    continue;
+                }
+                if baseline.as_ref().is_some_and(|b| b.contains(&diagnostic)) {
+                    baselined += 1;
+                    continue;
+                }
+                if let Some(blamer) = &mut blamer {
+                    blamer.annotate(&mut diagnostic);
+                }
+                if let Some(rewriter) = &path_rewriter {
+                    diagnostic = rewriter.rewrite_diagnostic(diagnostic);
+                }
+                if seen.as_ref().is_some_and(|seen| seen.contains(&diagnostic)) {
+                    repeated += 1;
+                    continue;
+                }
+                if let Some(label) = &config.label {
+                    diagnostic.rendered.insert_str(0, &format!("[{}] ", label));
+                }
+                let rendered = &diagnostic.rendered;
+                match diagnostic.level.as_str() {
                     "error" => {
                         has_errors = true;
-                        // Print error to stderr and log it
-                        eprint!("{}", rendered);
-                        logger.log_error(&rendered)?;
+                        // Print error to stderr and log it, a failed link without the linker's output
+                        missing.diagnostic(&diagnostic);
+                        let short = linker::simplify(&diagnostic);
+                        eprint!("{}", diagnostics::format_for_terminal(short.as_deref().unwrap_or(rendered), &config.terminal_color));
+                        logger.log_diagnostic(&diagnostic)?;
                     }
-                    "warning" if config.include_warnings => {
-                        // Print warning to stderr when warnings are enabled
-                        eprint!("{}", rendered);
+                    "warning" => {
+                        // Print warning to stderr, or into the group of them
+                        let formatted = diagnostics::format_for_terminal(rendered, &config.terminal_color);
+                        if grouped {
+                            grouped_warnings.push_str(&formatted);
+                        } else {
+                            eprint!("{}", formatted);
+                        }
                         if config.log_on_success {
-                            logger.log_error(&rendered)?;
+                            logger.log_diagnostic(&diagnostic)?;
                         }
                     }
                     _ => {} // Ignore other levels (like notes, help, etc.)
                 }
+                if config.annotations && (diagnostic.level == "error" || config.include_warnings) {
+                    if let Some(annotation) = ci::annotation(&diagnostic) {
+                        // Workflow commands work inside groups too
+                        if grouped && diagnostic.level == "warning" {
+                            grouped_warnings.push_str(&format!("{}\n", annotation));
+                        } else {
+                            eprintln!("{}", annotation);
+                        }
+                    }
+                }
+                // Keep the errors and warnings that were shown, for `cargo builder search`
+                if matches!(diagnostic.level.as_str(), "error" | "warning") {
+                    collected.push(diagnostic);
+                }
+            }
+            match message {
+                Some(diagnostics::CargoMessage::CompilerArtifact { package_id, fresh, filenames, executable, target_name, target_kind, test }) => {
+                    artifacts.record(fresh);
+                    wasm_modules.extend(filenames.iter().filter(|file| file.ends_with(".wasm")).map(PathBuf::from));
+                    for artifact in artifacts::Artifact::from_message(&package_id, &target_name, &target_kind, test, &filenames, executable.as_deref()) {
+                        built.insert(artifact.path.clone(), artifact);
+                    }
+                    executables.extend(executable.map(PathBuf::from));
+                    if let Some(timings) = &mut crate_timings {
+                        timings.artifact(&package_id, fresh, Instant::now());
+                    }
+                    rebuilds.artifact(&package_id, fresh, &filenames, Instant::now());
+                }
+                Some(diagnostics::CargoMessage::TimingInfo { package_id, duration }) => {
+                    if let Some(timings) = &mut crate_timings {
+                        timings.timing_info(&package_id, duration);
+                    }
+                    rebuilds.timing_info(&package_id, duration);
+                }
+                Some(diagnostics::CargoMessage::BuildFinished { success }) => {
+                    build_success = Some(success);
+                }
+                _ => {} // Handled above, or not a message we care about
             }
-            Some(diagnostics::CargoMessage::BuildFinished { success }) => {
-                build_success = Some(success);
+        }
+
+        let exit_status = child.wait()
+            .context("Failed to wait for cargo build process")?;
+
+        // Compile errors fail the same way every time, so only failures before compiling are retried
+        let failed_early = !exit_status.success() && !compiling && stopper.reason().is_none();
+        let early_output = String::from_utf8_lossy(&early_output);
+        let Some(cause) = (failed_early && retries < config.retry).then(|| retry::transient_cause(&early_output)).flatten() else {
+            // Retrying didn't help, but the dependencies already downloaded may be all the build needs
+            let fall_back = failed_early && config.offline_fallback && offline_after.is_none() && !offline;
+            if let Some(cause) = fall_back.then(|| retry::network_cause(&early_output)).flatten() {
+                eprintln!("cargo-builder: Network error: {}; building offline with the dependencies already downloaded", cause);
+                logger.log_diagnostic(&entry("warning", format!("network error, built offline: {}", cause)))?;
+                cmd.env("CARGO_NET_OFFLINE", "true");
+                offline_after = Some(cause);
+                continue;
             }
-            None => {} // Not a message we care about
+            break exit_status;
+        };
+        retries += 1;
+        let wait = retry::backoff(retries);
+        if deadline.as_ref().is_some_and(|deadline| deadline.passed(Instant::now() + wait)) {
+            break exit_status;
+        }
+        eprintln!(
+            "cargo-builder: Attempt {} of {} failed on a transient error: {}; retrying in {}",
+            retries, config.retry + 1, cause, util::format_duration(wait),
+        );
+        logger.log_diagnostic(&entry("warning", format!("attempt {} failed on a transient error, retried: {}", retries, cause)))?;
+        if !wait_for_retry(wait, interrupts) {
+            interrupted_waiting = true;
+            break exit_status;
         }
+    };
+    // cargo is done: from here on Ctrl-C and SIGTERM end cargo-builder as usual
+    drop(handlers);
+
+    if let Some(mut capture) = capture {
+        capture.flush().context("Failed to write captured JSON")?;
     }
 
-    let exit_status = child.wait()
-        .context("Failed to wait for cargo build process")?;
+    if interrupted_waiting || stopper.reason() == Some(process::Stop::Interrupted) {
+        // What was collected is incomplete: history, baselines and notifications are left alone
+        logger.log_diagnostic(&stop_entry("build interrupted".to_string()))?;
+        let log_paths: Vec<String> = logger.paths().iter().map(|p| p.display().to_string()).collect();
+        logger.finalize(false)?;
+        clear_progress_line(group_cargo_output);
+        eprintln!("cargo-builder: Build interrupted after {}; log written to: {}", util::format_duration(start.elapsed()), log_paths.join(", "));
+        let status = BuildStatus { interrupted: true, ..Default::default() };
+        return Ok(Outcome { exit_code: apply_exit_code_policy(config.exit_code, &status), ..Default::default() });
+    }
 
-    let exit_code = exit_status.code().unwrap_or(1);
-    let final_success = build_success.unwrap_or(exit_code == 0);
+    let cargo_exit_code = exit_status.code().unwrap_or(1);
+    // Set by checks that fail builds cargo finished, e.g. new warnings not in the baseline
+    let mut check_failed = false;
+    let final_success = build_success.unwrap_or(cargo_exit_code == 0);
 
-    // Handle case where build failed but we didn't capture any JSON error messages
-    if !final_success && !has_errors {
+    if !cargo_output.is_empty() {
+        let output = String::from_utf8_lossy(&cargo_output);
+        if !final_success && !has_errors {
+            // cargo's own output is all there is to explain the failure
+            eprint!("{}", output);
+        } else {
+            ci::print_group(&format!("Cargo output ({} lines)", output.lines().count()), &output);
+        }
+    }
+
+    // Code a future Rust will reject, which cargo only mentions in passing, even when it
+    // reused what it compiled; dependencies' lints are capped, so this is all there is of them.
+    // They are warnings, so they go the way of rustc's before the checks below count them
+    let mut future_incompat_summary = None;
+    if let Some(id) = future_incompat.id() {
+        let reported = futureincompat::load(&workspace.target_directory, id);
+        if !reported.is_empty() {
+            future_incompat_summary = Some(format!("Future incompatibilities: {}", futureincompat::describe(&reported, id)));
+        }
+        for diagnostic in reported {
+            if !config.include_warnings || suppressions.as_mut().is_some_and(|s| s.suppresses(&diagnostic)) {
+                continue;
+            }
+            if against.as_ref().is_some_and(|a| a.contains(&diagnostic)) {
+                preexisting += 1;
+                continue;
+            }
+            if baseline.as_ref().is_some_and(|b| b.contains(&diagnostic)) {
+                baselined += 1;
+                continue;
+            }
+            eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &config.terminal_color));
+            if config.log_on_success {
+                logger.log_diagnostic(&diagnostic)?;
+            }
+            collected.push(diagnostic);
+        }
+    }
+
+    if !grouped_warnings.is_empty() {
+        let count = collected.iter().filter(|d| d.level == "warning").count();
+        ci::print_group(&format!("{} warnings", count), &grouped_warnings);
+    }
+
+    if let Some(suppressions) = &suppressions {
+        if !config.quiet {
+            for line in suppressions.report() {
+                eprintln!("cargo-builder: {}", line);
+            }
+        }
+    }
+
+    if baseline.is_some() {
+        if baselined > 0 && !config.quiet {
+            eprintln!("cargo-builder: {} known diagnostics hidden by the baseline", baselined);
+        }
+        // Warnings missing from the baseline are regressions too
+        if cargo_exit_code == 0 && collected.iter().any(|d| d.level == "warning") {
+            eprintln!("cargo-builder: New warnings not in the baseline");
+            check_failed = true;
+        }
+    }
+
+    if let (Some(against), Some(reference)) = (&against, &config.against) {
+        let new_errors = collected.iter().filter(|d| d.level == "error").count();
+        let new_warnings = collected.iter().filter(|d| d.level == "warning").count();
+        if !config.quiet {
+            eprintln!(
+                "cargo-builder: {} errors and {} warnings introduced since {} ({}); {} present there hidden",
+                new_errors, new_warnings, reference, against.commit, preexisting,
+            );
+        }
+        // Like a baseline, warnings the compared commit didn't have fail the build
+        if cargo_exit_code == 0 && new_warnings > 0 {
+            check_failed = true;
+        }
+    }
+
+    if unchanged > 0 && !config.quiet {
+        eprintln!("cargo-builder: {} diagnostics in unchanged files hidden by --changed-only", unchanged);
+    }
+    if repeated > 0 && !config.quiet {
+        eprintln!("cargo-builder: {} diagnostics shown by an earlier build hidden", repeated);
+    }
+    if let Some(seen) = seen {
+        seen.add(&collected);
+    }
+
+    let timed_out = stopper.reason() == Some(process::Stop::TimedOut);
+    if let (Some(deadline), true) = (&deadline, timed_out) {
+        logger.log_diagnostic(&stop_entry(format!("build timed out after {}", humantime::format_duration(deadline.limit()))))?;
+    }
+    if let Some(exceeded) = &exceeded {
+        logger.log_diagnostic(&stop_entry(exceeded.describe()))?;
+    }
+    if let Some(message) = &lock_gave_up {
+        logger.log_diagnostic(&stop_entry(message.clone()))?;
+    }
+    if !test_output.is_empty() {
+        let results = doctest::parse(&test_output);
+        tests.passed += results.tally.passed;
+        tests.failed += results.tally.failed;
+        tests.ignored += results.tally.ignored;
+        for diagnostic in results.failures {
+            collected.push(show_run_failure(config, &mut logger, path_rewriter.as_ref(), diagnostic)?);
+            has_errors = true;
+        }
+    }
+    // A report Miri was still writing when it was stopped
+    if let Some(diagnostic) = miri_reports.as_mut().and_then(miri::Reports::finish) {
+        collected.push(show_run_failure(config, &mut logger, path_rewriter.as_ref(), diagnostic)?);
+        has_errors = true;
+    }
+    // Failed build scripts, which cargo reports on stderr with all they printed
+    for diagnostic in script_failures.finish() {
+        collected.push(show_run_failure(config, &mut logger, path_rewriter.as_ref(), diagnostic)?);
+        has_errors = true;
+    }
+    // rustc's crashes, bundled into a report instead of scrolling by with cargo's output
+    for ice in ices.finish() {
+        let report = ice.write(&workspace.state_dir().join("ice"), &context::get(config), started_at)?;
+        collected.push(show_run_failure(config, &mut logger, path_rewriter.as_ref(), ice.diagnostic(&report))?);
+        has_errors = true;
+    }
+    let stopped = stopper.reason().is_some();
+
+    // nextest leaves the doctests out; run them once the tests could be
+    if config.doctests && (final_success || tests.failed > 0) && !stopped {
         if !config.quiet {
-            eprintln!("cargo-builder: Build failed (no specific error messages captured)");
+            eprintln!("cargo-builder: Running doctests...");
+        }
+        let doctests = doctest::run(config)?;
+        tests.passed += doctests.tally.passed;
+        tests.failed += doctests.tally.failed;
+        tests.ignored += doctests.tally.ignored;
+        for diagnostic in doctests.failures {
+            collected.push(show_run_failure(config, &mut logger, path_rewriter.as_ref(), diagnostic)?);
+            has_errors = true;
         }
     }
 
+    let built: Vec<artifacts::Artifact> = built.into_values().collect();
+    // What the audits after the build found, and a line on each for before the summary
+    let mut findings = Vec::new();
+    let mut summaries = Vec::new();
+    let mut covered = None;
+    let mut binary_sizes = BTreeMap::new();
+    let mut produced = Vec::new();
+    summaries.extend(future_incompat_summary);
+    // Native libraries and tools the build stopped on, and how to install them here
+    if !final_success || has_errors {
+        summaries.extend(missing.hints());
+    }
+    // Post-steps on what the build wrote; their failures fail the build
+    if final_success && !has_errors && !stopped {
+        let mut problems = Vec::new();
+        // Before anything after the build changes the files in place
+        if config.verify_reproducible {
+            if !config.quiet {
+                eprintln!("cargo-builder: Building again to check the build is reproducible...");
+            }
+            match reproducible::verify(config, &workspace, &built) {
+                Ok(count) => summaries.push(format!("Reproducible: {} {} built the same twice", count, if count == 1 { "artifact" } else { "artifacts" })),
+                Err(problem) => problems.push(problem),
+            }
+        }
+        if (config.wasm_bindgen.is_some() || config.wasm_opt.is_some()) && problems.is_empty() {
+            problems.extend(wasm::post_steps(config, &workspace.root, &wasm_modules));
+        }
+        if config.flash && problems.is_empty() {
+            problems.extend(flash::run(config, &settings.flash, &executables));
+        }
+        let out_dir = config.out_dir.as_ref().map(PathBuf::from).or_else(|| settings.artifacts.out_dir.as_ref().map(|dir| workspace.root.join(dir)));
+        let target = context::option_value(&config.cargo_args, &["--target"]).or_else(|| context::host(config)).unwrap_or("unknown");
+        // The steps work on the copies when there are any, leaving target/ as cargo wrote it
+        let mut processed = None;
+        if let (Some(dir), true) = (out_dir, problems.is_empty()) {
+            let template = config.out_name.as_deref().or(settings.artifacts.name.as_deref()).unwrap_or("{file}");
+            match artifacts::copy(&built, &dir, template, target, &profile) {
+                Ok(copied) => {
+                    summaries.push(format!("Copied {} {} to {}", copied.len(), if copied.len() == 1 { "artifact" } else { "artifacts" }, dir.display()));
+                    processed = Some(copied);
+                }
+                Err(problem) => problems.push(problem),
+            }
+        }
+        if !settings.artifacts.steps.is_empty() && problems.is_empty() {
+            if !config.quiet {
+                eprintln!("cargo-builder: Running the artifact steps...");
+            }
+            match artifacts::run_steps(&settings.artifacts.steps, processed.as_deref().unwrap_or(&built), target, &profile) {
+                Ok(notes) if config.log_on_success => notes.iter().try_for_each(|note| logger.log_diagnostic(note))?,
+                Ok(_) => {}
+                Err(problem) => problems.push(problem),
+            }
+        }
+        produced = processed.unwrap_or_else(|| built.iter().filter(|artifact| artifact.is_local() && artifact.is_shipped()).cloned().collect());
+        if config.checksums && problems.is_empty() {
+            match artifacts::checksum(&mut produced) {
+                Ok(()) => {
+                    summaries.extend(produced.iter().filter_map(|artifact| Some(format!("SHA-256 of {}: {}", artifact.name(), artifact.sha256.as_ref()?))));
+                    if config.log_on_success {
+                        logger.log_diagnostic(&artifacts::checksum_diagnostic(&produced))?;
+                    }
+                }
+                Err(problem) => problems.push(problem),
+            }
+        }
+        if let Some(deny) = config.audit {
+            if !config.quiet {
+                eprintln!("cargo-builder: Auditing dependencies...");
+            }
+            match audit::run(config, &workspace.root, deny) {
+                Ok(report) => {
+                    summaries.push(format!("Audit: {}", report.describe()));
+                    findings.extend(report.diagnostics);
+                }
+                Err(problem) => problems.push(problem),
+            }
+        }
+        if let Some(format) = &config.coverage {
+            match coverage::report(config, &workspace.target_directory, format) {
+                Ok(report) => {
+                    summaries.push(format!("Coverage: {}", report.describe()));
+                    summaries.push(format!("Coverage report: {}", report.path.display()));
+                    covered = Some(report);
+                }
+                Err(problem) => problems.push(problem),
+            }
+        }
+        if let Some(checks) = &config.deny_check {
+            if !config.quiet {
+                eprintln!("cargo-builder: Running cargo deny check {}...", checks.replace(',', " "));
+            }
+            match deny::run(config, checks) {
+                Ok(report) => {
+                    summaries.push(format!("cargo-deny: {}", report.describe()));
+                    findings.extend(report.diagnostics);
+                }
+                Err(problem) => problems.push(problem),
+            }
+        }
+        if config.udeps {
+            if !config.quiet {
+                eprintln!("cargo-builder: Looking for unused dependencies...");
+            }
+            match udeps::run(config, &workspace.root) {
+                Ok(report) => {
+                    summaries.push(format!("Unused dependencies: {}", report.describe()));
+                    findings.extend(report.diagnostics);
+                }
+                Err(problem) => problems.push(problem),
+            }
+        }
+        // After the steps that rewrite them, like wasm-opt
+        binary_sizes = sizes::measure(&built);
+        if config.sizes {
+            let history = store::load_history(&workspace)?;
+            let previous = history.iter().rev()
+                .find(|run| run.success && run.profile.as_deref() == Some(profile.as_str()) && !run.binary_sizes.is_empty())
+                .map(|run| (run.id, &run.binary_sizes));
+            summaries.extend(sizes::describe(&binary_sizes, previous));
+        }
+        if let Some(count) = config.bloat {
+            for binary in built.iter().filter(|artifact| matches!(artifact.kind, "bin" | "example")) {
+                if !config.quiet {
+                    eprintln!("cargo-builder: Running cargo bloat on {}...", binary.name());
+                }
+                match sizes::bloat(config, binary, count) {
+                    Ok(breakdown) => summaries.push(breakdown),
+                    Err(problem) => problems.push(problem),
+                }
+            }
+        }
+        for problem in problems {
+            let diagnostic = problem.diagnostic();
+            eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &config.terminal_color));
+            logger.log_diagnostic(&diagnostic)?;
+            collected.push(diagnostic);
+            has_errors = true;
+            check_failed = true;
+        }
+        // Findings go by the rules of compiler diagnostics of their level
+        for diagnostic in findings {
+            if diagnostic.level == "error" || config.include_warnings {
+                eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &config.terminal_color));
+            }
+            if diagnostic.level == "error" || config.log_on_success {
+                logger.log_diagnostic(&diagnostic)?;
+            }
+            if diagnostic.level == "error" {
+                has_errors = true;
+                check_failed = true;
+            }
+            collected.push(diagnostic);
+        }
+        if config.list_artifacts.is_some() && config.log_on_success && !has_errors {
+            let listed: Vec<_> = built.iter().filter(|artifact| artifact.is_local()).cloned().collect();
+            logger.log_diagnostic(&artifacts::diagnostic(&listed))?;
+        }
+    }
+
+    // Handle case where build failed but we didn't capture any JSON error messages
+    let hidden = baselined + unchanged + preexisting + repeated + suppressions.as_ref().map_or(0, |s| s.silenced());
+    if !final_success && !has_errors && hidden == 0 && !stopped && !config.quiet {
+        eprintln!("cargo-builder: Build failed (no specific error messages captured)");
+    }
+
+    store::write_diagnostics(&store::last_run_path(&workspace), &collected)?;
+    let recorded = store::record_run(&workspace, store::RunRecord {
+        id: 0,
+        timestamp: started_at.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()),
+        command: format_command(config),
+        profile: Some(profile.clone()),
+        duration_ms: start.elapsed().as_millis() as u64,
+        success: final_success && !has_errors,
+        errors: collected.iter().filter(|d| d.level == "error").count(),
+        warnings: collected.iter().filter(|d| d.level == "warning").count(),
+        fingerprints: store::fingerprints(&collected),
+        branch: vcs.as_ref().and_then(|vcs| vcs.branch.clone()),
+        commit: vcs.as_ref().map(|vcs| vcs.commit.clone()),
+        dirty: vcs.as_ref().is_some_and(|vcs| vcs.dirty),
+        // Hidden warnings would skew the trend
+        warnings_counted: config.include_warnings && changed.is_none() && against.is_none() && baseline.is_none(),
+        package_warnings: store::package_warnings(&collected),
+        binary_sizes,
+    }, &collected)?;
+
     // Finalize logging
+    let log_paths: Vec<String> = logger.paths().iter().map(|p| p.display().to_string()).collect();
+    let split_logs: Vec<String> = logger.extra_paths().iter().map(|p| p.display().to_string()).collect();
+    let redactor = logger.redactor().cloned();
     logger.finalize(final_success && !has_errors)?;
 
+    if config.log_per_run {
+        let log = &log_targets[0].path;
+        if log.exists() {
+            let mut latest = workspace.state_dir().join("latest.log");
+            if logging::is_gzip_path(log) {
+                latest = logging::with_gzip_extension(&latest);
+            }
+            logging::update_latest(&latest, log)?;
+        }
+    }
+
+    if !config.quiet && tests.passed + tests.failed + tests.ignored > 0 {
+        eprintln!("cargo-builder: Tests: {}", tests.describe());
+    }
+    for summary in summaries.iter().filter(|_| !config.quiet) {
+        eprintln!("cargo-builder: {}", summary);
+    }
+    // On stdout, for scripts, whatever --quiet says
+    if let (Some(format), true) = (&config.list_artifacts, final_success && !has_errors) {
+        let listed: Vec<_> = built.iter().filter(|artifact| artifact.is_local()).cloned().collect();
+        match format.as_str() {
+            "json" => println!("{}", serde_json::to_string_pretty(&listed)?),
+            _ => artifacts::format_list(&listed).iter().for_each(|line| println!("{}", line)),
+        }
+    }
+
     if !config.quiet {
+        for line in rebuilds.describe(artifacts.fresh) {
+            eprintln!("cargo-builder: {}", line);
+        }
+        let elapsed = start.elapsed();
+        let mut crates = artifacts.describe(elapsed).map(|text| format!(": {}", text)).unwrap_or_default();
+        let mut notes = Vec::new();
+        if profile != "dev" {
+            notes.push(format!("{} profile", profile));
+        }
+        if offline_after.is_some() {
+            notes.push("offline after a network error".to_string());
+        }
+        if let Some(label) = &config.label {
+            notes.push(label.clone());
+        }
+        if !notes.is_empty() {
+            crates.insert_str(0, &format!(" ({})", notes.join(", ")));
+        }
         if final_success && !has_errors {
-            eprintln!("cargo-builder: Build completed successfully");
+            eprintln!("cargo-builder: Build completed successfully in {}{}", util::format_duration(elapsed), crates);
         } else {
-            eprintln!("cargo-builder: Build failed with errors");
-            if has_errors {
-                eprintln!("cargo-builder: Error details written to: {}", log_path);
+            eprintln!("cargo-builder: Build failed with errors after {}{}", util::format_duration(elapsed), crates);
+            if has_errors || stopped {
+                eprintln!("cargo-builder: Error details written to: {}", log_paths.join(", "));
+                if !split_logs.is_empty() {
+                    eprintln!("cargo-builder: Per-target logs: {}", split_logs.join(", "));
+                }
+            }
+        }
+    }
+
+    if config.timings {
+        match timings::collect_report(&workspace, started_at)? {
+            Some(report) => {
+                eprintln!("cargo-builder: Timings: {}; slowest units:", report.headline());
+                for row in report.slowest_units() {
+                    eprintln!("{}", row);
+                }
+                eprintln!("cargo-builder: Timing report: {}", report.path.display());
+                if let Some(timings) = &mut crate_timings {
+                    report.feed(timings);
+                }
+            }
+            None => eprintln!("cargo-builder: cargo did not write a timing report"),
+        }
+    }
+    if let Some(timings) = &crate_timings {
+        timings::report(&workspace, timings, &format_command(config), grouped)?;
+    }
+
+    let history = store::load_history(&workspace)?;
+    if let Some(trend) = trend::compare(&history, &recorded) {
+        if !config.quiet {
+            eprintln!("cargo-builder: {}", trend.describe());
+        }
+        if config.fail_on_warning_increase && trend.delta > 0 && cargo_exit_code == 0 {
+            eprintln!("cargo-builder: Warnings went up since #{}", trend.previous.id);
+            check_failed = true;
+        }
+    }
+
+    if config.diff_last {
+        diff::print_against_previous(&workspace, recorded.id, &collected, &config.terminal_color)?;
+    }
+
+    // Notifications and summary.json hide what the logs hide
+    let outcome = notify::BuildOutcome {
+        success: final_success && !has_errors,
+        command: format_command(config),
+        started_at,
+        duration: start.elapsed(),
+        diagnostics: collected.as_slice().into(),
+        // Logs of successful builds are usually removed by now
+        log_paths: log_paths.iter().filter(|p| Path::new(p).exists()).cloned().collect(),
+        vcs,
+        artifacts,
+        coverage: covered.as_ref(),
+        produced: &produced,
+    }.redact(redactor.as_ref());
+    notify::write_summary(&workspace.state_dir().join("summary.json"), &outcome)?;
+    if let Some(address) = config.statsd.as_deref().or(settings.statsd.address.as_deref()) {
+        if let Err(e) = statsd::send(address, config, &settings.statsd, &outcome) {
+            eprintln!("cargo-builder: StatsD metrics failed: {:#}", e);
+        }
+    }
+
+    // Quick builds finish before anyone looks away
+    let long_enough = config.notify_threshold.is_none_or(|threshold| outcome.duration >= threshold);
+    if long_enough {
+        notify::send_all(config, &settings.notify, &outcome);
+    }
+
+    if config.bell && long_enough && (has_errors || !final_success) {
+        if let Err(e) = notify::ring_bell(settings.notify.sound_command.as_deref()) {
+            eprintln!("cargo-builder: Warning: {:#}", e);
+        }
+    }
+
+    if config.view && has_errors && atty::is(atty::Stream::Stdout) {
+        // Prefer a human-readable log for the viewer
+        let view_target = log_targets.iter()
+            .find(|target| target.format == logging::LogFormat::Text)
+            .or(log_targets.first());
+        if let Some(target) = view_target {
+            if let Err(e) = open_viewer(&target.path.display().to_string(), config.viewer.as_deref()) {
+                eprintln!("cargo-builder: Warning: {:#}", e);
             }
         }
     }
 
-    Ok(exit_code)
+    let status = BuildStatus {
+        cargo_exit_code,
+        // Tests only run once everything compiled
+        shown_errors: has_errors && tests.failed == 0,
+        tests_failed: tests.failed > 0,
+        hidden,
+        check_failed,
+        timed_out: timed_out || lock_gave_up.is_some(),
+        over_memory: exceeded.is_some(),
+        interrupted: false,
+    };
+    Ok(Outcome { exit_code: apply_exit_code_policy(config.exit_code, &status), errors: recorded.errors, warnings: recorded.warnings, repeated })
+}
+
+/// What a build came to, for the summary of a matrix of builds.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub exit_code: i32,
+    /// Errors and warnings shown, after filters and baselines
+    pub errors: usize,
+    pub warnings: usize,
+    /// Diagnostics hidden as an earlier build of the matrix showed them
+    pub repeated: usize,
+}
+
+/// Show, log and annotate what went wrong running the program, a failed test or a
+/// report of Miri's, like a compiler error, returning it to collect.
+fn show_run_failure(config: &Config, logger: &mut logging::Logger, path_rewriter: Option<&paths::PathRewriter>, mut diagnostic: diagnostics::Diagnostic) -> Result<diagnostics::Diagnostic> {
+    if let Some(rewriter) = path_rewriter {
+        diagnostic = rewriter.rewrite_diagnostic(diagnostic);
+    }
+    if let Some(label) = &config.label {
+        diagnostic.rendered.insert_str(0, &format!("[{}] ", label));
+    }
+    eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &config.terminal_color));
+    logger.log_diagnostic(&diagnostic)?;
+    if let Some(annotation) = ci::annotation(&diagnostic).filter(|_| config.annotations) {
+        eprintln!("{}", annotation);
+    }
+    Ok(diagnostic)
+}
+
+/// Report `diagnostics` found before cargo started, failing with `cargo_exit_code`.
+fn fail_before_build(config: &Config, mut logger: logging::Logger, what: &str, diagnostics: &[diagnostics::Diagnostic], cargo_exit_code: i32) -> Result<Outcome> {
+    // Nothing was built: history, baselines and notifications are left alone
+    for diagnostic in diagnostics {
+        eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &config.terminal_color));
+        logger.log_diagnostic(diagnostic)?;
+    }
+    let log_paths: Vec<String> = logger.paths().iter().map(|p| p.display().to_string()).collect();
+    logger.finalize(false)?;
+    eprintln!(
+        "cargo-builder: {} failed with {} {}; log written to: {}",
+        what,
+        diagnostics.len(),
+        if diagnostics.len() == 1 { "error" } else { "errors" },
+        log_paths.join(", "),
+    );
+    let status = BuildStatus { cargo_exit_code, ..Default::default() };
+    Ok(Outcome { exit_code: apply_exit_code_policy(config.exit_code, &status), errors: diagnostics.len(), ..Default::default() })
+}
+
+/// What the exit code is derived from.
+#[derive(Debug, Default)]
+struct BuildStatus {
+    cargo_exit_code: i32,
+    /// Errors were shown, after filters and baselines
+    shown_errors: bool,
+    /// `--nextest` ran tests that failed
+    tests_failed: bool,
+    /// Diagnostics hidden by filters, baselines and suppressions
+    hidden: usize,
+    /// A warning check failed a build cargo finished
+    check_failed: bool,
+    /// cargo was stopped by `--timeout`
+    timed_out: bool,
+    /// cargo was stopped by `--max-memory`
+    over_memory: bool,
+    /// cargo was stopped by Ctrl-C
+    interrupted: bool,
 }
 
-fn setup_environment(cmd: &mut Command, config: &Config) -> Result<()> {
-    // Handle RUSTFLAGS - only modify if needed to preserve build cache
-    if !config.include_warnings {
-        let existing_rustflags = env::var("RUSTFLAGS").unwrap_or_default();
-        
-        // Check if -Awarnings is already present to avoid cache invalidation
-        if !existing_rustflags.contains("-Awarnings") {
-            let mut rustflags = existing_rustflags;
-            if !rustflags.is_empty() {
-                rustflags.push(' ');
+/// The exit code under `policy`. A failed cargo run is explained by its
+/// errors, shown or hidden; without any, cargo itself couldn't build.
+fn apply_exit_code_policy(policy: ExitCodePolicy, status: &BuildStatus) -> i32 {
+    let cargo_failed = status.cargo_exit_code != 0;
+    match policy {
+        ExitCodePolicy::Never => 0,
+        _ if status.interrupted => exit_code::INTERRUPTED,
+        _ if status.timed_out => exit_code::TIMED_OUT,
+        _ if status.over_memory => exit_code::MEMORY_LIMIT,
+        ExitCodePolicy::Detailed => {
+            if status.shown_errors || (cargo_failed && status.hidden > 0) {
+                exit_code::COMPILE_ERRORS
+            } else if status.tests_failed {
+                exit_code::TESTS_FAILED
+            } else if cargo_failed {
+                exit_code::CARGO_FAILED
+            } else if status.check_failed {
+                exit_code::WARNING_CHECK
+            } else {
+                0
             }
-            rustflags.push_str("-Awarnings");
-            cmd.env("RUSTFLAGS", rustflags);
         }
-        // If -Awarnings already exists, don't modify RUSTFLAGS to preserve cache
+        ExitCodePolicy::Cargo if status.check_failed => 1,
+        ExitCodePolicy::Cargo => status.cargo_exit_code,
+        // Errors hidden by filters and baselines don't count
+        ExitCodePolicy::Errors => (status.shown_errors || status.tests_failed || (cargo_failed && status.hidden == 0) || status.check_failed) as i32,
+    }
+}
+
+/// The log entry for a build cargo-builder stopped.
+fn stop_entry(message: String) -> diagnostics::Diagnostic {
+    let rendered = format!("error: {}; cargo was stopped\n", message);
+    diagnostics::Diagnostic { rendered, ..entry("error", message) }
+}
+
+/// A log entry of cargo-builder's own, rather than the compiler's.
+fn entry(level: &str, message: String) -> diagnostics::Diagnostic {
+    diagnostics::Diagnostic {
+        rendered: format!("{}: {}\n", level, message),
+        ..diagnostics::Diagnostic::new(level, message)
+    }
+}
+
+/// Replace the current terminal line with `text`, without ending it.
+fn show_status_line(text: &str) {
+    let _ = crossterm::execute!(
+        std::io::stderr(),
+        crossterm::cursor::MoveToColumn(0),
+        crossterm::terminal::Clear(crossterm::terminal::ClearType::CurrentLine),
+        crossterm::style::Print(text),
+    );
+}
+
+/// Sleep before a retry; false if Ctrl-C was pressed meanwhile.
+fn wait_for_retry(wait: Duration, interrupts: usize) -> bool {
+    let until = Instant::now() + wait;
+    while let Some(left) = until.checked_duration_since(Instant::now()).filter(|left| !left.is_zero()) {
+        if signals::interrupts() > interrupts {
+            return false;
+        }
+        std::thread::sleep(left.min(events::TICK));
+    }
+    true
+}
+
+/// Clear the rest of cargo's progress bar, which a stopped cargo leaves behind.
+fn clear_progress_line(grouped: bool) {
+    if !grouped && atty::is(atty::Stream::Stderr) {
+        let _ = crossterm::execute!(
+            std::io::stderr(),
+            crossterm::cursor::MoveToColumn(0),
+            crossterm::terminal::Clear(crossterm::terminal::ClearType::CurrentLine),
+        );
+    }
+}
+
+/// Create the `--capture-json` file, replacing any previous capture.
+fn open_capture(path: &str) -> Result<BufWriter<File>> {
+    let path = Path::new(path);
+    if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create directory: {}", parent.display()))?;
+    }
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create capture file: {}", path.display()))?;
+    Ok(BufWriter::new(file))
+}
+
+/// Open the log in `viewer`, falling back to `$PAGER`, `$EDITOR` and finally a platform default.
+fn open_viewer(log_path: &str, viewer: Option<&str>) -> Result<()> {
+    let viewer = viewer.map(str::to_string)
+        .or_else(|| env::var("PAGER").ok().filter(|v| !v.trim().is_empty()))
+        .or_else(|| env::var("EDITOR").ok().filter(|v| !v.trim().is_empty()))
+        .unwrap_or_else(|| if cfg!(windows) { "notepad".to_string() } else { "less -R".to_string() });
+
+    // Allow viewers with arguments, e.g. `less -R` or `code --wait`
+    let mut parts = viewer.split_whitespace();
+    let program = parts.next().context("Empty viewer command")?;
+
+    Command::new(program)
+        .args(parts)
+        .arg(log_path)
+        .status()
+        .with_context(|| format!("Failed to run viewer: {}", viewer))?;
+
+    Ok(())
+}
+
+pub fn format_command(config: &Config) -> String {
+    // `cross build` for `--cargo-path cross`, but not the full path of $CARGO
+    let cargo = Path::new(&config.cargo).file_stem().map_or("cargo".into(), |stem| stem.to_string_lossy().into_owned());
+    let subcommand = match &config.miri {
+        Some(command) => format!("miri {}", command),
+        None if config.nextest => "nextest run".to_string(),
+        None if config.coverage.is_some() => "llvm-cov".to_string(),
+        None => "build".to_string(),
+    };
+    let mut command = match &config.toolchain {
+        Some(toolchain) => format!("{} +{} {}", cargo, toolchain, subcommand),
+        None => format!("{} {}", cargo, subcommand),
+    };
+    for arg in &config.cargo_args {
+        command.push(' ');
+        command.push_str(arg);
+    }
+    command
+}
+
+pub fn setup_environment(cmd: &mut Command, config: &Config) -> Result<()> {
+    // RUSTFLAGS is left alone: changing it would rebuild everything plain `cargo build`
+    // compiled, so warnings are hidden when their messages arrive instead
+
+    // Except for fuzz builds, which are instrumented and have a target directory of their own
+    if let (Some(sanitizer), Some(host)) = (&config.fuzz, context::host(config)) {
+        cmd.env("RUSTFLAGS", fuzz::rustflags(sanitizer, host));
+    }
+
+    // What `cargo +toolchain` does through rustup's proxy, without an override for the whole directory
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
     }
 
     // Handle CARGO_TERM_COLOR for terminal output
@@ -128,10 +1229,51 @@ fn setup_environment(cmd: &mut Command, config: &Config) -> Result<()> {
         ColorChoice::Always => cmd.env("CARGO_TERM_COLOR", "always"),
         ColorChoice::Never => cmd.env("CARGO_TERM_COLOR", "never"),
         ColorChoice::Auto => {
-            // Let cargo decide based on TTY detection
-            cmd
+            // cargo's stderr is a pipe, so decide for it based on ours
+            cmd.env("CARGO_TERM_COLOR", if term::should_use_color() { "always" } else { "never" })
         }
     };
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(cargo_exit_code: i32, shown_errors: bool, hidden: usize, check_failed: bool) -> BuildStatus {
+        BuildStatus { cargo_exit_code, shown_errors, hidden, check_failed, ..Default::default() }
+    }
+
+    #[test]
+    fn test_exit_code_policy() {
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Cargo, &status(101, false, 3, false)), 101);
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Cargo, &status(0, false, 0, true)), 1);
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Never, &status(101, true, 0, false)), 0);
+
+        // Errors hidden by a baseline or --changed-only don't fail the build
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Errors, &status(101, false, 3, false)), 0);
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Errors, &status(101, true, 3, false)), 1);
+        // e.g. a missing feature, which cargo reports without a diagnostic
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Errors, &status(101, false, 0, false)), 1);
+        // New warnings not in the baseline
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Errors, &status(0, false, 2, true)), 1);
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Errors, &status(0, false, 2, false)), 0);
+    }
+
+    #[test]
+    fn test_detailed_exit_codes() {
+        let detailed = |status: BuildStatus| apply_exit_code_policy(ExitCodePolicy::Detailed, &status);
+        assert_eq!(detailed(status(0, false, 0, false)), 0);
+        assert_eq!(detailed(status(101, true, 0, false)), exit_code::COMPILE_ERRORS);
+        assert_eq!(detailed(status(101, false, 2, false)), exit_code::COMPILE_ERRORS);
+        assert_eq!(detailed(status(101, false, 0, false)), exit_code::CARGO_FAILED);
+        assert_eq!(detailed(status(0, false, 0, true)), exit_code::WARNING_CHECK);
+        assert_eq!(detailed(BuildStatus { tests_failed: true, ..status(100, false, 0, false) }), exit_code::TESTS_FAILED);
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Errors, &BuildStatus { tests_failed: true, ..status(100, false, 0, false) }), 1);
+        assert_eq!(detailed(BuildStatus { timed_out: true, ..status(1, true, 0, false) }), exit_code::TIMED_OUT);
+        assert_eq!(detailed(BuildStatus { over_memory: true, ..status(101, false, 0, false) }), exit_code::MEMORY_LIMIT);
+        assert_eq!(detailed(BuildStatus { interrupted: true, ..Default::default() }), exit_code::INTERRUPTED);
+        assert_eq!(apply_exit_code_policy(ExitCodePolicy::Never, &BuildStatus { interrupted: true, ..Default::default() }), 0);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{context, Config};
+use serde_json::Value;
+use std::env;
+
+/// Variables that replace the rustflags in cargo's configuration, in cargo's order.
+const VARIABLES: [&str; 2] = ["CARGO_ENCODED_RUSTFLAGS", "RUSTFLAGS"];
+
+/// A warning when a rustflags variable in the environment makes cargo ignore rustflags
+/// the project configures. cargo uses the first source it finds and doesn't merge them,
+/// so `build.rustflags` in `.cargo/config.toml` is otherwise lost without a word.
+pub fn overridden(config: &Config) -> Option<String> {
+    let variable = VARIABLES.into_iter().find(|name| env::var_os(name).is_some())?;
+    let configured = configured(context::cargo_config(config, "build.rustflags"), context::cargo_config(config, "target"));
+    if configured.is_empty() {
+        return None;
+    }
+    Some(format!(
+        "{} is set, so cargo ignores {} from its configuration; add them to {} to keep them",
+        variable,
+        configured.join(" and "),
+        variable,
+    ))
+}
+
+/// `build.rustflags` and the `target.<triple or cfg>.rustflags` of `target`, e.g.
+/// `build.rustflags = "-C debuginfo=1"`.
+fn configured(build: Option<Value>, target: Option<Value>) -> Vec<String> {
+    let mut found = Vec::new();
+    if let Some(flags) = build.as_ref().and_then(flags) {
+        found.push(format!("build.rustflags = \"{}\"", flags));
+    }
+    if let Some(Value::Object(targets)) = target {
+        for (name, table) in targets {
+            if let Some(flags) = table.get("rustflags").and_then(flags) {
+                found.push(format!("target.{}.rustflags = \"{}\"", name, flags));
+            }
+        }
+    }
+    found
+}
+
+/// Rustflags given as a string or a list.
+fn flags(value: &Value) -> Option<String> {
+    match value {
+        Value::String(flags) => Some(flags.clone()),
+        Value::Array(flags) => Some(flags.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(" ")),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_configured() {
+        let target = json!({
+            "x86_64-unknown-linux-gnu": { "runner": "qemu", "rustflags": "-C target-cpu=native" },
+            "cfg(windows)": { "linker": "lld-link" },
+        });
+        assert_eq!(
+            configured(Some(json!(["-C", "debuginfo=1"])), Some(target)),
+            [
+                "build.rustflags = \"-C debuginfo=1\"",
+                "target.x86_64-unknown-linux-gnu.rustflags = \"-C target-cpu=native\"",
+            ],
+        );
+        assert!(configured(None, Some(json!({ "cfg(unix)": { "runner": "x" } }))).is_empty());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::{diagnostics, store, util, ColorChoice};
+use crate::diagnostics::Diagnostic;
+use anyhow::{Result, Context};
+use clap::{Arg, ArgAction, Command};
+use regex::{Regex, RegexBuilder};
+
+#[derive(Clone)]
+pub enum Matcher {
+    Regex(Regex),
+    Fuzzy(String),
+}
+
+impl Matcher {
+    pub fn new(pattern: &str, fuzzy: bool, ignore_case: bool) -> Result<Self> {
+        if fuzzy {
+            return Ok(Matcher::Fuzzy(pattern.to_lowercase()));
+        }
+
+        let regex = RegexBuilder::new(pattern)
+            .case_insensitive(ignore_case)
+            .build()
+            .with_context(|| format!("Invalid search pattern: {}", pattern))?;
+        Ok(Matcher::Regex(regex))
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            Matcher::Regex(regex) => regex.is_match(text),
+            Matcher::Fuzzy(pattern) => fuzzy_match(pattern, &text.to_lowercase()),
+        }
+    }
+
+    /// Match against the message, error code and primary file path of a diagnostic.
+    pub fn matches_diagnostic(&self, diagnostic: &Diagnostic) -> bool {
+        self.is_match(&diagnostic.message)
+            || diagnostic.code.as_deref().is_some_and(|code| self.is_match(code))
+            || diagnostic.file.as_deref().is_some_and(|file| self.is_match(file))
+    }
+}
+
+/// True if every character of `pattern` appears in `text`, in order.
+fn fuzzy_match(pattern: &str, text: &str) -> bool {
+    let mut chars = text.chars();
+    pattern.chars().all(|p| chars.any(|c| c == p))
+}
+
+pub fn run(args: &[String]) -> Result<i32> {
+    let matches = Command::new("cargo-builder search")
+        .about("Search the diagnostics collected by the last build")
+        .arg(
+            Arg::new("pattern")
+                .value_name("PATTERN")
+                .required(true)
+                .help("Regex matched against messages, error codes and file paths")
+        )
+        .arg(
+            Arg::new("fuzzy")
+                .long("fuzzy")
+                .action(ArgAction::SetTrue)
+                .help("Treat PATTERN as a fuzzy subsequence instead of a regex")
+        )
+        .arg(
+            Arg::new("ignore-case")
+                .short('i')
+                .long("ignore-case")
+                .action(ArgAction::SetTrue)
+                .help("Match the regex case-insensitively")
+        )
+        .arg(
+            Arg::new("level")
+                .long("level")
+                .value_name("LEVEL")
+                .help("Only show diagnostics of this level (error, warning, ...)")
+        )
+        .arg(
+            Arg::new("terminal-color")
+                .long("terminal-color")
+                .value_name("CHOICE")
+                .value_parser(["auto", "never", "always"])
+                .default_value("auto")
+                .help("Color control for terminal output")
+        )
+        .try_get_matches_from(std::iter::once("cargo-builder search".to_string()).chain(args.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+
+    let pattern = matches.get_one::<String>("pattern").unwrap();
+    let matcher = Matcher::new(pattern, matches.get_flag("fuzzy"), matches.get_flag("ignore-case"))?;
+    let level = matches.get_one::<String>("level");
+    let color: ColorChoice = matches.get_one::<String>("terminal-color").unwrap().parse()?;
+
+    let workspace = util::find_workspace()?;
+    let path = store::last_run_path(&workspace);
+    if !path.exists() {
+        eprintln!("cargo-builder: No diagnostics recorded yet; run a build first");
+        return Ok(1);
+    }
+
+    let all = store::read_diagnostics(&path)?;
+    let found: Vec<&Diagnostic> = all.iter()
+        .filter(|d| level.is_none_or(|level| &d.level == level))
+        .filter(|d| matcher.matches_diagnostic(d))
+        .collect();
+
+    for diagnostic in &found {
+        eprint!("{}", diagnostics::format_for_terminal(&diagnostic.rendered, &color));
+    }
+    eprintln!("cargo-builder: {} of {} diagnostics match", found.len(), all.len());
+
+    Ok(if found.is_empty() { 1 } else { 0 })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fuzzy_match() {
+        assert!(fuzzy_match("srcnet", "src/net.rs"));
+        assert!(!fuzzy_match("netsrc", "src/net.rs"));
+    }
+
+    #[test]
+    fn test_regex_matches_code_and_file() {
+        let d = Diagnostic::new("error", "trait bound not satisfied").with_code("E0277").at("src/net.rs", 1, 1);
+        assert!(Matcher::new("E02\\d\\d", false, false).unwrap().matches_diagnostic(&d));
+        assert!(Matcher::new("net\\.rs$", false, false).unwrap().matches_diagnostic(&d));
+        assert!(!Matcher::new("E0425", false, false).unwrap().matches_diagnostic(&d));
+    }
+
+    #[test]
+    fn test_ignore_case() {
+        let d = Diagnostic::new("error", "Mismatched types");
+        assert!(!Matcher::new("mismatched", false, false).unwrap().matches_diagnostic(&d));
+        assert!(Matcher::new("mismatched", false, true).unwrap().matches_diagnostic(&d));
+    }
+}
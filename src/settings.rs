@@ -0,0 +1,404 @@
+use crate::util::Workspace;
+use anyhow::{Result, Context};
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+pub const FILE_NAME: &str = "cargo-builder.toml";
+
+/// Workspace-wide settings from `cargo-builder.toml` at the workspace root.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Settings {
+    pub log: LogSettings,
+    pub notify: NotifySettings,
+    pub statsd: StatsdSettings,
+    pub history: HistorySettings,
+    pub flash: FlashSettings,
+    pub artifacts: ArtifactSettings,
+    /// Overrides for builds with a cargo profile, under `[profile.<name>]`
+    pub profile: HashMap<String, ProfileSettings>,
+}
+
+impl Settings {
+    /// The settings for a build with `profile`: its `[profile.<name>]` sections
+    /// replace the workspace-wide ones of the same name.
+    pub fn for_profile(mut self, profile: &str) -> Settings {
+        if let Some(overrides) = self.profile.remove(profile) {
+            if let Some(log) = overrides.log {
+                self.log = log;
+            }
+            if let Some(notify) = overrides.notify {
+                self.notify = notify;
+            }
+            if let Some(statsd) = overrides.statsd {
+                self.statsd = statsd;
+            }
+            if let Some(artifacts) = overrides.artifacts {
+                self.artifacts = artifacts;
+            }
+        }
+        self
+    }
+}
+
+/// Sections that can be set per profile; the history is shared by all builds.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProfileSettings {
+    pub log: Option<LogSettings>,
+    pub notify: Option<NotifySettings>,
+    pub statsd: Option<StatsdSettings>,
+    pub artifacts: Option<ArtifactSettings>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogSettings {
+    /// Rotated and per-run logs older than this are deleted when a build starts
+    #[serde(deserialize_with = "deserialize_duration")]
+    pub retention: Option<Duration>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifySettings {
+    /// Command run by `--bell` instead of ringing the terminal bell, e.g. `paplay fail.oga`
+    pub sound_command: Option<String>,
+    pub slack: Option<ChatSettings>,
+    pub discord: Option<ChatSettings>,
+    pub email: Option<EmailSettings>,
+    pub ntfy: Option<NtfySettings>,
+}
+
+/// A chat webhook under `[notify.slack]` or `[notify.discord]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChatSettings {
+    pub webhook: String,
+    #[serde(default)]
+    pub on: NotifyOn,
+    /// Prepended to the message, e.g. `@here` or `<@U024BE7LH>`
+    pub mention: Option<String>,
+}
+
+impl ChatSettings {
+    pub fn wants(&self, success: bool) -> bool {
+        self.on.includes(success)
+    }
+}
+
+/// SMTP delivery under `[notify.email]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EmailSettings {
+    pub server: String,
+    /// Defaults to 25, 587 or 465 depending on `tls`
+    pub port: Option<u16>,
+    #[serde(default)]
+    pub tls: SmtpTls,
+    pub username: Option<String>,
+    /// Environment variable holding the SMTP password, so it stays out of the file
+    pub password_env: Option<String>,
+    pub from: String,
+    pub to: Vec<String>,
+    /// Attach the log instead of including it in the body
+    #[serde(default)]
+    pub attach: bool,
+    /// Gzip the attached log
+    #[serde(default)]
+    pub compress: bool,
+    #[serde(default)]
+    pub on: NotifyOn,
+}
+
+impl EmailSettings {
+    pub fn wants(&self, success: bool) -> bool {
+        self.on.includes(success)
+    }
+}
+
+/// The target `--flash` writes to, so it needn't be given every time.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlashSettings {
+    /// As probe-rs names it, e.g. `STM32F411RETx`
+    pub chip: Option<String>,
+    /// `VID:PID[:SERIAL]`, when more than one probe is connected
+    pub probe: Option<String>,
+}
+
+/// Where the programs and libraries of successful builds are copied, under `[artifacts]`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ArtifactSettings {
+    /// Relative to the workspace root; `--out-dir` overrides it
+    pub out_dir: Option<PathBuf>,
+    /// A template like `--out-name`'s, which overrides it
+    pub name: Option<String>,
+    /// Commands run on each of them, in order, under `[[artifacts.steps]]`
+    pub steps: Vec<ArtifactStep>,
+}
+
+/// A command such as `strip {path}` run on each program and library a successful
+/// build produced; its arguments can use the placeholders of `--out-name` and `{path}`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ArtifactStep {
+    pub command: String,
+    /// The kinds of artifact it's for, e.g. `["bin"]`; all by default
+    #[serde(default)]
+    pub kinds: Vec<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct HistorySettings {
+    pub backend: HistoryBackend,
+}
+
+/// Where the build history is kept.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HistoryBackend {
+    /// `history.json` plus a JSON-lines file per run
+    #[default]
+    Json,
+    /// `history.db`, indexed by code, file and package for large workspaces
+    Sqlite,
+}
+
+/// Build metrics under `[statsd]`.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StatsdSettings {
+    /// `host:port` of the StatsD agent; `--statsd` overrides it
+    pub address: Option<String>,
+    pub prefix: String,
+    pub flavor: StatsdFlavor,
+    /// Extra tags such as `team:infra`, DogStatsD only
+    pub tags: Vec<String>,
+}
+
+impl Default for StatsdSettings {
+    fn default() -> Self {
+        StatsdSettings {
+            address: None,
+            prefix: "cargo_builder".to_string(),
+            flavor: StatsdFlavor::default(),
+            tags: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StatsdFlavor {
+    /// Tagged metrics, understood by Datadog, Telegraf and statsd_exporter
+    #[default]
+    Dogstatsd,
+    /// Plain StatsD without tags
+    Statsd,
+}
+
+/// Push notifications under `[notify.ntfy]`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NtfySettings {
+    /// A self-hosted server, or the public one by default
+    #[serde(default = "default_ntfy_server")]
+    pub server: String,
+    pub topic: String,
+    /// Environment variable holding an access token for protected topics
+    pub token_env: Option<String>,
+    #[serde(default)]
+    pub on: NotifyOn,
+}
+
+impl NtfySettings {
+    pub fn wants(&self, success: bool) -> bool {
+        self.on.includes(success)
+    }
+}
+
+fn default_ntfy_server() -> String {
+    "https://ntfy.sh".to_string()
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SmtpTls {
+    /// Plain SMTP, e.g. an internal relay
+    None,
+    #[default]
+    Starttls,
+    Tls,
+}
+
+impl SmtpTls {
+    pub fn default_port(self) -> u16 {
+        match self {
+            SmtpTls::None => 25,
+            SmtpTls::Starttls => 587,
+            SmtpTls::Tls => 465,
+        }
+    }
+}
+
+/// Which builds a notification is sent for.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum NotifyOn {
+    #[default]
+    Failure,
+    Always,
+}
+
+impl NotifyOn {
+    pub fn includes(self, success: bool) -> bool {
+        self == NotifyOn::Always || !success
+    }
+}
+
+fn deserialize_duration<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    let text = String::deserialize(deserializer)?;
+    humantime::parse_duration(&text)
+        .map(Some)
+        .map_err(|e| serde::de::Error::custom(format!("invalid duration `{}`: {}", text, e)))
+}
+
+pub fn path(workspace: &Workspace) -> PathBuf {
+    workspace.root.join(FILE_NAME)
+}
+
+/// Load the workspace settings; a missing file means defaults.
+pub fn load(workspace: &Workspace) -> Result<Settings> {
+    load_from(&path(workspace))
+}
+
+fn load_from(path: &Path) -> Result<Settings> {
+    if !path.exists() {
+        return Ok(Settings::default());
+    }
+
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    toml::from_str(&content)
+        .with_context(|| format!("Invalid settings in {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_missing_file_is_default() {
+        let temp_dir = TempDir::new().unwrap();
+        let settings = load_from(&temp_dir.path().join(FILE_NAME)).unwrap();
+        assert!(settings.log.retention.is_none());
+    }
+
+    #[test]
+    fn test_log_retention() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(FILE_NAME);
+
+        fs::write(&path, "[log]\nretention = \"7d\"\n").unwrap();
+        assert_eq!(load_from(&path).unwrap().log.retention, Some(Duration::from_secs(7 * 24 * 3600)));
+
+        fs::write(&path, "[log]\nretention = \"a week\"\n").unwrap();
+        assert!(load_from(&path).is_err());
+
+        fs::write(&path, "[log]\nretentoin = \"7d\"\n").unwrap();
+        assert!(load_from(&path).is_err());
+    }
+
+    #[test]
+    fn test_flash_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(FILE_NAME);
+        fs::write(&path, "[flash]\nchip = \"nRF52840_xxAA\"\n").unwrap();
+
+        let flash = load_from(&path).unwrap().flash;
+        assert_eq!(flash.chip.as_deref(), Some("nRF52840_xxAA"));
+        assert!(flash.probe.is_none());
+    }
+
+    #[test]
+    fn test_artifact_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(FILE_NAME);
+        fs::write(&path, "[profile.release.artifacts]\nout_dir = \"dist\"\nname = \"{name}-{version}{ext}\"\n").unwrap();
+
+        assert!(load_from(&path).unwrap().for_profile("dev").artifacts.out_dir.is_none());
+        let artifacts = load_from(&path).unwrap().for_profile("release").artifacts;
+        assert_eq!(artifacts.out_dir, Some(PathBuf::from("dist")));
+        assert_eq!(artifacts.name.as_deref(), Some("{name}-{version}{ext}"));
+        assert!(artifacts.steps.is_empty());
+
+        fs::write(&path, "[[artifacts.steps]]\ncommand = \"strip {path}\"\nkinds = [\"bin\"]\n\n[[artifacts.steps]]\ncommand = \"upx --best {path}\"\n").unwrap();
+        let steps = load_from(&path).unwrap().artifacts.steps;
+        assert_eq!((steps[0].command.as_str(), &steps[0].kinds[..]), ("strip {path}", &["bin".to_string()][..]));
+        assert!(steps[1].kinds.is_empty());
+    }
+
+    #[test]
+    fn test_chat_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(FILE_NAME);
+        fs::write(&path, "[notify.slack]\nwebhook = \"https://hooks.slack.com/x\"\n\n[notify.discord]\nwebhook = \"https://discord.com/api/webhooks/x\"\non = \"always\"\n").unwrap();
+
+        let notify = load_from(&path).unwrap().notify;
+        let slack = notify.slack.unwrap();
+        assert_eq!(slack.on, NotifyOn::Failure);
+        assert!(slack.wants(false) && !slack.wants(true));
+        assert!(notify.discord.unwrap().wants(true));
+    }
+
+    #[test]
+    fn test_statsd_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(FILE_NAME);
+        fs::write(&path, "[statsd]\naddress = \"127.0.0.1:8125\"\nflavor = \"statsd\"\n").unwrap();
+
+        let statsd = load_from(&path).unwrap().statsd;
+        assert_eq!(statsd.address.as_deref(), Some("127.0.0.1:8125"));
+        assert_eq!(statsd.prefix, "cargo_builder");
+        assert_eq!(statsd.flavor, StatsdFlavor::Statsd);
+    }
+
+    #[test]
+    fn test_profile_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(FILE_NAME);
+        fs::write(&path, "[log]\nretention = \"7d\"\n\n[profile.release.notify.ntfy]\ntopic = \"releases\"\n\n[profile.release.log]\n").unwrap();
+
+        let dev = load_from(&path).unwrap().for_profile("dev");
+        assert!(dev.notify.ntfy.is_none());
+        assert!(dev.log.retention.is_some());
+        let release = load_from(&path).unwrap().for_profile("release");
+        assert_eq!(release.notify.ntfy.unwrap().topic, "releases");
+        // A section replaces the workspace-wide one as a whole
+        assert!(release.log.retention.is_none());
+
+        fs::write(&path, "[profile.release.history]\nbackend = \"sqlite\"\n").unwrap();
+        assert!(load_from(&path).is_err());
+    }
+
+    #[test]
+    fn test_ntfy_settings() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join(FILE_NAME);
+
+        fs::write(&path, "[notify.ntfy]\ntopic = \"my-builds\"\n").unwrap();
+        let ntfy = load_from(&path).unwrap().notify.ntfy.unwrap();
+        assert_eq!(ntfy.server, "https://ntfy.sh");
+        assert!(ntfy.token_env.is_none());
+
+        fs::write(&path, "[notify.ntfy]\nserver = \"https://ntfy.example.com\"\n").unwrap();
+        assert!(load_from(&path).is_err());
+    }
+}
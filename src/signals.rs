@@ -0,0 +1,76 @@
+use anyhow::Result;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Ctrl-C presses (or SIGTERMs) received since the handler was installed.
+static INTERRUPTS: AtomicUsize = AtomicUsize::new(0);
+
+/// The handlers `install` replaced, put back when dropped: once cargo is done, Ctrl-C
+/// and SIGTERM end cargo-builder again instead of being counted by no one.
+#[must_use = "the handlers are restored when this is dropped"]
+pub struct Handlers {
+    #[cfg(unix)]
+    previous: Vec<(libc::c_int, libc::sighandler_t)>,
+}
+
+/// Count Ctrl-C instead of exiting, so the build loop can stop cargo and
+/// finish the log. The loop polls `interrupts` on every event.
+#[cfg(unix)]
+pub fn install() -> Result<Handlers> {
+    extern "C" fn handle(_signal: libc::c_int) {
+        // Only async-signal-safe work is allowed here
+        INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+    }
+
+    let mut handlers = Handlers { previous: Vec::new() };
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        // SAFETY: the handler only touches an atomic, which is async-signal-safe
+        let previous = unsafe { libc::signal(signal, handle as extern "C" fn(libc::c_int) as libc::sighandler_t) };
+        if previous == libc::SIG_ERR {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        handlers.previous.push((signal, previous));
+    }
+    Ok(handlers)
+}
+
+#[cfg(unix)]
+impl Drop for Handlers {
+    fn drop(&mut self) {
+        for &(signal, previous) in self.previous.iter().rev() {
+            // SAFETY: puts back a handler the process had before
+            unsafe { libc::signal(signal, previous) };
+        }
+    }
+}
+
+#[cfg(windows)]
+unsafe extern "system" fn handle(_event: u32) -> windows_sys::Win32::Foundation::BOOL {
+    INTERRUPTS.fetch_add(1, Ordering::SeqCst);
+    // Handled: don't end the process
+    1
+}
+
+#[cfg(windows)]
+pub fn install() -> Result<Handlers> {
+    use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+
+    // SAFETY: the handler only touches an atomic and stays valid for the whole process
+    if unsafe { SetConsoleCtrlHandler(Some(handle), 1) } == 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(Handlers {})
+}
+
+#[cfg(windows)]
+impl Drop for Handlers {
+    fn drop(&mut self) {
+        use windows_sys::Win32::System::Console::SetConsoleCtrlHandler;
+
+        // SAFETY: removes the handler added by `install`
+        unsafe { SetConsoleCtrlHandler(Some(handle), 0) };
+    }
+}
+
+pub fn interrupts() -> usize {
+    INTERRUPTS.load(Ordering::SeqCst)
+}
@@ -0,0 +1,104 @@
+use crate::artifacts::Artifact;
+use crate::steps::Problem;
+use crate::{context, util, Config};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::process::Command;
+
+/// The size of each program and library to ship by name, leaving out those that are gone.
+pub fn measure(artifacts: &[Artifact]) -> BTreeMap<String, u64> {
+    artifacts.iter()
+        .filter(|artifact| artifact.is_shipped())
+        .filter_map(|artifact| Some((artifact.name(), fs::metadata(&artifact.path).ok()?.len())))
+        .collect()
+}
+
+/// A line per binary with its size, and how that changed since the sizes of an
+/// earlier run, if there are any.
+pub fn describe(sizes: &BTreeMap<String, u64>, previous: Option<(u64, &BTreeMap<String, u64>)>) -> Vec<String> {
+    sizes.iter().map(|(name, &size)| {
+        let change = match previous {
+            Some((run, before)) => match before.get(name) {
+                Some(&before) if before == size => format!(" (unchanged since run #{})", run),
+                Some(&before) if before < size => format!(" (+{} since run #{})", util::format_size(size - before), run),
+                Some(&before) => format!(" (-{} since run #{})", util::format_size(before - size), run),
+                None => " (new)".to_string(),
+            },
+            None => String::new(),
+        };
+        format!("Size of {}: {}{}", name, util::format_size(size), change)
+    }).collect()
+}
+
+/// The crates taking up the most of a program's code, from `cargo bloat --crates`.
+pub fn bloat(config: &Config, binary: &Artifact, count: usize) -> Result<String, Problem> {
+    let mut cmd = Command::new(&config.cargo);
+    cmd.args(["bloat", "--crates", "--message-format", "json", "-n", &count.to_string(), "-p", &binary.package_id]);
+    if binary.kind == "example" {
+        cmd.args(["--example", &binary.target_name]);
+    } else {
+        cmd.args(["--bin", &binary.target_name]);
+    }
+    if let Some(toolchain) = &config.toolchain {
+        cmd.env("RUSTUP_TOOLCHAIN", toolchain);
+    }
+    // It builds the program again, so it needs what the build was given
+    let args = &config.cargo_args;
+    for flag in ["--release", "--all-features", "--no-default-features"].into_iter().filter(|flag| context::has_flag(args, flag)) {
+        cmd.arg(flag);
+    }
+    for name in ["--profile", "--target", "--features", "--manifest-path"] {
+        if let Some(value) = context::option_value(args, &[name]) {
+            cmd.args([name, value]);
+        }
+    }
+    let output = cmd.output().map_err(|e| Problem { message: format!("failed to run cargo bloat: {}", e), output: None, help: None })?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if let Some(breakdown) = serde_json::from_str(&stdout).ok().and_then(|json: Value| breakdown(&json)) {
+        return Ok(format!("Size of {} by crate: {}", binary.name(), breakdown));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+    if stderr.contains("no such command") {
+        return Err(Problem::new("cargo-bloat isn't installed".to_string(), "install it with `cargo install cargo-bloat --locked`".to_string()));
+    }
+    Err(Problem {
+        message: format!("cargo bloat failed on {}", binary.name()),
+        output: Some(stderr).filter(|stderr| !stderr.is_empty()),
+        help: None,
+    })
+}
+
+fn breakdown(json: &Value) -> Option<String> {
+    let text = json.get("text-section-size")?.as_u64()?;
+    let crates: Vec<String> = json.get("crates")?.as_array()?.iter()
+        .filter_map(|krate| Some((krate.get("name")?.as_str()?, krate.get("size")?.as_u64()?)))
+        .map(|(name, size)| format!("{} {}", name, util::format_size(size)))
+        .collect();
+    Some(format!("{} (of {} of code)", crates.join(", "), util::format_size(text)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe() {
+        let sizes = BTreeMap::from([("app".to_string(), 3 << 20), ("helper".to_string(), 1536), ("libapp.so".to_string(), 4096)]);
+        let before = BTreeMap::from([("app".to_string(), (3 << 20) - 2048), ("libapp.so".to_string(), 4096)]);
+        assert_eq!(describe(&sizes, Some((7, &before))), [
+            "Size of app: 3M (+2K since run #7)",
+            "Size of helper: 1.5K (new)",
+            "Size of libapp.so: 4K (unchanged since run #7)",
+        ]);
+        assert_eq!(describe(&before, Some((8, &sizes)))[0], "Size of app: 3.0M (-2K since run #8)");
+        assert_eq!(describe(&sizes, None)[1], "Size of helper: 1.5K");
+    }
+
+    #[test]
+    fn test_breakdown() {
+        let json: Value = serde_json::from_str(r#"{"file-size":4500000,"text-section-size":1048576,
+            "crates":[{"name":"std","size":524288},{"name":"regex_automata","size":262144},{"name":"app","size":1024}]}"#).unwrap();
+        assert_eq!(breakdown(&json).unwrap(), "std 512K, regex_automata 256K, app 1K (of 1M of code)");
+    }
+}
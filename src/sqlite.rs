@@ -0,0 +1,372 @@
+use crate::diagnostics::Diagnostic;
+use crate::store::{self, DiagnosticQuery, RunRecord};
+use crate::util::Workspace;
+use anyhow::{Result, Context};
+use rusqlite::{params, params_from_iter, Connection, Row};
+use std::fs;
+use std::path::PathBuf;
+use std::time::Duration;
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id INTEGER PRIMARY KEY,
+    timestamp INTEGER NOT NULL,
+    command TEXT NOT NULL,
+    duration_ms INTEGER NOT NULL,
+    success INTEGER NOT NULL,
+    errors INTEGER NOT NULL,
+    warnings INTEGER NOT NULL,
+    fingerprints TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS diagnostics (
+    run_id INTEGER NOT NULL REFERENCES runs(id) ON DELETE CASCADE,
+    seq INTEGER NOT NULL,
+    level TEXT NOT NULL,
+    code TEXT,
+    message TEXT NOT NULL,
+    file TEXT,
+    line INTEGER,
+    col INTEGER,
+    package_id TEXT,
+    target_kind TEXT NOT NULL,
+    rendered TEXT NOT NULL,
+    fingerprint TEXT NOT NULL,
+    PRIMARY KEY (run_id, seq)
+);
+CREATE INDEX IF NOT EXISTS diagnostics_code ON diagnostics(code);
+CREATE INDEX IF NOT EXISTS diagnostics_file ON diagnostics(file);
+CREATE INDEX IF NOT EXISTS diagnostics_package ON diagnostics(package_id);
+CREATE INDEX IF NOT EXISTS diagnostics_fingerprint ON diagnostics(fingerprint);
+";
+
+/// Changes to `SCHEMA` in order; `user_version` counts those already applied.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE runs ADD COLUMN branch TEXT;
+     ALTER TABLE runs ADD COLUMN warnings_counted INTEGER NOT NULL DEFAULT 0;
+     ALTER TABLE runs ADD COLUMN package_warnings TEXT NOT NULL DEFAULT '{}';",
+    "ALTER TABLE runs ADD COLUMN git_commit TEXT;
+     ALTER TABLE runs ADD COLUMN dirty INTEGER NOT NULL DEFAULT 0;",
+    "ALTER TABLE runs ADD COLUMN profile TEXT;",
+    "ALTER TABLE runs ADD COLUMN binary_sizes TEXT NOT NULL DEFAULT '{}';",
+    "ALTER TABLE diagnostics ADD COLUMN cargo_file TEXT;",
+];
+
+const DIAGNOSTIC_COLUMNS: &str = "level, code, message, file, line, col, package_id, target_kind, rendered, cargo_file";
+
+pub fn path(workspace: &Workspace) -> PathBuf {
+    workspace.state_dir().join("history.db")
+}
+
+/// Runs and their diagnostics in `target/cargo-builder/history.db`.
+pub struct Database {
+    conn: Connection,
+}
+
+impl Database {
+    /// Open the database, creating it on first use from any existing JSON history.
+    pub fn open(workspace: &Workspace) -> Result<Self> {
+        let path = path(workspace);
+        let fresh = !path.exists();
+        fs::create_dir_all(workspace.state_dir())
+            .with_context(|| format!("Failed to create state directory: {}", workspace.state_dir().display()))?;
+
+        let conn = Connection::open(&path)
+            .with_context(|| format!("Failed to open history database: {}", path.display()))?;
+        // Builds running in parallel record their runs at the same time
+        conn.busy_timeout(Duration::from_secs(10))?;
+        conn.pragma_update(None, "foreign_keys", true)?;
+        migrate(&conn)
+            .with_context(|| format!("Failed to set up history database: {}", path.display()))?;
+
+        let mut database = Database { conn };
+        if fresh {
+            database.import_json(workspace)?;
+        }
+        Ok(database)
+    }
+
+    /// Copy the runs of `history.json` so switching backends keeps the history.
+    fn import_json(&mut self, workspace: &Workspace) -> Result<()> {
+        for record in store::load_json_history(workspace)? {
+            let diagnostics = store::load_json_run_diagnostics(workspace, record.id)?;
+            self.insert(Some(record.id), &record, &diagnostics)?;
+        }
+        Ok(())
+    }
+
+    pub fn load_history(&self) -> Result<Vec<RunRecord>> {
+        let mut statement = self.conn.prepare(
+            "SELECT id, timestamp, command, duration_ms, success, errors, warnings, fingerprints, branch, warnings_counted, package_warnings, git_commit, dirty, profile, binary_sizes
+             FROM runs ORDER BY id",
+        )?;
+        let runs = statement.query_map([], |row| {
+            let fingerprints: String = row.get(7)?;
+            let package_warnings: String = row.get(10)?;
+            let binary_sizes: String = row.get(14)?;
+            Ok(RunRecord {
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                command: row.get(2)?,
+                duration_ms: row.get(3)?,
+                success: row.get(4)?,
+                errors: row.get(5)?,
+                warnings: row.get(6)?,
+                fingerprints: fingerprints.split_whitespace().map(str::to_string).collect(),
+                branch: row.get(8)?,
+                warnings_counted: row.get(9)?,
+                package_warnings: serde_json::from_str(&package_warnings).unwrap_or_default(),
+                commit: row.get(11)?,
+                dirty: row.get(12)?,
+                profile: row.get(13)?,
+                binary_sizes: serde_json::from_str(&binary_sizes).unwrap_or_default(),
+            })
+        })?;
+        runs.collect::<rusqlite::Result<_>>().context("Failed to read history database")
+    }
+
+    pub fn load_run_diagnostics(&self, id: u64) -> Result<Vec<Diagnostic>> {
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT {} FROM diagnostics WHERE run_id = ?1 ORDER BY seq", DIAGNOSTIC_COLUMNS,
+        ))?;
+        let diagnostics = statement.query_map([id], |row| diagnostic(row, 0))?;
+        diagnostics.collect::<rusqlite::Result<_>>().context("Failed to read history database")
+    }
+
+    /// Diagnostics of all runs matching `query`, oldest run first, using the indexes.
+    pub fn query(&self, query: &DiagnosticQuery) -> Result<Vec<(u64, Diagnostic)>> {
+        let mut conditions = Vec::new();
+        let mut values = Vec::new();
+        if let Some(code) = &query.code {
+            conditions.push("code = ?");
+            values.push(code.as_str());
+        }
+        if let Some(level) = &query.level {
+            conditions.push("level = ?");
+            values.push(level.as_str());
+        }
+        if let Some(file) = &query.file {
+            conditions.push("instr(file, ?) > 0");
+            values.push(file.as_str());
+        }
+        let filter = if conditions.is_empty() {
+            String::new()
+        } else {
+            format!("WHERE {}", conditions.join(" AND "))
+        };
+
+        let mut statement = self.conn.prepare(&format!(
+            "SELECT run_id, {} FROM diagnostics {} ORDER BY run_id, seq", DIAGNOSTIC_COLUMNS, filter,
+        ))?;
+        let found = statement.query_map(params_from_iter(values), |row| Ok((row.get(0)?, diagnostic(row, 1)?)))?;
+        found.collect::<rusqlite::Result<_>>().context("Failed to query history database")
+    }
+
+    /// Add a run with a fresh id, dropping the oldest runs beyond `keep`.
+    pub fn record_run(&mut self, record: RunRecord, diagnostics: &[Diagnostic], keep: usize) -> Result<RunRecord> {
+        let id = self.insert(None, &record, diagnostics)?;
+        self.conn.execute(
+            "DELETE FROM runs WHERE id NOT IN (SELECT id FROM runs ORDER BY id DESC LIMIT ?1)",
+            [keep as i64],
+        )?;
+        Ok(RunRecord { id, ..record })
+    }
+
+    fn insert(&mut self, id: Option<u64>, record: &RunRecord, diagnostics: &[Diagnostic]) -> Result<u64> {
+        let transaction = self.conn.transaction()?;
+        transaction.execute(
+            "INSERT INTO runs (id, timestamp, command, duration_ms, success, errors, warnings, fingerprints, branch, warnings_counted, package_warnings, git_commit, dirty, profile, binary_sizes)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+            params![
+                id,
+                record.timestamp,
+                record.command,
+                record.duration_ms,
+                record.success,
+                record.errors,
+                record.warnings,
+                record.fingerprints.join(" "),
+                record.branch,
+                record.warnings_counted,
+                serde_json::to_string(&record.package_warnings)?,
+                record.commit,
+                record.dirty,
+                record.profile,
+                serde_json::to_string(&record.binary_sizes)?,
+            ],
+        )?;
+        let id = transaction.last_insert_rowid() as u64;
+
+        {
+            let mut statement = transaction.prepare(&format!(
+                "INSERT INTO diagnostics (run_id, seq, {}, fingerprint) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+                DIAGNOSTIC_COLUMNS,
+            ))?;
+            for (seq, d) in diagnostics.iter().enumerate() {
+                statement.execute(params![
+                    id,
+                    seq,
+                    d.level,
+                    d.code,
+                    d.message,
+                    d.file,
+                    d.line,
+                    d.column,
+                    d.package_id,
+                    serde_json::to_string(&d.target_kind)?,
+                    d.rendered,
+                    d.cargo_file,
+                    d.fingerprint(),
+                ])?;
+            }
+        }
+        transaction.commit().context("Failed to record run in history database")?;
+        Ok(id)
+    }
+}
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(SCHEMA)?;
+    let applied: usize = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    for migration in MIGRATIONS.iter().skip(applied) {
+        conn.execute_batch(migration)?;
+    }
+    conn.pragma_update(None, "user_version", MIGRATIONS.len())
+}
+
+/// Read a diagnostic from the `DIAGNOSTIC_COLUMNS` of `row`, starting at column `start`.
+fn diagnostic(row: &Row, start: usize) -> rusqlite::Result<Diagnostic> {
+    let target_kind: String = row.get(start + 7)?;
+    Ok(Diagnostic {
+        level: row.get(start)?,
+        code: row.get(start + 1)?,
+        message: row.get(start + 2)?,
+        file: row.get(start + 3)?,
+        line: row.get(start + 4)?,
+        column: row.get(start + 5)?,
+        package_id: row.get(start + 6)?,
+        target_kind: serde_json::from_str(&target_kind).unwrap_or_default(),
+        rendered: row.get(start + 8)?,
+        cargo_file: row.get(start + 9)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_workspace(temp_dir: &TempDir) -> Workspace {
+        Workspace {
+            root: temp_dir.path().to_path_buf(),
+            target_directory: temp_dir.path().join("target"),
+        }
+    }
+
+    fn record() -> RunRecord {
+        RunRecord {
+            id: 0,
+            timestamp: 1_700_000_000,
+            command: "cargo build".to_string(),
+            profile: Some("release".to_string()),
+            duration_ms: 900,
+            success: false,
+            errors: 1,
+            warnings: 1,
+            fingerprints: vec!["00aa".to_string(), "00bb".to_string()],
+            branch: Some("main".to_string()),
+            commit: Some("1a2b3c4d5e6f7a8b9c0d1a2b3c4d5e6f7a8b9c0d".to_string()),
+            dirty: true,
+            warnings_counted: true,
+            package_warnings: [("app".to_string(), 1)].into(),
+            binary_sizes: [("app".to_string(), 4096)].into(),
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut database = Database::open(&test_workspace(&temp_dir)).unwrap();
+        let diagnostics = [Diagnostic {
+            target_kind: vec!["lib".to_string()],
+            ..Diagnostic::new("error", "mismatched types").with_code("E0308").at("src/lib.rs", 3, 7)
+        }];
+
+        let first = database.record_run(record(), &diagnostics, 10).unwrap();
+        let second = database.record_run(record(), &[], 10).unwrap();
+        assert_eq!((first.id, second.id), (1, 2));
+
+        let history = database.load_history().unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].fingerprints, ["00aa", "00bb"]);
+        assert_eq!(history[0].branch.as_deref(), Some("main"));
+        assert_eq!(history[0].commit.as_deref(), Some("1a2b3c4d5e6f7a8b9c0d1a2b3c4d5e6f7a8b9c0d"));
+        assert!(history[0].dirty);
+        assert_eq!(history[0].profile.as_deref(), Some("release"));
+        assert_eq!(history[0].package_warnings["app"], 1);
+        assert_eq!(history[0].binary_sizes["app"], 4096);
+
+        let stored = database.load_run_diagnostics(1).unwrap();
+        assert_eq!(stored[0].code.as_deref(), Some("E0308"));
+        assert_eq!(stored[0].column, Some(7));
+        assert_eq!(stored[0].target_kind, ["lib"]);
+    }
+
+    #[test]
+    fn test_query_and_pruning() {
+        let temp_dir = TempDir::new().unwrap();
+        let mut database = Database::open(&test_workspace(&temp_dir)).unwrap();
+        for _ in 0..3 {
+            database.record_run(record(), &[
+                Diagnostic::new("error", "mismatched types").with_code("E0308").at("crates/net/src/lib.rs", 3, 7),
+                Diagnostic::new("warning", "mismatched types").at("src/main.rs", 3, 7),
+            ], 2).unwrap();
+        }
+
+        // Run 1 was dropped together with its diagnostics
+        let query = DiagnosticQuery { code: Some("E0308".to_string()), file: Some("net/".to_string()), level: None };
+        let found = database.query(&query).unwrap();
+        assert_eq!(found.iter().map(|(id, _)| *id).collect::<Vec<_>>(), [2, 3]);
+
+        let warnings = database.query(&DiagnosticQuery { level: Some("warning".to_string()), ..Default::default() }).unwrap();
+        assert_eq!(warnings.len(), 2);
+        assert!(database.load_run_diagnostics(1).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_migrates_older_databases() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = test_workspace(&temp_dir);
+        fs::create_dir_all(workspace.state_dir()).unwrap();
+        {
+            let conn = Connection::open(path(&workspace)).unwrap();
+            conn.execute_batch(SCHEMA).unwrap();
+            conn.execute(
+                "INSERT INTO runs (timestamp, command, duration_ms, success, errors, warnings, fingerprints)
+                 VALUES (0, 'cargo build', 1, 1, 0, 0, '')",
+                [],
+            ).unwrap();
+        }
+
+        let mut database = Database::open(&workspace).unwrap();
+        database.record_run(record(), &[], 10).unwrap();
+        let history = database.load_history().unwrap();
+        assert!(history[0].branch.is_none());
+        assert!(history[0].commit.is_none());
+        assert!(history[0].profile.is_none());
+        assert!(history[0].binary_sizes.is_empty());
+        assert_eq!(history[1].branch.as_deref(), Some("main"));
+    }
+
+    #[test]
+    fn test_imports_json_history() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = test_workspace(&temp_dir);
+        let diagnostics = [Diagnostic::new("error", "mismatched types").with_code("E0308").at("src/lib.rs", 3, 7)];
+        store::record_json_run(&workspace, record(), &diagnostics).unwrap();
+        store::record_json_run(&workspace, record(), &[]).unwrap();
+
+        let database = Database::open(&workspace).unwrap();
+        assert_eq!(database.load_history().unwrap().len(), 2);
+        assert_eq!(database.load_run_diagnostics(1).unwrap().len(), 1);
+    }
+}
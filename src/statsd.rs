@@ -0,0 +1,118 @@
+use crate::context;
+use crate::notify::BuildOutcome;
+use crate::settings::{StatsdFlavor, StatsdSettings};
+use crate::Config;
+use anyhow::{Result, Context};
+use std::net::{ToSocketAddrs, UdpSocket};
+
+/// Tags describing the build: profile, target, packages and status, then the configured ones.
+fn tags(args: &[String], host: Option<&str>, settings: &StatsdSettings, success: bool) -> Vec<String> {
+    let target = context::option_value(args, &["--target"])
+        .or(host)
+        .unwrap_or("host");
+
+    let mut tags = vec![
+        format!("profile:{}", context::profile(args)),
+        format!("target:{}", target),
+    ];
+    for package in context::option_values(args, &["--package", "-p"]) {
+        tags.push(format!("package:{}", package));
+    }
+    tags.push(format!("status:{}", if success { "success" } else { "failure" }));
+    tags.extend(settings.tags.iter().cloned());
+    tags
+}
+
+/// The metric lines of a build, e.g. `cargo_builder.build.duration:4200|ms|#profile:dev`.
+fn lines(outcome: &BuildOutcome, settings: &StatsdSettings, tags: &[String]) -> Vec<String> {
+    let prefix = settings.prefix.trim_end_matches('.');
+    let mut metrics = vec![
+        ("duration", format!("{}|ms", outcome.duration.as_millis())),
+        ("errors", format!("{}|g", outcome.error_count())),
+        ("warnings", format!("{}|g", outcome.warning_count())),
+        ("runs", "1|c".to_string()),
+    ];
+    if !outcome.success {
+        metrics.push(("failures", "1|c".to_string()));
+    }
+
+    // `|`, `,` and `#` delimit the DogStatsD format
+    let tags: Vec<String> = tags.iter().map(|t| t.replace(['|', ',', '#'], "_")).collect();
+    metrics.into_iter()
+        .map(|(name, value)| {
+            let line = format!("{}.build.{}:{}", prefix, name, value);
+            match settings.flavor {
+                StatsdFlavor::Dogstatsd if !tags.is_empty() => format!("{}|#{}", line, tags.join(",")),
+                _ => line,
+            }
+        })
+        .collect()
+}
+
+/// Send the build's metrics to `address` in a single UDP packet.
+pub fn send(address: &str, config: &Config, settings: &StatsdSettings, outcome: &BuildOutcome) -> Result<()> {
+    let target = address.to_socket_addrs()
+        .with_context(|| format!("Invalid StatsD address: {}", address))?
+        .next()
+        .with_context(|| format!("StatsD address {} did not resolve", address))?;
+    let socket = UdpSocket::bind(if target.is_ipv4() { "0.0.0.0:0" } else { "[::]:0" })
+        .context("Failed to open a UDP socket")?;
+
+    let context = context::get(config);
+    let tags = tags(&config.cargo_args, context.host.as_deref(), settings, outcome.success);
+    let packet = lines(outcome, settings, &tags).join("\n");
+    socket.send_to(packet.as_bytes(), target)
+        .with_context(|| format!("Failed to send metrics to {}", address))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notify::test_outcome;
+
+    fn settings(flavor: StatsdFlavor) -> StatsdSettings {
+        StatsdSettings {
+            address: None,
+            prefix: "ci".to_string(),
+            flavor,
+            tags: vec!["team:infra".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_dogstatsd_lines() {
+        let tags = vec!["profile:dev".to_string(), "package:a,b".to_string()];
+        let lines = lines(&test_outcome(&[]), &settings(StatsdFlavor::Dogstatsd), &tags);
+        assert_eq!(lines, [
+            "ci.build.duration:4200|ms|#profile:dev,package:a_b",
+            "ci.build.errors:0|g|#profile:dev,package:a_b",
+            "ci.build.warnings:0|g|#profile:dev,package:a_b",
+            "ci.build.runs:1|c|#profile:dev,package:a_b",
+            "ci.build.failures:1|c|#profile:dev,package:a_b",
+        ]);
+    }
+
+    #[test]
+    fn test_plain_statsd_has_no_tags() {
+        let lines = lines(&test_outcome(&[]), &settings(StatsdFlavor::Statsd), &["profile:dev".to_string()]);
+        assert_eq!(lines[0], "ci.build.duration:4200|ms");
+    }
+
+    #[test]
+    fn test_tags() {
+        let args: Vec<String> = ["--release", "--target", "wasm32-unknown-unknown", "-p", "core"]
+            .iter().map(|s| s.to_string()).collect();
+        assert_eq!(tags(&args, Some("x86_64-unknown-linux-gnu"), &settings(StatsdFlavor::Dogstatsd), true), [
+            "profile:release",
+            "target:wasm32-unknown-unknown",
+            "package:core",
+            "status:success",
+            "team:infra",
+        ]);
+        assert_eq!(tags(&[], Some("x86_64-unknown-linux-gnu"), &settings(StatsdFlavor::Statsd), false)[..2], [
+            "profile:dev",
+            "target:x86_64-unknown-linux-gnu",
+        ]);
+    }
+}
@@ -0,0 +1,84 @@
+use crate::diagnostics::{strip_ansi_codes, Diagnostic};
+use std::io::ErrorKind;
+use std::process::Command;
+
+/// Why a check before the build or a step after it failed, and what to do about it.
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    pub message: String,
+    /// What the tool printed
+    pub output: Option<String>,
+    pub help: Option<String>,
+}
+
+impl Problem {
+    pub fn new(message: String, help: String) -> Self {
+        Problem { message, output: None, help: Some(help) }
+    }
+
+    /// The problem as an error entry, rendered the way the compiler renders its own.
+    pub fn diagnostic(&self) -> Diagnostic {
+        let mut rendered = format!("error: {}\n", self.message);
+        for line in self.output.iter().flat_map(|output| output.lines()) {
+            if line.is_empty() {
+                rendered.push('\n');
+            } else {
+                rendered.push_str(&format!("  {}\n", line));
+            }
+        }
+        if let Some(help) = &self.help {
+            rendered.push_str(&format!("  = help: {}\n", help));
+        }
+        Diagnostic {
+            rendered,
+            ..Diagnostic::new("error", self.message.clone())
+        }
+    }
+}
+
+/// Run `cmd`, the `tool` of a step on `subject`, turning its failure into a problem.
+/// `hint` makes the help from what it printed; `install` says how to get it.
+pub fn run(cmd: Command, tool: &str, subject: &str, install: &str, hint: impl Fn(&str) -> Option<String>) -> Option<Problem> {
+    output(cmd, tool, subject, install, hint).err()
+}
+
+/// Like `run`, with what the step printed when it succeeded.
+pub fn output(mut cmd: Command, tool: &str, subject: &str, install: &str, hint: impl Fn(&str) -> Option<String>) -> Result<String, Problem> {
+    let output = match cmd.output() {
+        Ok(output) => output,
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            return Err(Problem::new(format!("{} isn't installed", tool), install.to_string()));
+        }
+        Err(e) => return Err(Problem { message: format!("failed to run {}: {}", tool, e), output: None, help: None }),
+    };
+    // Some tools report errors on stdout
+    let mut printed = String::from_utf8_lossy(&output.stderr).into_owned();
+    printed.push_str(&String::from_utf8_lossy(&output.stdout));
+    let printed = strip_ansi_codes(&printed).trim_end().to_string();
+    if output.status.success() {
+        return Ok(printed);
+    }
+    Err(Problem {
+        message: format!("{} failed on {}", tool, subject),
+        help: hint(&printed),
+        output: Some(printed).filter(|printed| !printed.is_empty()),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diagnostic() {
+        let problem = Problem {
+            message: "wasm-bindgen failed on app.wasm".to_string(),
+            output: Some("error: \n\nit looks like the Rust project used to create this wasm file was linked against".to_string()),
+            help: Some("install the matching version".to_string()),
+        };
+        assert_eq!(
+            problem.diagnostic().rendered,
+            "error: wasm-bindgen failed on app.wasm\n  error: \n\n  it looks like the Rust project used to create this wasm file was linked against\n  = help: install the matching version\n",
+        );
+    }
+}
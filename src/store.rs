@@ -0,0 +1,372 @@
+use crate::diagnostics::Diagnostic;
+use crate::settings::{self, HistoryBackend};
+use crate::sqlite;
+use crate::util::{self, Workspace};
+use anyhow::{Result, Context};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Number of runs kept in the history before the oldest are dropped.
+const MAX_HISTORY: usize = 200;
+
+/// Summary of one cargo-builder invocation.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunRecord {
+    pub id: u64,
+    /// Seconds since the Unix epoch when the run started
+    pub timestamp: u64,
+    pub command: String,
+    /// Cargo profile the build used; unknown for runs recorded before it was kept
+    #[serde(default)]
+    pub profile: Option<String>,
+    pub duration_ms: u64,
+    pub success: bool,
+    pub errors: usize,
+    pub warnings: usize,
+    /// Sorted, distinct fingerprints of the run's diagnostics
+    #[serde(default)]
+    pub fingerprints: Vec<String>,
+    /// Git branch the workspace was on, if any
+    #[serde(default)]
+    pub branch: Option<String>,
+    /// Git commit checked out when the build started
+    #[serde(default)]
+    pub commit: Option<String>,
+    /// Whether the working tree had uncommitted changes
+    #[serde(default)]
+    pub dirty: bool,
+    /// Whether every warning was counted: kept with `--include-warnings`, and not
+    /// hidden by `--changed-only`, `--against` or `--baseline`
+    #[serde(default)]
+    pub warnings_counted: bool,
+    /// Number of warnings per package name
+    #[serde(default)]
+    pub package_warnings: BTreeMap<String, usize>,
+    /// Size in bytes of each program and library a successful build produced, by file name
+    #[serde(default)]
+    pub binary_sizes: BTreeMap<String, u64>,
+}
+
+impl RunRecord {
+    pub fn started_at(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(self.timestamp)
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_ms)
+    }
+
+    /// One line with the id, start time, duration, status, profile, counts and command.
+    pub fn format_row(&self) -> String {
+        format!(
+            "#{:<5} {}  {:>8}  {:<6}  {:<8}  {:>4} errors  {:>4} warnings  {}",
+            self.id,
+            humantime::format_rfc3339_seconds(self.started_at()),
+            util::format_duration(self.duration()),
+            if self.success { "ok" } else { "FAILED" },
+            self.profile.as_deref().unwrap_or("-"),
+            self.errors,
+            self.warnings,
+            self.command,
+        )
+    }
+}
+
+/// Distinct fingerprints of `diagnostics`, sorted for comparison between runs.
+pub fn fingerprints(diagnostics: &[Diagnostic]) -> Vec<String> {
+    let mut fingerprints: Vec<String> = diagnostics.iter().map(Diagnostic::fingerprint).collect();
+    fingerprints.sort();
+    fingerprints.dedup();
+    fingerprints
+}
+
+/// Warnings of `diagnostics` counted by package name.
+pub fn package_warnings(diagnostics: &[Diagnostic]) -> BTreeMap<String, usize> {
+    let mut counts = BTreeMap::new();
+    for diagnostic in diagnostics.iter().filter(|d| d.level == "warning") {
+        if let Some(package) = diagnostic.package_name() {
+            *counts.entry(package.to_string()).or_default() += 1;
+        }
+    }
+    counts
+}
+
+pub fn last_run_path(workspace: &Workspace) -> PathBuf {
+    workspace.state_dir().join("last-run.jsonl")
+}
+
+pub fn history_path(workspace: &Workspace) -> PathBuf {
+    workspace.state_dir().join("history.json")
+}
+
+pub fn run_diagnostics_path(workspace: &Workspace, id: u64) -> PathBuf {
+    workspace.state_dir().join("runs").join(format!("{}.jsonl", id))
+}
+
+/// Criteria for diagnostics across the history; every given one must match.
+#[derive(Debug, Default)]
+pub struct DiagnosticQuery {
+    pub code: Option<String>,
+    pub level: Option<String>,
+    /// Substring of the file path
+    pub file: Option<String>,
+}
+
+impl DiagnosticQuery {
+    pub fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        self.code.as_ref().is_none_or(|code| diagnostic.code.as_ref() == Some(code))
+            && self.file.as_ref().is_none_or(|file| diagnostic.file.as_ref().is_some_and(|f| f.contains(file.as_str())))
+            && self.level.as_ref().is_none_or(|level| &diagnostic.level == level)
+    }
+}
+
+fn backend(workspace: &Workspace) -> Result<HistoryBackend> {
+    Ok(settings::load(workspace)?.history.backend)
+}
+
+/// Load all recorded runs, oldest first. A missing history is an empty history.
+pub fn load_history(workspace: &Workspace) -> Result<Vec<RunRecord>> {
+    match backend(workspace)? {
+        HistoryBackend::Json => load_json_history(workspace),
+        HistoryBackend::Sqlite => sqlite::Database::open(workspace)?.load_history(),
+    }
+}
+
+/// Diagnostics stored for run `id`; runs recorded without them have none.
+pub fn load_run_diagnostics(workspace: &Workspace, id: u64) -> Result<Vec<Diagnostic>> {
+    match backend(workspace)? {
+        HistoryBackend::Json => load_json_run_diagnostics(workspace, id),
+        HistoryBackend::Sqlite => sqlite::Database::open(workspace)?.load_run_diagnostics(id),
+    }
+}
+
+/// Append a run to the history, storing its diagnostics alongside it.
+/// The `id` of the given record is ignored and a fresh one is assigned.
+pub fn record_run(workspace: &Workspace, record: RunRecord, diagnostics: &[Diagnostic]) -> Result<RunRecord> {
+    match backend(workspace)? {
+        HistoryBackend::Json => record_json_run(workspace, record, diagnostics),
+        HistoryBackend::Sqlite => sqlite::Database::open(workspace)?.record_run(record, diagnostics, MAX_HISTORY),
+    }
+}
+
+/// Diagnostics of all recorded runs matching `query`, with their run ids, oldest run first.
+pub fn query_diagnostics(workspace: &Workspace, query: &DiagnosticQuery) -> Result<Vec<(u64, Diagnostic)>> {
+    if backend(workspace)? == HistoryBackend::Sqlite {
+        return sqlite::Database::open(workspace)?.query(query);
+    }
+
+    let mut found = Vec::new();
+    for run in load_json_history(workspace)? {
+        for diagnostic in load_json_run_diagnostics(workspace, run.id)? {
+            if query.matches(&diagnostic) {
+                found.push((run.id, diagnostic));
+            }
+        }
+    }
+    Ok(found)
+}
+
+pub fn load_json_run_diagnostics(workspace: &Workspace, id: u64) -> Result<Vec<Diagnostic>> {
+    let path = run_diagnostics_path(workspace, id);
+    if path.exists() {
+        read_diagnostics(&path)
+    } else {
+        Ok(Vec::new())
+    }
+}
+
+pub fn load_json_history(workspace: &Workspace) -> Result<Vec<RunRecord>> {
+    let path = history_path(workspace);
+    if !path.exists() {
+        return Ok(Vec::new());
+    }
+
+    let content = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read history: {}", path.display()))?;
+    serde_json::from_str(&content)
+        .with_context(|| format!("Malformed history file: {}", path.display()))
+}
+
+pub fn record_json_run(workspace: &Workspace, mut record: RunRecord, diagnostics: &[Diagnostic]) -> Result<RunRecord> {
+    // Held until the new history is in place, so concurrent builds take turns
+    let _lock = lock_history(workspace)?;
+    let mut history = load_json_history(workspace)?;
+    record.id = history.last().map_or(1, |last| last.id + 1);
+
+    write_diagnostics(&run_diagnostics_path(workspace, record.id), diagnostics)?;
+    history.push(record.clone());
+
+    // Drop the oldest runs together with their diagnostics
+    if history.len() > MAX_HISTORY {
+        let excess = history.len() - MAX_HISTORY;
+        for old in history.drain(..excess) {
+            let _ = fs::remove_file(run_diagnostics_path(workspace, old.id));
+        }
+    }
+
+    // Replaced in one step, so no one reads half a history
+    let path = history_path(workspace);
+    let written = path.with_extension("json.tmp");
+    fs::write(&written, serde_json::to_string_pretty(&history)?)
+        .with_context(|| format!("Failed to write history: {}", written.display()))?;
+    fs::rename(&written, &path)
+        .with_context(|| format!("Failed to write history: {}", path.display()))?;
+
+    Ok(record)
+}
+
+/// Lock `history.lock` in the state directory, waiting while another build holds it.
+fn lock_history(workspace: &Workspace) -> Result<File> {
+    let dir = workspace.state_dir();
+    fs::create_dir_all(&dir)
+        .with_context(|| format!("Failed to create state directory: {}", dir.display()))?;
+    let path = dir.join("history.lock");
+    let file = File::options()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&path)
+        .with_context(|| format!("Failed to create lock file: {}", path.display()))?;
+    file.lock()
+        .with_context(|| format!("Failed to lock {}", path.display()))?;
+    Ok(file)
+}
+
+/// Write the diagnostics of a run as JSON lines, replacing any previous contents.
+pub fn write_diagnostics(path: &Path, diagnostics: &[Diagnostic]) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)
+            .with_context(|| format!("Failed to create state directory: {}", parent.display()))?;
+    }
+
+    let file = File::create(path)
+        .with_context(|| format!("Failed to create diagnostics file: {}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    for diagnostic in diagnostics {
+        serde_json::to_writer(&mut writer, diagnostic)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
+
+pub fn read_diagnostics(path: &Path) -> Result<Vec<Diagnostic>> {
+    let file = File::open(path)
+        .with_context(|| format!("Failed to open diagnostics file: {}", path.display()))?;
+
+    let mut diagnostics = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let diagnostic = serde_json::from_str(&line)
+            .with_context(|| format!("Malformed entry in {}", path.display()))?;
+        diagnostics.push(diagnostic);
+    }
+
+    Ok(diagnostics)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    fn test_workspace(temp_dir: &TempDir) -> Workspace {
+        Workspace {
+            root: temp_dir.path().to_path_buf(),
+            target_directory: temp_dir.path().join("target"),
+        }
+    }
+
+    fn test_record() -> RunRecord {
+        RunRecord {
+            id: 0,
+            timestamp: 1_700_000_000,
+            command: "cargo build".to_string(),
+            profile: None,
+            duration_ms: 1500,
+            success: true,
+            errors: 0,
+            warnings: 0,
+            fingerprints: Vec::new(),
+            branch: None,
+            commit: None,
+            dirty: false,
+            warnings_counted: false,
+            package_warnings: BTreeMap::new(),
+            binary_sizes: BTreeMap::new(),
+        }
+    }
+
+    #[test]
+    fn test_record_run_assigns_sequential_ids() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = test_workspace(&temp_dir);
+
+        assert!(load_history(&workspace).unwrap().is_empty());
+
+        let first = record_run(&workspace, test_record(), &[]).unwrap();
+        let second = record_run(&workspace, test_record(), &[]).unwrap();
+        assert_eq!(first.id, 1);
+        assert_eq!(second.id, 2);
+
+        let history = load_history(&workspace).unwrap();
+        assert_eq!(history.len(), 2);
+        assert!(run_diagnostics_path(&workspace, 2).exists());
+    }
+
+    #[test]
+    fn test_concurrent_runs_are_all_recorded() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = test_workspace(&temp_dir);
+
+        std::thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| record_json_run(&workspace, test_record(), &[]).unwrap());
+            }
+        });
+
+        let ids: Vec<u64> = load_json_history(&workspace).unwrap().iter().map(|run| run.id).collect();
+        assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+        assert!(!history_path(&workspace).with_extension("json.tmp").exists());
+    }
+
+    #[test]
+    fn test_history_without_fingerprints() {
+        let temp_dir = TempDir::new().unwrap();
+        let workspace = test_workspace(&temp_dir);
+        fs::create_dir_all(workspace.state_dir()).unwrap();
+        fs::write(
+            history_path(&workspace),
+            r#"[{"id":1,"timestamp":0,"command":"cargo build","duration_ms":1,"success":true,"errors":0,"warnings":0}]"#,
+        ).unwrap();
+
+        let history = load_history(&workspace).unwrap();
+        assert!(history[0].fingerprints.is_empty());
+        assert!(history[0].branch.is_none());
+    }
+
+    #[test]
+    fn test_diagnostics_round_trip() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("nested").join("last-run.jsonl");
+        let diagnostics = vec![Diagnostic::new("error", "cannot find value `x` in this scope")
+            .with_code("E0425")
+            .at("src/main.rs", 2, 5)
+            .with_rendered("error[E0425]: cannot find value `x` in this scope\n")];
+
+        write_diagnostics(&path, &diagnostics).unwrap();
+        let read_back = read_diagnostics(&path).unwrap();
+
+        assert_eq!(read_back.len(), 1);
+        assert_eq!(read_back[0].code.as_deref(), Some("E0425"));
+        assert_eq!(read_back[0].line, Some(2));
+    }
+}
@@ -0,0 +1,214 @@
+use crate::diagnostics::Diagnostic;
+use crate::util::Workspace;
+use anyhow::{Result, Context, bail};
+use regex::Regex;
+use serde::Deserialize;
+use std::fs;
+use std::time::SystemTime;
+
+pub const FILE_NAME: &str = "cargo-builder-suppressions.toml";
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SuppressionFile {
+    #[serde(default)]
+    suppress: Vec<Rule>,
+}
+
+/// One `[[suppress]]` entry; all given criteria must match.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Error code or lint name, e.g. `E0599` or `dead_code`
+    pub code: Option<String>,
+    /// Glob over the workspace-relative file path, e.g. `src/generated/**`
+    pub path: Option<String>,
+    pub reason: Option<String>,
+    /// Date (`YYYY-MM-DD`) from which the rule stops applying
+    pub expires: Option<String>,
+}
+
+impl Rule {
+    fn describe(&self) -> String {
+        match (&self.code, &self.path) {
+            (Some(code), Some(path)) => format!("{} in {}", code, path),
+            (Some(code), None) => code.clone(),
+            (None, Some(path)) => format!("anything in {}", path),
+            (None, None) => "nothing".to_string(),
+        }
+    }
+}
+
+struct ActiveRule {
+    rule: Rule,
+    path: Option<Regex>,
+    expired: bool,
+    hits: usize,
+}
+
+impl ActiveRule {
+    fn matches(&self, diagnostic: &Diagnostic) -> bool {
+        self.rule.code.as_ref().is_none_or(|code| diagnostic.code.as_ref() == Some(code))
+            && self.path.as_ref().is_none_or(|glob| {
+                diagnostic.file.as_ref().is_some_and(|file| glob.is_match(&file.replace('\\', "/")))
+            })
+    }
+}
+
+/// Rules from `cargo-builder-suppressions.toml` that silence specific diagnostics.
+pub struct Suppressions {
+    rules: Vec<ActiveRule>,
+}
+
+/// Translate a path glob into an anchored regex: `**` spans directories, `*` and `?` don't.
+fn glob_to_regex(glob: &str) -> Result<Regex> {
+    let mut pattern = String::from("^");
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                if chars.peek() == Some(&'/') {
+                    chars.next();
+                    pattern.push_str("(?:.*/)?");
+                } else {
+                    pattern.push_str(".*");
+                }
+            }
+            '*' => pattern.push_str("[^/]*"),
+            '?' => pattern.push_str("[^/]"),
+            c => pattern.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    pattern.push('$');
+    Regex::new(&pattern).with_context(|| format!("Invalid path glob: {}", glob))
+}
+
+impl Suppressions {
+    /// Load the workspace's suppression file, if it has one.
+    pub fn load(workspace: &Workspace) -> Result<Option<Self>> {
+        let path = workspace.root.join(FILE_NAME);
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&content, SystemTime::now())
+            .with_context(|| format!("Invalid suppressions in {}", path.display()))
+            .map(Some)
+    }
+
+    fn parse(content: &str, now: SystemTime) -> Result<Self> {
+        let file: SuppressionFile = toml::from_str(content)?;
+        let mut rules = Vec::new();
+        for rule in file.suppress {
+            if rule.code.is_none() && rule.path.is_none() {
+                bail!("Every suppression needs a `code`, a `path` or both");
+            }
+            let expired = match &rule.expires {
+                Some(date) => {
+                    let expiry = humantime::parse_rfc3339(&format!("{}T00:00:00Z", date))
+                        .with_context(|| format!("Invalid expiry date `{}`; expected YYYY-MM-DD", date))?;
+                    now >= expiry
+                }
+                None => false,
+            };
+            let path = rule.path.as_deref().map(glob_to_regex).transpose()?;
+            rules.push(ActiveRule { rule, path, expired, hits: 0 });
+        }
+        Ok(Suppressions { rules })
+    }
+
+    /// Whether an unexpired rule silences `diagnostic`, counting the match.
+    pub fn suppresses(&mut self, diagnostic: &Diagnostic) -> bool {
+        match self.rules.iter_mut().find(|r| !r.expired && r.matches(diagnostic)) {
+            Some(rule) => {
+                rule.hits += 1;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Number of diagnostics silenced so far.
+    pub fn silenced(&self) -> usize {
+        self.rules.iter().map(|r| r.hits).sum()
+    }
+
+    /// How much was silenced, then the rules that matched nothing or have expired, so they can be cleaned up.
+    pub fn report(&self) -> Vec<String> {
+        let mut lines = Vec::new();
+        let silenced = self.silenced();
+        if silenced > 0 {
+            lines.push(format!("{} diagnostics silenced by {}", silenced, FILE_NAME));
+        }
+        for rule in &self.rules {
+            let reason = rule.rule.reason.as_ref().map(|r| format!(" ({})", r)).unwrap_or_default();
+            if rule.expired {
+                lines.push(format!(
+                    "Suppression of {}{} expired on {}",
+                    rule.rule.describe(), reason, rule.rule.expires.as_deref().unwrap_or(""),
+                ));
+            } else if rule.hits == 0 {
+                lines.push(format!("Suppression of {}{} matched nothing; remove it if it is stale", rule.rule.describe(), reason));
+            }
+        }
+        lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_glob_to_regex() {
+        let glob = glob_to_regex("src/generated/**").unwrap();
+        assert!(glob.is_match("src/generated/a/b.rs"));
+        assert!(!glob.is_match("src/lib.rs"));
+
+        let glob = glob_to_regex("**/tests/*.rs").unwrap();
+        assert!(glob.is_match("tests/it.rs"));
+        assert!(glob.is_match("crates/io/tests/it.rs"));
+        assert!(!glob.is_match("crates/io/tests/nested/it.rs"));
+    }
+
+    #[test]
+    fn test_suppresses_and_reports_stale_rules() {
+        let content = r#"
+[[suppress]]
+code = "dead_code"
+path = "src/generated/**"
+reason = "bindgen output"
+
+[[suppress]]
+code = "E0599"
+
+[[suppress]]
+path = "src/legacy.rs"
+expires = "2020-01-01"
+"#;
+        let mut suppressions = Suppressions::parse(content, SystemTime::UNIX_EPOCH + Duration::from_secs(1_700_000_000)).unwrap();
+
+        let never_used = Diagnostic::new("warning", "function is never used");
+        assert!(suppressions.suppresses(&never_used.clone().with_code("dead_code").at("src/generated/ffi.rs", 1, 1)));
+        assert!(!suppressions.suppresses(&never_used.clone().with_code("dead_code").at("src/main.rs", 1, 1)));
+        // Expired rules no longer apply
+        assert!(!suppressions.suppresses(&never_used.with_code("unused_imports").at("src/legacy.rs", 1, 1)));
+
+        assert_eq!(suppressions.report(), [
+            "1 diagnostics silenced by cargo-builder-suppressions.toml",
+            "Suppression of E0599 matched nothing; remove it if it is stale",
+            "Suppression of anything in src/legacy.rs expired on 2020-01-01",
+        ]);
+    }
+
+    #[test]
+    fn test_invalid_rules() {
+        let now = SystemTime::UNIX_EPOCH;
+        assert!(Suppressions::parse("[[suppress]]\nreason = \"no criteria\"\n", now).is_err());
+        assert!(Suppressions::parse("[[suppress]]\ncode = \"E0599\"\nexpires = \"soon\"\n", now).is_err());
+        assert!(Suppressions::parse("[[suppress]]\ncod = \"E0599\"\n", now).is_err());
+    }
+}
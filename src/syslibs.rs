@@ -0,0 +1,237 @@
+use crate::diagnostics::{self, Diagnostic};
+use crate::linker;
+use regex::Regex;
+use std::fs;
+
+/// A library or tool builds look for on the system, and its package with each
+/// package manager, if it has one there.
+struct Library {
+    name: &'static str,
+    /// What linkers, pkg-config and headers call it, lowercase
+    aliases: &'static [&'static str],
+    apt: Option<&'static str>,
+    dnf: Option<&'static str>,
+    brew: Option<&'static str>,
+    pkg: Option<&'static str>,
+}
+
+const fn library(name: &'static str, aliases: &'static [&'static str], apt: &'static str, dnf: &'static str, brew: Option<&'static str>, pkg: Option<&'static str>) -> Library {
+    Library { name, aliases, apt: Some(apt), dnf: Some(dnf), brew, pkg }
+}
+
+/// The ones `-sys` crates and build scripts most often stop on
+const LIBRARIES: &[Library] = &[
+    library("OpenSSL", &["openssl", "ssl", "crypto"], "libssl-dev", "openssl-devel", Some("openssl@3"), Some("openssl")),
+    library("pkg-config", &["pkg-config", "pkgconf"], "pkg-config", "pkgconf-pkg-config", Some("pkgconf"), Some("pkgconf")),
+    library("a C compiler", &["cc", "gcc"], "build-essential", "gcc", None, None),
+    library("cmake", &["cmake"], "cmake", "cmake", Some("cmake"), Some("cmake")),
+    library("protoc", &["protoc", "protobuf"], "protobuf-compiler", "protobuf-compiler", Some("protobuf"), Some("protobuf")),
+    library("libclang", &["libclang", "clang"], "libclang-dev", "clang-devel", Some("llvm"), Some("llvm")),
+    library("ALSA", &["alsa", "asound"], "libasound2-dev", "alsa-lib-devel", None, Some("alsa-lib")),
+    library("libudev", &["libudev", "udev"], "libudev-dev", "systemd-devel", None, Some("libudev-devd")),
+    library("libsystemd", &["libsystemd", "systemd"], "libsystemd-dev", "systemd-devel", None, None),
+    library("D-Bus", &["dbus-1", "dbus"], "libdbus-1-dev", "dbus-devel", Some("dbus"), Some("dbus")),
+    library("SQLite", &["sqlite3", "sqlite"], "libsqlite3-dev", "sqlite-devel", Some("sqlite"), Some("sqlite3")),
+    library("zlib", &["zlib", "z"], "zlib1g-dev", "zlib-devel", Some("zlib"), None),
+    library("libpq", &["libpq", "pq", "libpq-fe"], "libpq-dev", "libpq-devel", Some("libpq"), Some("postgresql17-client")),
+    library("libmysqlclient", &["mysqlclient", "mysql"], "libmysqlclient-dev", "mysql-devel", Some("mysql-client"), Some("mysql80-client")),
+    library("libcurl", &["libcurl", "curl"], "libcurl4-openssl-dev", "libcurl-devel", Some("curl"), Some("curl")),
+    library("libssh2", &["libssh2", "ssh2"], "libssh2-1-dev", "libssh2-devel", Some("libssh2"), Some("libssh2")),
+    library("libusb", &["libusb-1.0", "usb-1.0", "libusb"], "libusb-1.0-0-dev", "libusb1-devel", Some("libusb"), None),
+    library("hidapi", &["hidapi", "hidapi-hidraw", "hidapi-libusb"], "libhidapi-dev", "hidapi-devel", Some("hidapi"), Some("hidapi")),
+    library("FUSE", &["fuse3", "fuse"], "libfuse3-dev", "fuse3-devel", None, Some("fusefs-libs3")),
+    library("X11", &["x11"], "libx11-dev", "libX11-devel", Some("libx11"), Some("libX11")),
+    library("xkbcommon", &["xkbcommon"], "libxkbcommon-dev", "libxkbcommon-devel", Some("libxkbcommon"), Some("libxkbcommon")),
+    library("Wayland", &["wayland-client", "wayland"], "libwayland-dev", "wayland-devel", Some("wayland"), Some("wayland")),
+    library("fontconfig", &["fontconfig"], "libfontconfig1-dev", "fontconfig-devel", Some("fontconfig"), Some("fontconfig")),
+    library("FreeType", &["freetype2", "freetype", "ft2build"], "libfreetype-dev", "freetype-devel", Some("freetype"), Some("freetype2")),
+    library("GTK 3", &["gtk+-3.0", "gtk-3", "gtk"], "libgtk-3-dev", "gtk3-devel", Some("gtk+3"), Some("gtk3")),
+    library("GStreamer", &["gstreamer-1.0", "gstreamer", "gst"], "libgstreamer1.0-dev", "gstreamer1-devel", Some("gstreamer"), Some("gstreamer1")),
+];
+
+lazy_static::lazy_static! {
+    /// pkg-config, the `pkg-config` crate and C compilers saying what they didn't find
+    static ref NOT_FOUND: Regex = Regex::new(concat!(
+        r"The system library `([^`]+)` required by crate",
+        r"|^Package '?([\w.+-]+)'?(?:,| was not found)",
+        r"|fatal error: '?([\w./+-]+\.h)'?: No such file",
+        r"|fatal error: '([\w./+-]+\.h)' file not found",
+    )).unwrap();
+}
+
+/// What the usual build scripts and rustc say when a tool isn't there
+const TOOL_MESSAGES: &[(&str, &str)] = &[
+    ("Could not find directory of OpenSSL installation", "openssl"),
+    ("Could not find `protoc`", "protoc"),
+    ("Unable to find libclang", "libclang"),
+    ("is `cmake` not installed?", "cmake"),
+    ("The pkg-config command could not be found", "pkg-config"),
+    ("linker `cc` not found", "cc"),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Manager {
+    Apt,
+    Dnf,
+    Brew,
+    Pkg,
+}
+
+impl Manager {
+    fn package(self, library: &Library) -> Option<&'static str> {
+        match self {
+            Manager::Apt => library.apt,
+            Manager::Dnf => library.dnf,
+            Manager::Brew => library.brew,
+            Manager::Pkg => library.pkg,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Manager::Apt => "apt",
+            Manager::Dnf => "dnf",
+            Manager::Brew => "brew",
+            Manager::Pkg => "pkg",
+        }
+    }
+
+    fn install(self, package: &str) -> String {
+        match self {
+            Manager::Brew => format!("brew install {}", package),
+            manager => format!("sudo {} install {}", manager.name(), package),
+        }
+    }
+}
+
+/// The native libraries and tools a failed build was missing, from what the linker, build
+/// scripts and rustc said, for install hints after it.
+#[derive(Default)]
+pub struct Missing {
+    partial: String,
+    found: Vec<&'static Library>,
+}
+
+impl Missing {
+    /// cargo's stderr, with what failed build scripts printed
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.partial.push_str(&String::from_utf8_lossy(chunk));
+        while let Some(end) = self.partial.find(['\n', '\r']) {
+            let line: String = self.partial.drain(..=end).collect();
+            self.line(&diagnostics::strip_ansi_codes(&line));
+        }
+    }
+
+    /// An error reported on the JSON stream, like a failed link or a build script's `cargo::error=`
+    pub fn diagnostic(&mut self, diagnostic: &Diagnostic) {
+        for line in diagnostics::strip_ansi_codes(&diagnostic.rendered).lines() {
+            self.line(line);
+        }
+    }
+
+    fn line(&mut self, line: &str) {
+        let line = line.trim();
+        let name = TOOL_MESSAGES.iter().find(|(message, _)| line.contains(message)).map(|(_, name)| *name)
+            .or_else(|| linker::missing_library(line))
+            .or_else(|| NOT_FOUND.captures(line).and_then(|c| c.iter().skip(1).flatten().next()).map(|found| found.as_str()));
+        if let Some(library) = name.and_then(lookup) {
+            if !self.found.iter().any(|found| std::ptr::eq(*found, library)) {
+                self.found.push(library);
+            }
+        }
+    }
+
+    /// A line on how to install each, with this system's package manager, or all of them
+    /// when it isn't one of those known.
+    pub fn hints(&self) -> Vec<String> {
+        hints(&self.found, &managers())
+    }
+}
+
+fn hints(found: &[&Library], managers: &[Manager]) -> Vec<String> {
+    found.iter().filter_map(|library| {
+        let packages: Vec<(Manager, &str)> = managers.iter().filter_map(|manager| manager.package(library).map(|package| (*manager, package))).collect();
+        let how = match packages.as_slice() {
+            [] => return None,
+            [(manager, package)] => format!("install it with `{}`", manager.install(package)),
+            _ => format!("install {}", packages.iter().map(|(manager, package)| format!("{} ({})", package, manager.name())).collect::<Vec<_>>().join(", ")),
+        };
+        Some(format!("Missing {}; {}", library.name, how))
+    }).collect()
+}
+
+/// The library a linker, pkg-config or header name is, e.g. `ssl`, `alsa` or `X11/Xlib.h`
+fn lookup(name: &str) -> Option<&'static Library> {
+    let name = name.to_lowercase();
+    let mut candidates = vec![name.as_str()];
+    if let Some(header) = name.strip_suffix(".h") {
+        candidates.push(header.split('/').next().unwrap_or(header));
+        candidates.push(header.rsplit('/').next().unwrap_or(header));
+    }
+    candidates.iter().find_map(|candidate| LIBRARIES.iter().find(|library| library.aliases.contains(candidate)))
+}
+
+/// The package manager of this system, or all of them when it's not known
+fn managers() -> Vec<Manager> {
+    let known = match std::env::consts::OS {
+        "macos" => Some(Manager::Brew),
+        "freebsd" => Some(Manager::Pkg),
+        "linux" => {
+            let release = fs::read_to_string("/etc/os-release").unwrap_or_default();
+            let ids: Vec<&str> = release.lines()
+                .filter_map(|line| line.strip_prefix("ID=").or_else(|| line.strip_prefix("ID_LIKE=")))
+                .flat_map(|value| value.trim_matches('"').split_whitespace())
+                .collect();
+            if ids.iter().any(|id| matches!(*id, "debian" | "ubuntu")) {
+                Some(Manager::Apt)
+            } else if ids.iter().any(|id| matches!(*id, "fedora" | "rhel" | "centos")) {
+                Some(Manager::Dnf)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    known.map_or_else(|| vec![Manager::Apt, Manager::Dnf, Manager::Brew, Manager::Pkg], |manager| vec![manager])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_missing() {
+        let mut missing = Missing::default();
+        missing.feed(b"  --- stderr\n  The system library `alsa` required by crate `alsa-sys` was not found.\n  Package alsa was not found in the pkg-config search path.\n");
+        missing.feed(b"  src/ffi.c:1:10: fatal error: openssl/ssl.h: No such file or dir");
+        missing.feed(b"ectory\nPackage 'xkbcommon', required by 'virtual:world', not found\n");
+        missing.feed(b"  Could not find `protoc`. If `protoc` is installed, try setting the `PROTOC` environment variable\n");
+        let names: Vec<&str> = missing.found.iter().map(|library| library.name).collect();
+        assert_eq!(names, ["ALSA", "OpenSSL", "xkbcommon", "protoc"]);
+
+        let mut linked = Missing::default();
+        linked.diagnostic(&Diagnostic {
+            target_kind: vec!["bin".to_string()],
+            rendered: "error: linking with `cc` failed: exit status: 1\n  = note: /usr/bin/ld: cannot find -lasound: No such file or directory\n          /usr/bin/ld: cannot find -lfoo_private\n".to_string(),
+            ..Diagnostic::new("error", "linking with `cc` failed: exit status: 1")
+        });
+        assert_eq!(linked.found.len(), 1);
+        assert_eq!(lookup("X11/Xlib.h").map(|library| library.name), Some("X11"));
+        assert_eq!(lookup("libpq-fe.h").map(|library| library.name), Some("libpq"));
+    }
+
+    #[test]
+    fn test_hints() {
+        let alsa = lookup("asound").unwrap();
+        let openssl = lookup("ssl").unwrap();
+        assert_eq!(hints(&[alsa, openssl], &[Manager::Apt]), [
+            "Missing ALSA; install it with `sudo apt install libasound2-dev`",
+            "Missing OpenSSL; install it with `sudo apt install libssl-dev`",
+        ]);
+        // ALSA is Linux only
+        assert_eq!(hints(&[alsa, openssl], &[Manager::Brew]), ["Missing OpenSSL; install it with `brew install openssl@3`"]);
+        assert_eq!(hints(&[alsa], &[Manager::Apt, Manager::Dnf, Manager::Brew, Manager::Pkg]), [
+            "Missing ALSA; install libasound2-dev (apt), alsa-lib-devel (dnf), alsa-lib (pkg)",
+        ]);
+    }
+}
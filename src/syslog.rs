@@ -0,0 +1,162 @@
+use crate::diagnostics::Diagnostic;
+use crate::logging::LogSink;
+use anyhow::{Result, Context, bail};
+use std::net::{ToSocketAddrs, UdpSocket};
+use std::time::SystemTime;
+
+const APP_NAME: &str = "cargo-builder";
+
+/// Facility names accepted by `--syslog-facility`.
+pub const FACILITIES: [&str; 20] = [
+    "kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron",
+    "authpriv", "ftp", "local0", "local1", "local2", "local3", "local4", "local5", "local6", "local7",
+];
+
+fn facility_code(name: &str) -> Result<u8> {
+    match FACILITIES.iter().position(|f| *f == name) {
+        // local0..local7 are facilities 16..23; 12..15 are reserved
+        Some(i) if i >= 12 => Ok(i as u8 + 4),
+        Some(i) => Ok(i as u8),
+        None => bail!("Unknown syslog facility: {}", name),
+    }
+}
+
+fn severity(level: &str) -> u8 {
+    match level {
+        "error" => 3,   // err
+        "warning" => 4, // warning
+        _ => 5,         // notice
+    }
+}
+
+enum Transport {
+    Udp(UdpSocket),
+    #[cfg(unix)]
+    Local(std::os::unix::net::UnixDatagram),
+}
+
+/// Forwards logged diagnostics to syslog as RFC 5424 messages, either to the
+/// local daemon (`/dev/log`) or over UDP to `host:port`.
+pub struct SyslogSink {
+    transport: Transport,
+    facility: u8,
+    hostname: String,
+}
+
+impl SyslogSink {
+    pub fn connect(target: &str, facility: &str) -> Result<Self> {
+        let facility = facility_code(facility)?;
+        let transport = if target == "local" {
+            connect_local()?
+        } else {
+            let address = target.to_socket_addrs().ok().and_then(|mut addresses| addresses.next())
+                .with_context(|| format!("Failed to resolve syslog server: {}", target))?;
+            // A socket only sends to its own address family
+            let wildcard = if address.is_ipv6() { "[::]:0" } else { "0.0.0.0:0" };
+            let socket = UdpSocket::bind(wildcard)
+                .context("Failed to open UDP socket for syslog")?;
+            socket.connect(address)
+                .with_context(|| format!("Failed to connect to syslog server: {}", target))?;
+            Transport::Udp(socket)
+        };
+
+        Ok(SyslogSink { transport, facility, hostname: hostname() })
+    }
+
+    fn format(&self, diagnostic: &Diagnostic) -> String {
+        // <PRI>VERSION TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA MSG
+        format!(
+            "<{}>1 {} {} {} {} {} - {}",
+            self.facility as u16 * 8 + severity(&diagnostic.level) as u16,
+            humantime::format_rfc3339_seconds(SystemTime::now()),
+            self.hostname,
+            APP_NAME,
+            std::process::id(),
+            diagnostic.code.as_deref().unwrap_or("-"),
+            diagnostic.summary(),
+        )
+    }
+}
+
+#[cfg(unix)]
+fn connect_local() -> Result<Transport> {
+    let socket = std::os::unix::net::UnixDatagram::unbound()?;
+    socket.connect("/dev/log")
+        .context("Failed to connect to the local syslog daemon at /dev/log")?;
+    Ok(Transport::Local(socket))
+}
+
+#[cfg(not(unix))]
+fn connect_local() -> Result<Transport> {
+    bail!("Local syslog is only available on Unix; pass a host:port instead")
+}
+
+fn hostname() -> String {
+    std::env::var("HOSTNAME").ok()
+        .or_else(|| std::fs::read_to_string("/proc/sys/kernel/hostname").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .map(|h| h.trim().to_string())
+        .filter(|h| !h.is_empty() && !h.contains(' '))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+impl LogSink for SyslogSink {
+    fn log_diagnostic(&mut self, diagnostic: &Diagnostic) -> Result<()> {
+        let message = self.format(diagnostic);
+        match &self.transport {
+            Transport::Udp(socket) => socket.send(message.as_bytes()),
+            #[cfg(unix)]
+            Transport::Local(socket) => socket.send(message.as_bytes()),
+        }
+        .context("Failed to send message to syslog")?;
+        Ok(())
+    }
+
+    fn finalize(self: Box<Self>, _build_success: bool) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_facility_codes() {
+        assert_eq!(facility_code("kern").unwrap(), 0);
+        assert_eq!(facility_code("user").unwrap(), 1);
+        assert_eq!(facility_code("local0").unwrap(), 16);
+        assert_eq!(facility_code("local7").unwrap(), 23);
+        assert!(facility_code("nope").is_err());
+    }
+
+    #[test]
+    fn test_sends_rfc5424_over_udp() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = server.local_addr().unwrap().to_string();
+
+        let mut sink = SyslogSink::connect(&address, "local3").unwrap();
+        sink.log_diagnostic(&Diagnostic::new("error", "cannot find value `x` in this scope").with_code("E0425").at("src/main.rs", 2, 5)).unwrap();
+
+        let mut buf = [0u8; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        let message = String::from_utf8_lossy(&buf[..len]);
+        // local3 (19) * 8 + err (3)
+        assert!(message.starts_with("<155>1 "), "{}", message);
+        assert!(message.contains(" cargo-builder "));
+        assert!(message.ends_with(" E0425 - error[E0425]: cannot find value `x` in this scope (src/main.rs:2:5)"));
+    }
+
+    #[test]
+    fn test_sends_over_ipv6() {
+        // Hosts without IPv6 have nothing to test
+        let Ok(server) = UdpSocket::bind("[::1]:0") else { return };
+        let address = server.local_addr().unwrap().to_string();
+
+        let mut sink = SyslogSink::connect(&address, "user").unwrap();
+        sink.log_diagnostic(&Diagnostic::new("warning", "unused variable: `x`")).unwrap();
+        let mut buf = [0u8; 1024];
+        let len = server.recv(&mut buf).unwrap();
+        assert!(String::from_utf8_lossy(&buf[..len]).ends_with(" - - warning: unused variable: `x`"));
+    }
+}
@@ -0,0 +1,264 @@
+use crate::{logging, util, ColorChoice};
+use anyhow::{Result, Context};
+use clap::{Arg, Command};
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+/// Enough of the file to cover the header's `Date:` line, which differs between runs
+const HEAD_LEN: usize = 128;
+
+const RED: &str = "\x1b[1m\x1b[91m";
+const YELLOW: &str = "\x1b[1m\x1b[93m";
+const BLUE: &str = "\x1b[1m\x1b[94m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// Incrementally reads lines appended to a log, starting over when the log is
+/// replaced by a new run.
+struct Follower {
+    path: PathBuf,
+    offset: u64,
+    head: Vec<u8>,
+    partial: Vec<u8>,
+}
+
+impl Follower {
+    fn new(path: PathBuf) -> Self {
+        Follower { path, offset: 0, head: Vec::new(), partial: Vec::new() }
+    }
+
+    /// Complete lines written since the last poll; a missing log yields nothing.
+    fn poll(&mut self) -> Result<Vec<String>> {
+        let mut file = match File::open(&self.path) {
+            Ok(file) => file,
+            Err(_) => {
+                self.reset();
+                return Ok(Vec::new());
+            }
+        };
+
+        let len = file.metadata()?.len();
+        let mut head = Vec::new();
+        (&mut file).take(HEAD_LEN as u64).read_to_end(&mut head)?;
+        // A shorter file or a different header means a new run truncated or replaced the log
+        if len < self.offset || !head.starts_with(&self.head[..self.head.len().min(head.len())]) {
+            self.reset();
+        }
+        self.head = head;
+
+        if len == self.offset {
+            return Ok(Vec::new());
+        }
+        file.seek(SeekFrom::Start(self.offset))?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .with_context(|| format!("Failed to read {}", self.path.display()))?;
+        self.offset += data.len() as u64;
+
+        self.partial.extend_from_slice(&data);
+        let Some(end) = self.partial.iter().rposition(|&b| b == b'\n') else {
+            return Ok(Vec::new());
+        };
+        let complete: Vec<u8> = self.partial.drain(..=end).collect();
+        Ok(String::from_utf8_lossy(&complete).lines().map(str::to_string).collect())
+    }
+
+    fn reset(&mut self) {
+        self.offset = 0;
+        self.head.clear();
+        self.partial.clear();
+    }
+}
+
+/// Re-apply rustc-style colors to a line of a plain-text log.
+fn colorize_line(line: &str) -> String {
+    if line.contains('\x1b') {
+        return line.to_string(); // Logged with --log-color always
+    }
+
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    for (prefix, color) in [("error", RED), ("warning", YELLOW)] {
+        if let Some(rest) = line.strip_prefix(prefix) {
+            let code_end = if rest.starts_with('[') { rest.find(']').map_or(0, |i| i + 1) } else { 0 };
+            if rest[code_end..].starts_with(':') {
+                let (code, message) = rest.split_at(code_end);
+                return format!("{}{}{}{}{}{}{}", color, prefix, code, RESET, BOLD, message, RESET);
+            }
+        }
+    }
+    for arrow in ["-->", ":::"] {
+        if let Some(rest) = trimmed.strip_prefix(arrow) {
+            return format!("{}{}{}{}{}", indent, BLUE, arrow, RESET, rest);
+        }
+    }
+    if let Some(rest) = trimmed.strip_prefix("= ") {
+        if let Some((label, text)) = rest.split_once(':') {
+            if matches!(label, "note" | "help") {
+                return format!("{}{}={} {}{}:{}{}", indent, BLUE, RESET, BOLD, label, RESET, text);
+            }
+        }
+    }
+    // Source gutter: `12 | code`, `   |`
+    if let Some(bar) = line.find('|') {
+        let gutter = &line[..bar];
+        if gutter.trim().chars().all(|c| c.is_ascii_digit()) && !gutter.is_empty() {
+            return format!("{}{}|{}{}", BLUE, gutter, RESET, &line[bar + 1..]);
+        }
+    }
+    line.to_string()
+}
+
+/// The log a build is most likely writing: the newest of the default logs of each
+/// profile and the `--log-per-run` logs. Compressed logs cannot be followed.
+fn default_log(workspace: &util::Workspace) -> PathBuf {
+    let default = workspace.target_directory.join(logging::default_log_name("dev"));
+    let mut candidates = vec![default.clone()];
+    if let Ok(entries) = fs::read_dir(&workspace.target_directory) {
+        // `build-errors-release.log`, but not rotated or per-target ones like `build-errors-release.1.log`
+        candidates.extend(entries.flatten().map(|entry| entry.path()).filter(|p| {
+            p.file_name().and_then(|name| name.to_str()).is_some_and(|name| {
+                name.strip_prefix("build-errors-").and_then(|rest| rest.strip_suffix(".log")).is_some_and(|profile| !profile.contains('.'))
+            })
+        }));
+    }
+    if let Ok(entries) = fs::read_dir(workspace.state_dir().join("logs")) {
+        candidates.extend(entries.flatten().map(|entry| entry.path()).filter(|p| p.extension().is_some_and(|e| e == "log")));
+    }
+
+    candidates.into_iter()
+        .filter_map(|path| Some((fs::metadata(&path).ok()?.modified().ok()?, path)))
+        .max_by_key(|(modified, _)| *modified)
+        .map_or(default, |(_, path)| path)
+}
+
+fn print_lines(lines: &[String], color: bool) {
+    for line in lines {
+        if color {
+            eprintln!("{}", colorize_line(line));
+        } else {
+            eprintln!("{}", line);
+        }
+    }
+}
+
+pub fn run(args: &[String]) -> Result<i32> {
+    let matches = Command::new("cargo-builder tail")
+        .about("Follow the workspace's error log while a build writes it")
+        .arg(
+            Arg::new("log")
+                .long("log")
+                .value_name("PATH")
+                .help("Log to follow (default: the most recently written build log)")
+        )
+        .arg(
+            Arg::new("lines")
+                .short('n')
+                .long("lines")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .help("Start with the last N lines instead of the whole log")
+        )
+        .arg(
+            Arg::new("terminal-color")
+                .long("terminal-color")
+                .value_name("CHOICE")
+                .value_parser(["auto", "never", "always"])
+                .default_value("auto")
+                .help("Color control for terminal output")
+        )
+        .try_get_matches_from(std::iter::once("cargo-builder tail".to_string()).chain(args.iter().cloned()))
+        .unwrap_or_else(|e| e.exit());
+
+    let color = match matches.get_one::<String>("terminal-color").unwrap().parse()? {
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+        ColorChoice::Auto => crate::term::should_use_color(),
+    };
+    let explicit = matches.get_one::<String>("log").map(PathBuf::from);
+    let workspace = match explicit {
+        Some(_) => None,
+        None => Some(util::find_workspace()?),
+    };
+    let resolve = || explicit.clone().unwrap_or_else(|| default_log(workspace.as_ref().unwrap()));
+
+    let mut follower = Follower::new(resolve());
+    if !follower.path.exists() {
+        eprintln!("cargo-builder: Waiting for {}...", follower.path.display());
+    }
+    let initial = follower.poll()?;
+    let skip = matches.get_one::<usize>("lines").map_or(0, |n| initial.len().saturating_sub(*n));
+    print_lines(&initial[skip..], color);
+
+    let mut last_seen = SystemTime::now();
+    loop {
+        thread::sleep(POLL_INTERVAL);
+
+        // Pick up a new per-run log once a build starts writing it
+        let path = resolve();
+        if path != follower.path && modified(&path).is_some_and(|m| m >= last_seen) {
+            eprintln!("cargo-builder: Following {}", path.display());
+            follower = Follower::new(path);
+        }
+        if let Some(m) = modified(&follower.path) {
+            last_seen = last_seen.max(m);
+        }
+
+        print_lines(&follower.poll()?, color);
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).ok()?.modified().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_colorize_line() {
+        assert_eq!(
+            colorize_line("error[E0425]: cannot find value"),
+            "\x1b[1m\x1b[91merror[E0425]\x1b[0m\x1b[1m: cannot find value\x1b[0m"
+        );
+        assert_eq!(colorize_line(" --> src/main.rs:2:5"), " \x1b[1m\x1b[94m-->\x1b[0m src/main.rs:2:5");
+        assert_eq!(colorize_line("2 |     let x = y;"), "\x1b[1m\x1b[94m2 |\x1b[0m     let x = y;");
+        assert_eq!(colorize_line("Command:  cargo build"), "Command:  cargo build");
+        assert_eq!(colorize_line("errors found: 2"), "errors found: 2");
+    }
+
+    #[test]
+    fn test_follower_reads_appended_lines() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("build-errors.log");
+        let mut follower = Follower::new(path.clone());
+        assert!(follower.poll().unwrap().is_empty());
+
+        let mut file = File::create(&path).unwrap();
+        write!(file, "header\nerror: one\npart").unwrap();
+        assert_eq!(follower.poll().unwrap(), ["header", "error: one"]);
+
+        writeln!(file, "ial").unwrap();
+        assert_eq!(follower.poll().unwrap(), ["partial"]);
+    }
+
+    #[test]
+    fn test_follower_restarts_on_new_run() {
+        let temp_dir = TempDir::new().unwrap();
+        let path = temp_dir.path().join("build-errors.log");
+        fs::write(&path, "Date: 1\nerror: old\n").unwrap();
+        let mut follower = Follower::new(path.clone());
+        assert_eq!(follower.poll().unwrap().len(), 2);
+
+        // Same length, different header
+        fs::write(&path, "Date: 2\nerror: new\n").unwrap();
+        assert_eq!(follower.poll().unwrap(), ["Date: 2", "error: new"]);
+    }
+}
@@ -36,30 +36,38 @@ fn is_terminal() -> bool {
 mod tests {
     use super::*;
     use std::env;
+    use std::sync::Mutex;
+
+    // The color tests mutate shared environment variables, so they must not run in parallel
+    static ENV_LOCK: Mutex<()> = Mutex::new(());
 
     #[test]
     fn test_no_color_env_var() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         env::set_var("NO_COLOR", "1");
-        assert_eq!(should_use_color(), false);
+        assert!(!should_use_color());
         env::remove_var("NO_COLOR");
     }
 
     #[test]
     fn test_cargo_term_color_always() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         env::set_var("CARGO_TERM_COLOR", "always");
-        assert_eq!(should_use_color(), true);
+        assert!(should_use_color());
         env::remove_var("CARGO_TERM_COLOR");
     }
 
     #[test]
     fn test_cargo_term_color_never() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         env::set_var("CARGO_TERM_COLOR", "never");
-        assert_eq!(should_use_color(), false);
+        assert!(!should_use_color());
         env::remove_var("CARGO_TERM_COLOR");
     }
 
     #[test]
     fn test_term_dumb() {
+        let _guard = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         // Temporarily remove other color vars
         let no_color = env::var("NO_COLOR");
         let cargo_color = env::var("CARGO_TERM_COLOR");
@@ -68,7 +76,7 @@ mod tests {
         env::remove_var("CARGO_TERM_COLOR");
         
         env::set_var("TERM", "dumb");
-        assert_eq!(should_use_color(), false);
+        assert!(!should_use_color());
         
         env::remove_var("TERM");
         
@@ -0,0 +1,340 @@
+use crate::{ci, diagnostics, logging};
+use crate::util::{self, Workspace};
+use anyhow::{Result, Context};
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// Crates listed in the terminal; the log has all of them.
+const LISTED_CRATES: usize = 10;
+/// Units listed in the `--timings` summary.
+const LISTED_UNITS: usize = 5;
+
+pub fn log_path(workspace: &Workspace) -> PathBuf {
+    workspace.state_dir().join("crate-timings.log")
+}
+
+/// Compile time of one package, summed over its units (lib, bins, build script, ...).
+#[derive(Debug, PartialEq)]
+pub struct CrateTime {
+    pub package_id: String,
+    pub duration: Duration,
+    pub units: usize,
+}
+
+impl CrateTime {
+    /// `name vX.Y.Z`, or the name alone if the id has no version.
+    pub fn label(&self) -> String {
+        let name = diagnostics::package_name(&self.package_id);
+        match diagnostics::package_version(&self.package_id) {
+            Some(version) => format!("{} v{}", name, version),
+            None => name.to_string(),
+        }
+    }
+}
+
+/// Per-unit compile times collected from cargo's messages during a build.
+///
+/// Stable cargo only says when a unit finished, so each unit is credited
+/// with the time since the previous one finished. That is exact with `-j1`;
+/// in parallel builds, work done alongside is credited to whichever unit
+/// finishes. Exact times from nightly cargo's `-Zunstable-options --timings=json`
+/// or from a `--timings` report replace the estimates.
+pub struct CrateTimings {
+    last_finish: Instant,
+    units: Vec<(String, Duration)>,
+    exact: bool,
+}
+
+impl CrateTimings {
+    pub fn new(started: Instant) -> Self {
+        CrateTimings { last_finish: started, units: Vec::new(), exact: false }
+    }
+
+    /// A `compiler-artifact` message arrived at `now`.
+    pub fn artifact(&mut self, package_id: &str, fresh: bool, now: Instant) {
+        if !self.exact && !fresh {
+            self.units.push((package_id.to_string(), now.saturating_duration_since(self.last_finish)));
+        }
+        self.last_finish = now;
+    }
+
+    /// A `timing-info` message with the exact time of a unit.
+    pub fn timing_info(&mut self, package_id: &str, duration: Duration) {
+        if !self.exact {
+            self.units.clear();
+            self.exact = true;
+        }
+        self.units.push((package_id.to_string(), duration));
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.exact
+    }
+
+    /// Crates by total compile time, slowest first.
+    pub fn ranking(&self) -> Vec<CrateTime> {
+        let mut crates: HashMap<&str, CrateTime> = HashMap::new();
+        for (package_id, duration) in &self.units {
+            let entry = crates.entry(package_id).or_insert_with(|| CrateTime {
+                package_id: package_id.clone(),
+                duration: Duration::ZERO,
+                units: 0,
+            });
+            entry.duration += *duration;
+            entry.units += 1;
+        }
+
+        let mut ranking: Vec<CrateTime> = crates.into_values().collect();
+        ranking.sort_by(|a, b| b.duration.cmp(&a.duration).then_with(|| a.package_id.cmp(&b.package_id)));
+        ranking
+    }
+}
+
+/// Table rows, e.g. `   1.    12.3s  syn v2.0.48 (2 units)`.
+pub fn format_table(ranking: &[CrateTime]) -> Vec<String> {
+    ranking.iter()
+        .enumerate()
+        .map(|(i, time)| format!(
+            "{:>4}.  {:>8}  {} ({} unit{})",
+            i + 1,
+            util::format_duration(time.duration),
+            time.label(),
+            time.units,
+            if time.units == 1 { "" } else { "s" },
+        ))
+        .collect()
+}
+
+/// Print the slowest crates and write the full ranking to `crate-timings.log`,
+/// also printing all of it as a collapsed group when `grouped`.
+pub fn report(workspace: &Workspace, timings: &CrateTimings, command: &str, grouped: bool) -> Result<()> {
+    let ranking = timings.ranking();
+    if ranking.is_empty() {
+        eprintln!("cargo-builder: No crates were compiled");
+        return Ok(());
+    }
+
+    let source = if timings.is_exact() {
+        "reported by cargo"
+    } else {
+        "estimated from when units finished"
+    };
+    let table = format_table(&ranking);
+    eprintln!("cargo-builder: Slowest crates ({}):", source);
+    for row in table.iter().take(LISTED_CRATES) {
+        eprintln!("{}", row);
+    }
+
+    let path = log_path(workspace);
+    fs::create_dir_all(workspace.state_dir())
+        .with_context(|| format!("Failed to create state directory: {}", workspace.state_dir().display()))?;
+    let content = format!("Crate compile times for `{}`, {}\n\n{}\n", command, source, table.join("\n"));
+    fs::write(&path, content)
+        .with_context(|| format!("Failed to write crate timings: {}", path.display()))?;
+    eprintln!("cargo-builder: All {} crates written to {}", ranking.len(), path.display());
+    // The file stays behind on the runner
+    if grouped && ranking.len() > LISTED_CRATES {
+        ci::print_group(&format!("All {} crates", ranking.len()), &table.join("\n"));
+    }
+    Ok(())
+}
+
+/// A unit from the `UNIT_DATA` of cargo's `--timings` report; times are in seconds.
+#[derive(Debug, Deserialize)]
+pub struct ReportUnit {
+    pub name: String,
+    pub version: String,
+    /// e.g. `""` for a lib, ` app "bin"` or ` build-script (run)`, as cargo lists it
+    pub target: String,
+    pub start: f64,
+    pub duration: f64,
+}
+
+impl ReportUnit {
+    pub fn label(&self) -> String {
+        format!("{} v{}{}", self.name, self.version, self.target)
+    }
+}
+
+/// The HTML report written by `cargo build --timings`, moved to the log directory.
+pub struct TimingReport {
+    pub path: PathBuf,
+    pub units: Vec<ReportUnit>,
+    /// `-j` of the build
+    pub jobs: Option<usize>,
+}
+
+impl TimingReport {
+    fn parse(path: PathBuf, html: &str) -> Result<Self> {
+        // The report embeds its data as JavaScript constants in JSON syntax
+        let data = Regex::new(r"(?s)const UNIT_DATA = (\[.*?\n\]);").unwrap();
+        let units = data.captures(html)
+            .context("No unit data in the timing report")?;
+        let units = serde_json::from_str(&units[1])
+            .context("Malformed unit data in the timing report")?;
+        let jobs = Regex::new(r"jobs=(\d+)").unwrap()
+            .captures(html)
+            .and_then(|c| c[1].parse().ok());
+        Ok(TimingReport { path, units, jobs })
+    }
+
+    /// Wall time from the start of the build until the last unit finished.
+    pub fn total(&self) -> Duration {
+        let end = self.units.iter().map(|u| u.start + u.duration).fold(0.0, f64::max);
+        Duration::try_from_secs_f64(end).unwrap_or_default()
+    }
+
+    /// Share of the available job slots that were busy compiling.
+    pub fn utilization(&self) -> Option<f64> {
+        let busy: f64 = self.units.iter().map(|u| u.duration).sum();
+        let available = self.total().as_secs_f64() * self.jobs? as f64;
+        (available > 0.0).then(|| busy / available)
+    }
+
+    /// Total time and parallelism, e.g. `42.1s total, 63% of 8 jobs used`.
+    pub fn headline(&self) -> String {
+        let mut headline = format!("{} total", util::format_duration(self.total()));
+        if let (Some(jobs), Some(utilization)) = (self.jobs, self.utilization()) {
+            headline.push_str(&format!(", {:.0}% of {} jobs used", utilization * 100.0, jobs));
+        }
+        headline
+    }
+
+    /// Table rows of the slowest units.
+    pub fn slowest_units(&self) -> Vec<String> {
+        let mut units: Vec<&ReportUnit> = self.units.iter().collect();
+        units.sort_by(|a, b| b.duration.total_cmp(&a.duration));
+        units.iter()
+            .take(LISTED_UNITS)
+            .enumerate()
+            .map(|(i, unit)| format!(
+                "{:>4}.  {:>8}  {}",
+                i + 1,
+                util::format_duration(Duration::try_from_secs_f64(unit.duration).unwrap_or_default()),
+                unit.label(),
+            ))
+            .collect()
+    }
+
+    /// Replace the estimates of `--crate-timings` with the report's exact times.
+    pub fn feed(&self, timings: &mut CrateTimings) {
+        for unit in &self.units {
+            let duration = Duration::try_from_secs_f64(unit.duration).unwrap_or_default();
+            timings.timing_info(&format!("{} {}", unit.name, unit.version), duration);
+        }
+    }
+}
+
+/// Newest `cargo-timing-*.html` written since `since`.
+fn find_report(dir: &Path, since: SystemTime) -> Option<PathBuf> {
+    fs::read_dir(dir).ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            name.starts_with("cargo-timing-") && name.ends_with(".html")
+        })
+        .filter(|entry| entry.metadata().and_then(|m| m.modified()).is_ok_and(|modified| modified >= since))
+        .map(|entry| entry.path())
+        .max()
+}
+
+/// Move the report cargo wrote during this build into `target/cargo-builder/logs/`
+/// and point `target/cargo-builder/latest-timing.html` at it.
+pub fn collect_report(workspace: &Workspace, since: SystemTime) -> Result<Option<TimingReport>> {
+    let Some(written) = find_report(&workspace.target_directory.join("cargo-timings"), since) else {
+        return Ok(None);
+    };
+
+    let logs = workspace.state_dir().join("logs");
+    fs::create_dir_all(&logs)
+        .with_context(|| format!("Failed to create log directory: {}", logs.display()))?;
+    let path = logs.join(written.file_name().expect("report has a file name"));
+    if fs::rename(&written, &path).is_err() {
+        // The target directory may be on another filesystem
+        fs::copy(&written, &path)
+            .with_context(|| format!("Failed to copy {} to {}", written.display(), path.display()))?;
+        let _ = fs::remove_file(&written);
+    }
+    logging::update_latest(&workspace.state_dir().join("latest-timing.html"), &path)?;
+
+    let html = fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read timing report: {}", path.display()))?;
+    TimingReport::parse(path, &html).map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CORE: &str = "path+file:///ws/core#0.1.0";
+    const SYN: &str = "registry+https://github.com/rust-lang/crates.io-index#syn@2.0.48";
+
+    #[test]
+    fn test_estimates_from_artifacts() {
+        let start = Instant::now();
+        let mut timings = CrateTimings::new(start);
+        timings.artifact(SYN, true, start + Duration::from_millis(100));
+        timings.artifact(SYN, false, start + Duration::from_secs(3));
+        timings.artifact(CORE, false, start + Duration::from_secs(4));
+        timings.artifact(SYN, false, start + Duration::from_secs(5));
+
+        let ranking = timings.ranking();
+        assert_eq!(ranking[0].label(), "syn v2.0.48");
+        assert_eq!(ranking[0].duration, Duration::from_millis(3900));
+        assert_eq!(ranking[0].units, 2);
+        assert_eq!(format_table(&ranking), [
+            "   1.      3.9s  syn v2.0.48 (2 units)",
+            "   2.      1.0s  core v0.1.0 (1 unit)",
+        ]);
+    }
+
+    #[test]
+    fn test_timing_info_replaces_estimates() {
+        let start = Instant::now();
+        let mut timings = CrateTimings::new(start);
+        timings.artifact(CORE, false, start + Duration::from_secs(9));
+        timings.timing_info(SYN, Duration::from_secs(2));
+        timings.artifact(SYN, false, start + Duration::from_secs(10));
+        timings.timing_info(CORE, Duration::from_secs(1));
+
+        assert!(timings.is_exact());
+        let ranking = timings.ranking();
+        assert_eq!(ranking.iter().map(|t| t.duration.as_secs()).collect::<Vec<_>>(), [2, 1]);
+    }
+
+    const REPORT: &str = r#"<td>Max concurrency:</td><td>2 (jobs=2 ncpu=8)</td>
+<script>
+DURATION = 5;
+const UNIT_DATA = [
+  {"i": 0, "name": "core", "version": "0.1.0", "mode": "todo", "target": " build-script", "start": 0.0, "duration": 1.0, "unblocked_units": [], "unblocked_rmeta_units": [], "sections": null},
+  {"i": 1, "name": "syn", "version": "2.0.48", "mode": "todo", "target": "", "start": 0.0, "duration": 3.0, "unblocked_units": [], "unblocked_rmeta_units": [], "sections": null},
+  {"i": 2, "name": "core", "version": "0.1.0", "mode": "todo", "target": "", "start": 3.0, "duration": 1.0, "unblocked_units": [], "unblocked_rmeta_units": [], "sections": null}
+];
+const CONCURRENCY_DATA = [];
+</script>"#;
+
+    #[test]
+    fn test_parse_report() {
+        let report = TimingReport::parse(PathBuf::from("cargo-timing.html"), REPORT).unwrap();
+        assert_eq!(report.jobs, Some(2));
+        assert_eq!(report.total(), Duration::from_secs(4));
+        assert_eq!(report.utilization(), Some(5.0 / 8.0));
+        assert_eq!(report.headline(), "4.0s total, 62% of 2 jobs used");
+        assert_eq!(report.slowest_units(), [
+            "   1.      3.0s  syn v2.0.48",
+            "   2.      1.0s  core v0.1.0 build-script",
+            "   3.      1.0s  core v0.1.0",
+        ]);
+
+        let mut timings = CrateTimings::new(Instant::now());
+        report.feed(&mut timings);
+        assert_eq!(format_table(&timings.ranking()), [
+            "   1.      3.0s  syn v2.0.48 (1 unit)",
+            "   2.      2.0s  core v0.1.0 (2 units)",
+        ]);
+    }
+}
@@ -0,0 +1,120 @@
+use crate::store::RunRecord;
+use std::collections::BTreeSet;
+
+/// Change in warnings since the last comparable run.
+#[derive(Debug)]
+pub struct WarningTrend<'a> {
+    pub previous: &'a RunRecord,
+    pub delta: i64,
+    /// Packages whose warning count changed, by name
+    pub packages: Vec<(String, i64)>,
+}
+
+/// Whether `run` reported all of its warnings: builds that stop at an error
+/// don't get to, and builds without `--include-warnings` drop them.
+fn comparable(run: &RunRecord) -> bool {
+    run.errors == 0 && run.warnings_counted
+}
+
+/// Compare `current` with the last earlier run of the same command on the same branch.
+pub fn compare<'a>(history: &'a [RunRecord], current: &RunRecord) -> Option<WarningTrend<'a>> {
+    if !comparable(current) {
+        return None;
+    }
+    let previous = history.iter().rev().find(|run| {
+        run.id < current.id
+            && comparable(run)
+            && run.branch == current.branch
+            && run.command == current.command
+    })?;
+
+    let names: BTreeSet<&String> = previous.package_warnings.keys()
+        .chain(current.package_warnings.keys())
+        .collect();
+    let count = |run: &RunRecord, name: &String| run.package_warnings.get(name).copied().unwrap_or(0) as i64;
+    let packages = names.into_iter()
+        .map(|name| (name.clone(), count(current, name) - count(previous, name)))
+        .filter(|(_, delta)| *delta != 0)
+        .collect();
+
+    Some(WarningTrend {
+        previous,
+        delta: current.warnings as i64 - previous.warnings as i64,
+        packages,
+    })
+}
+
+impl WarningTrend<'_> {
+    /// e.g. `+4 warnings vs last run on main (#12): app +3, core +1`
+    pub fn describe(&self) -> String {
+        let since = match &self.previous.branch {
+            Some(branch) => format!("last run on {} (#{})", branch, self.previous.id),
+            None => format!("last run (#{})", self.previous.id),
+        };
+        if self.delta == 0 && self.packages.is_empty() {
+            return format!("No change in warnings vs {}", since);
+        }
+
+        let mut text = format!("{:+} warnings vs {}", self.delta, since);
+        if !self.packages.is_empty() {
+            let packages: Vec<String> = self.packages.iter()
+                .map(|(name, delta)| format!("{} {:+}", name, delta))
+                .collect();
+            text.push_str(&format!(": {}", packages.join(", ")));
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(id: u64, branch: &str, errors: usize, packages: &[(&str, usize)]) -> RunRecord {
+        RunRecord {
+            id,
+            timestamp: 1_700_000_000 + id,
+            command: "cargo build".to_string(),
+            profile: None,
+            duration_ms: 1000,
+            success: errors == 0,
+            errors,
+            warnings: packages.iter().map(|(_, n)| n).sum(),
+            fingerprints: Vec::new(),
+            branch: Some(branch.to_string()),
+            commit: None,
+            dirty: false,
+            warnings_counted: true,
+            package_warnings: packages.iter().map(|(name, n)| (name.to_string(), *n)).collect(),
+            binary_sizes: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_compares_with_last_clean_run_on_branch() {
+        let history = [
+            run(1, "main", 0, &[("app", 2), ("core", 1)]),
+            run(2, "feature", 0, &[("app", 9)]),
+            run(3, "main", 1, &[("app", 0)]),
+        ];
+        let current = run(4, "main", 0, &[("app", 5), ("util", 1)]);
+
+        let trend = compare(&history, &current).unwrap();
+        assert_eq!(trend.previous.id, 1);
+        assert_eq!(trend.delta, 3);
+        assert_eq!(trend.describe(), "+3 warnings vs last run on main (#1): app +3, core -1, util +1");
+    }
+
+    #[test]
+    fn test_no_trend() {
+        let history = [run(1, "main", 0, &[("app", 2)])];
+        assert_eq!(
+            compare(&history, &run(2, "main", 0, &[("app", 2)])).unwrap().describe(),
+            "No change in warnings vs last run on main (#1)",
+        );
+        assert!(compare(&history, &run(2, "main", 1, &[])).is_none());
+        assert!(compare(&history, &run(2, "other", 0, &[])).is_none());
+        let allowed = RunRecord { warnings_counted: false, ..run(2, "main", 0, &[]) };
+        assert!(compare(&history, &allowed).is_none());
+    }
+}
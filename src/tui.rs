@@ -0,0 +1,128 @@
+use anyhow::Result;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyEvent, KeyEventKind};
+use crossterm::style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use std::io::{self, Stdout, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Style {
+    Normal,
+    Title,
+    Selected,
+    Error,
+    Warning,
+    Dim,
+}
+
+#[derive(Debug, Clone)]
+pub struct Line {
+    pub text: String,
+    pub style: Style,
+}
+
+impl Line {
+    pub fn new(text: impl Into<String>, style: Style) -> Self {
+        Line { text: text.into(), style }
+    }
+}
+
+/// Full-screen terminal session; raw mode and the alternate screen are
+/// restored when dropped, including on early returns via `?`.
+pub struct Terminal {
+    stdout: Stdout,
+}
+
+impl Terminal {
+    pub fn new() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        let mut stdout = io::stdout();
+        execute!(stdout, EnterAlternateScreen, Hide)?;
+        Ok(Terminal { stdout })
+    }
+
+    /// Number of rows available for content between the title and the footer.
+    pub fn body_height(&self) -> Result<usize> {
+        let (_, rows) = terminal::size()?;
+        Ok((rows as usize).saturating_sub(2))
+    }
+
+    /// Draw a frame: a title row, the body lines, and a footer pinned to the last row.
+    pub fn draw(&mut self, title: &str, body: &[Line], footer: &str) -> Result<()> {
+        let (cols, rows) = terminal::size()?;
+        let width = cols as usize;
+
+        queue!(self.stdout, Clear(ClearType::All))?;
+        self.draw_line(0, &Line::new(title, Style::Title), width)?;
+        for (i, line) in body.iter().take((rows as usize).saturating_sub(2)).enumerate() {
+            self.draw_line(i as u16 + 1, line, width)?;
+        }
+        self.draw_line(rows.saturating_sub(1), &Line::new(footer, Style::Dim), width)?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    fn draw_line(&mut self, row: u16, line: &Line, width: usize) -> Result<()> {
+        let text: String = line.text.chars().take(width).collect();
+        queue!(self.stdout, MoveTo(0, row))?;
+        match line.style {
+            Style::Normal => {}
+            Style::Title => queue!(self.stdout, SetAttribute(Attribute::Bold))?,
+            Style::Selected => queue!(self.stdout, SetAttribute(Attribute::Reverse))?,
+            Style::Error => queue!(self.stdout, SetForegroundColor(Color::Red))?,
+            Style::Warning => queue!(self.stdout, SetForegroundColor(Color::Yellow))?,
+            Style::Dim => queue!(self.stdout, SetAttribute(Attribute::Dim))?,
+        }
+        if line.style == Style::Selected {
+            // Highlight the whole row, not just the text
+            queue!(self.stdout, Print(format!("{:<width$}", text, width = width)))?;
+        } else {
+            queue!(self.stdout, Print(text))?;
+        }
+        queue!(self.stdout, SetAttribute(Attribute::Reset), ResetColor)?;
+        Ok(())
+    }
+
+    /// Block until the next key press.
+    pub fn read_key(&mut self) -> Result<KeyEvent> {
+        loop {
+            if let Event::Key(key) = event::read()? {
+                if key.kind == KeyEventKind::Press {
+                    return Ok(key);
+                }
+            }
+        }
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.stdout, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Keep `selected` within `[offset, offset + height)` by moving the scroll offset.
+pub fn scroll_to(selected: usize, offset: usize, height: usize) -> usize {
+    if height == 0 || selected < offset {
+        selected
+    } else if selected >= offset + height {
+        selected + 1 - height
+    } else {
+        offset
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scroll_to() {
+        assert_eq!(scroll_to(0, 0, 10), 0);
+        assert_eq!(scroll_to(9, 0, 10), 0);
+        assert_eq!(scroll_to(10, 0, 10), 1);
+        assert_eq!(scroll_to(3, 5, 10), 3);
+    }
+}
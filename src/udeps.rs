@@ -0,0 +1,229 @@
+use crate::diagnostics::Diagnostic;
+use crate::steps::Problem;
+use crate::{buildstd, context, Config};
+use cargo_metadata::{DependencyKind, MetadataCommand};
+use regex::Regex;
+use serde_json::Value;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// Of the build's options, those that pick what cargo-udeps checks
+const SELECTION: [&str; 5] = ["-p", "--package", "--exclude", "--features", "-F"];
+const SELECTION_FLAGS: [&str; 3] = ["--workspace", "--all-features", "--no-default-features"];
+
+/// What `--udeps` found.
+#[derive(Debug, Default)]
+pub struct Report {
+    /// A warning per dependency that looks unused
+    pub diagnostics: Vec<Diagnostic>,
+    pub crates: usize,
+    /// Whether cargo-udeps checked, or the dependencies were looked for in the sources
+    pub guessed: bool,
+}
+
+impl Report {
+    pub fn describe(&self) -> String {
+        let count = self.diagnostics.len();
+        let mut text = match count {
+            0 => "none".to_string(),
+            _ => format!("{} in {} {}", count, self.crates, if self.crates == 1 { "crate" } else { "crates" }),
+        };
+        if self.guessed {
+            text.push_str(" (guessed from the sources; install cargo-udeps for a check by the compiler)");
+        }
+        text
+    }
+}
+
+/// Look for unused dependencies with cargo-udeps, or in the sources when it isn't
+/// installed.
+pub fn run(config: &Config, root: &Path) -> Result<Report, Problem> {
+    let mut cmd = Command::new(&config.cargo);
+    cmd.args(["udeps", "--output", "json", "--all-targets"]).args(selection(&config.cargo_args));
+    // It builds with -Z options, so it needs a nightly toolchain whatever the build used
+    let nightly = context::cargo_version(config).is_some_and(buildstd::is_nightly) || env::var_os("RUSTC_BOOTSTRAP").is_some();
+    match &config.toolchain {
+        Some(toolchain) if nightly => cmd.env("RUSTUP_TOOLCHAIN", toolchain),
+        _ if nightly => &mut cmd,
+        _ => cmd.env("RUSTUP_TOOLCHAIN", "nightly"),
+    };
+    let output = cmd.output().map_err(|e| Problem { message: format!("failed to run cargo udeps: {}", e), output: None, help: None })?;
+    // It exits with 1 when it finds unused dependencies, and prints its report either way
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if let Ok(json) = serde_json::from_str::<Value>(&stdout) {
+        return Ok(parse(&json, root));
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr).trim_end().to_string();
+    if stderr.contains("no such command") || stderr.contains("is not installed") {
+        return guess(config, root);
+    }
+    Err(Problem {
+        message: "cargo udeps failed".to_string(),
+        output: Some(stderr).filter(|stderr| !stderr.is_empty()),
+        help: None,
+    })
+}
+
+fn selection(cargo_args: &[String]) -> Vec<String> {
+    let end = cargo_args.iter().position(|arg| arg == "--").unwrap_or(cargo_args.len());
+    let mut args = Vec::new();
+    let mut iter = cargo_args[..end].iter();
+    while let Some(arg) = iter.next() {
+        if SELECTION_FLAGS.contains(&arg.as_str()) || arg.starts_with("--manifest-path=") || SELECTION.iter().any(|option| arg.starts_with(&format!("{}=", option))) {
+            args.push(arg.clone());
+        } else if arg == "--manifest-path" || SELECTION.contains(&arg.as_str()) {
+            args.push(arg.clone());
+            args.extend(iter.next().cloned());
+        }
+    }
+    args
+}
+
+/// cargo-udeps' JSON report, with a set of unused dependencies per kind for each crate.
+fn parse(json: &Value, root: &Path) -> Report {
+    let mut report = Report::default();
+    for (id, unused) in json.get("unused_deps").and_then(Value::as_object).into_iter().flatten() {
+        let package = id.split(' ').next().unwrap_or(id);
+        let manifest = unused.get("manifest_path").and_then(Value::as_str).map(PathBuf::from).unwrap_or_default();
+        let mut found = false;
+        for (key, kind) in [("normal", DependencyKind::Normal), ("development", DependencyKind::Development), ("build", DependencyKind::Build)] {
+            for name in unused.get(key).and_then(Value::as_array).into_iter().flatten().filter_map(Value::as_str) {
+                let note = format!("cargo-udeps found no use of it in the build of {}", package);
+                report.diagnostics.push(diagnostic(package, id, &manifest, root, name, kind, note));
+                found = true;
+            }
+        }
+        report.crates += found as usize;
+    }
+    report
+}
+
+/// Without cargo-udeps: the dependencies of each workspace member whose crate name
+/// appears nowhere in the sources that could use it.
+fn guess(config: &Config, root: &Path) -> Result<Report, Problem> {
+    let mut metadata = MetadataCommand::new();
+    metadata.cargo_path(&config.cargo).no_deps();
+    if let Some(manifest_path) = context::manifest_path(&config.cargo_args) {
+        metadata.manifest_path(manifest_path);
+    }
+    let metadata = metadata.exec().map_err(|e| Problem { message: format!("failed to get cargo metadata: {}", e), output: None, help: None })?;
+    let mut report = Report { guessed: true, ..Report::default() };
+    for package in metadata.workspace_packages() {
+        let dependencies: Vec<(String, DependencyKind)> = package.dependencies.iter()
+            .map(|dependency| (dependency.rename.clone().unwrap_or_else(|| dependency.name.clone()), dependency.kind))
+            .collect();
+        let build_script = package.targets.iter().find(|target| target.kind.iter().any(|kind| kind == "custom-build"))
+            .map(|target| target.src_path.clone().into_std_path_buf());
+        let dir = package.manifest_path.parent().map_or_else(PathBuf::new, |dir| dir.as_std_path().to_path_buf());
+        let unused = unused(&dir, build_script.as_deref(), &dependencies);
+        report.crates += !unused.is_empty() as usize;
+        for (name, kind) in unused {
+            let note = format!("no source file of {} mentions `{}`", package.name, name.replace('-', "_"));
+            report.diagnostics.push(diagnostic(&package.name, &package.id.repr, package.manifest_path.as_std_path(), root, &name, kind, note));
+        }
+    }
+    Ok(report)
+}
+
+/// The dependencies no source file that could use them mentions: the build script
+/// for build dependencies, anything but it for dev-dependencies, and the rest for
+/// the others. Macros and `links` crates can use a dependency without naming it.
+fn unused(dir: &Path, build_script: Option<&Path>, dependencies: &[(String, DependencyKind)]) -> Vec<(String, DependencyKind)> {
+    let mut sources = Vec::new();
+    collect_sources(dir, dir, &mut sources);
+    let is_dev = |path: &Path| ["tests", "benches", "examples"].iter().any(|name| path.starts_with(dir.join(name)));
+    dependencies.iter().filter(|(name, kind)| {
+        let word = Regex::new(&format!(r"\b{}\b", regex::escape(&name.replace('-', "_")))).expect("valid regex");
+        !sources.iter().any(|(path, text)| {
+            let in_scope = match kind {
+                DependencyKind::Build => Some(path.as_path()) == build_script,
+                DependencyKind::Development => Some(path.as_path()) != build_script,
+                _ => Some(path.as_path()) != build_script && !is_dev(path),
+            };
+            in_scope && word.is_match(text)
+        })
+    }).cloned().collect()
+}
+
+/// The Rust files of a package, leaving out its target directory and the packages
+/// nested in it.
+fn collect_sources(root: &Path, dir: &Path, sources: &mut Vec<(PathBuf, String)>) {
+    let Ok(entries) = fs::read_dir(dir) else { return };
+    for path in entries.flatten().map(|entry| entry.path()) {
+        let hidden = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with('.') || name == "target");
+        if path.is_dir() && !hidden && (dir == root || !path.join("Cargo.toml").is_file()) {
+            collect_sources(root, &path, sources);
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            sources.extend(fs::read_to_string(&path).ok().map(|text| (path, text)));
+        }
+    }
+}
+
+/// An unused dependency as a warning on the manifest that has it.
+fn diagnostic(package: &str, id: &str, manifest: &Path, root: &Path, name: &str, kind: DependencyKind, note: String) -> Diagnostic {
+    let section = match kind {
+        DependencyKind::Development => "dev-dependencies",
+        DependencyKind::Build => "build-dependencies",
+        _ => "dependencies",
+    };
+    let file = manifest.strip_prefix(root).unwrap_or(manifest).display().to_string();
+    let message = format!("unused dependency `{}` in [{}] of {}", name, section, package);
+    Diagnostic {
+        rendered: format!(
+            "warning: {}\n --> {}\n  = note: {}\n  = help: remove it, or list it in `package.metadata.cargo-udeps.ignore` if it's used in a way that doesn't show\n",
+            message, file, note,
+        ),
+        file: Some(file),
+        package_id: Some(id.to_string()),
+        ..Diagnostic::new("warning", message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let json: Value = serde_json::from_str(r#"{
+            "success": false,
+            "unused_deps": {
+                "app 0.1.0 (path+file:///work/app)": {"manifest_path": "/work/app/Cargo.toml", "normal": ["itoa"], "development": ["tempfile"], "build": []},
+                "core 0.1.0 (path+file:///work/core)": {"manifest_path": "/work/core/Cargo.toml", "normal": [], "development": [], "build": []}
+            },
+            "note": "Note: They might be false-positive."
+        }"#).unwrap();
+        let report = parse(&json, Path::new("/work"));
+        assert_eq!(report.describe(), "2 in 1 crate");
+        assert_eq!(report.diagnostics[1].message, "unused dependency `tempfile` in [dev-dependencies] of app");
+        assert_eq!(report.diagnostics[0].file.as_deref(), Some("app/Cargo.toml"));
+        assert!(report.diagnostics[0].rendered.contains("= note: cargo-udeps found no use of it in the build of app\n"));
+    }
+
+    #[test]
+    fn test_unused() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path();
+        fs::create_dir_all(path.join("src")).unwrap();
+        fs::create_dir_all(path.join("tests")).unwrap();
+        fs::create_dir_all(path.join("target")).unwrap();
+        fs::write(path.join("src/lib.rs"), "use serde_json::Value;\n").unwrap();
+        fs::write(path.join("tests/it.rs"), "use tempfile::tempdir;\nuse regex::Regex;\n").unwrap();
+        fs::write(path.join("build.rs"), "fn main() { cc::Build::new(); }\n").unwrap();
+        fs::write(path.join("target/gen.rs"), "use itoa;\n").unwrap();
+        let dependencies = [
+            ("serde-json".to_string(), DependencyKind::Normal),
+            ("regex".to_string(), DependencyKind::Normal),
+            ("itoa".to_string(), DependencyKind::Normal),
+            ("tempfile".to_string(), DependencyKind::Development),
+            ("cc".to_string(), DependencyKind::Build),
+            ("serde-json".to_string(), DependencyKind::Build),
+        ];
+        let unused = unused(path, Some(&path.join("build.rs")), &dependencies);
+        // regex is only in the tests, and the target directory doesn't count
+        let names: Vec<_> = unused.iter().map(|(name, kind)| (name.as_str(), *kind)).collect();
+        assert_eq!(names, [("regex", DependencyKind::Normal), ("itoa", DependencyKind::Normal), ("serde-json", DependencyKind::Build)]);
+    }
+}
@@ -1,20 +1,37 @@
 use anyhow::{Result, Context};
 use cargo_metadata::MetadataCommand;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::env;
+use std::time::Duration;
 
 pub struct Workspace {
     pub root: PathBuf,
     pub target_directory: PathBuf,
 }
 
+impl Workspace {
+    /// Directory where cargo-builder keeps its own state between runs.
+    pub fn state_dir(&self) -> PathBuf {
+        self.target_directory.join("cargo-builder")
+    }
+}
+
 pub fn find_workspace() -> Result<Workspace> {
+    find_workspace_for(None)
+}
+
+/// The workspace of `manifest_path`, as `--manifest-path` gives it to cargo, or else
+/// of the current directory.
+pub fn find_workspace_for(manifest_path: Option<&Path>) -> Result<Workspace> {
     let mut metadata_cmd = MetadataCommand::new();
     
     // Start from current directory
     let current_dir = env::current_dir()
         .context("Failed to get current directory")?;
     metadata_cmd.current_dir(&current_dir);
+    if let Some(manifest_path) = manifest_path {
+        metadata_cmd.manifest_path(manifest_path);
+    }
     
     // Don't fetch dependencies to make this faster
     metadata_cmd.no_deps();
@@ -28,6 +45,71 @@ pub fn find_workspace() -> Result<Workspace> {
     })
 }
 
+/// cargo's home directory: `$CARGO_HOME`, or `.cargo` in the home directory.
+pub fn cargo_home() -> Option<PathBuf> {
+    env::var_os("CARGO_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os(if cfg!(windows) { "USERPROFILE" } else { "HOME" }).map(|home| Path::new(&home).join(".cargo")))
+}
+
+/// Hash of `fields` as 16 hex digits that stays the same between versions and
+/// platforms, unlike `std`'s hashers, so it can be stored.
+pub fn stable_hash(fields: &[&str]) -> String {
+    // FNV-1a, with a separator so ["ab", "c"] and ["a", "bc"] differ
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for field in fields {
+        for byte in field.bytes().chain([0]) {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+    }
+    format!("{:016x}", hash)
+}
+
+/// Compact human-readable duration, e.g. `4.2s`, `3m 07s`, `1h 02m`.
+pub fn format_duration(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    if secs < 60 {
+        format!("{:.1}s", duration.as_secs_f64())
+    } else if secs < 3600 {
+        format!("{}m {:02}s", secs / 60, secs % 60)
+    } else {
+        format!("{}h {:02}m", secs / 3600, (secs % 3600) / 60)
+    }
+}
+
+/// Parse a byte size such as `5MB`, `512K` or `1048576`. Suffixes are powers of 1024.
+pub fn parse_size(text: &str) -> std::result::Result<u64, String> {
+    let text = text.trim();
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("Invalid size: {}", text))?;
+    let multiplier = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 1,
+        "K" | "KB" | "KIB" => 1 << 10,
+        "M" | "MB" | "MIB" => 1 << 20,
+        "G" | "GB" | "GIB" => 1 << 30,
+        _ => return Err(format!("Invalid size unit: {}", unit)),
+    };
+    number.checked_mul(multiplier).ok_or_else(|| format!("Size too large: {}", text))
+}
+
+/// Format a byte size the way `parse_size` reads it, e.g. `9.1G` or `512M`.
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [(&str, u64); 3] = [("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+    for (unit, size) in UNITS {
+        if bytes >= size {
+            return if bytes.is_multiple_of(size) {
+                format!("{}{}", bytes / size, unit)
+            } else {
+                format!("{:.1}{}", bytes as f64 / size as f64, unit)
+            };
+        }
+    }
+    format!("{}B", bytes)
+}
+
+#[allow(dead_code)]
 pub fn is_in_workspace() -> bool {
     MetadataCommand::new()
         .no_deps()
@@ -39,10 +121,39 @@ pub fn is_in_workspace() -> bool {
 mod tests {
     use super::*;
     use std::fs;
+    use std::sync::Mutex;
     use tempfile::TempDir;
 
+    // These tests change the process-wide current directory, so they must not run in parallel
+    static CWD_LOCK: Mutex<()> = Mutex::new(());
+
+    #[test]
+    fn test_format_duration() {
+        assert_eq!(format_duration(Duration::from_millis(4200)), "4.2s");
+        assert_eq!(format_duration(Duration::from_secs(187)), "3m 07s");
+        assert_eq!(format_duration(Duration::from_secs(3720)), "1h 02m");
+    }
+
+    #[test]
+    fn test_parse_size() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("5MB"), Ok(5 * 1024 * 1024));
+        assert_eq!(parse_size("512k"), Ok(512 * 1024));
+        assert!(parse_size("5 parsecs").is_err());
+        assert!(parse_size("MB").is_err());
+    }
+
+    #[test]
+    fn test_format_size() {
+        assert_eq!(format_size(8 << 30), "8G");
+        assert_eq!(format_size(9_771_000_000), "9.1G");
+        assert_eq!(format_size(512 << 20), "512M");
+        assert_eq!(format_size(100), "100B");
+    }
+
     #[test]
     fn test_find_workspace_in_rust_project() {
+        let _guard = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         // This test will work if run from within the cargo-builder project
         if is_in_workspace() {
             let workspace = find_workspace().unwrap();
@@ -51,8 +162,23 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_find_workspace_for_manifest() {
+        let _guard = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        let temp_dir = TempDir::new().unwrap();
+        fs::write(temp_dir.path().join("Cargo.toml"), "[package]\nname = \"other\"\nversion = \"0.1.0\"\nedition = \"2021\"\n").unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src").join("lib.rs"), "").unwrap();
+
+        // Wherever the current directory is
+        let workspace = find_workspace_for(Some(&temp_dir.path().join("Cargo.toml"))).unwrap();
+        assert_eq!(workspace.root, temp_dir.path().canonicalize().unwrap());
+        assert_eq!(workspace.target_directory, workspace.root.join("target"));
+    }
+
     #[test]
     fn test_is_in_workspace() {
+        let _guard = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         // Create a temporary directory with a Cargo.toml to test workspace detection
         let temp_dir = TempDir::new().unwrap();
         let cargo_toml = temp_dir.path().join("Cargo.toml");
@@ -63,6 +189,8 @@ name = "test"
 version = "0.1.0"
 edition = "2021"
 "#).unwrap();
+        fs::create_dir(temp_dir.path().join("src")).unwrap();
+        fs::write(temp_dir.path().join("src").join("lib.rs"), "").unwrap();
 
         // Change to the temp directory
         let original_dir = env::current_dir().unwrap();
@@ -77,6 +205,7 @@ edition = "2021"
 
     #[test] 
     fn test_not_in_workspace() {
+        let _guard = CWD_LOCK.lock().unwrap_or_else(|e| e.into_inner());
         // Create a temporary directory without Cargo.toml
         let temp_dir = TempDir::new().unwrap();
         
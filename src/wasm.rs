@@ -0,0 +1,127 @@
+use crate::steps::{self, Problem};
+use crate::{context, Config};
+use regex::Regex;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+/// What `--target-wasm` builds for without a value.
+pub const DEFAULT_TARGET: &str = "wasm32-unknown-unknown";
+
+const INSTALL_BINARYEN: &str = "install binaryen, which comes with wasm-opt, from your package manager or https://github.com/WebAssembly/binaryen/releases";
+
+lazy_static::lazy_static! {
+    /// wasm-bindgen refusing a module built with another version of the crate
+    static ref SCHEMA_MISMATCH: Regex = Regex::new(r"rust wasm file schema version: (\S+)").unwrap();
+}
+
+/// A problem when `target` isn't installed for the toolchain, instead of an
+/// error for every crate that can't find `core`.
+pub fn check_target(config: &Config, target: &str) -> Option<Problem> {
+    // Custom target specs have no prebuilt standard library to look for
+    if target.ends_with(".json") {
+        return None;
+    }
+    let sysroot = context::sysroot(config)?;
+    if Path::new(&sysroot).join("lib").join("rustlib").join(target).is_dir() {
+        return None;
+    }
+    let toolchain = config.toolchain.as_ref().map(|toolchain| format!(" --toolchain {}", toolchain)).unwrap_or_default();
+    Some(Problem::new(
+        format!("the `{}` target isn't installed in {}", target, sysroot),
+        format!("run `rustup target add {}{}`", target, toolchain),
+    ))
+}
+
+/// Run `--wasm-bindgen` and `--wasm-opt` on the `modules` the build wrote.
+pub fn post_steps(config: &Config, root: &Path, modules: &[PathBuf]) -> Vec<Problem> {
+    if let Some(problem) = config.wasm_bindgen.as_ref().and_then(|_| check_bindgen(root)) {
+        return vec![problem];
+    }
+    let mut problems = Vec::new();
+    for module in modules {
+        let name = module.file_name().map_or_else(|| module.display().to_string(), |name| name.to_string_lossy().into_owned());
+        let mut optimized = module.clone();
+        if let Some(target) = &config.wasm_bindgen {
+            let out_dir = module.with_file_name("bindgen");
+            if !config.quiet {
+                eprintln!("cargo-builder: Running wasm-bindgen on {}", name);
+            }
+            let mut cmd = Command::new("wasm-bindgen");
+            cmd.args(["--target", target, "--out-dir"]).arg(&out_dir).arg(module);
+            if let Some(problem) = steps::run(cmd, "wasm-bindgen", &name, &install_bindgen(None), bindgen_hint) {
+                problems.push(problem);
+                continue;
+            }
+            let stem = module.file_stem().unwrap_or_default().to_string_lossy();
+            optimized = out_dir.join(format!("{}_bg.wasm", stem));
+        }
+        if let Some(level) = &config.wasm_opt {
+            if !config.quiet {
+                eprintln!("cargo-builder: Running wasm-opt -O{} on {}", level, name);
+            }
+            let mut cmd = Command::new("wasm-opt");
+            cmd.arg(format!("-O{}", level)).arg(&optimized).arg("-o").arg(&optimized);
+            problems.extend(steps::run(cmd, "wasm-opt", &name, INSTALL_BINARYEN, |_| None));
+        }
+    }
+    problems
+}
+
+/// A problem when the wasm-bindgen on PATH isn't the version of the crate in
+/// Cargo.lock, which it would refuse to process modules of.
+fn check_bindgen(root: &Path) -> Option<Problem> {
+    let locked = locked_version(&fs::read_to_string(root.join("Cargo.lock")).ok()?, "wasm-bindgen")?;
+    let Ok(output) = Command::new("wasm-bindgen").arg("--version").output() else {
+        return Some(Problem::new("wasm-bindgen isn't installed".to_string(), install_bindgen(Some(&locked))));
+    };
+    // e.g. "wasm-bindgen 0.2.92"
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let installed = stdout.split_whitespace().nth(1)?;
+    (installed != locked).then(|| Problem::new(
+        format!("wasm-bindgen {} can't process modules built with the wasm-bindgen {} crate in Cargo.lock", installed, locked),
+        install_bindgen(Some(&locked)),
+    ))
+}
+
+/// The version of `name` in `lock`, the contents of a Cargo.lock.
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let lock: toml::Table = lock.parse().ok()?;
+    lock.get("package")?.as_array()?.iter()
+        .find(|package| package.get("name").and_then(|n| n.as_str()) == Some(name))?
+        .get("version")?.as_str().map(String::from)
+}
+
+/// The matching version to install when wasm-bindgen refuses a module built with another.
+fn bindgen_hint(output: &str) -> Option<String> {
+    SCHEMA_MISMATCH.captures(output).map(|captures| install_bindgen(Some(&captures[1])))
+}
+
+fn install_bindgen(version: Option<&str>) -> String {
+    match version {
+        Some(version) => format!("install the matching version with `cargo install wasm-bindgen-cli --version {} --locked`", version),
+        None => "install it with `cargo install wasm-bindgen-cli`, at the version of the wasm-bindgen crate in Cargo.lock".to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_locked_version() {
+        let lock = "version = 3\n\n[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n\n[[package]]\nname = \"wasm-bindgen\"\nversion = \"0.2.92\"\nsource = \"registry+https://github.com/rust-lang/crates.io-index\"\n";
+        assert_eq!(locked_version(lock, "wasm-bindgen").as_deref(), Some("0.2.92"));
+        assert_eq!(locked_version(lock, "wasm-bindgen-macro"), None);
+    }
+
+    #[test]
+    fn test_bindgen_hint() {
+        let stderr = "error: \n\nit looks like the Rust project used to create this wasm file was linked against\nversion of wasm-bindgen that uses a different bindgen format than this binary:\n\n  rust wasm file schema version: 0.2.92\n     this binary schema version: 0.2.87";
+        assert_eq!(
+            bindgen_hint(stderr).as_deref(),
+            Some("install the matching version with `cargo install wasm-bindgen-cli --version 0.2.92 --locked`"),
+        );
+        assert_eq!(bindgen_hint("error: failed to read app.wasm"), None);
+    }
+}